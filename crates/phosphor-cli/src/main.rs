@@ -2,11 +2,11 @@ use anyhow::Result;
 use clap::Parser;
 use crossterm::{
     cursor::{Hide, Show},
-    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{self, Clear, ClearType},
 };
-use phosphor_common::types::Size;
+use phosphor_common::types::{self as types, Size};
 use phosphor_core::{events::Command, Terminal};
 use std::io::{self, Write};
 use tokio::sync::mpsc;
@@ -76,7 +76,7 @@ async fn main() -> Result<()> {
     // Set up terminal
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, Clear(ClearType::All), Hide)?;
+    execute!(stdout, Clear(ClearType::All), Hide, EnableBracketedPaste)?;
     
     // Set shell override if provided
     if let Some(shell) = &args.shell {
@@ -150,7 +150,7 @@ async fn main() -> Result<()> {
     }
     
     // Cleanup
-    execute!(stdout, Show)?;
+    execute!(stdout, Show, DisableBracketedPaste)?;
     terminal::disable_raw_mode()?;
     
     Ok(())
@@ -170,80 +170,20 @@ async fn handle_input(cmd_sender: mpsc::Sender<Command>) -> Result<()> {
                     cmd_sender.send(Command::Close).await?;
                     break;
                 }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) => {
-                    debug!("Key pressed: '{}' (0x{:02x})", c, c as u8);
-                    let data = vec![c as u8];
-                    cmd_sender.send(Command::Write(data)).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    modifiers,
-                    ..
-                }) if modifiers.contains(KeyModifiers::SHIFT) => {
-                    // Handle shifted characters
-                    let data = vec![c as u8];
-                    cmd_sender.send(Command::Write(data)).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
-                    ..
-                }) => {
-                    cmd_sender.send(Command::Write(vec![b'\r'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Tab,
-                    ..
-                }) => {
-                    cmd_sender.send(Command::Write(vec![b'\t'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Backspace,
-                    ..
-                }) => {
-                    cmd_sender.send(Command::Write(vec![0x7f])).await?; // DEL character
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Up,
-                    ..
-                }) => {
-                    // Send cursor up sequence
-                    cmd_sender.send(Command::Write(vec![0x1b, b'[', b'A'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Down,
-                    ..
-                }) => {
-                    // Send cursor down sequence
-                    cmd_sender.send(Command::Write(vec![0x1b, b'[', b'B'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    ..
-                }) => {
-                    // Send cursor right sequence
-                    cmd_sender.send(Command::Write(vec![0x1b, b'[', b'C'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Left,
-                    ..
-                }) => {
-                    // Send cursor left sequence
-                    cmd_sender.send(Command::Write(vec![0x1b, b'[', b'D'])).await?;
-                }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc,
-                    ..
-                }) => {
-                    // Send ESC
-                    cmd_sender.send(Command::Write(vec![0x1b])).await?;
+                Event::Key(event) => match key_event(&event) {
+                    Some(key) => {
+                        debug!("Key pressed: {:?}", key);
+                        cmd_sender.send(Command::Key(key)).await?;
+                    }
+                    None => debug!("Unhandled key: {:?}", event.code),
+                },
+                Event::Paste(text) => {
+                    debug!("Pasted {} bytes", text.len());
+                    cmd_sender.send(Command::Paste(text)).await?;
                 }
                 Event::Resize(cols, rows) => {
                     info!("Terminal resized to {}x{}", cols, rows);
-                    cmd_sender.send(Command::Resize(Size::new(cols, rows))).await?;
+                    cmd_sender.send(Command::Resize { size: Size::new(cols, rows), pixels: None }).await?;
                 }
                 _ => {
                     debug!("Unhandled input event");
@@ -254,4 +194,42 @@ async fn handle_input(cmd_sender: mpsc::Sender<Command>) -> Result<()> {
     
     info!("Input handler exiting");
     Ok(())
+}
+
+/// Translate a crossterm key event for the terminal's key encoder
+fn key_event(event: &KeyEvent) -> Option<types::KeyEvent> {
+    let key = match event.code {
+        KeyCode::Char(c) => types::Key::Char(c),
+        KeyCode::Enter => types::Key::Enter,
+        KeyCode::Tab | KeyCode::BackTab => types::Key::Tab,
+        KeyCode::Backspace => types::Key::Backspace,
+        KeyCode::Esc => types::Key::Escape,
+        KeyCode::Up => types::Key::Up,
+        KeyCode::Down => types::Key::Down,
+        KeyCode::Left => types::Key::Left,
+        KeyCode::Right => types::Key::Right,
+        KeyCode::Home => types::Key::Home,
+        KeyCode::End => types::Key::End,
+        KeyCode::PageUp => types::Key::PageUp,
+        KeyCode::PageDown => types::Key::PageDown,
+        KeyCode::Insert => types::Key::Insert,
+        KeyCode::Delete => types::Key::Delete,
+        KeyCode::F(n) => types::Key::F(n),
+        _ => return None,
+    };
+    
+    let mut modifiers = types::KeyModifiers::empty();
+    if event.modifiers.contains(KeyModifiers::SHIFT) || event.code == KeyCode::BackTab {
+        modifiers |= types::KeyModifiers::SHIFT;
+    }
+    if event.modifiers.contains(KeyModifiers::ALT) {
+        modifiers |= types::KeyModifiers::ALT;
+    }
+    if event.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers |= types::KeyModifiers::CTRL;
+    }
+    if event.modifiers.contains(KeyModifiers::SUPER) {
+        modifiers |= types::KeyModifiers::SUPER;
+    }
+    Some(types::KeyEvent::new(key, modifiers))
 }
\ No newline at end of file
@@ -0,0 +1,275 @@
+use crate::types::{Color, Rgb};
+
+/// Parse an X11 color specification into an RGB color
+///
+/// Supports the formats accepted by XParseColor and used by OSC 4/10/11/12:
+/// - `rgb:R/G/B` with 1-4 hex digits per component
+/// - `rgbi:R/G/B` with floating point intensities in 0.0-1.0
+/// - `#RGB`, `#RRGGBB`, `#RRRGGGBBB` and `#RRRRGGGGBBBB`
+/// - X11 color names such as `red` or `dark slate gray` (case-insensitive)
+pub fn parse_color_spec(spec: &str) -> Option<Color> {
+    let spec = spec.trim();
+    
+    if let Some(rest) = strip_prefix_ignore_case(spec, "rgbi:") {
+        parse_rgbi(rest)
+    } else if let Some(rest) = strip_prefix_ignore_case(spec, "rgb:") {
+        parse_rgb(rest)
+    } else if let Some(rest) = spec.strip_prefix('#') {
+        parse_hash(rest)
+    } else {
+        lookup_name(spec)
+    }
+}
+
+/// Format a color as an X11 `rgb:RRRR/GGGG/BBBB` spec, as xterm reports colors
+///
+/// Returns `None` for colors without a fixed RGB value.
+pub fn format_color_spec(color: Color) -> Option<String> {
+    match color {
+        Color::Rgb(r, g, b) => Some(format!(
+            "rgb:{:04x}/{:04x}/{:04x}",
+            r as u16 * 0x101,
+            g as u16 * 0x101,
+            b as u16 * 0x101
+        )),
+        _ => None,
+    }
+}
+
+/// The RGB value of a 256-color palette entry before any OSC 4 change
+///
+/// Uses xterm's defaults: its 16 ANSI colors, the 6x6x6 cube and the
+/// 24-step grayscale ramp.
+pub fn default_rgb(index: u8) -> Rgb {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+        (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+        (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+        (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+    ];
+    match index {
+        0..=15 => {
+            let (r, g, b) = ANSI[index as usize];
+            Rgb::new(r, g, b)
+        }
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            Rgb::new(level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            Rgb::new(gray, gray, gray)
+        }
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    if s.len() >= prefix.len() && s[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Scale a hex component of 1-4 digits to 8 bits
+fn scale_hex(component: &str) -> Option<u8> {
+    if component.is_empty() || component.len() > 4 {
+        return None;
+    }
+    let value = u32::from_str_radix(component, 16).ok()?;
+    let max = (1u32 << (4 * component.len())) - 1;
+    Some(((value * 255 + max / 2) / max) as u8)
+}
+
+fn parse_rgb(rest: &str) -> Option<Color> {
+    let mut parts = rest.split('/');
+    let r = scale_hex(parts.next()?)?;
+    let g = scale_hex(parts.next()?)?;
+    let b = scale_hex(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Color::Rgb(r, g, b))
+}
+
+fn parse_rgbi(rest: &str) -> Option<Color> {
+    let mut parts = rest.split('/');
+    let mut component = || -> Option<u8> {
+        let value: f32 = parts.next()?.parse().ok()?;
+        if !(0.0..=1.0).contains(&value) {
+            return None;
+        }
+        Some((value * 255.0).round() as u8)
+    };
+    let (r, g, b) = (component()?, component()?, component()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Color::Rgb(r, g, b))
+}
+
+fn parse_hash(rest: &str) -> Option<Color> {
+    let len = rest.len();
+    if len == 0 || !len.is_multiple_of(3) || len > 12 || !rest.is_ascii() {
+        return None;
+    }
+    let width = len / 3;
+    // In the legacy #-syntax the digits are the most significant bits
+    let component = |i: usize| -> Option<u8> {
+        let value = u32::from_str_radix(&rest[i * width..(i + 1) * width], 16).ok()?;
+        Some(match width {
+            1 => (value << 4 | value) as u8,
+            _ => (value >> (4 * (width - 2))) as u8,
+        })
+    };
+    Some(Color::Rgb(component(0)?, component(1)?, component(2)?))
+}
+
+fn lookup_name(name: &str) -> Option<Color> {
+    // X11 names are matched ignoring case and spaces ("Dark Slate Gray")
+    let key: String = name
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    
+    if let Some(level) = key.strip_prefix("gray").or_else(|| key.strip_prefix("grey")) {
+        if !level.is_empty() {
+            let level: u32 = level.parse().ok()?;
+            if level > 100 {
+                return None;
+            }
+            let v = ((level * 255 + 50) / 100) as u8;
+            return Some(Color::Rgb(v, v, v));
+        }
+    }
+    
+    X11_COLORS
+        .iter()
+        .find(|(n, _)| *n == key)
+        .map(|&(_, (r, g, b))| Color::Rgb(r, g, b))
+}
+
+/// Commonly used X11 color names (lowercase, without spaces)
+const X11_COLORS: &[(&str, (u8, u8, u8))] = &[
+    ("black", (0, 0, 0)),
+    ("white", (255, 255, 255)),
+    ("red", (255, 0, 0)),
+    ("green", (0, 255, 0)),
+    ("blue", (0, 0, 255)),
+    ("yellow", (255, 255, 0)),
+    ("cyan", (0, 255, 255)),
+    ("magenta", (255, 0, 255)),
+    ("gray", (190, 190, 190)),
+    ("grey", (190, 190, 190)),
+    ("darkgray", (169, 169, 169)),
+    ("darkgrey", (169, 169, 169)),
+    ("lightgray", (211, 211, 211)),
+    ("lightgrey", (211, 211, 211)),
+    ("dimgray", (105, 105, 105)),
+    ("dimgrey", (105, 105, 105)),
+    ("slategray", (112, 128, 144)),
+    ("darkslategray", (47, 79, 79)),
+    ("darkred", (139, 0, 0)),
+    ("darkgreen", (0, 100, 0)),
+    ("darkblue", (0, 0, 139)),
+    ("darkcyan", (0, 139, 139)),
+    ("darkmagenta", (139, 0, 139)),
+    ("darkorange", (255, 140, 0)),
+    ("lightblue", (173, 216, 230)),
+    ("lightgreen", (144, 238, 144)),
+    ("lightcyan", (224, 255, 255)),
+    ("lightyellow", (255, 255, 224)),
+    ("navy", (0, 0, 128)),
+    ("navyblue", (0, 0, 128)),
+    ("royalblue", (65, 105, 225)),
+    ("steelblue", (70, 130, 180)),
+    ("skyblue", (135, 206, 235)),
+    ("dodgerblue", (30, 144, 255)),
+    ("forestgreen", (34, 139, 34)),
+    ("limegreen", (50, 205, 50)),
+    ("seagreen", (46, 139, 87)),
+    ("olivedrab", (107, 142, 35)),
+    ("orange", (255, 165, 0)),
+    ("orangered", (255, 69, 0)),
+    ("gold", (255, 215, 0)),
+    ("goldenrod", (218, 165, 32)),
+    ("brown", (165, 42, 42)),
+    ("maroon", (176, 48, 96)),
+    ("purple", (160, 32, 240)),
+    ("violet", (238, 130, 238)),
+    ("orchid", (218, 112, 214)),
+    ("pink", (255, 192, 203)),
+    ("hotpink", (255, 105, 180)),
+    ("salmon", (250, 128, 114)),
+    ("coral", (255, 127, 80)),
+    ("tomato", (255, 99, 71)),
+    ("khaki", (240, 230, 140)),
+    ("wheat", (245, 222, 179)),
+    ("tan", (210, 180, 140)),
+    ("beige", (245, 245, 220)),
+    ("ivory", (255, 255, 240)),
+    ("snow", (255, 250, 250)),
+    ("turquoise", (64, 224, 208)),
+    ("aquamarine", (127, 255, 212)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_format_color_spec() {
+        assert_eq!(format_color_spec(Color::Rgb(255, 128, 0)).as_deref(), Some("rgb:ffff/8080/0000"));
+        assert_eq!(format_color_spec(Color::Red), None);
+        
+        let spec = format_color_spec(Color::Rgb(18, 52, 86)).unwrap();
+        assert_eq!(parse_color_spec(&spec), Some(Color::Rgb(18, 52, 86)));
+    }
+    
+    #[test]
+    fn test_rgb_specs() {
+        assert_eq!(parse_color_spec("rgb:ff/80/00"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color_spec("rgb:ffff/0000/8080"), Some(Color::Rgb(255, 0, 128)));
+        assert_eq!(parse_color_spec("rgb:f/0/8"), Some(Color::Rgb(255, 0, 136)));
+        assert_eq!(parse_color_spec("RGB:FF/FF/FF"), Some(Color::Rgb(255, 255, 255)));
+        assert_eq!(parse_color_spec("rgb:ff/80"), None);
+        assert_eq!(parse_color_spec("rgb:ff/80/00/00"), None);
+        assert_eq!(parse_color_spec("rgb:fffff/0/0"), None);
+    }
+    
+    #[test]
+    fn test_rgbi_specs() {
+        assert_eq!(parse_color_spec("rgbi:1/0.5/0"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color_spec("rgbi:1.5/0/0"), None);
+    }
+    
+    #[test]
+    fn test_hash_specs() {
+        assert_eq!(parse_color_spec("#f80"), Some(Color::Rgb(255, 136, 0)));
+        assert_eq!(parse_color_spec("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color_spec("#ffff80800000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color_spec("#ff80"), None);
+        assert_eq!(parse_color_spec("#gg0000"), None);
+    }
+    
+    #[test]
+    fn test_color_names() {
+        assert_eq!(parse_color_spec("red"), Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(parse_color_spec("Dark Slate Gray"), Some(Color::Rgb(47, 79, 79)));
+        assert_eq!(parse_color_spec("gray50"), Some(Color::Rgb(128, 128, 128)));
+        assert_eq!(parse_color_spec("grey100"), Some(Color::Rgb(255, 255, 255)));
+        assert_eq!(parse_color_spec("not-a-color"), None);
+    }
+    
+    #[test]
+    fn test_default_rgb() {
+        assert_eq!(default_rgb(1), Rgb::new(205, 0, 0));
+        assert_eq!(default_rgb(16), Rgb::new(0, 0, 0));
+        assert_eq!(default_rgb(196), Rgb::new(255, 0, 0));
+        assert_eq!(default_rgb(231), Rgb::new(255, 255, 255));
+        assert_eq!(default_rgb(232), Rgb::new(8, 8, 8));
+        assert_eq!(default_rgb(255), Rgb::new(238, 238, 238));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::{PixelSize, Position, Size};
+
+/// Pixel dimensions of a single character cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellMetrics {
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Which half of a cell a pixel coordinate falls in
+///
+/// Selection uses this to decide whether a cell under the pointer is included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A cell position resolved from pixel coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GridPoint {
+    pub position: Position,
+    pub side: Side,
+}
+
+impl CellMetrics {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self { width, height }
+    }
+    
+    /// Cell size of a grid of `size` cells filling a text area of `pixels`
+    ///
+    /// Returns `None` if the area is smaller than one pixel per cell.
+    pub fn from_text_area(pixels: PixelSize, size: Size) -> Option<Self> {
+        let width = pixels.width / size.cols.max(1);
+        let height = pixels.height / size.rows.max(1);
+        (width > 0 && height > 0).then(|| Self::new(width, height))
+    }
+    
+    /// Convert a pixel coordinate relative to the grid origin into a cell
+    ///
+    /// Coordinates outside the grid are clamped to the nearest cell. On a
+    /// double-width line (DECDWL) every cell covers two cell widths.
+    pub fn pixel_to_cell(&self, x: f64, y: f64, size: Size, double_width: bool) -> GridPoint {
+        let cell_width = f64::from(self.width.max(1)) * if double_width { 2.0 } else { 1.0 };
+        let cell_height = f64::from(self.height.max(1));
+        let cols = if double_width { size.cols / 2 } else { size.cols };
+        
+        let x = x.max(0.0);
+        let y = y.max(0.0);
+        let col = (x / cell_width) as u32;
+        let row = (y / cell_height) as u32;
+        let max_col = u32::from(cols.max(1) - 1);
+        let max_row = u32::from(size.rows.max(1) - 1);
+        
+        let side = if col > max_col || x - f64::from(col) * cell_width >= cell_width / 2.0 {
+            Side::Right
+        } else {
+            Side::Left
+        };
+        
+        GridPoint {
+            position: Position::new(row.min(max_row) as u16, col.min(max_col) as u16),
+            side,
+        }
+    }
+    
+    /// Pixel coordinate of the top-left corner of a cell
+    pub fn cell_to_pixel(&self, pos: Position, double_width: bool) -> (u32, u32) {
+        let scale = if double_width { 2 } else { 1 };
+        (
+            u32::from(pos.col) * u32::from(self.width) * scale,
+            u32::from(pos.row) * u32::from(self.height),
+        )
+    }
+    
+    /// Pixel dimensions of a full grid of the given size
+    pub fn grid_pixel_size(&self, size: Size) -> (u32, u32) {
+        (
+            u32::from(size.cols) * u32::from(self.width),
+            u32::from(size.rows) * u32::from(self.height),
+        )
+    }
+    
+    /// Number of whole cells that fit in a pixel area, e.g. after a window resize
+    pub fn grid_size_for(&self, width_px: u32, height_px: u32) -> Size {
+        let cols = width_px / u32::from(self.width.max(1));
+        let rows = height_px / u32::from(self.height.max(1));
+        Size::new(
+            cols.min(u32::from(u16::MAX)) as u16,
+            rows.min(u32::from(u16::MAX)) as u16,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_pixel_to_cell() {
+        let metrics = CellMetrics::new(10, 20);
+        let size = Size::new(80, 24);
+        
+        let point = metrics.pixel_to_cell(23.0, 45.0, size, false);
+        assert_eq!(point.position, Position::new(2, 2));
+        assert_eq!(point.side, Side::Left);
+        
+        let point = metrics.pixel_to_cell(27.0, 45.0, size, false);
+        assert_eq!(point.side, Side::Right);
+        
+        // Out of bounds coordinates clamp to the grid
+        let point = metrics.pixel_to_cell(-5.0, 10_000.0, size, false);
+        assert_eq!(point.position, Position::new(23, 0));
+        let point = metrics.pixel_to_cell(10_000.0, 0.0, size, false);
+        assert_eq!(point.position, Position::new(0, 79));
+        assert_eq!(point.side, Side::Right);
+    }
+    
+    #[test]
+    fn test_double_width_lines() {
+        let metrics = CellMetrics::new(10, 20);
+        let size = Size::new(80, 24);
+        
+        let point = metrics.pixel_to_cell(25.0, 0.0, size, true);
+        assert_eq!(point.position, Position::new(0, 1));
+        assert_eq!(metrics.cell_to_pixel(Position::new(1, 3), true), (60, 20));
+        
+        // Only half the columns are addressable on a double-width line
+        let point = metrics.pixel_to_cell(795.0, 0.0, size, true);
+        assert_eq!(point.position, Position::new(0, 39));
+    }
+    
+    #[test]
+    fn test_grid_sizes() {
+        let metrics = CellMetrics::new(8, 16);
+        assert_eq!(metrics.grid_pixel_size(Size::new(80, 24)), (640, 384));
+        assert_eq!(metrics.grid_size_for(645, 390), Size::new(80, 24));
+        assert_eq!(metrics.cell_to_pixel(Position::new(2, 5), false), (40, 32));
+    }
+}
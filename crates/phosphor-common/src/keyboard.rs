@@ -0,0 +1,442 @@
+use crate::mouse::{MouseEncoding, MouseTracking};
+use crate::types::{Key, KeyEvent, KeyModifiers, KeypadKey};
+
+/// Terminal modes that change how keys are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputModes {
+    /// DECCKM: unmodified cursor keys send SS3 instead of CSI
+    pub application_cursor: bool,
+    /// DECKPAM: keypad keys send SS3 sequences instead of their characters
+    pub application_keypad: bool,
+    /// LNM: Enter sends CR LF instead of CR
+    pub line_feed: bool,
+    /// XTMODKEYS modifyOtherKeys level (0-2)
+    pub modify_other_keys: u8,
+    /// Kitty keyboard protocol flags; when set they take precedence over modifyOtherKeys
+    pub keyboard_flags: u8,
+    /// Which mouse events are reported
+    pub mouse_tracking: MouseTracking,
+    /// How reported mouse events are encoded
+    pub mouse_encoding: MouseEncoding,
+    /// What Alt does to a key
+    pub alt_key: AltKey,
+    /// DECSET 2004: pastes are wrapped in `CSI 200 ~` and `CSI 201 ~`
+    pub bracketed_paste: bool,
+}
+
+/// What the Alt modifier does, as chosen by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AltKey {
+    /// Prefix the key with ESC, or report it as a modifier when a
+    /// protocol can
+    #[default]
+    Escape,
+    /// Treat Alt as not held, for frontends whose keyboard layout already
+    /// composed the character (e.g. Option on macOS)
+    Ignore,
+}
+
+/// Kitty keyboard flag: report ambiguous keys (Esc, Alt/Ctrl combinations) as CSI u
+pub const KITTY_DISAMBIGUATE: u8 = 1 << 0;
+/// Kitty keyboard flag: report event types (press, repeat, release)
+pub const KITTY_REPORT_EVENT_TYPES: u8 = 1 << 1;
+/// Kitty keyboard flag: report shifted and base layout keys
+pub const KITTY_REPORT_ALTERNATE_KEYS: u8 = 1 << 2;
+/// Kitty keyboard flag: report every key, including plain text, as an escape code
+pub const KITTY_REPORT_ALL_KEYS: u8 = 1 << 3;
+/// Kitty keyboard flag: report the text a key generates
+pub const KITTY_REPORT_TEXT: u8 = 1 << 4;
+
+/// Encode pasted text, bracketed when the application asked for it
+///
+/// Bracket markers inside the text are removed, so a paste cannot end
+/// early and have the rest run as typed input.
+pub fn encode_paste(text: &str, modes: InputModes) -> Vec<u8> {
+    if !modes.bracketed_paste {
+        return text.as_bytes().to_vec();
+    }
+    // Removing a marker can join the text around it into a new one
+    let mut text = text.to_string();
+    while text.contains("\x1b[200~") || text.contains("\x1b[201~") {
+        text = text.replace("\x1b[200~", "").replace("\x1b[201~", "");
+    }
+    format!("\x1b[200~{}\x1b[201~", text).into_bytes()
+}
+
+/// Encode a key press as the bytes the application expects
+///
+/// Keys with no encoding (e.g. F13) produce no bytes.
+pub fn encode_key(mut event: KeyEvent, modes: InputModes) -> Vec<u8> {
+    if modes.alt_key == AltKey::Ignore {
+        event.modifiers.remove(KeyModifiers::ALT);
+    }
+    if modes.keyboard_flags != 0 {
+        if let Some(bytes) = kitty_key(event, modes.keyboard_flags) {
+            return bytes;
+        }
+        // The remaining keys use the legacy encoding, without modifyOtherKeys
+        let modes = InputModes { modify_other_keys: 0, ..modes };
+        return legacy_key(event, modes);
+    }
+    legacy_key(event, modes)
+}
+
+/// Encode a key with the kitty keyboard protocol
+///
+/// Only key presses are reported, so the event type, alternate key and
+/// text flags add nothing. Returns `None` for keys the protocol leaves in
+/// their legacy encoding.
+fn kitty_key(event: KeyEvent, flags: u8) -> Option<Vec<u8>> {
+    let all_keys = flags & KITTY_REPORT_ALL_KEYS != 0;
+    if !all_keys && flags & KITTY_DISAMBIGUATE == 0 {
+        return None;
+    }
+    
+    let mods = event.modifiers;
+    let (number, final_byte) = match event.key {
+        Key::Char(ch) if all_keys || mods.intersects(KeyModifiers::ALT | KeyModifiers::CTRL | KeyModifiers::SUPER) => {
+            // Keys are reported by their unshifted codepoint
+            let base = if mods.contains(KeyModifiers::SHIFT) {
+                ch.to_lowercase().next().unwrap_or(ch)
+            } else {
+                ch
+            };
+            (base as u32, 'u')
+        }
+        Key::Char(_) => return None,
+        Key::Escape => (27, 'u'),
+        // Enter, Tab and Backspace stay legacy so a shell stays usable if
+        // an application exits without resetting the flags
+        Key::Enter if all_keys => (13, 'u'),
+        Key::Tab if all_keys => (9, 'u'),
+        Key::Backspace if all_keys => (127, 'u'),
+        Key::Enter | Key::Tab | Key::Backspace => return None,
+        Key::Up => (1, 'A'),
+        Key::Down => (1, 'B'),
+        Key::Right => (1, 'C'),
+        Key::Left => (1, 'D'),
+        Key::Home => (1, 'H'),
+        Key::End => (1, 'F'),
+        Key::F(1) => (1, 'P'),
+        Key::F(2) => (1, 'Q'),
+        // CSI R would be mistaken for a cursor position report
+        Key::F(3) => (13, '~'),
+        Key::F(4) => (1, 'S'),
+        // Keypad keys have their own codes in the private use area
+        Key::Keypad(key) => {
+            let code = match key {
+                KeypadKey::Digit(digit) => 57399 + digit.min(9) as u32,
+                KeypadKey::Decimal => 57409,
+                KeypadKey::Divide => 57410,
+                KeypadKey::Multiply => 57411,
+                KeypadKey::Subtract => 57412,
+                KeypadKey::Add => 57413,
+                KeypadKey::Enter => 57414,
+                KeypadKey::Equal => 57415,
+            };
+            (code, 'u')
+        }
+        _ => return None,
+    };
+    
+    let param = modifier_param(mods);
+    Some(if param > 1 {
+        format!("\x1b[{};{}{}", number, param, final_byte).into_bytes()
+    } else if final_byte.is_ascii_uppercase() {
+        format!("\x1b[{}", final_byte).into_bytes()
+    } else {
+        format!("\x1b[{}{}", number, final_byte).into_bytes()
+    })
+}
+
+fn legacy_key(event: KeyEvent, modes: InputModes) -> Vec<u8> {
+    let mods = event.modifiers;
+    match event.key {
+        Key::Up => cursor_key(b'A', mods, modes),
+        Key::Down => cursor_key(b'B', mods, modes),
+        Key::Right => cursor_key(b'C', mods, modes),
+        Key::Left => cursor_key(b'D', mods, modes),
+        Key::Home => cursor_key(b'H', mods, modes),
+        Key::End => cursor_key(b'F', mods, modes),
+        Key::Insert => tilde_key(2, mods),
+        Key::Delete => tilde_key(3, mods),
+        Key::PageUp => tilde_key(5, mods),
+        Key::PageDown => tilde_key(6, mods),
+        Key::F(n) => function_key(n, mods),
+        Key::Char(ch) => char_key(ch, mods, modes),
+        Key::Enter | Key::Tab | Key::Backspace | Key::Escape => control_key(event.key, mods, modes),
+        Key::Keypad(key) => keypad_key(key, mods, modes),
+    }
+}
+
+/// Keypad keys send SS3 sequences in application mode, and otherwise
+/// what the matching main keyboard key would
+fn keypad_key(key: KeypadKey, mods: KeyModifiers, modes: InputModes) -> Vec<u8> {
+    let (final_byte, ch) = match key {
+        KeypadKey::Digit(digit) => {
+            let digit = digit.min(9);
+            (b'p' + digit, (b'0' + digit) as char)
+        }
+        KeypadKey::Decimal => (b'n', '.'),
+        KeypadKey::Divide => (b'o', '/'),
+        KeypadKey::Multiply => (b'j', '*'),
+        KeypadKey::Subtract => (b'm', '-'),
+        KeypadKey::Add => (b'k', '+'),
+        KeypadKey::Equal => (b'X', '='),
+        KeypadKey::Enter if modes.application_keypad => (b'M', '\r'),
+        KeypadKey::Enter => return control_key(Key::Enter, mods, modes),
+    };
+    if !modes.application_keypad {
+        return char_key(ch, mods, modes);
+    }
+    if mods.is_empty() {
+        vec![0x1b, b'O', final_byte]
+    } else {
+        format!("\x1bO{}{}", modifier_param(mods), final_byte as char).into_bytes()
+    }
+}
+
+/// xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4) + super(8)
+fn modifier_param(mods: KeyModifiers) -> u8 {
+    1 + mods.bits()
+}
+
+fn cursor_key(final_byte: u8, mods: KeyModifiers, modes: InputModes) -> Vec<u8> {
+    if !mods.is_empty() {
+        return format!("\x1b[1;{}{}", modifier_param(mods), final_byte as char).into_bytes();
+    }
+    let introducer = if modes.application_cursor { b'O' } else { b'[' };
+    vec![0x1b, introducer, final_byte]
+}
+
+fn tilde_key(code: u8, mods: KeyModifiers) -> Vec<u8> {
+    if mods.is_empty() {
+        format!("\x1b[{}~", code).into_bytes()
+    } else {
+        format!("\x1b[{};{}~", code, modifier_param(mods)).into_bytes()
+    }
+}
+
+fn function_key(n: u8, mods: KeyModifiers) -> Vec<u8> {
+    match n {
+        1..=4 => {
+            let final_byte = (b'P' + n - 1) as char;
+            if mods.is_empty() {
+                format!("\x1bO{}", final_byte).into_bytes()
+            } else {
+                format!("\x1b[1;{}{}", modifier_param(mods), final_byte).into_bytes()
+            }
+        }
+        5..=12 => {
+            const CODES: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
+            tilde_key(CODES[n as usize - 5], mods)
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Control code sent for Ctrl+`ch`, if there is one
+fn ctrl_byte(ch: char) -> Option<u8> {
+    Some(match ch {
+        'a'..='z' => ch as u8 - b'a' + 1,
+        'A'..='Z' => ch as u8 - b'A' + 1,
+        '@' | ' ' | '2' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' => 0x1e,
+        '_' | '/' | '7' => 0x1f,
+        '?' | '8' => 0x7f,
+        _ => return None,
+    })
+}
+
+/// Whether modifyOtherKeys replaces the legacy encoding
+///
+/// Level 1 only does so for combinations the legacy encoding cannot
+/// represent; level 2 also does for every Alt, Ctrl or Super combination.
+fn use_modify_other_keys(mods: KeyModifiers, legacy_ok: bool, modes: InputModes) -> bool {
+    match modes.modify_other_keys {
+        0 => false,
+        1 => !legacy_ok,
+        _ => !legacy_ok || mods.intersects(KeyModifiers::ALT | KeyModifiers::CTRL | KeyModifiers::SUPER),
+    }
+}
+
+fn modify_other_keys(code: u32, mods: KeyModifiers) -> Vec<u8> {
+    format!("\x1b[27;{};{}~", modifier_param(mods), code).into_bytes()
+}
+
+fn char_key(ch: char, mods: KeyModifiers, modes: InputModes) -> Vec<u8> {
+    let ctrl = mods.contains(KeyModifiers::CTRL);
+    let ctrl_code = ctrl_byte(ch);
+    
+    // Shift is already applied to `ch`, except that Ctrl folds case
+    let legacy_ok = !mods.contains(KeyModifiers::SUPER)
+        && (!ctrl || (ctrl_code.is_some() && !(mods.contains(KeyModifiers::SHIFT) && ch.is_ascii_alphabetic())));
+    if use_modify_other_keys(mods, legacy_ok, modes) {
+        return modify_other_keys(ch as u32, mods);
+    }
+    
+    let mut bytes = Vec::new();
+    if mods.contains(KeyModifiers::ALT) {
+        bytes.push(0x1b);
+    }
+    match ctrl_code.filter(|_| ctrl) {
+        Some(code) => bytes.push(code),
+        None => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+    }
+    bytes
+}
+
+fn control_key(key: Key, mods: KeyModifiers, modes: InputModes) -> Vec<u8> {
+    let ctrl = mods.contains(KeyModifiers::CTRL);
+    let shift = mods.contains(KeyModifiers::SHIFT);
+    let (code, legacy): (u8, &[u8]) = match key {
+        Key::Enter if modes.line_feed => (b'\r', b"\r\n"),
+        Key::Enter => (b'\r', b"\r"),
+        Key::Tab if shift => (b'\t', b"\x1b[Z"),
+        Key::Tab => (b'\t', b"\t"),
+        Key::Backspace if ctrl => (0x7f, b"\x08"),
+        Key::Backspace => (0x7f, b"\x7f"),
+        _ => (0x1b, b"\x1b"),
+    };
+    let legacy_ok = !mods.contains(KeyModifiers::SUPER)
+        && match key {
+            Key::Tab => !ctrl,
+            Key::Backspace => !shift,
+            _ => !ctrl && !shift,
+        };
+    if use_modify_other_keys(mods, legacy_ok, modes) {
+        return modify_other_keys(code as u32, mods);
+    }
+    
+    let mut bytes = Vec::new();
+    if mods.contains(KeyModifiers::ALT) {
+        bytes.push(0x1b);
+    }
+    bytes.extend_from_slice(legacy);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn key(key: Key, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(key, modifiers)
+    }
+    
+    #[test]
+    fn test_legacy_encoding() {
+        let modes = InputModes::default();
+        let none = KeyModifiers::empty();
+        assert_eq!(encode_key(key(Key::Char('a'), none), modes), b"a");
+        assert_eq!(encode_key(key(Key::Char('é'), none), modes), "é".as_bytes());
+        assert_eq!(encode_key(key(Key::Char('c'), KeyModifiers::CTRL), modes), b"\x03");
+        assert_eq!(encode_key(key(Key::Char('x'), KeyModifiers::ALT), modes), b"\x1bx");
+        assert_eq!(encode_key(key(Key::Tab, KeyModifiers::SHIFT), modes), b"\x1b[Z");
+        assert_eq!(encode_key(key(Key::Backspace, none), modes), b"\x7f");
+        assert_eq!(encode_key(key(Key::Up, none), modes), b"\x1b[A");
+        assert_eq!(encode_key(key(Key::Up, KeyModifiers::CTRL), modes), b"\x1b[1;5A");
+        assert_eq!(encode_key(key(Key::Delete, KeyModifiers::SHIFT), modes), b"\x1b[3;2~");
+        assert_eq!(encode_key(key(Key::F(1), none), modes), b"\x1bOP");
+        assert_eq!(encode_key(key(Key::F(5), none), modes), b"\x1b[15~");
+        assert!(encode_key(key(Key::F(13), none), modes).is_empty());
+        
+        let app = InputModes { application_cursor: true, ..modes };
+        assert_eq!(encode_key(key(Key::Left, none), app), b"\x1bOD");
+        
+        let lnm = InputModes { line_feed: true, ..modes };
+        assert_eq!(encode_key(key(Key::Enter, none), lnm), b"\r\n");
+    }
+    
+    #[test]
+    fn test_modify_other_keys() {
+        let level1 = InputModes { modify_other_keys: 1, ..Default::default() };
+        let level2 = InputModes { modify_other_keys: 2, ..Default::default() };
+        let ctrl_shift = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+        
+        // Level 1 keeps the well-known encodings
+        assert_eq!(encode_key(key(Key::Char('c'), KeyModifiers::CTRL), level1), b"\x03");
+        assert_eq!(encode_key(key(Key::Char('x'), KeyModifiers::ALT), level1), b"\x1bx");
+        assert_eq!(encode_key(key(Key::Char('A'), ctrl_shift), level1), b"\x1b[27;6;65~");
+        assert_eq!(encode_key(key(Key::Char(';'), KeyModifiers::CTRL), level1), b"\x1b[27;5;59~");
+        assert_eq!(encode_key(key(Key::Enter, KeyModifiers::SHIFT), level1), b"\x1b[27;2;13~");
+        
+        // Level 2 reports every Alt/Ctrl/Super combination
+        assert_eq!(encode_key(key(Key::Char('c'), KeyModifiers::CTRL), level2), b"\x1b[27;5;99~");
+        assert_eq!(encode_key(key(Key::Char('x'), KeyModifiers::ALT), level2), b"\x1b[27;3;120~");
+        assert_eq!(encode_key(key(Key::Tab, KeyModifiers::CTRL), level2), b"\x1b[27;5;9~");
+        assert_eq!(encode_key(key(Key::Char('A'), KeyModifiers::SHIFT), level2), b"A");
+        
+        // Cursor and function keys are unaffected
+        assert_eq!(encode_key(key(Key::Up, KeyModifiers::CTRL), level2), b"\x1b[1;5A");
+    }
+    
+    #[test]
+    fn test_kitty_keyboard() {
+        let disambiguate = InputModes { keyboard_flags: KITTY_DISAMBIGUATE, modify_other_keys: 2, ..Default::default() };
+        let none = KeyModifiers::empty();
+        
+        assert_eq!(encode_key(key(Key::Char('a'), none), disambiguate), b"a");
+        assert_eq!(encode_key(key(Key::Char('A'), KeyModifiers::SHIFT), disambiguate), b"A");
+        assert_eq!(encode_key(key(Key::Char('c'), KeyModifiers::CTRL), disambiguate), b"\x1b[99;5u");
+        assert_eq!(
+            encode_key(key(Key::Char('A'), KeyModifiers::CTRL | KeyModifiers::SHIFT), disambiguate),
+            b"\x1b[97;6u"
+        );
+        assert_eq!(encode_key(key(Key::Escape, none), disambiguate), b"\x1b[27u");
+        assert_eq!(encode_key(key(Key::Enter, KeyModifiers::CTRL), disambiguate), b"\r");
+        assert_eq!(encode_key(key(Key::Up, none), disambiguate), b"\x1b[A");
+        assert_eq!(encode_key(key(Key::F(3), KeyModifiers::SHIFT), disambiguate), b"\x1b[13;2~");
+        assert_eq!(encode_key(key(Key::PageUp, none), disambiguate), b"\x1b[5~");
+        
+        let all_keys = InputModes { keyboard_flags: KITTY_DISAMBIGUATE | KITTY_REPORT_ALL_KEYS, ..Default::default() };
+        assert_eq!(encode_key(key(Key::Char('a'), none), all_keys), b"\x1b[97u");
+        assert_eq!(encode_key(key(Key::Char('A'), KeyModifiers::SHIFT), all_keys), b"\x1b[97;2u");
+        assert_eq!(encode_key(key(Key::Enter, none), all_keys), b"\x1b[13u");
+    }
+    
+    #[test]
+    fn test_keypad() {
+        let none = KeyModifiers::empty();
+        let numeric = InputModes::default();
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Digit(7)), none), numeric), b"7");
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Add), KeyModifiers::ALT), numeric), b"\x1b+");
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Enter), none), numeric), b"\r");
+        
+        let application = InputModes { application_keypad: true, ..numeric };
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Digit(7)), none), application), b"\x1bOw");
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Enter), none), application), b"\x1bOM");
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Subtract), KeyModifiers::CTRL), application), b"\x1bO5m");
+        // Application keypad mode does not affect the main keyboard
+        assert_eq!(encode_key(key(Key::Char('7'), none), application), b"7");
+        
+        let kitty = InputModes { keyboard_flags: KITTY_DISAMBIGUATE, ..application };
+        assert_eq!(encode_key(key(Key::Keypad(KeypadKey::Digit(0)), none), kitty), b"\x1b[57399u");
+    }
+    
+    #[test]
+    fn test_alt_key() {
+        let ignore = InputModes { alt_key: AltKey::Ignore, ..Default::default() };
+        assert_eq!(encode_key(key(Key::Char('ø'), KeyModifiers::ALT), ignore), "ø".as_bytes());
+        assert_eq!(encode_key(key(Key::Char('c'), KeyModifiers::ALT | KeyModifiers::CTRL), ignore), b"\x03");
+        
+        let level2 = InputModes { modify_other_keys: 2, ..ignore };
+        assert_eq!(encode_key(key(Key::Char('x'), KeyModifiers::ALT), level2), b"x");
+    }
+    
+    #[test]
+    fn test_encode_paste() {
+        let plain = InputModes::default();
+        assert_eq!(encode_paste("ls\n", plain), b"ls\n");
+        
+        let bracketed = InputModes { bracketed_paste: true, ..plain };
+        assert_eq!(encode_paste("ls\n", bracketed), b"\x1b[200~ls\n\x1b[201~");
+        assert_eq!(
+            encode_paste("a\x1b[201~rm -rf ~\x1b[20\x1b[201~1~", bracketed),
+            b"\x1b[200~arm -rf ~\x1b[201~"
+        );
+    }
+}
\ No newline at end of file
@@ -1,3 +1,7 @@
+pub mod color;
 pub mod error;
+pub mod geometry;
+pub mod keyboard;
+pub mod mouse;
 pub mod traits;
 pub mod types;
\ No newline at end of file
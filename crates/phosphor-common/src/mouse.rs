@@ -0,0 +1,205 @@
+use crate::types::{KeyModifiers, MouseAction, MouseButton, MouseEvent, TerminalMode};
+
+/// Mouse events the application asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// DECSET 1000: presses and releases
+    Press,
+    /// DECSET 1002: presses, releases and motion while a button is held
+    Drag,
+    /// DECSET 1003: presses, releases and all motion
+    Motion,
+}
+
+/// Byte encoding of reported mouse events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MouseEncoding {
+    /// `CSI M Cb Cx Cy` with single-byte values (coordinates up to 223)
+    #[default]
+    X10,
+    /// DECSET 1005: X10 with UTF-8 encoded values (coordinates up to 2015)
+    Utf8,
+    /// DECSET 1006: `CSI < Cb ; Cx ; Cy M/m`
+    Sgr,
+    /// DECSET 1015: `CSI Cb ; Cx ; Cy M`
+    Urxvt,
+}
+
+impl MouseTracking {
+    /// The tracking mode set by DECSET 1000, 1002 or 1003
+    pub fn from_mode(mode: &TerminalMode) -> Self {
+        if mode.contains(TerminalMode::MOUSE_MOTION) {
+            Self::Motion
+        } else if mode.contains(TerminalMode::MOUSE_DRAG) {
+            Self::Drag
+        } else if mode.contains(TerminalMode::MOUSE_REPORTING) {
+            Self::Press
+        } else {
+            Self::Off
+        }
+    }
+}
+
+impl MouseEncoding {
+    /// The encoding set by DECSET 1005, 1006 or 1015
+    pub fn from_mode(mode: &TerminalMode) -> Self {
+        if mode.contains(TerminalMode::MOUSE_SGR) {
+            Self::Sgr
+        } else if mode.contains(TerminalMode::MOUSE_URXVT) {
+            Self::Urxvt
+        } else if mode.contains(TerminalMode::MOUSE_UTF8) {
+            Self::Utf8
+        } else {
+            Self::X10
+        }
+    }
+}
+
+/// Encode a mouse event for the mouse modes the application set
+///
+/// Events the application did not ask for, and events the protocol
+/// cannot represent (e.g. X10 coordinates past 223), produce no bytes.
+pub fn encode_mouse(event: MouseEvent, mode: &TerminalMode) -> Vec<u8> {
+    encode_mouse_with(event, MouseTracking::from_mode(mode), MouseEncoding::from_mode(mode))
+}
+
+/// Encode a mouse event for an explicit tracking mode and encoding
+pub fn encode_mouse_with(event: MouseEvent, tracking: MouseTracking, encoding: MouseEncoding) -> Vec<u8> {
+    let reported = match (tracking, event.action) {
+        (MouseTracking::Off, _) => false,
+        (_, MouseAction::Press) => true,
+        // Wheel "buttons" have no release
+        (_, MouseAction::Release) => !matches!(
+            event.button,
+            Some(MouseButton::WheelUp | MouseButton::WheelDown | MouseButton::WheelLeft | MouseButton::WheelRight)
+        ),
+        (MouseTracking::Press, MouseAction::Motion) => false,
+        (MouseTracking::Drag, MouseAction::Motion) => event.button.is_some(),
+        (MouseTracking::Motion, MouseAction::Motion) => true,
+    };
+    if !reported {
+        return Vec::new();
+    }
+    
+    let button = match event.button {
+        Some(MouseButton::Left) => 0,
+        Some(MouseButton::Middle) => 1,
+        Some(MouseButton::Right) => 2,
+        None => 3,
+        Some(MouseButton::WheelUp) => 64,
+        Some(MouseButton::WheelDown) => 65,
+        Some(MouseButton::WheelLeft) => 66,
+        Some(MouseButton::WheelRight) => 67,
+    };
+    let mut code = button;
+    if event.modifiers.contains(KeyModifiers::SHIFT) {
+        code += 4;
+    }
+    if event.modifiers.contains(KeyModifiers::ALT) {
+        code += 8;
+    }
+    if event.modifiers.contains(KeyModifiers::CTRL) {
+        code += 16;
+    }
+    if event.action == MouseAction::Motion {
+        code += 32;
+    }
+    // Only SGR says which button was released; the others report button 3
+    let legacy_code = if event.action == MouseAction::Release { code - button + 3 } else { code };
+    
+    let x = event.position.col as u32 + 1;
+    let y = event.position.row as u32 + 1;
+    match encoding {
+        MouseEncoding::Sgr => {
+            let final_byte = if event.action == MouseAction::Release { 'm' } else { 'M' };
+            format!("\x1b[<{};{};{}{}", code, x, y, final_byte).into_bytes()
+        }
+        MouseEncoding::Urxvt => format!("\x1b[{};{};{}M", legacy_code + 32, x, y).into_bytes(),
+        MouseEncoding::X10 => {
+            if x > 223 || y > 223 {
+                return Vec::new();
+            }
+            vec![0x1b, b'[', b'M', (legacy_code + 32) as u8, (x + 32) as u8, (y + 32) as u8]
+        }
+        MouseEncoding::Utf8 => {
+            if x > 2015 || y > 2015 {
+                return Vec::new();
+            }
+            let mut bytes = b"\x1b[M".to_vec();
+            for value in [legacy_code + 32, x + 32, y + 32] {
+                let ch = char::from_u32(value).unwrap_or(' ');
+                bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            bytes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Position;
+    
+    fn mouse(action: MouseAction, button: Option<MouseButton>, col: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            action,
+            button,
+            position: Position::new(row, col),
+            modifiers: KeyModifiers::empty(),
+        }
+    }
+    
+    #[test]
+    fn test_mouse_tracking_filters_events() {
+        let press = mouse(MouseAction::Press, Some(MouseButton::Left), 0, 0);
+        let drag = mouse(MouseAction::Motion, Some(MouseButton::Left), 1, 0);
+        let hover = mouse(MouseAction::Motion, None, 1, 0);
+        let wheel_release = mouse(MouseAction::Release, Some(MouseButton::WheelUp), 0, 0);
+        
+        let off = TerminalMode::default();
+        assert!(encode_mouse(press, &off).is_empty());
+        
+        let mode = off | TerminalMode::MOUSE_REPORTING;
+        assert!(!encode_mouse(press, &mode).is_empty());
+        assert!(encode_mouse(drag, &mode).is_empty());
+        assert!(encode_mouse(wheel_release, &mode).is_empty());
+        
+        let mode = off | TerminalMode::MOUSE_DRAG;
+        assert!(!encode_mouse(drag, &mode).is_empty());
+        assert!(encode_mouse(hover, &mode).is_empty());
+        
+        let mode = off | TerminalMode::MOUSE_MOTION;
+        assert!(!encode_mouse(hover, &mode).is_empty());
+    }
+    
+    #[test]
+    fn test_mouse_encodings() {
+        let tracking = TerminalMode::default() | TerminalMode::MOUSE_MOTION;
+        let press = MouseEvent {
+            modifiers: KeyModifiers::CTRL,
+            ..mouse(MouseAction::Press, Some(MouseButton::Right), 4, 9)
+        };
+        let release = mouse(MouseAction::Release, Some(MouseButton::Right), 4, 9);
+        let hover = mouse(MouseAction::Motion, None, 4, 9);
+        let far = mouse(MouseAction::Press, Some(MouseButton::Left), 299, 0);
+        
+        let x10 = tracking;
+        assert_eq!(encode_mouse(press, &x10), b"\x1b[M\x32\x25\x2a");
+        assert_eq!(encode_mouse(release, &x10), b"\x1b[M\x23\x25\x2a");
+        assert_eq!(encode_mouse(hover, &x10), b"\x1b[M\x43\x25\x2a");
+        assert!(encode_mouse(far, &x10).is_empty());
+        
+        let utf8 = tracking | TerminalMode::MOUSE_UTF8;
+        assert_eq!(encode_mouse(far, &utf8), "\x1b[M\u{20}\u{14c}\u{21}".as_bytes());
+        
+        let sgr = tracking | TerminalMode::MOUSE_SGR;
+        assert_eq!(encode_mouse(press, &sgr), b"\x1b[<18;5;10M");
+        assert_eq!(encode_mouse(release, &sgr), b"\x1b[<2;5;10m");
+        assert_eq!(encode_mouse(far, &sgr), b"\x1b[<0;300;1M");
+        
+        let urxvt = tracking | TerminalMode::MOUSE_URXVT;
+        assert_eq!(encode_mouse(release, &urxvt), b"\x1b[35;5;10M");
+    }
+}
@@ -1,6 +1,8 @@
 use crate::error::Result;
-use crate::types::{Position, Size, TerminalSnapshot, Color};
+use crate::types::{PixelSize, Position, Size, TerminalSnapshot, Charset, Color, CursorStyle, DynamicColor, LineRendition};
 use async_trait::async_trait;
+use smallvec::SmallVec;
+use std::borrow::Cow;
 
 /// Trait for terminal frontends (GUI frameworks)
 #[async_trait]
@@ -30,6 +32,14 @@ pub trait TerminalBackend: Send + Sync {
     /// Resize the terminal
     async fn resize(&mut self, size: Size) -> Result<()>;
     
+    /// Resize the terminal, also reporting the text area size in pixels
+    ///
+    /// Backends with no use for the pixel size just resize.
+    async fn resize_with_pixels(&mut self, size: Size, pixels: PixelSize) -> Result<()> {
+        let _ = pixels;
+        self.resize(size).await
+    }
+    
     /// Check if the backend is still alive
     async fn is_alive(&self) -> bool;
 }
@@ -37,17 +47,68 @@ pub trait TerminalBackend: Send + Sync {
 /// Trait for terminal parsers
 pub trait TerminalParser: Send + Sync {
     /// Parse input data and return parsed events
-    fn parse(&mut self, data: &[u8]) -> Vec<ParsedEvent>;
+    fn parse(&mut self, data: &[u8]) -> Vec<ParsedEvent<'static>>;
 }
 
 /// Events produced by the parser
+///
+/// Text may borrow from the input being parsed; use
+/// [`ParsedEvent::into_owned`] to keep an event past the parse call.
 #[derive(Debug, Clone)]
-pub enum ParsedEvent {
-    Text(String),
+pub enum ParsedEvent<'a> {
+    Text(Cow<'a, str>),
     Control(ControlEvent),
     Csi(CsiSequence),
     Osc(OscSequence),
     Esc(EscSequence),
+    Dcs(DcsSequence),
+    /// Application Program Command payload (`ESC _ ... ST`), e.g. kitty graphics
+    ApcString(Vec<u8>),
+    /// Privacy Message payload (`ESC ^ ... ST`)
+    PmString(Vec<u8>),
+    /// Start Of String payload (`ESC X ... ST`)
+    SosString(Vec<u8>),
+    /// A sequence the parser does not handle, so users can handle or count it
+    ///
+    /// `params` holds CSI/DCS parameters with their subparameters and `data`
+    /// the raw payload of an OSC. For an OSC, `final_byte` is the terminator
+    /// (BEL, or `\` for ST).
+    Unknown {
+        kind: SequenceKind,
+        params: Vec<Vec<u16>>,
+        intermediates: Vec<u8>,
+        final_byte: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl ParsedEvent<'_> {
+    /// Detach the event from the input it was parsed from
+    pub fn into_owned(self) -> ParsedEvent<'static> {
+        match self {
+            ParsedEvent::Text(text) => ParsedEvent::Text(Cow::Owned(text.into_owned())),
+            ParsedEvent::Control(control) => ParsedEvent::Control(control),
+            ParsedEvent::Csi(csi) => ParsedEvent::Csi(csi),
+            ParsedEvent::Osc(osc) => ParsedEvent::Osc(osc),
+            ParsedEvent::Esc(esc) => ParsedEvent::Esc(esc),
+            ParsedEvent::Dcs(dcs) => ParsedEvent::Dcs(dcs),
+            ParsedEvent::ApcString(data) => ParsedEvent::ApcString(data),
+            ParsedEvent::PmString(data) => ParsedEvent::PmString(data),
+            ParsedEvent::SosString(data) => ParsedEvent::SosString(data),
+            ParsedEvent::Unknown { kind, params, intermediates, final_byte, data } => {
+                ParsedEvent::Unknown { kind, params, intermediates, final_byte, data }
+            }
+        }
+    }
+}
+
+/// Kinds of escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceKind {
+    Csi,
+    Osc,
+    Esc,
+    Dcs,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +121,8 @@ pub enum ControlEvent {
     Bell,
     FormFeed,
     VerticalTab,
+    ShiftOut,                 // SO - invoke G1
+    ShiftIn,                  // SI - invoke G0
 }
 
 /// Control Sequence Introducer (CSI) sequences
@@ -72,17 +135,52 @@ pub enum CsiSequence {
     CursorBack(u16),
     CursorPosition { row: u16, col: u16 },
     CursorColumn(u16),
+    CursorRow(u16),
     CursorNextLine(u16),
     CursorPreviousLine(u16),
     
     // Screen manipulation
     EraseDisplay(EraseMode),
     EraseLine(EraseMode),
+    /// DECSED: erase in display, sparing protected cells
+    SelectiveEraseDisplay(EraseMode),
+    /// DECSEL: erase in line, sparing protected cells
+    SelectiveEraseLine(EraseMode),
+    /// DECSCA: protect subsequently written cells from selective erase
+    SetCharacterProtection(bool),
+    /// DECSCUSR: cursor shape and whether it blinks
+    SetCursorStyle(CursorStyle),
+    InsertCharacters(u16),
+    DeleteCharacters(u16),
+    EraseCharacters(u16),
+    RepeatCharacter { ch: char, count: u16 },
+    InsertColumns(u16),
+    DeleteColumns(u16),
     ScrollUp(u16),
     ScrollDown(u16),
+    /// DECSTBM margins, 1-based; a bottom of 0 means the last row
+    SetScrollRegion { top: u16, bottom: u16 },
+    /// DECSLRM margins, 1-based; a right of 0 means the last column
+    SetLeftRightMargins { left: u16, right: u16 },
+    
+    // Tab stops (TBC)
+    ClearTabStop,
+    ClearAllTabStops,
     
     // Text attributes
-    SetGraphicsRendition(Vec<SgrParameter>),
+    SetGraphicsRendition(SgrParameters),
+    
+    // Keyboard
+    /// XTMODKEYS 4: set the modifyOtherKeys level (0 disables)
+    SetModifyOtherKeys(u8),
+    /// Kitty keyboard protocol: push flags onto the stack (`CSI > flags u`)
+    PushKeyboardFlags(u8),
+    /// Kitty keyboard protocol: pop entries off the stack (`CSI < n u`)
+    PopKeyboardFlags(u16),
+    /// Kitty keyboard protocol: update the current flags (`CSI = flags ; mode u`)
+    SetKeyboardFlags { flags: u8, mode: KeyboardFlagsMode },
+    /// Kitty keyboard protocol: report the current flags (`CSI ? u`)
+    QueryKeyboardFlags,
     
     // Cursor visibility
     ShowCursor,
@@ -91,10 +189,34 @@ pub enum CsiSequence {
     // Modes
     SetMode(Vec<Mode>),
     ResetMode(Vec<Mode>),
+    /// DECRQM: query a DEC private mode, by number and by the mode it maps
+    /// to if the parser knows it
+    RequestMode { number: u16, mode: Option<Mode> },
+    /// DECRQM: query an ANSI mode (`CSI Ps $ p`), as for `RequestMode`
+    RequestAnsiMode { number: u16, mode: Option<Mode> },
+    
+    /// MC: media copy to the printer
+    MediaCopy(MediaCopy),
     
     // Device status
+    /// DA1: request primary device attributes
+    PrimaryDeviceAttributes,
+    /// XTVERSION: request the terminal name and version
+    ReportVersion,
     DeviceStatusReport,
     CursorPositionReport,
+    /// XTWINOPS 14: report the text area size in pixels
+    ReportTextAreaPixels,
+    /// XTWINOPS 16: report the cell size in pixels
+    ReportCellPixels,
+    /// XTWINOPS 18: report the text area size in characters
+    ReportTextAreaChars,
+    
+    // Window manipulation (XTWINOPS)
+    Iconify,
+    Deiconify,
+    PushTitle(TitleKind),
+    PopTitle(TitleKind),
     
     // Save/Restore cursor
     SaveCursor,
@@ -110,7 +232,24 @@ pub enum OscSequence {
     ResetHyperlink,
     SetColor { index: u8, color: Color },
     ResetColor(u8),
+    SetDynamicColor { target: DynamicColor, color: Color },
+    /// Query a dynamic color; the reply uses the same terminator as the query
+    QueryDynamicColor { target: DynamicColor, bell_terminated: bool },
     Clipboard { clipboard: ClipboardType, data: String },
+    /// Shell integration marker (FinalTerm / OSC 133)
+    SemanticPrompt(SemanticMark),
+    /// Desktop notification (OSC 9 or OSC 777;notify)
+    Notification { title: Option<String>, body: String },
+}
+
+/// Device Control String (DCS) sequences
+#[derive(Debug, Clone)]
+pub enum DcsSequence {
+    /// XTGETTCAP: look up termcap/terminfo capabilities by name
+    RequestTermcap(Vec<String>),
+    /// DECRQSS: request the setting of a control function, named by its
+    /// intermediate and final bytes (e.g. `m` for SGR, ` q` for DECSCUSR)
+    RequestStatus(String),
 }
 
 /// ESC sequences (without CSI)
@@ -125,6 +264,21 @@ pub enum EscSequence {
     SaveCursor,               // DECSC
     RestoreCursor,            // DECRC
     Reset,                    // RIS - Reset to Initial State
+    DesignateCharset { slot: u8, charset: Charset }, // SCS - designate into G0-G3
+    LineRendition(LineRendition), // DECSWL/DECDWL/DECDHL - ESC # 3/4/5/6
+}
+
+/// Media copy (MC) operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaCopy {
+    /// `CSI i`: print the screen
+    PrintScreen,
+    /// `CSI ? 1 i`: print the cursor line
+    PrintLine,
+    /// `CSI 5 i` / `CSI 4 i`: send all output to the printer instead of the screen
+    PrinterController(bool),
+    /// `CSI ? 5 i` / `CSI ? 4 i`: print each line as the cursor leaves it
+    AutoPrint(bool),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -135,6 +289,12 @@ pub enum EraseMode {
     Saved,      // Erase saved lines (xterm)
 }
 
+/// Parameters of one SGR sequence
+///
+/// Almost every SGR sequence sets only a few attributes, so they are kept
+/// inline rather than allocated per sequence.
+pub type SgrParameters = SmallVec<[SgrParameter; 4]>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SgrParameter {
     Reset,
@@ -146,6 +306,13 @@ pub enum SgrParameter {
     Reverse,
     Hidden,
     Strikethrough,
+    DoubleUnderline,          // 4:2
+    CurlyUnderline,           // 4:3
+    DottedUnderline,          // 4:4
+    DashedUnderline,          // 4:5
+    Overline,                 // 53
+    Superscript,              // 73
+    Subscript,                // 74
     
     NoBold,
     NoDim,
@@ -155,6 +322,8 @@ pub enum SgrParameter {
     NoReverse,
     NoHidden,
     NoStrikethrough,
+    NoOverline,               // 55
+    NoSuperSubscript,         // 75
     
     Foreground(Color),
     Background(Color),
@@ -182,11 +351,30 @@ pub enum Mode {
     OriginMode,               // DECOM
     AutoWrap,                 // DECAWM
     AutoRepeat,               // DECARM
-    MouseReporting,           // Various mouse modes
+    CursorBlink,              // 12 - blinking cursor (att610)
+    MouseReporting,           // 1000 - report presses and releases
+    MouseDrag,                // 1002 - also report motion while a button is held
+    MouseMotion,              // 1003 - report all motion
+    MouseUtf8,                // 1005 - UTF-8 coordinates
+    MouseSgr,                 // 1006 - SGR encoding
+    MouseUrxvt,               // 1015 - urxvt encoding
     CursorVisible,            // DECTCEM
-    AlternateScreen,          // Alternate screen buffer
+    AlternateScreen,          // 47 - alternate screen buffer
+    AlternateScreenClear,     // 1047 - alternate screen, cleared on leaving it
+    SaveCursor,               // 1048 - save cursor as DECSC, restore on reset
+    AlternateScreenSaveCursor, // 1049 - 1048 plus the alternate screen, cleared on entry
     BracketedPaste,           // Bracketed paste mode
     FocusReporting,           // Focus in/out reporting
+    LeftRightMargin,          // DECLRMM
+}
+
+/// OSC 133 shell integration markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemanticMark {
+    PromptStart,                  // A
+    CommandStart,                 // B - end of prompt, start of user input
+    OutputStart,                  // C
+    CommandFinished(Option<i32>), // D, with the exit code if given
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -194,4 +382,20 @@ pub enum ClipboardType {
     Clipboard,
     Primary,
     Secondary,
-}
\ No newline at end of file
+}
+
+/// Which titles an XTWINOPS title stack operation applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleKind {
+    Both,                     // 0
+    IconName,                 // 1
+    WindowTitle,              // 2
+}
+
+/// How `CSI = flags ; mode u` combines flags with the current ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardFlagsMode {
+    Replace,                  // 1
+    Set,                      // 2 - set the given bits
+    Clear,                    // 3 - clear the given bits
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use bitflags::bitflags;
+use std::sync::Arc;
 
 /// Terminal dimensions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -14,6 +15,19 @@ impl Size {
     }
 }
 
+/// Pixel dimensions of the terminal's text area
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PixelSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl PixelSize {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self { width, height }
+    }
+}
+
 /// Cursor position (0-indexed)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Position {
@@ -27,12 +41,27 @@ impl Position {
     }
 }
 
+/// Link set by OSC 8, shared by every cell written while it is active
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Hyperlink {
+    /// Application-supplied id; cells with the same id and URI are one link
+    /// even when split, e.g. across wrapped lines
+    pub id: Option<String>,
+    pub uri: String,
+}
+
 /// Character cell in the terminal
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Cell {
     pub ch: char,
     pub attrs: CellAttributes,
-    pub hyperlink: Option<String>,
+    pub hyperlink: Option<Arc<Hyperlink>>,
+    pub tags: CellTags,
+    /// Protected from selective erase (DECSCA)
+    pub protected: bool,
+    /// Columns the character takes: 2 for a wide (CJK, emoji) character,
+    /// whose second column holds a spacer cell of width 0
+    pub width: u8,
 }
 
 impl Cell {
@@ -41,11 +70,19 @@ impl Cell {
             ch,
             attrs: CellAttributes::default(),
             hyperlink: None,
+            tags: CellTags::empty(),
+            protected: false,
+            width: 1,
         }
     }
 
     pub fn with_attrs(ch: char, attrs: CellAttributes) -> Self {
-        Self { ch, attrs, hyperlink: None }
+        Self { ch, attrs, hyperlink: None, tags: CellTags::empty(), protected: false, width: 1 }
+    }
+
+    /// Whether this is the spacer following a wide character
+    pub fn is_spacer(&self) -> bool {
+        self.width == 0
     }
 
     pub fn blank() -> Self {
@@ -59,6 +96,26 @@ impl Default for Cell {
     }
 }
 
+bitflags! {
+    /// Semantic tags on a cell, so renderers can style zones without recomputing them
+    ///
+    /// The zone tags (prompt, command, output) are stamped on cells as they are
+    /// written; the overlay tags (search match, selected) are set on existing cells.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+    pub struct CellTags: u8 {
+        const PROMPT       = 1 << 0;
+        const COMMAND      = 1 << 1;
+        const OUTPUT       = 1 << 2;
+        const SEARCH_MATCH = 1 << 3;
+        const SELECTED     = 1 << 4;
+        /// On the last cell of a line that autowrapped onto the next one
+        const WRAPPED      = 1 << 5;
+        
+        const ZONES    = Self::PROMPT.bits() | Self::COMMAND.bits() | Self::OUTPUT.bits();
+        const OVERLAYS = Self::SEARCH_MATCH.bits() | Self::SELECTED.bits();
+    }
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     pub struct AttributeFlags: u16 {
@@ -75,6 +132,15 @@ bitflags! {
         const CURLY_UNDERLINE  = 1 << 10;
         const DOTTED_UNDERLINE = 1 << 11;
         const DASHED_UNDERLINE = 1 << 12;
+        const OVERLINE         = 1 << 13;
+        const SUPERSCRIPT      = 1 << 14;
+        const SUBSCRIPT        = 1 << 15;
+        
+        const UNDERLINES = Self::UNDERLINE.bits()
+            | Self::DOUBLE_UNDERLINE.bits()
+            | Self::CURLY_UNDERLINE.bits()
+            | Self::DOTTED_UNDERLINE.bits()
+            | Self::DASHED_UNDERLINE.bits();
     }
 }
 
@@ -143,11 +209,204 @@ impl Color {
             _ => Color::Indexed(index),
         }
     }
+    
+    /// Index of the palette entry this color refers to, if any
+    pub fn palette_index(self) -> Option<u8> {
+        match self {
+            Color::Default | Color::Rgb(..) => None,
+            Color::Black => Some(0),
+            Color::Red => Some(1),
+            Color::Green => Some(2),
+            Color::Yellow => Some(3),
+            Color::Blue => Some(4),
+            Color::Magenta => Some(5),
+            Color::Cyan => Some(6),
+            Color::White => Some(7),
+            Color::BrightBlack => Some(8),
+            Color::BrightRed => Some(9),
+            Color::BrightGreen => Some(10),
+            Color::BrightYellow => Some(11),
+            Color::BrightBlue => Some(12),
+            Color::BrightMagenta => Some(13),
+            Color::BrightCyan => Some(14),
+            Color::BrightWhite => Some(15),
+            Color::Indexed(index) => Some(index),
+        }
+    }
 }
 
-/// Cursor style
+/// A color resolved to its red, green and blue components
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(rgb: Rgb) -> Self {
+        Color::Rgb(rgb.r, rgb.g, rgb.b)
+    }
+}
+
+/// Colors settable with OSC 10/11/12
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DynamicColor {
+    Foreground,
+    Background,
+    Cursor,
+}
+
+impl DynamicColor {
+    /// Map an OSC number (10-12) to the color it addresses
+    pub fn from_osc(number: u32) -> Option<Self> {
+        match number {
+            10 => Some(Self::Foreground),
+            11 => Some(Self::Background),
+            12 => Some(Self::Cursor),
+            _ => None,
+        }
+    }
+    
+    /// The OSC number addressing this color
+    pub fn osc(self) -> u32 {
+        match self {
+            Self::Foreground => 10,
+            Self::Background => 11,
+            Self::Cursor => 12,
+        }
+    }
+}
+
+/// Default foreground, background and cursor colors
+///
+/// These should be RGB so they can be reported back to applications.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicColors {
+    pub foreground: Color,
+    pub background: Color,
+    pub cursor: Color,
+}
+
+impl Default for DynamicColors {
+    fn default() -> Self {
+        Self {
+            foreground: Color::Rgb(229, 229, 229),
+            background: Color::Rgb(0, 0, 0),
+            cursor: Color::Rgb(229, 229, 229),
+        }
+    }
+}
+
+impl DynamicColors {
+    pub fn get(&self, target: DynamicColor) -> Color {
+        match target {
+            DynamicColor::Foreground => self.foreground,
+            DynamicColor::Background => self.background,
+            DynamicColor::Cursor => self.cursor,
+        }
+    }
+    
+    pub fn set(&mut self, target: DynamicColor, color: Color) {
+        match target {
+            DynamicColor::Foreground => self.foreground = color,
+            DynamicColor::Background => self.background = color,
+            DynamicColor::Cursor => self.cursor = color,
+        }
+    }
+    
+    /// The colors with foreground and background swapped, as drawn in reverse video
+    pub fn reversed(self) -> Self {
+        Self { foreground: self.background, background: self.foreground, ..self }
+    }
+}
+
+/// Character sets that can be designated into G0-G3 (SCS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Charset {
+    /// US ASCII (`ESC ( B`)
+    #[default]
+    Ascii,
+    /// DEC Special Graphics line drawing (`ESC ( 0`)
+    DecSpecialGraphics,
+    /// United Kingdom, `#` is the pound sign (`ESC ( A`)
+    Uk,
+}
+
+impl Charset {
+    /// Map a character written while this set is active to the glyph it stands for
+    pub fn map(self, ch: char) -> char {
+        match self {
+            Charset::Ascii => ch,
+            Charset::Uk => if ch == '#' { '£' } else { ch },
+            Charset::DecSpecialGraphics => match ch {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => ch,
+            },
+        }
+    }
+}
+
+/// Line rendition, set per line by DECSWL/DECDWL/DECDHL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineRendition {
+    #[default]
+    SingleWidth,
+    DoubleWidth,
+    /// Top half of a double-height, double-width line
+    DoubleHeightTop,
+    /// Bottom half of a double-height, double-width line
+    DoubleHeightBottom,
+}
+
+impl LineRendition {
+    /// Whether each character takes two cell widths
+    pub fn is_double_width(self) -> bool {
+        self != LineRendition::SingleWidth
+    }
+}
+
+/// Cursor style
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum CursorStyle {
+    #[default]
     Block,
     Underline,
     Bar,
@@ -156,10 +415,117 @@ pub enum CursorStyle {
     BlinkingBar,
 }
 
-impl Default for CursorStyle {
-    fn default() -> Self {
-        Self::Block
+impl CursorStyle {
+    pub fn is_blinking(self) -> bool {
+        matches!(self, Self::BlinkingBlock | Self::BlinkingUnderline | Self::BlinkingBar)
     }
+    
+    /// The same shape, blinking or steady
+    pub fn with_blinking(self, blinking: bool) -> Self {
+        match (self, blinking) {
+            (Self::Block | Self::BlinkingBlock, false) => Self::Block,
+            (Self::Block | Self::BlinkingBlock, true) => Self::BlinkingBlock,
+            (Self::Underline | Self::BlinkingUnderline, false) => Self::Underline,
+            (Self::Underline | Self::BlinkingUnderline, true) => Self::BlinkingUnderline,
+            (Self::Bar | Self::BlinkingBar, false) => Self::Bar,
+            (Self::Bar | Self::BlinkingBar, true) => Self::BlinkingBar,
+        }
+    }
+}
+
+/// A key, as reported by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Key {
+    /// A character key, already shifted (e.g. `'A'` for Shift+a)
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// Function key F1-F12
+    F(u8),
+    /// A key on the numeric keypad, with Num Lock on
+    Keypad(KeypadKey),
+}
+
+/// Numeric keypad keys, which DECKPAM sets apart from the main keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeypadKey {
+    /// Digit 0-9
+    Digit(u8),
+    Decimal,
+    Divide,
+    Multiply,
+    Subtract,
+    Add,
+    Enter,
+    Equal,
+}
+
+bitflags! {
+    /// Modifier keys held during a key press
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+    pub struct KeyModifiers: u8 {
+        const SHIFT = 1 << 0;
+        const ALT   = 1 << 1;
+        const CTRL  = 1 << 2;
+        const SUPER = 1 << 3;
+    }
+}
+
+/// A key press to be encoded for the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyEvent {
+    pub fn new(key: Key, modifiers: KeyModifiers) -> Self {
+        Self { key, modifiers }
+    }
+}
+
+/// A mouse button, including wheel "buttons"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+/// What happened to the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseAction {
+    Press,
+    Release,
+    /// Pointer moved, with `button` held if any
+    Motion,
+}
+
+/// A mouse event to be encoded for the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// Button pressed, released or held; `None` for motion with no button held
+    pub button: Option<MouseButton>,
+    /// Cell under the pointer (0-based)
+    pub position: Position,
+    pub modifiers: KeyModifiers,
 }
 
 bitflags! {
@@ -181,6 +547,17 @@ bitflags! {
         const ORIGIN_MODE       = 1 << 13;
         const INSERT_MODE       = 1 << 14;
         const REVERSE_VIDEO     = 1 << 15;
+        const LEFT_RIGHT_MARGIN = 1 << 16;
+        const MOUSE_DRAG        = 1 << 17;
+        const MOUSE_UTF8        = 1 << 18;
+        const MOUSE_URXVT       = 1 << 19;
+        const LINE_FEED         = 1 << 20;
+        const COLUMN_132        = 1 << 21;
+        
+        /// Mouse tracking modes: 1000 (press), 1002 (drag) and 1003 (any motion)
+        const MOUSE_TRACKING = Self::MOUSE_REPORTING.bits() | Self::MOUSE_DRAG.bits() | Self::MOUSE_MOTION.bits();
+        /// Mouse coordinate encodings: 1005 (UTF-8), 1006 (SGR) and 1015 (urxvt)
+        const MOUSE_ENCODINGS = Self::MOUSE_UTF8.bits() | Self::MOUSE_SGR.bits() | Self::MOUSE_URXVT.bits();
     }
 }
 
@@ -199,4 +576,112 @@ pub struct TerminalSnapshot {
     pub mode: TerminalMode,
     pub active_attributes: CellAttributes,
     pub alternate_screen_active: bool,
+    pub dynamic_colors: DynamicColors,
+    /// Rendition of each visible line, top to bottom
+    #[serde(default)]
+    pub line_renditions: Vec<LineRendition>,
+    /// DECSCNM: the whole screen is drawn in reverse video
+    #[serde(default)]
+    pub reverse_video: bool,
+    /// The cursor blinks, from DECSCUSR or DECSET 12
+    #[serde(default)]
+    pub cursor_blinking: bool,
+    /// The 256-color palette resolved to RGB, including OSC 4 changes
+    #[serde(default)]
+    pub palette: Vec<Rgb>,
+    /// Window title set by the application (OSC 0/2)
+    #[serde(default)]
+    pub title: String,
+    /// Icon name set by the application (OSC 0/1)
+    #[serde(default)]
+    pub icon_name: String,
+}
+
+impl TerminalSnapshot {
+    /// Default colors as they should be drawn, swapped under reverse video
+    pub fn display_colors(&self) -> DynamicColors {
+        if self.reverse_video {
+            self.dynamic_colors.reversed()
+        } else {
+            self.dynamic_colors
+        }
+    }
+}
+
+/// A cell in a `GridSnapshot`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GridCell {
+    pub ch: char,
+    pub attrs: CellAttributes,
+    pub tags: CellTags,
+    /// 2 for a wide character, 0 for the spacer after it
+    pub width: u8,
+    /// Index into `GridSnapshot::hyperlinks`
+    pub hyperlink: Option<usize>,
+}
+
+/// Everything needed to draw the terminal, captured at one instant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridSnapshot {
+    /// Modes, cursor, colors and titles; `line_renditions` follows `rows`
+    pub terminal: TerminalSnapshot,
+    /// The rows in view, top to bottom
+    pub rows: Vec<Vec<GridCell>>,
+    /// Lines the view is scrolled back into history
+    ///
+    /// The cursor is in screen coordinates, so it is this many rows lower
+    /// in the view (or out of it).
+    pub display_offset: usize,
+    /// Each distinct hyperlink in `rows`, once
+    pub hyperlinks: Vec<Hyperlink>,
+}
+
+impl GridSnapshot {
+    /// The cells that changed since `older`, one span per changed row
+    ///
+    /// Rows whose width or rendition changed are sent whole, as is every row
+    /// when the size changed. Hyperlink indices in the deltas refer to this
+    /// snapshot's `hyperlinks`. Palette and default color changes aren't
+    /// cell changes; compare `terminal` for those.
+    pub fn diff(&self, older: &GridSnapshot) -> Vec<RowDelta> {
+        let link = |snapshot: &GridSnapshot, cell: &GridCell| cell.hyperlink.and_then(|index| snapshot.hyperlinks.get(index).cloned());
+        let same = |new: &GridCell, old: &GridCell| {
+            new.ch == old.ch
+                && new.attrs == old.attrs
+                && new.tags == old.tags
+                && new.width == old.width
+                && link(self, new) == link(older, old)
+        };
+        
+        let mut deltas = Vec::new();
+        for (row, cells) in self.rows.iter().enumerate() {
+            let old = older.rows.get(row).filter(|old| {
+                old.len() == cells.len()
+                    && self.terminal.line_renditions.get(row) == older.terminal.line_renditions.get(row)
+            });
+            let span = match old {
+                None => Some(0..cells.len()),
+                Some(old) => {
+                    let changed = |col: &usize| !same(&cells[*col], &old[*col]);
+                    let first = (0..cells.len()).find(changed);
+                    let last = (0..cells.len()).rev().find(changed);
+                    first.zip(last).map(|(first, last)| first..last + 1)
+                }
+            };
+            if let Some(span) = span {
+                deltas.push(RowDelta { row: row as u16, start: span.start as u16, cells: cells[span].to_vec() });
+            }
+        }
+        deltas
+    }
+}
+
+/// Changed cells of one row, from `GridSnapshot::diff`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowDelta {
+    pub row: u16,
+    /// Column of the first changed cell
+    pub start: u16,
+    /// The new cells from `start` through the last changed cell
+    pub cells: Vec<GridCell>,
 }
\ No newline at end of file
@@ -0,0 +1,42 @@
+//! Memory held by a full scrollback of typical terminal output
+//!
+//! Run with `cargo bench -p phosphor-core --bench scrollback_memory`. Reports
+//! the bytes per line against storing every cell of every line.
+
+use phosphor_common::types::{Cell, Size};
+use phosphor_core::ansi::AnsiProcessor;
+use phosphor_core::TerminalState;
+use phosphor_parser::VteParser;
+
+/// Lines kept in scrollback, the default limit
+const LINES: usize = 10_000;
+
+const COLS: u16 = 80;
+
+/// Plain source text, as from `cat` of a file
+const PLAIN: &[u8] = b"fn main() {\r\n    println!(\"hello, world\");\r\n}\r\n\r\n// the quick brown fox jumps over the lazy dog\r\n";
+
+/// Colored listing, as from `ls --color` or a compiler
+const COLORED: &[u8] = b"\x1b[0m\x1b[01;34mtarget\x1b[0m  \x1b[01;32mbuild.sh\x1b[0m  Cargo.toml  \x1b[38;2;255;128;0mwarning\x1b[39m: unused\r\n";
+
+/// Full-width lines with hyperlinks, as from `ls --hyperlink`
+const LINKED: &[u8] = b"\x1b]8;;file:///home/user/src/main.rs\x1b\\main.rs\x1b]8;;\x1b\\ \x1b[7m                                                                        \x1b[0m\r\n";
+
+fn measure(name: &str, chunk: &[u8]) {
+    let mut state = TerminalState::new(Size::new(COLS, 24));
+    let mut parser = VteParser::new();
+    while state.scrollback_buffer().len() < LINES {
+        parser.parse_with(chunk, |event| AnsiProcessor::process_event(&mut state, event));
+    }
+    
+    let scrollback = state.scrollback_buffer();
+    let compact = scrollback.memory_usage() as f64 / scrollback.len() as f64;
+    let full = (std::mem::size_of::<Vec<Cell>>() + COLS as usize * std::mem::size_of::<Cell>()) as f64;
+    println!("{:<10} {:>8.1} bytes/line ({:.1}x smaller than {:.0})", name, compact, full / compact, full);
+}
+
+fn main() {
+    measure("plain", PLAIN);
+    measure("colored", COLORED);
+    measure("linked", LINKED);
+}
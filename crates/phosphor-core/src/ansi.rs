@@ -1,18 +1,100 @@
 use phosphor_common::traits::{
     ParsedEvent, ControlEvent, CsiSequence, OscSequence, EscSequence,
-    EraseMode, SgrParameter, Mode
+    DcsSequence, EraseMode, SgrParameter, Mode, SemanticMark, MediaCopy
 };
-use phosphor_common::types::{Position, Color, AttributeFlags};
+use phosphor_common::color::format_color_spec;
+use phosphor_common::types::{Position, Color, AttributeFlags, CellAttributes, CellTags, CursorStyle, Hyperlink, TerminalMode};
+use base64::prelude::{Engine, BASE64_STANDARD};
 use tracing::{debug, trace};
 
+use crate::capabilities::capabilities;
+use crate::events::Event;
 use crate::terminal::TerminalState;
 
+/// Upper-case hex encoding used by XTGETTCAP replies
+fn hex(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// SGR parameters that recreate `attrs` from the default rendition
+pub(crate) fn sgr_string(attrs: &CellAttributes) -> String {
+    let mut params = vec![String::from("0")];
+    let flags = [
+        (AttributeFlags::BOLD, "1"),
+        (AttributeFlags::DIM, "2"),
+        (AttributeFlags::ITALIC, "3"),
+        (AttributeFlags::UNDERLINE, "4"),
+        (AttributeFlags::DOUBLE_UNDERLINE, "4:2"),
+        (AttributeFlags::CURLY_UNDERLINE, "4:3"),
+        (AttributeFlags::DOTTED_UNDERLINE, "4:4"),
+        (AttributeFlags::DASHED_UNDERLINE, "4:5"),
+        (AttributeFlags::BLINK_SLOW, "5"),
+        (AttributeFlags::BLINK_FAST, "6"),
+        (AttributeFlags::REVERSE, "7"),
+        (AttributeFlags::HIDDEN, "8"),
+        (AttributeFlags::STRIKETHROUGH, "9"),
+        (AttributeFlags::OVERLINE, "53"),
+        (AttributeFlags::SUPERSCRIPT, "73"),
+        (AttributeFlags::SUBSCRIPT, "74"),
+    ];
+    for (flag, param) in flags {
+        if attrs.flags.contains(flag) {
+            params.push(param.to_string());
+        }
+    }
+    params.extend(sgr_color(attrs.fg_color, 30));
+    params.extend(sgr_color(attrs.bg_color, 40));
+    if let Some(color) = attrs.underline_color {
+        params.extend(sgr_color(color, 50));
+    }
+    params.join(";")
+}
+
+/// SGR parameter selecting `color`, with `base` 30 (foreground), 40
+/// (background) or 50 (underline, which only has the extended forms)
+fn sgr_color(color: Color, base: u16) -> Option<String> {
+    let ansi = |index: u16| {
+        if base == 50 {
+            format!("58:5:{}", index)
+        } else if index < 8 {
+            (base + index).to_string()
+        } else {
+            (base + 60 + index - 8).to_string()
+        }
+    };
+    Some(match color {
+        Color::Default => return None,
+        Color::Black => ansi(0),
+        Color::Red => ansi(1),
+        Color::Green => ansi(2),
+        Color::Yellow => ansi(3),
+        Color::Blue => ansi(4),
+        Color::Magenta => ansi(5),
+        Color::Cyan => ansi(6),
+        Color::White => ansi(7),
+        Color::BrightBlack => ansi(8),
+        Color::BrightRed => ansi(9),
+        Color::BrightGreen => ansi(10),
+        Color::BrightYellow => ansi(11),
+        Color::BrightBlue => ansi(12),
+        Color::BrightMagenta => ansi(13),
+        Color::BrightCyan => ansi(14),
+        Color::BrightWhite => ansi(15),
+        Color::Indexed(index) => format!("{}:5:{}", base + 8, index),
+        Color::Rgb(r, g, b) => format!("{}:2::{}:{}:{}", base + 8, r, g, b),
+    })
+}
+
 /// ANSI escape sequence processor
 pub struct AnsiProcessor;
 
 impl AnsiProcessor {
     /// Process a parsed event and apply it to the terminal state
-    pub fn process_event(state: &mut TerminalState, event: ParsedEvent) {
+    pub fn process_event(state: &mut TerminalState, event: ParsedEvent<'_>) {
+        if state.printer_controller() {
+            Self::print_event(state, event);
+            return;
+        }
         match event {
             ParsedEvent::Text(text) => {
                 trace!("Processing text: {:?}", text);
@@ -30,6 +112,53 @@ impl AnsiProcessor {
             ParsedEvent::Esc(esc) => {
                 Self::process_esc(state, esc);
             }
+            ParsedEvent::Dcs(dcs) => {
+                Self::process_dcs(state, dcs);
+            }
+            ParsedEvent::ApcString(data) => {
+                trace!("Passing through APC string: {} bytes", data.len());
+                state.emit(Event::ApcString(data));
+            }
+            ParsedEvent::PmString(data) => {
+                state.emit(Event::PmString(data));
+            }
+            ParsedEvent::SosString(data) => {
+                state.emit(Event::SosString(data));
+            }
+            ParsedEvent::Unknown { kind, final_byte, .. } => {
+                trace!("Ignoring unknown {:?} sequence ending in {:?}", kind, final_byte as char);
+            }
+        }
+    }
+    
+    /// Send output to the printer in printer controller mode
+    ///
+    /// `Terminal` forwards the raw bytes with `TerminalState::print_raw`
+    /// instead; this covers callers that only have parsed events. Text and
+    /// C0 controls are passed on; other sequences are swallowed so they
+    /// can't reach the screen. MC 4 leaves the mode.
+    fn print_event(state: &mut TerminalState, event: ParsedEvent<'_>) {
+        match event {
+            ParsedEvent::Text(text) => state.print(text.as_bytes()),
+            ParsedEvent::Control(control) => {
+                let byte = match control {
+                    ControlEvent::Bell => 0x07,
+                    ControlEvent::Backspace => 0x08,
+                    ControlEvent::Tab => 0x09,
+                    ControlEvent::NewLine => 0x0a,
+                    ControlEvent::VerticalTab => 0x0b,
+                    ControlEvent::FormFeed => 0x0c,
+                    ControlEvent::CarriageReturn => 0x0d,
+                    ControlEvent::ShiftOut => 0x0e,
+                    ControlEvent::ShiftIn => 0x0f,
+                    ControlEvent::Clear => return,
+                };
+                state.print(&[byte]);
+            }
+            ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::PrinterController(false))) => {
+                state.set_printer_controller(false);
+            }
+            event => trace!("Swallowing {:?} in printer controller mode", event),
         }
     }
     
@@ -41,19 +170,21 @@ impl AnsiProcessor {
             ControlEvent::Tab => state.write_char('\t'),
             ControlEvent::Backspace => state.write_char('\x08'),
             ControlEvent::Bell => {
-                // TODO: Trigger bell event
                 debug!("Bell");
+                state.ring_bell();
             }
             ControlEvent::FormFeed => {
                 // Form feed - often treated as clear screen
-                Self::clear_screen(state, EraseMode::All);
+                Self::clear_screen(state, EraseMode::All, false);
             }
             ControlEvent::VerticalTab => {
                 // Vertical tab - usually treated as newline
                 state.write_char('\n');
             }
+            ControlEvent::ShiftOut => state.shift_charset(1),
+            ControlEvent::ShiftIn => state.shift_charset(0),
             ControlEvent::Clear => {
-                Self::clear_screen(state, EraseMode::All);
+                Self::clear_screen(state, EraseMode::All, false);
             }
         }
     }
@@ -69,10 +200,10 @@ impl AnsiProcessor {
                 state.cursor_mut().move_down(n);
             }
             CsiSequence::CursorForward(n) => {
-                state.cursor_mut().move_right(n);
+                state.cursor_forward(n);
             }
             CsiSequence::CursorBack(n) => {
-                state.cursor_mut().move_left(n);
+                state.cursor_back(n);
             }
             CsiSequence::CursorPosition { row, col } => {
                 // ANSI uses 1-based indexing
@@ -84,7 +215,12 @@ impl AnsiProcessor {
             }
             CsiSequence::CursorColumn(col) => {
                 // ANSI uses 1-based indexing
-                state.cursor_mut().set_column(col.saturating_sub(1));
+                state.set_cursor_column(col.saturating_sub(1));
+            }
+            CsiSequence::CursorRow(row) => {
+                // ANSI uses 1-based indexing; rows past the bottom clamp to it
+                let last = state.size().rows.saturating_sub(1);
+                state.cursor_mut().set_row(row.saturating_sub(1).min(last));
             }
             CsiSequence::CursorNextLine(n) => {
                 state.cursor_mut().set_column(0);
@@ -97,10 +233,46 @@ impl AnsiProcessor {
             
             // Screen manipulation
             CsiSequence::EraseDisplay(mode) => {
-                Self::clear_screen(state, mode);
+                Self::clear_screen(state, mode, false);
             }
             CsiSequence::EraseLine(mode) => {
-                Self::clear_line(state, mode);
+                Self::clear_line(state, mode, false);
+            }
+            CsiSequence::SelectiveEraseDisplay(mode) => {
+                Self::clear_screen(state, mode, true);
+            }
+            CsiSequence::SelectiveEraseLine(mode) => {
+                Self::clear_line(state, mode, true);
+            }
+            CsiSequence::SetCharacterProtection(protected) => {
+                state.set_character_protection(protected);
+            }
+            CsiSequence::SetCursorStyle(style) => {
+                state.set_cursor_style(style);
+            }
+            CsiSequence::MediaCopy(mc) => match mc {
+                MediaCopy::PrintScreen => state.print_screen(),
+                MediaCopy::PrintLine => state.print_line(state.cursor_position().row),
+                MediaCopy::PrinterController(enabled) => state.set_printer_controller(enabled),
+                MediaCopy::AutoPrint(enabled) => state.set_autoprint(enabled),
+            },
+            CsiSequence::InsertCharacters(n) => {
+                state.insert_characters(n);
+            }
+            CsiSequence::DeleteCharacters(n) => {
+                state.delete_characters(n);
+            }
+            CsiSequence::EraseCharacters(n) => {
+                state.erase_characters(n);
+            }
+            CsiSequence::RepeatCharacter { ch, count } => {
+                state.repeat_char(ch, count);
+            }
+            CsiSequence::InsertColumns(n) => {
+                state.insert_columns(n);
+            }
+            CsiSequence::DeleteColumns(n) => {
+                state.delete_columns(n);
             }
             CsiSequence::ScrollUp(n) => {
                 for _ in 0..n {
@@ -112,6 +284,26 @@ impl AnsiProcessor {
                     state.scroll_down();
                 }
             }
+            CsiSequence::SetScrollRegion { top, bottom } => {
+                // ANSI uses 1-based indexing; a missing bottom means the last row
+                let bottom = if bottom == 0 { state.size().rows } else { bottom };
+                state.set_scroll_region(top.saturating_sub(1), bottom.saturating_sub(1));
+            }
+            CsiSequence::ClearTabStop => {
+                state.clear_tab_stop();
+            }
+            CsiSequence::ClearAllTabStops => {
+                state.clear_all_tab_stops();
+            }
+            CsiSequence::SetLeftRightMargins { left, right } => {
+                if state.mode().contains(TerminalMode::LEFT_RIGHT_MARGIN) {
+                    let right = if right == 0 { state.size().cols } else { right };
+                    state.set_left_right_margins(left.saturating_sub(1), right.saturating_sub(1));
+                } else {
+                    // Without DECLRMM this is SCOSC, which ignores its parameters
+                    state.save_cursor();
+                }
+            }
             
             // Text attributes
             CsiSequence::SetGraphicsRendition(params) => {
@@ -119,6 +311,21 @@ impl AnsiProcessor {
                     Self::apply_sgr(state, param);
                 }
             }
+            CsiSequence::SetModifyOtherKeys(level) => {
+                state.set_modify_other_keys(level);
+            }
+            CsiSequence::PushKeyboardFlags(flags) => {
+                state.push_keyboard_flags(flags);
+            }
+            CsiSequence::PopKeyboardFlags(count) => {
+                state.pop_keyboard_flags(count);
+            }
+            CsiSequence::SetKeyboardFlags { flags, mode } => {
+                state.set_keyboard_flags(flags, mode);
+            }
+            CsiSequence::QueryKeyboardFlags => {
+                state.push_response(format!("\x1b[?{}u", state.keyboard_flags()));
+            }
             
             // Cursor visibility
             CsiSequence::ShowCursor => {
@@ -142,54 +349,142 @@ impl AnsiProcessor {
             
             // Save/Restore cursor
             CsiSequence::SaveCursor => {
-                state.save_cursor();
+                if state.mode().contains(TerminalMode::LEFT_RIGHT_MARGIN) {
+                    // DECSLRM with default parameters resets the margins
+                    state.set_left_right_margins(0, state.size().cols.saturating_sub(1));
+                } else {
+                    state.save_cursor();
+                }
             }
             CsiSequence::RestoreCursor => {
                 state.restore_cursor();
             }
             
             // Device status
+            CsiSequence::PrimaryDeviceAttributes => {
+                state.push_response(capabilities().primary_device_attributes());
+            }
+            CsiSequence::ReportVersion => {
+                state.push_response(capabilities().version_report());
+            }
+            CsiSequence::RequestMode { number, mode } => {
+                let status = Self::mode_status(state, mode);
+                state.push_response(format!("\x1b[?{};{}$y", number, status));
+            }
+            CsiSequence::RequestAnsiMode { number, mode } => {
+                let status = Self::mode_status(state, mode);
+                state.push_response(format!("\x1b[{};{}$y", number, status));
+            }
             CsiSequence::DeviceStatusReport => {
-                // TODO: Send response
-                debug!("Device status report requested");
+                // Always report "OK"
+                state.push_response("\x1b[0n");
             }
             CsiSequence::CursorPositionReport => {
-                // TODO: Send cursor position
-                debug!("Cursor position report requested");
+                // ANSI uses 1-based indexing
+                let pos = state.cursor_position();
+                state.push_response(format!("\x1b[{};{}R", pos.row + 1, pos.col + 1));
+            }
+            CsiSequence::ReportTextAreaPixels => {
+                // Reply: CSI 4 ; height ; width t
+                match state.cell_metrics() {
+                    Some(metrics) => {
+                        let (width, height) = metrics.grid_pixel_size(state.size());
+                        state.push_response(format!("\x1b[4;{};{}t", height, width));
+                    }
+                    None => debug!("Text area pixel size requested without cell metrics"),
+                }
+            }
+            CsiSequence::ReportTextAreaChars => {
+                // Reply: CSI 8 ; rows ; cols t
+                let size = state.size();
+                state.push_response(format!("\x1b[8;{};{}t", size.rows, size.cols));
+            }
+            CsiSequence::Iconify => {
+                state.request_iconify(true);
+            }
+            CsiSequence::Deiconify => {
+                state.request_iconify(false);
+            }
+            CsiSequence::PushTitle(kind) => {
+                state.push_title(kind);
+            }
+            CsiSequence::PopTitle(kind) => {
+                state.pop_title(kind);
+            }
+            CsiSequence::ReportCellPixels => {
+                // Reply: CSI 6 ; height ; width t
+                match state.cell_metrics() {
+                    Some(metrics) => {
+                        state.push_response(format!("\x1b[6;{};{}t", metrics.height, metrics.width));
+                    }
+                    None => debug!("Cell pixel size requested without cell metrics"),
+                }
             }
         }
     }
     
-    fn process_osc(_state: &mut TerminalState, osc: OscSequence) {
+    fn process_osc(state: &mut TerminalState, osc: OscSequence) {
         trace!("Processing OSC: {:?}", osc);
         match osc {
             OscSequence::SetTitle(title) => {
-                // TODO: Set window title
-                debug!("Set title: {}", title);
+                state.set_title(title);
             }
             OscSequence::SetIcon(icon) => {
-                // TODO: Set window icon
-                debug!("Set icon: {}", icon);
+                state.set_icon_name(icon);
             }
             OscSequence::SetHyperlink { id, uri } => {
-                // TODO: Store hyperlink info
                 debug!("Set hyperlink: id={:?}, uri={}", id, uri);
+                state.set_hyperlink(Some(Hyperlink { id, uri }));
             }
             OscSequence::ResetHyperlink => {
-                // TODO: Clear hyperlink
                 debug!("Reset hyperlink");
+                state.set_hyperlink(None);
             }
             OscSequence::SetColor { index, color } => {
-                // TODO: Update color palette
                 debug!("Set color {}: {:?}", index, color);
+                state.set_palette_color(index, color);
             }
             OscSequence::ResetColor(index) => {
                 // TODO: Reset color to default
                 debug!("Reset color {}", index);
             }
+            OscSequence::SetDynamicColor { target, color } => {
+                debug!("Set {:?} color: {:?}", target, color);
+                state.set_dynamic_color(target, color);
+            }
+            OscSequence::QueryDynamicColor { target, bell_terminated } => {
+                let color = state.dynamic_colors().get(target);
+                match format_color_spec(color) {
+                    Some(spec) => {
+                        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+                        state.push_response(format!("\x1b]{};{}{}", target.osc(), spec, terminator));
+                    }
+                    None => debug!("Cannot report non-RGB {:?} color: {:?}", target, color),
+                }
+            }
+            OscSequence::SemanticPrompt(mark) => {
+                debug!("Semantic prompt mark: {:?}", mark);
+                match mark {
+                    SemanticMark::PromptStart => state.set_semantic_zone(CellTags::PROMPT),
+                    SemanticMark::CommandStart => state.start_command(),
+                    SemanticMark::OutputStart => state.start_command_output(),
+                    SemanticMark::CommandFinished(exit_code) => state.finish_command(exit_code),
+                }
+            }
+            OscSequence::Notification { title, body } => {
+                debug!("Notification {:?}: {}", title, body);
+                state.notify(title, body);
+            }
             OscSequence::Clipboard { clipboard, data } => {
-                // TODO: Handle clipboard operations
                 debug!("Clipboard {:?}: {}", clipboard, data);
+                if data == "?" {
+                    state.request_clipboard(clipboard);
+                } else {
+                    match BASE64_STANDARD.decode(&data) {
+                        Ok(bytes) => state.set_clipboard(clipboard, String::from_utf8_lossy(&bytes).into_owned()),
+                        Err(e) => debug!("Invalid clipboard data: {}", e),
+                    }
+                }
             }
         }
     }
@@ -198,26 +493,17 @@ impl AnsiProcessor {
         trace!("Processing ESC: {:?}", esc);
         match esc {
             EscSequence::Index => {
-                // Move cursor down one line, scroll if at bottom
-                state.cursor_mut().move_down(1);
-                if state.cursor_position().row >= state.size().rows - 1 {
-                    state.scroll_up();
-                }
+                state.index();
             }
             EscSequence::NextLine => {
                 state.cursor_mut().set_column(0);
-                state.cursor_mut().move_down(1);
+                state.index();
             }
             EscSequence::TabSet => {
                 state.set_tab_stop();
             }
             EscSequence::ReverseIndex => {
-                // Move cursor up one line, scroll if at top
-                if state.cursor_position().row == 0 {
-                    state.scroll_down();
-                } else {
-                    state.cursor_mut().move_up(1);
-                }
+                state.reverse_index();
             }
             EscSequence::KeypadApplicationMode => {
                 state.set_mode_flag(Mode::ApplicationKeypad, true);
@@ -232,12 +518,80 @@ impl AnsiProcessor {
                 state.restore_cursor();
             }
             EscSequence::Reset => {
-                // Reset terminal to initial state
-                *state = TerminalState::new(state.size());
+                state.reset();
+            }
+            EscSequence::LineRendition(rendition) => {
+                state.set_line_rendition(rendition);
+            }
+            EscSequence::DesignateCharset { slot, charset } => {
+                state.designate_charset(slot, charset);
+            }
+        }
+    }
+    
+    fn process_dcs(state: &mut TerminalState, dcs: DcsSequence) {
+        trace!("Processing DCS: {:?}", dcs);
+        match dcs {
+            DcsSequence::RequestTermcap(names) => {
+                // Reply per name: DCS 1 + r name=value ST, or DCS 0 + r name ST
+                // when unknown; names and values are hex encoded
+                let caps = capabilities();
+                for name in names {
+                    let reply = match caps.termcap(&name) {
+                        Some(value) if value.is_empty() => format!("\x1bP1+r{}\x1b\\", hex(&name)),
+                        Some(value) => format!("\x1bP1+r{}={}\x1b\\", hex(&name), hex(&value)),
+                        None => format!("\x1bP0+r{}\x1b\\", hex(&name)),
+                    };
+                    state.push_response(reply);
+                }
+            }
+            DcsSequence::RequestStatus(setting) => {
+                // Reply: DCS 1 $ r value setting ST, or DCS 0 $ r ST when the
+                // setting is not supported
+                let reply = match Self::status_string(state, &setting) {
+                    Some(value) => format!("\x1bP1$r{}{}\x1b\\", value, setting),
+                    None => String::from("\x1bP0$r\x1b\\"),
+                };
+                state.push_response(reply);
             }
         }
     }
     
+    /// Parameters that restore a setting queried by DECRQSS
+    fn status_string(state: &TerminalState, setting: &str) -> Option<String> {
+        Some(match setting {
+            // SGR
+            "m" => sgr_string(state.attributes()),
+            // DECSCA
+            "\"q" => if state.character_protection() { "1" } else { "0" }.to_string(),
+            // DECSCUSR
+            " q" => {
+                let style = match state.cursor_style() {
+                    CursorStyle::BlinkingBlock => 1,
+                    CursorStyle::Block => 2,
+                    CursorStyle::BlinkingUnderline => 3,
+                    CursorStyle::Underline => 4,
+                    CursorStyle::BlinkingBar => 5,
+                    CursorStyle::Bar => 6,
+                };
+                style.to_string()
+            }
+            // DECSTBM
+            "r" => {
+                let (top, bottom) = state.scroll_region();
+                format!("{};{}", top + 1, bottom + 1)
+            }
+            // DECSLRM
+            "s" => {
+                let (left, right) = state.left_right_margins();
+                format!("{};{}", left + 1, right + 1)
+            }
+            // DECSCL: VT200 level, 7-bit controls
+            "\"p" => String::from("62;1"),
+            _ => return None,
+        })
+    }
+    
     fn apply_sgr(state: &mut TerminalState, param: SgrParameter) {
         match param {
             SgrParameter::Reset => {
@@ -253,7 +607,7 @@ impl AnsiProcessor {
                 state.set_attribute_flag(AttributeFlags::ITALIC, true);
             }
             SgrParameter::Underline => {
-                state.set_attribute_flag(AttributeFlags::UNDERLINE, true);
+                Self::set_underline_style(state, AttributeFlags::UNDERLINE);
             }
             SgrParameter::Blink => {
                 state.set_attribute_flag(AttributeFlags::BLINK_SLOW, true);
@@ -267,6 +621,29 @@ impl AnsiProcessor {
             SgrParameter::Strikethrough => {
                 state.set_attribute_flag(AttributeFlags::STRIKETHROUGH, true);
             }
+            SgrParameter::Overline => {
+                state.set_attribute_flag(AttributeFlags::OVERLINE, true);
+            }
+            SgrParameter::Superscript => {
+                state.set_attribute_flag(AttributeFlags::SUBSCRIPT, false);
+                state.set_attribute_flag(AttributeFlags::SUPERSCRIPT, true);
+            }
+            SgrParameter::Subscript => {
+                state.set_attribute_flag(AttributeFlags::SUPERSCRIPT, false);
+                state.set_attribute_flag(AttributeFlags::SUBSCRIPT, true);
+            }
+            SgrParameter::DoubleUnderline => {
+                Self::set_underline_style(state, AttributeFlags::DOUBLE_UNDERLINE);
+            }
+            SgrParameter::CurlyUnderline => {
+                Self::set_underline_style(state, AttributeFlags::CURLY_UNDERLINE);
+            }
+            SgrParameter::DottedUnderline => {
+                Self::set_underline_style(state, AttributeFlags::DOTTED_UNDERLINE);
+            }
+            SgrParameter::DashedUnderline => {
+                Self::set_underline_style(state, AttributeFlags::DASHED_UNDERLINE);
+            }
             
             SgrParameter::NoBold => {
                 state.set_attribute_flag(AttributeFlags::BOLD, false);
@@ -279,7 +656,7 @@ impl AnsiProcessor {
                 state.set_attribute_flag(AttributeFlags::ITALIC, false);
             }
             SgrParameter::NoUnderline => {
-                state.set_attribute_flag(AttributeFlags::UNDERLINE, false);
+                state.set_attribute_flag(AttributeFlags::UNDERLINES, false);
             }
             SgrParameter::NoBlink => {
                 state.set_attribute_flag(AttributeFlags::BLINK_SLOW, false);
@@ -294,6 +671,12 @@ impl AnsiProcessor {
             SgrParameter::NoStrikethrough => {
                 state.set_attribute_flag(AttributeFlags::STRIKETHROUGH, false);
             }
+            SgrParameter::NoOverline => {
+                state.set_attribute_flag(AttributeFlags::OVERLINE, false);
+            }
+            SgrParameter::NoSuperSubscript => {
+                state.set_attribute_flag(AttributeFlags::SUPERSCRIPT | AttributeFlags::SUBSCRIPT, false);
+            }
             
             SgrParameter::Foreground(color) => {
                 state.set_foreground_color(color);
@@ -317,7 +700,43 @@ impl AnsiProcessor {
         }
     }
     
-    fn clear_screen(state: &mut TerminalState, mode: EraseMode) {
+    /// DECRPM status of a mode: 1 set, 2 reset, 3 permanently set,
+    /// 4 permanently reset, 0 not recognized
+    fn mode_status(state: &TerminalState, mode: Option<Mode>) -> u8 {
+        match mode {
+            // Accepted but never acted on, so they can't change
+            Some(Mode::AutoRepeat | Mode::ScrollMode | Mode::KeyboardAction) => 4,
+            // There is no local echo
+            Some(Mode::SendReceive) => 3,
+            Some(mode) => match state.mode_flag(mode) {
+                Some(true) => 1,
+                Some(false) => 2,
+                None => 0,
+            },
+            None => 0,
+        }
+    }
+    
+    /// Underline styles are exclusive; setting one replaces the others
+    fn set_underline_style(state: &mut TerminalState, style: AttributeFlags) {
+        state.set_attribute_flag(AttributeFlags::UNDERLINES, false);
+        state.set_attribute_flag(style, true);
+    }
+    
+    /// Erase a cell; a selective erase spares protected cells
+    fn erase_cell(state: &mut TerminalState, pos: Position, selective: bool) {
+        let protected = state
+            .screen_buffer()
+            .get_line(pos.row)
+            .and_then(|line| line.get(pos.col as usize))
+            .is_some_and(|cell| cell.protected);
+        if selective && protected {
+            return;
+        }
+        state.screen_buffer_mut().clear_cell(pos);
+    }
+    
+    fn clear_screen(state: &mut TerminalState, mode: EraseMode, selective: bool) {
         let size = state.size();
         let cursor_pos = state.cursor_position();
         
@@ -329,7 +748,7 @@ impl AnsiProcessor {
                         if row == cursor_pos.row && col < cursor_pos.col {
                             continue;
                         }
-                        state.screen_buffer_mut().clear_cell(Position::new(row, col));
+                        Self::erase_cell(state, Position::new(row, col), selective);
                     }
                 }
             }
@@ -340,7 +759,14 @@ impl AnsiProcessor {
                         if row == cursor_pos.row && col > cursor_pos.col {
                             break;
                         }
-                        state.screen_buffer_mut().clear_cell(Position::new(row, col));
+                        Self::erase_cell(state, Position::new(row, col), selective);
+                    }
+                }
+            }
+            EraseMode::All if selective => {
+                for row in 0..size.rows {
+                    for col in 0..size.cols {
+                        Self::erase_cell(state, Position::new(row, col), true);
                     }
                 }
             }
@@ -349,13 +775,15 @@ impl AnsiProcessor {
                 state.screen_buffer_mut().clear();
             }
             EraseMode::Saved => {
-                // Clear saved lines (scrollback)
-                state.scrollback_buffer_mut().clear();
+                // Clear saved lines (scrollback); DECSED has no such mode
+                if !selective {
+                    state.clear_scrollback();
+                }
             }
         }
     }
     
-    fn clear_line(state: &mut TerminalState, mode: EraseMode) {
+    fn clear_line(state: &mut TerminalState, mode: EraseMode, selective: bool) {
         let cursor_pos = state.cursor_position();
         let cols = state.size().cols;
         
@@ -363,19 +791,19 @@ impl AnsiProcessor {
             EraseMode::Below => {
                 // Clear from cursor to end of line
                 for col in cursor_pos.col..cols {
-                    state.screen_buffer_mut().clear_cell(Position::new(cursor_pos.row, col));
+                    Self::erase_cell(state, Position::new(cursor_pos.row, col), selective);
                 }
             }
             EraseMode::Above => {
                 // Clear from beginning to cursor
                 for col in 0..=cursor_pos.col {
-                    state.screen_buffer_mut().clear_cell(Position::new(cursor_pos.row, col));
+                    Self::erase_cell(state, Position::new(cursor_pos.row, col), selective);
                 }
             }
             EraseMode::All | EraseMode::Saved => {
                 // Clear entire line
                 for col in 0..cols {
-                    state.screen_buffer_mut().clear_cell(Position::new(cursor_pos.row, col));
+                    Self::erase_cell(state, Position::new(cursor_pos.row, col), selective);
                 }
             }
         }
@@ -386,12 +814,18 @@ impl AnsiProcessor {
             Mode::Insert => {
                 state.set_mode_flag(Mode::Insert, enabled);
             }
+            Mode::LineFeed => {
+                state.set_mode_flag(Mode::LineFeed, enabled);
+            }
             Mode::AutoWrap => {
                 state.set_mode_flag(Mode::AutoWrap, enabled);
             }
             Mode::CursorVisible => {
                 state.set_cursor_visible(enabled);
             }
+            Mode::CursorBlink => {
+                state.set_cursor_blinking(enabled);
+            }
             Mode::AlternateScreen => {
                 if enabled {
                     state.enable_alternate_screen();
@@ -399,14 +833,42 @@ impl AnsiProcessor {
                     state.disable_alternate_screen();
                 }
             }
+            Mode::AlternateScreenClear => {
+                if enabled {
+                    state.enable_alternate_screen();
+                } else {
+                    state.clear_alternate_screen();
+                    state.disable_alternate_screen();
+                }
+            }
+            Mode::SaveCursor => {
+                if enabled {
+                    state.save_cursor();
+                } else {
+                    state.restore_cursor();
+                }
+            }
+            Mode::AlternateScreenSaveCursor => {
+                if enabled {
+                    state.save_cursor();
+                    if !state.mode().contains(TerminalMode::ALTERNATE_SCREEN) {
+                        state.enable_alternate_screen();
+                        state.clear_alternate_screen();
+                    }
+                } else {
+                    state.disable_alternate_screen();
+                    state.restore_cursor();
+                }
+            }
             Mode::BracketedPaste => {
                 state.set_mode_flag(Mode::BracketedPaste, enabled);
             }
             Mode::FocusReporting => {
                 state.set_mode_flag(Mode::FocusReporting, enabled);
             }
-            Mode::MouseReporting => {
-                state.set_mode_flag(Mode::MouseReporting, enabled);
+            Mode::MouseReporting | Mode::MouseDrag | Mode::MouseMotion
+            | Mode::MouseUtf8 | Mode::MouseSgr | Mode::MouseUrxvt => {
+                state.set_mode_flag(mode, enabled);
             }
             Mode::ApplicationCursor => {
                 state.set_mode_flag(Mode::ApplicationCursor, enabled);
@@ -414,6 +876,15 @@ impl AnsiProcessor {
             Mode::OriginMode => {
                 state.set_mode_flag(Mode::OriginMode, enabled);
             }
+            Mode::ColumnMode => {
+                state.set_column_mode(enabled);
+            }
+            Mode::ScreenMode => {
+                state.set_mode_flag(Mode::ScreenMode, enabled);
+            }
+            Mode::LeftRightMargin => {
+                state.set_mode_flag(Mode::LeftRightMargin, enabled);
+            }
             _ => {
                 debug!("Unhandled mode: {:?}", mode);
             }
@@ -424,9 +895,11 @@ impl AnsiProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use phosphor_common::types::Size;
+    use phosphor_common::geometry::CellMetrics;
+    use phosphor_common::types::{DynamicColors, LineRendition, Rgb, Size};
     use phosphor_parser::VteParser;
-    use phosphor_common::traits::TerminalParser;
+    use phosphor_common::traits::{ClipboardType, TerminalParser};
+    use crate::input::{MouseEncoding, MouseTracking};
     
     #[test]
     fn test_cursor_movement() {
@@ -446,6 +919,46 @@ mod tests {
             AnsiProcessor::process_event(&mut state, event);
         }
         assert_eq!(state.cursor_position(), Position::new(4, 19));
+        
+        // Absolute row (VPA) keeps the column, absolute column (HPA) keeps the row
+        let events = parser.parse(b"\x1b[12d");
+        for event in events {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(11, 19));
+        
+        let events = parser.parse(b"\x1b[3`");
+        for event in events {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(11, 2));
+        
+        // VPA past the bottom stops on the last row without scrolling
+        for event in parser.parse(b"\x1b[1;1Htop\x1b[99dX") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(23, 4));
+        let row = |r: u16| state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect::<String>();
+        assert!(row(0).starts_with("top"));
+        assert_eq!(row(23).trim_end(), "   X");
+        
+        // HPA past the right edge stops on the last column without wrapping
+        let mut state = TerminalState::new(Size::new(10, 3));
+        for event in parser.parse(b"ab\x1b[99`X") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(0, 9));
+        let row = |r: u16| state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect::<String>();
+        assert_eq!(row(0), "ab       X");
+        assert_eq!(row(1).trim_end(), "");
+        
+        // With DECLRMM the cursor stops on the right margin
+        for event in parser.parse(b"\x1b[?69h\x1b[2;6s\x1b[1;3H\x1b[99GY") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(0, 5));
+        let row = |r: u16| state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect::<String>();
+        assert_eq!(row(0), "ab   Y   X");
     }
     
     #[test]
@@ -490,4 +1003,889 @@ mod tests {
         assert!(attrs.flags.contains(AttributeFlags::ITALIC));
         assert!(attrs.flags.contains(AttributeFlags::UNDERLINE));
     }
+    
+    #[test]
+    fn test_insert_delete_characters() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        let mut parser = VteParser::new();
+        
+        // Write text, move back to column 2 and insert two blanks
+        for event in parser.parse(b"abcdef\x1b[3G\x1b[2@") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let row: String = state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect();
+        assert_eq!(row, "ab  cdef  ");
+        
+        // Delete them again
+        for event in parser.parse(b"\x1b[2P") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let row: String = state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect();
+        assert_eq!(row, "abcdef    ");
+        assert_eq!(state.cursor_position(), Position::new(0, 2));
+        
+        // Wide characters cut by the shift are blanked rather than split
+        let mut state = TerminalState::new(Size::new(6, 2));
+        let row = |state: &TerminalState| -> String {
+            state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect()
+        };
+        let widths = |state: &TerminalState| -> Vec<u8> {
+            state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.width).collect()
+        };
+        for event in parser.parse("中b\x1b[1;2H\x1b[P".as_bytes()) {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state), " b    ");
+        assert_eq!(widths(&state), vec![1; 6]);
+        
+        for event in parser.parse("\x1b[2J\x1b[1;1Habcd中\x1b[1;1H\x1b[@".as_bytes()) {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state), " abcd ");
+        assert_eq!(widths(&state), vec![1; 6]);
+        
+        // DECIC shifts columns the same way
+        for event in parser.parse("\x1b[2J\x1b[1;1Habcd中\x1b[1;1H\x1b['}".as_bytes()) {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state), " abcd ");
+        assert_eq!(widths(&state), vec![1; 6]);
+        
+        // Deleting the first half takes the second with it
+        for event in parser.parse("\x1b[2J\x1b[1;1Ha中b\x1b[1;1H\x1b[2P".as_bytes()) {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state), " b    ");
+        assert_eq!(widths(&state), vec![1; 6]);
+    }
+    
+    #[test]
+    fn test_scroll_region() {
+        let mut state = TerminalState::new(Size::new(5, 5));
+        let mut parser = VteParser::new();
+        let column = |state: &TerminalState| -> String {
+            state.screen_buffer().lines().iter().map(|l| l[0].ch).collect()
+        };
+        
+        // Status line on the last row, scrolling confined to rows 1-4
+        for event in parser.parse(b"\x1b[5;1HS\x1b[1;4r\x1b[4;1Ha\r\nb\r\nc") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(column(&state), " abcS");
+        assert_eq!(state.cursor_position(), Position::new(3, 1));
+        
+        // Reverse index at the top margin scrolls the region down
+        for event in parser.parse(b"\x1b[1;1H\x1bM") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(column(&state), "  abS");
+        
+        // Resetting the region restores full-screen scrolling
+        for event in parser.parse(b"\x1b[r") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.scroll_region(), (0, 4));
+    }
+    
+    #[test]
+    fn test_tab_clear() {
+        let mut state = TerminalState::new(Size::new(40, 2));
+        let mut parser = VteParser::new();
+        
+        // Clear the stop at column 8, so a tab from 0 goes to 16
+        for event in parser.parse(b"\x1b[1;9H\x1b[g\r\t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(0, 16));
+        
+        // With all stops cleared a tab goes to the last column
+        for event in parser.parse(b"\x1b[3g\r\t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(0, 39));
+    }
+    
+    #[test]
+    fn test_device_status_reports() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[5n\x1b[12;34H\x1b[6n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[0n".to_vec(), b"\x1b[12;34R".to_vec()]);
+    }
+    
+    #[test]
+    fn test_pixel_size_reports() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        // Nothing to report until the frontend provides cell metrics
+        for event in parser.parse(b"\x1b[14t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(state.take_responses().is_empty());
+        
+        state.set_cell_metrics(CellMetrics::new(9, 18));
+        for event in parser.parse(b"\x1b[14t\x1b[16t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[4;432;720t".to_vec(), b"\x1b[6;18;9t".to_vec()]);
+    }
+    
+    #[test]
+    fn test_save_cursor_or_set_margins() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let mut parser = VteParser::new();
+        
+        // Without DECLRMM, CSI s saves the cursor even with parameters
+        for event in parser.parse(b"\x1b[2;3H\x1b[2;5s\x1b[H\x1b[u") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(1, 2));
+        assert_eq!(state.left_right_margins(), (0, 9));
+        
+        for event in parser.parse(b"\x1b[?69h\x1b[2;5s") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.left_right_margins(), (1, 4));
+        assert_eq!(state.cursor_position(), Position::new(0, 0));
+        
+        // With DECLRMM, CSI s without parameters resets the margins
+        for event in parser.parse(b"\x1b[s") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.left_right_margins(), (0, 9));
+    }
+    
+    #[test]
+    fn test_osc_palette() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b]4;1;rgb:12/34/56;200;#abcdef\x07") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.palette_color(1), Color::Rgb(0x12, 0x34, 0x56));
+        assert_eq!(state.palette_color(200), Color::Rgb(0xab, 0xcd, 0xef));
+        assert_eq!(state.palette_color(2), Color::from_ansi(2));
+    }
+    
+    #[test]
+    fn test_osc_dynamic_colors() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b]11;#102030\x07\x1b]11;?\x07\x1b]12;?\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.dynamic_colors().background, Color::Rgb(0x10, 0x20, 0x30));
+        assert_eq!(state.snapshot().dynamic_colors, state.dynamic_colors());
+        assert_eq!(
+            state.take_responses(),
+            vec![
+                b"\x1b]11;rgb:1010/2020/3030\x07".to_vec(),
+                b"\x1b]12;rgb:e5e5/e5e5/e5e5\x1b\\".to_vec(),
+            ]
+        );
+        
+        // RIS restores the configured colors
+        for event in parser.parse(b"\x1bc") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.dynamic_colors(), DynamicColors::default());
+    }
+    
+    #[test]
+    fn test_osc_clipboard() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b]52;c;aGVsbG8=\x07\x1b]52;p;?\x07\x1b]52;c;!!\x07") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let events = state.take_events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[0],
+            Event::ClipboardSet { clipboard: ClipboardType::Clipboard, text } if text == "hello"
+        ));
+        assert!(matches!(events[1], Event::ClipboardQuery(ClipboardType::Primary)));
+        
+        state.reply_clipboard(ClipboardType::Primary, "hello");
+        assert_eq!(state.take_responses(), vec![b"\x1b]52;p;aGVsbG8=\x1b\\".to_vec()]);
+    }
+    
+    #[test]
+    fn test_osc_notification() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b]777;notify;cargo;build done\x07") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let events = state.take_events();
+        assert!(matches!(
+            &events[..],
+            [Event::Notification { title: Some(title), body }] if title == "cargo" && body == "build done"
+        ));
+    }
+    
+    #[test]
+    fn test_osc_semantic_prompt() {
+        let mut state = TerminalState::new(Size::new(20, 5));
+        let mut parser = VteParser::new();
+        
+        let input = b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a b\r\n\x1b]133;D;0\x07\x1b]133;A\x07$ ";
+        for event in parser.parse(input) {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(matches!(state.take_events()[..], [Event::CommandFinished { exit_code: Some(0) }]));
+        assert_eq!(state.semantic_zone(), CellTags::PROMPT);
+        
+        let commands = state.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!((commands[0].text.as_str(), commands[0].exit_code), ("ls", Some(0)));
+        let command = commands[0].command.unwrap();
+        assert_eq!((command.start.line, command.start.col, command.end.col), (0, 2, 3));
+        let output = commands[0].output.unwrap();
+        assert_eq!((output.start.line, output.start.col), (1, 0));
+        assert_eq!((output.end.line, output.end.col), (1, 2));
+        assert_eq!(commands[1].prompt.unwrap().start.line, 2);
+    }
+    
+    #[test]
+    fn test_underline_styles() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[4m\x1b[4:3;58:2::255:0:0m") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let attrs = state.snapshot().active_attributes;
+        assert_eq!(attrs.flags & AttributeFlags::UNDERLINES, AttributeFlags::CURLY_UNDERLINE);
+        assert_eq!(attrs.underline_color, Some(Color::Rgb(255, 0, 0)));
+        
+        for event in parser.parse(b"\x1b[24m") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(!state.snapshot().active_attributes.flags.intersects(AttributeFlags::UNDERLINES));
+    }
+    
+    #[test]
+    fn test_underline_style_subparameters() {
+        let styles: [(&[u8], AttributeFlags); 6] = [
+            (b"\x1b[4:1m", AttributeFlags::UNDERLINE),
+            (b"\x1b[4:2m", AttributeFlags::DOUBLE_UNDERLINE),
+            (b"\x1b[4:3m", AttributeFlags::CURLY_UNDERLINE),
+            (b"\x1b[4:4m", AttributeFlags::DOTTED_UNDERLINE),
+            (b"\x1b[4:5m", AttributeFlags::DASHED_UNDERLINE),
+            (b"\x1b[4:0m", AttributeFlags::empty()),
+        ];
+        
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        for (sequence, expected) in styles {
+            for event in parser.parse(sequence) {
+                AnsiProcessor::process_event(&mut state, event);
+            }
+            let flags = state.snapshot().active_attributes.flags;
+            assert_eq!(flags & AttributeFlags::UNDERLINES, expected, "{:?}", sequence);
+        }
+    }
+    
+    #[test]
+    fn test_overline_and_scripts() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[53;73m\x1b[74mx") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let flags = state.screen_buffer().get_cell(Position::new(0, 0)).attrs.flags;
+        assert!(flags.contains(AttributeFlags::OVERLINE | AttributeFlags::SUBSCRIPT));
+        assert!(!flags.contains(AttributeFlags::SUPERSCRIPT));
+        
+        for event in parser.parse(b"\x1b[55;75m") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let flags = state.snapshot().active_attributes.flags;
+        assert!(!flags.intersects(AttributeFlags::OVERLINE | AttributeFlags::SUPERSCRIPT | AttributeFlags::SUBSCRIPT));
+    }
+    
+    #[test]
+    fn test_side_margins_confine_edits_and_scrolling() {
+        let mut state = TerminalState::new(Size::new(7, 3));
+        let mut parser = VteParser::new();
+        let rows = |state: &TerminalState| -> Vec<String> {
+            state.screen_buffer().lines().iter().map(|l| l.iter().map(|c| c.ch).collect()).collect()
+        };
+        
+        for event in parser.parse(b"abcdef\r\nghijkl\r\nmnopqr\x1b[?69h\x1b[2;4s") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        
+        // ICH and DCH only shift cells up to the right margin
+        for event in parser.parse(b"\x1b[1;2H\x1b[@\x1b[2;2H\x1b[P") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(rows(&state), vec!["a bcef ", "gij kl ", "mnopqr "]);
+        
+        // Scrolling at the bottom margin only moves the margin columns
+        for event in parser.parse(b"\x1b[3;2H\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(rows(&state), vec!["aij ef ", "gnopkl ", "m   qr "]);
+        assert!(state.scrollback_buffer().is_empty());
+    }
+    
+    #[test]
+    fn test_dec_special_graphics() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let mut parser = VteParser::new();
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        for event in parser.parse(b"\x1b(0lqqk\x1b(B lq\r\n\x1b)0x\x0ex\x0fx") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state, 0), "┌──┐ lq   ");
+        assert_eq!(row(&state, 1), "x│x       ");
+    }
+    
+    #[test]
+    fn test_device_attributes_and_termcap_replies() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[c") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[?62;22;52c".to_vec()]);
+        
+        for event in parser.parse(b"\x1b[>q") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let version = format!("\x1bP>|phosphor({})\x1b\\", env!("CARGO_PKG_VERSION"));
+        assert_eq!(state.take_responses(), vec![version.into_bytes()]);
+        
+        // TN, RGB (boolean) and an unknown name
+        for event in parser.parse(b"\x1bP+q544e;524742;78797a\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.take_responses(),
+            vec![
+                format!("\x1bP1+r544E={}\x1b\\", hex("xterm-256color")).into_bytes(),
+                b"\x1bP1+r524742\x1b\\".to_vec(),
+                b"\x1bP0+r78797A\x1b\\".to_vec(),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_window_operations() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[18t\x1b[2t\x1b[1t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[8;24;80t".to_vec()]);
+        let events = state.take_events();
+        assert!(matches!(events[..], [Event::Iconify(true), Event::Iconify(false)]));
+        
+        // Push, change both titles, then pop only the window title
+        for event in parser.parse(b"\x1b]2;vim\x07\x1b]1;v\x07\x1b[22t\x1b]2;less\x07\x1b]1;l\x07\x1b[23;2t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.title(), "vim");
+        assert_eq!(state.icon_name(), "l");
+        assert_eq!(state.snapshot().title, "vim");
+        assert_eq!(state.snapshot().icon_name, "l");
+        assert!(matches!(state.take_events().last(), Some(Event::TitleChanged(title)) if title == "vim"));
+        
+        // Popping an empty stack does nothing
+        for event in parser.parse(b"\x1b[23t") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.title(), "vim");
+        assert!(state.take_events().is_empty());
+    }
+    
+    #[test]
+    fn test_modify_other_keys_level() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[?1h\x1b[>4;3m") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let modes = state.input_modes();
+        assert!(modes.application_cursor);
+        assert_eq!(modes.modify_other_keys, 2);
+        
+        for event in parser.parse(b"\x1b[>4n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.input_modes().modify_other_keys, 0);
+    }
+    
+    #[test]
+    fn test_kitty_keyboard_flags() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[>1u\x1b[>9u\x1b[=2;2u\x1b[?u") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[?11u".to_vec()]);
+        assert_eq!(state.input_modes().keyboard_flags, 11);
+        
+        // The alternate screen has its own stack
+        for event in parser.parse(b"\x1b[?1049h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.keyboard_flags(), 0);
+        for event in parser.parse(b"\x1b[?1049l\x1b[<u") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.keyboard_flags(), 1);
+        
+        // Popping more entries than pushed empties the stack
+        for event in parser.parse(b"\x1b[<5u") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.keyboard_flags(), 0);
+    }
+    
+    #[test]
+    fn test_mouse_modes() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[?1000h\x1b[?1002h\x1b[?1006h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let modes = state.input_modes();
+        assert_eq!(modes.mouse_tracking, MouseTracking::Drag);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Sgr);
+        assert!(!state.mode().contains(TerminalMode::MOUSE_REPORTING));
+        
+        // Resetting a mode that is not active leaves tracking on
+        for event in parser.parse(b"\x1b[?1003l\x1b[?1015h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let modes = state.input_modes();
+        assert_eq!(modes.mouse_tracking, MouseTracking::Drag);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Urxvt);
+        
+        for event in parser.parse(b"\x1b[?1002l\x1b[?1015l") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let modes = state.input_modes();
+        assert_eq!(modes.mouse_tracking, MouseTracking::Off);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::X10);
+    }
+    
+    #[test]
+    fn test_control_strings_become_events() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b_Ga=q\x1b\\\x1b^pm\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let events = state.take_events();
+        assert!(matches!(&events[..], [Event::ApcString(apc), Event::PmString(pm)] if apc == b"Ga=q" && pm == b"pm"));
+    }
+    
+    #[test]
+    fn test_mode_report() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        // Bracketed paste reset, cursor visible set, synchronized output unknown
+        for event in parser.parse(b"\x1b[?2004$p\x1b[?25$p\x1b[?2026$p") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.take_responses(),
+            vec![b"\x1b[?2004;2$y".to_vec(), b"\x1b[?25;1$y".to_vec(), b"\x1b[?2026;0$y".to_vec()]
+        );
+        
+        // The reply echoes the queried number, whichever alias was used
+        for event in parser.parse(b"\x1b[?2004h\x1b[?1049h\x1b[?2004$p\x1b[?1049$p") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[?2004;1$y".to_vec(), b"\x1b[?1049;1$y".to_vec()]);
+        
+        // 1048 is known but never reads as set
+        for event in parser.parse(b"\x1b[?1048h\x1b[?1048$p") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[?1048;2$y".to_vec()]);
+        
+        // DECARM is accepted but never changes
+        for event in parser.parse(b"\x1b[?8h\x1b[?8$p") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1b[?8;4$y".to_vec()]);
+        
+        // ANSI modes reply without the `?`
+        for event in parser.parse(b"\x1b[4$p\x1b[4h\x1b[4$p\x1b[20$p\x1b[12$p\x1b[8$p") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.take_responses(),
+            vec![
+                b"\x1b[4;2$y".to_vec(),
+                b"\x1b[4;1$y".to_vec(),
+                b"\x1b[20;2$y".to_vec(),
+                b"\x1b[12;3$y".to_vec(),
+                b"\x1b[8;0$y".to_vec(),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_status_string_replies() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        
+        for event in parser.parse(b"\x1b[1;4:3;38;5;208;48;2;1;2;3;58;5;9m\x1b[3;20r\x1bP$qm\x1b\\\x1bP$qr\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.take_responses(),
+            vec![
+                b"\x1bP1$r0;1;4:3;38:5:208;48:2::1:2:3;58:5:9m\x1b\\".to_vec(),
+                b"\x1bP1$r3;20r\x1b\\".to_vec(),
+            ]
+        );
+        
+        // The SGR reply restores the same rendition
+        let attrs = *state.attributes();
+        for event in parser.parse(b"\x1b[0m\x1b[0;1;4:3;38:5:208;48:2::1:2:3;58:5:9m") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(*state.attributes(), attrs);
+        
+        for event in parser.parse(b"\x1b[0;91m\x1bP$qm\x1b\\\x1bP$q q\x1b\\\x1bP$qx\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.take_responses(),
+            vec![
+                b"\x1bP1$r0;91m\x1b\\".to_vec(),
+                b"\x1bP1$r2 q\x1b\\".to_vec(),
+                b"\x1bP0$r\x1b\\".to_vec(),
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_line_renditions() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let mut parser = VteParser::new();
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        // A double-width line wraps after half the columns
+        for event in parser.parse(b"\x1b#6abcdefg") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state, 0), "abcde     ");
+        assert_eq!(row(&state, 1), "fg        ");
+        assert_eq!(
+            state.snapshot().line_renditions,
+            vec![LineRendition::DoubleWidth, LineRendition::SingleWidth, LineRendition::SingleWidth]
+        );
+        
+        // Renditions scroll with their lines
+        for event in parser.parse(b"\x1b#3\r\n\x1b#4\r\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(
+            state.snapshot().line_renditions,
+            vec![LineRendition::DoubleHeightTop, LineRendition::DoubleHeightBottom, LineRendition::SingleWidth]
+        );
+        
+        // DECSWL and erasing the display return lines to single width
+        for event in parser.parse(b"\x1b[H\x1b#5") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.screen_buffer().line_rendition(0), LineRendition::SingleWidth);
+        for event in parser.parse(b"\x1b[2J") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(state.snapshot().line_renditions.iter().all(|r| *r == LineRendition::SingleWidth));
+    }
+    
+    #[test]
+    fn test_selective_erase() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        for event in parser.parse(b"ab\x1b[1\"qCD\x1b[0\"qef\r\nghij") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(state.screen_buffer().get_line(0).unwrap()[2].protected);
+        assert!(!state.screen_buffer().get_line(0).unwrap()[4].protected);
+        
+        // DECSEL spares protected cells, EL does not
+        for event in parser.parse(b"\x1b[H\x1b[?2K") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state, 0), "  CD      ");
+        
+        for event in parser.parse(b"\x1b[?2J") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state, 0), "  CD      ");
+        assert_eq!(row(&state, 1), "          ");
+        
+        for event in parser.parse(b"\x1b[2K") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(row(&state, 0), "          ");
+        
+        for event in parser.parse(b"\x1b[1\"q\x1bP$q\"q\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.take_responses(), vec![b"\x1bP1$r1\"q\x1b\\".to_vec()]);
+    }
+    
+    #[test]
+    fn test_linefeed_mode() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(80, 24));
+        
+        for event in parser.parse(b"abc\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(1, 3));
+        
+        for event in parser.parse(b"\x1b[20habc\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.mode_flag(Mode::LineFeed), Some(true));
+        assert_eq!(state.cursor_position(), Position::new(2, 0));
+        
+        for event in parser.parse(b"\x1b[20labc\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_position(), Position::new(3, 3));
+    }
+    
+    #[test]
+    fn test_column_mode() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(80, 24));
+        
+        for event in parser.parse(b"hello\x1b[5;10r\x1b[?3h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.size(), Size::new(132, 24));
+        assert_eq!(state.cursor_position(), Position::new(0, 0));
+        assert_eq!(state.scroll_region(), (0, 23));
+        assert!(state.screen_buffer().get_line(0).unwrap().iter().all(|c| c.ch == ' '));
+        assert_eq!(state.mode_flag(Mode::ColumnMode), Some(true));
+        assert!(matches!(state.take_events()[..], [Event::ResizeRequested(size)] if size == Size::new(132, 24)));
+        
+        for event in parser.parse(b"\x1b[?3l") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.size(), Size::new(80, 24));
+        assert_eq!(state.mode_flag(Mode::ColumnMode), Some(false));
+        assert!(matches!(state.take_events()[..], [Event::ResizeRequested(size)] if size == Size::new(80, 24)));
+    }
+    
+    #[test]
+    fn test_reverse_video() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let colors = state.dynamic_colors();
+        assert_eq!(state.snapshot().display_colors(), colors);
+        
+        for event in parser.parse(b"\x1b[?5h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let snapshot = state.snapshot();
+        assert!(snapshot.reverse_video);
+        assert!(snapshot.mode.contains(TerminalMode::REVERSE_VIDEO));
+        assert_eq!(snapshot.display_colors().foreground, colors.background);
+        assert_eq!(snapshot.display_colors().background, colors.foreground);
+        // Queries still report the configured colors
+        assert_eq!(state.dynamic_colors(), colors);
+        
+        for event in parser.parse(b"\x1b[?5l") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert!(!state.snapshot().reverse_video);
+    }
+    
+    #[test]
+    fn test_printer_controller() {
+        use crate::printer::CallbackPrinter;
+        use std::sync::{Arc, Mutex};
+        
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let sink = printed.clone();
+        state.set_printer(Some(Box::new(CallbackPrinter::new(move |data: &[u8]| {
+            sink.lock().unwrap().extend_from_slice(data)
+        }))));
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        // Printed output and its sequences never reach the screen
+        for event in parser.parse(b"ab\x1b[5ipage 1\r\n\x1b[2J\x1b[1mx\x1b[4icd") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(std::mem::take(&mut *printed.lock().unwrap()), b"page 1\r\nx");
+        assert_eq!(row(&state, 0), "abcd      ");
+        assert!(!state.printer_controller());
+        
+        // Print line and autoprint copy screen lines
+        for event in parser.parse(b"\x1b[?1i\x1b[?5i\r\nef\r\n\x1b[?4igh\r\n") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(std::mem::take(&mut *printed.lock().unwrap()), b"abcd\nabcd\nef\n");
+        
+        for event in parser.parse(b"\x1b[i") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(std::mem::take(&mut *printed.lock().unwrap()), b"ef\ngh\n\n");
+    }
+    
+    #[test]
+    fn test_hyperlinks() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(20, 2));
+        for event in parser.parse(b"see \x1b]8;id=doc;https://example.com\x1b\\docs\x1b]8;;\x1b\\ now") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        
+        assert_eq!(state.hyperlink_at(Position::new(0, 3)), None);
+        let link = state.hyperlink_at(Position::new(0, 4)).unwrap();
+        assert_eq!(link.uri, "https://example.com");
+        assert_eq!(link.id.as_deref(), Some("doc"));
+        assert_eq!(state.hyperlink_at(Position::new(0, 7)), Some(link));
+        assert_eq!(state.hyperlink_at(Position::new(0, 8)), None);
+        assert!(state.hyperlink().is_none());
+        
+        // Erasing removes the link
+        for event in parser.parse(b"\x1b[1;5H\x1b[K") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.hyperlink_at(Position::new(0, 5)), None);
+    }
+    
+    #[test]
+    fn test_snapshot_colors() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.palette.len(), 256);
+        assert_eq!(snapshot.palette[9], Rgb::new(255, 0, 0));
+        
+        for event in parser.parse(b"\x1b]4;9;rgb:12/34/56\x07\x1b]10;#ffffff\x07\x1b]11;#202020\x07\x1b]12;#ff0000\x07") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.palette[9], Rgb::new(0x12, 0x34, 0x56));
+        assert_eq!(snapshot.dynamic_colors.foreground, Color::Rgb(255, 255, 255));
+        assert_eq!(snapshot.dynamic_colors.background, Color::Rgb(0x20, 0x20, 0x20));
+        assert_eq!(snapshot.dynamic_colors.cursor, Color::Rgb(255, 0, 0));
+    }
+    
+    #[test]
+    fn test_alternate_screen_variants() {
+        let mut parser = VteParser::new();
+        let mut state = TerminalState::new(Size::new(10, 3));
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect::<String>().trim_end().to_string()
+        };
+        let mut feed = |state: &mut TerminalState, bytes: &[u8]| {
+            for event in parser.parse(bytes) {
+                AnsiProcessor::process_event(state, event);
+            }
+        };
+        
+        // 1049 saves the cursor and enters a cleared alternate screen
+        feed(&mut state, b"shell\x1b[2;4H\x1b[?1049h");
+        assert!(state.mode().contains(TerminalMode::ALTERNATE_SCREEN));
+        assert_eq!(row(&state, 0), "");
+        feed(&mut state, b"\x1b[Hvim\x1b[3;1H");
+        feed(&mut state, b"\x1b[?1049l");
+        assert_eq!(row(&state, 0), "shell");
+        assert_eq!(state.cursor_position(), Position::new(1, 3));
+        
+        // Entering again starts from a blank screen
+        feed(&mut state, b"\x1b[?1049h");
+        assert_eq!(row(&state, 0), "");
+        feed(&mut state, b"\x1b[Hleft\x1b[?1049l");
+        
+        // 47 keeps the alternate screen's contents
+        feed(&mut state, b"\x1b[?47h");
+        assert_eq!(row(&state, 0), "left");
+        feed(&mut state, b"\x1b[?47l");
+        
+        // 1047 clears it on the way out
+        feed(&mut state, b"\x1b[?1047h");
+        assert_eq!(row(&state, 0), "left");
+        feed(&mut state, b"\x1b[?1047l\x1b[?47h");
+        assert_eq!(row(&state, 0), "");
+        feed(&mut state, b"\x1b[?47l");
+        
+        // 1048 only saves and restores the cursor
+        feed(&mut state, b"\x1b[1;2H\x1b[?1048h\x1b[3;3H\x1b[?1048l");
+        assert_eq!(state.cursor_position(), Position::new(0, 1));
+        assert!(!state.mode().contains(TerminalMode::ALTERNATE_SCREEN));
+        
+        // A DECSC on the alternate screen leaves the cursor 1049 saved
+        feed(&mut state, b"\x1b[3;4H\x1b[?1049h\x1b[H\x1b7\x1b[?1049l");
+        assert_eq!(state.cursor_position(), Position::new(2, 3));
+        feed(&mut state, b"\x1b[?47h\x1b8");
+        assert_eq!(state.cursor_position(), Position::new(0, 0));
+        feed(&mut state, b"\x1b[?47l\x1b[H\x1b8");
+        assert_eq!(state.cursor_position(), Position::new(2, 3));
+    }
+    
+    #[test]
+    fn test_cursor_blink() {
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        assert!(!state.cursor_blinking());
+        assert_eq!(state.cursor_blink_interval(), None);
+        
+        for event in parser.parse(b"\x1b[5 q") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_style(), CursorStyle::BlinkingBar);
+        assert!(state.snapshot().cursor_blinking);
+        assert_eq!(state.cursor_blink_interval(), Some(std::time::Duration::from_millis(500)));
+        
+        // DECRST 12 keeps the shape, and DECRQM and DECRQSS follow it
+        for event in parser.parse(b"\x1b[?12l\x1b[?12$p\x1bP$q q\x1b\\") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_style(), CursorStyle::Bar);
+        assert!(!state.snapshot().cursor_blinking);
+        assert_eq!(state.take_responses(), vec![b"\x1b[?12;2$y".to_vec(), b"\x1bP1$r6 q\x1b\\".to_vec()]);
+        
+        for event in parser.parse(b"\x1b[?12h") {
+            AnsiProcessor::process_event(&mut state, event);
+        }
+        assert_eq!(state.cursor_style(), CursorStyle::BlinkingBar);
+    }
 }
\ No newline at end of file
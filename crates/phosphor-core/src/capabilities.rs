@@ -0,0 +1,199 @@
+use phosphor_common::traits::Mode;
+use phosphor_parser::DEC_PRIVATE_MODES;
+
+/// Terminal features supported by phosphor
+///
+/// This is the single source for everything phosphor advertises about
+/// itself: the `TERM` value exported to the shell, the primary device
+/// attributes (DA1) reply, XTGETTCAP answers and the generated terminfo entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Value exported as `TERM`
+    pub term: &'static str,
+    /// phosphor release, reported by XTVERSION
+    pub version: &'static str,
+    /// Number of indexed palette colors
+    pub colors: u16,
+    /// 24-bit SGR colors (`COLORTERM=truecolor`)
+    pub truecolor: bool,
+    /// SGR 4:n underline styles and SGR 58 underline color
+    pub styled_underlines: bool,
+    /// OSC 8 hyperlinks
+    pub hyperlinks: bool,
+    /// OSC 52 clipboard access
+    pub clipboard: bool,
+    /// OSC 133 shell integration marks
+    pub shell_integration: bool,
+    /// OSC 9 / OSC 777 desktop notifications
+    pub notifications: bool,
+    /// Kitty keyboard protocol (CSI u)
+    pub kitty_keyboard: bool,
+    /// Sixel graphics
+    pub sixel: bool,
+    /// DEC private modes accepted by DECSET/DECRST, by number, from the
+    /// parser's own table
+    pub dec_modes: &'static [(u16, Mode)],
+}
+
+/// Extended (non-standard) capabilities, as terminfo names and values
+const EXTENDED_CAPS: &[(&str, &str)] = &[
+    ("Ms", "\x1b]52;%p1%s;%p2%s\x07"),
+    ("Setulc", "\x1b[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%dm"),
+    ("Smulx", "\x1b[4:%p1%dm"),
+];
+
+/// Describe the features this build of phosphor supports
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        term: "xterm-256color",
+        version: env!("CARGO_PKG_VERSION"),
+        colors: 256,
+        truecolor: true,
+        styled_underlines: true,
+        hyperlinks: true,
+        clipboard: true,
+        shell_integration: true,
+        notifications: true,
+        kitty_keyboard: true,
+        sixel: false,
+        dec_modes: DEC_PRIVATE_MODES,
+    }
+}
+
+impl Capabilities {
+    /// Primary device attributes (DA1) reply
+    ///
+    /// Reports a VT220 (62) with ANSI color (22), plus sixel (4) and
+    /// clipboard access (52) when supported.
+    pub fn primary_device_attributes(&self) -> String {
+        let mut reply = String::from("\x1b[?62;22");
+        if self.sixel {
+            reply.push_str(";4");
+        }
+        if self.clipboard {
+            reply.push_str(";52");
+        }
+        reply.push('c');
+        reply
+    }
+    
+    /// XTVERSION reply: DCS > | name(version) ST
+    pub fn version_report(&self) -> String {
+        format!("\x1bP>|phosphor({})\x1b\\", self.version)
+    }
+    
+    fn extended_caps(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        EXTENDED_CAPS.iter().copied().filter(move |(name, _)| match *name {
+            "Ms" => self.clipboard,
+            "Setulc" | "Smulx" => self.styled_underlines,
+            _ => true,
+        })
+    }
+    
+    /// Look up a capability by its termcap or terminfo name (XTGETTCAP)
+    ///
+    /// Boolean capabilities answer with an empty value.
+    pub fn termcap(&self, name: &str) -> Option<String> {
+        match name {
+            "TN" | "name" => Some(self.term.to_string()),
+            "Co" | "colors" => Some(self.colors.to_string()),
+            "Tc" | "RGB" if self.truecolor => Some(String::new()),
+            _ => self
+                .extended_caps()
+                .find(|(cap, _)| *cap == name)
+                .map(|(_, value)| value.to_string()),
+        }
+    }
+    
+    /// Generate a terminfo source entry (for `tic -x`)
+    ///
+    /// The entry adds phosphor's extensions on top of the `TERM` it
+    /// advertises.
+    pub fn terminfo(&self) -> String {
+        let mut entry = String::from("phosphor|phosphor terminal emulator,\n");
+        if self.truecolor {
+            entry.push_str("\tRGB,\n");
+            entry.push_str("\tTc,\n");
+        }
+        for (name, value) in self.extended_caps() {
+            entry.push_str(&format!("\t{}={},\n", name, escape_terminfo(value)));
+        }
+        entry.push_str(&format!("\tuse={},\n", self.term));
+        entry
+    }
+}
+
+/// Escape a capability value for terminfo source
+fn escape_terminfo(value: &str) -> String {
+    let mut escaped = String::new();
+    for ch in value.chars() {
+        match ch {
+            '\x1b' => escaped.push_str("\\E"),
+            '\x07' => escaped.push_str("^G"),
+            ',' => escaped.push_str("\\,"),
+            '\\' => escaped.push_str("\\\\"),
+            '^' => escaped.push_str("\\^"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_device_attributes() {
+        let caps = capabilities();
+        assert_eq!(caps.primary_device_attributes(), "\x1b[?62;22;52c");
+        
+        let caps = Capabilities { sixel: true, clipboard: false, ..caps };
+        assert_eq!(caps.primary_device_attributes(), "\x1b[?62;22;4c");
+    }
+    
+    #[test]
+    fn test_version_report() {
+        let caps = Capabilities { version: "1.2.3", ..capabilities() };
+        assert_eq!(caps.version_report(), "\x1bP>|phosphor(1.2.3)\x1b\\");
+    }
+    
+    #[test]
+    fn test_termcap_lookup() {
+        let caps = capabilities();
+        assert_eq!(caps.termcap("TN").as_deref(), Some("xterm-256color"));
+        assert_eq!(caps.termcap("colors").as_deref(), Some("256"));
+        assert_eq!(caps.termcap("RGB").as_deref(), Some(""));
+        assert_eq!(caps.termcap("Smulx").as_deref(), Some("\x1b[4:%p1%dm"));
+        assert_eq!(caps.termcap("bogus"), None);
+        
+        let caps = Capabilities { clipboard: false, ..caps };
+        assert_eq!(caps.termcap("Ms"), None);
+    }
+    
+    #[test]
+    fn test_dec_modes_parse() {
+        use phosphor_common::traits::{CsiSequence, ParsedEvent, TerminalParser};
+        use phosphor_parser::VteParser;
+        
+        let mut parser = VteParser::new();
+        for &(number, mode) in capabilities().dec_modes {
+            let events = parser.parse(format!("\x1b[?{}h", number).as_bytes());
+            let parsed = match &events[..] {
+                [ParsedEvent::Csi(CsiSequence::SetMode(modes))] => modes == &[mode],
+                [ParsedEvent::Csi(CsiSequence::ShowCursor)] => mode == Mode::CursorVisible,
+                _ => false,
+            };
+            assert!(parsed, "mode {} parsed as {:?}", number, events);
+        }
+    }
+    
+    #[test]
+    fn test_terminfo_entry() {
+        let entry = capabilities().terminfo();
+        assert!(entry.starts_with("phosphor|"));
+        assert!(entry.contains("\tMs=\\E]52;%p1%s;%p2%s^G,\n"));
+        assert!(entry.contains("\tSmulx=\\E[4:%p1%dm,\n"));
+        assert!(entry.ends_with("\tuse=xterm-256color,\n"));
+    }
+}
@@ -1,4 +1,10 @@
-use phosphor_common::types::Size;
+use phosphor_common::geometry::CellMetrics;
+use phosphor_common::traits::ClipboardType;
+use phosphor_common::types::{GridSnapshot, KeyEvent, MouseEvent, PixelSize, Size};
+use std::sync::Arc;
+
+use crate::limits::LimitKind;
+use crate::terminal::BellAction;
 
 /// Commands that can be sent to the terminal
 #[derive(Debug, Clone)]
@@ -6,8 +12,42 @@ pub enum Command {
     /// Write data to the PTY
     Write(Vec<u8>),
     
+    /// Write a reply generated by the terminal itself (e.g. device
+    /// attributes, cursor and mode reports) to the PTY
+    ///
+    /// Replies reach the input taps but not the input journal, which only
+    /// records input.
+    Reply(Vec<u8>),
+    
+    /// Encode a key press for the current keyboard modes and write it to the PTY
+    Key(KeyEvent),
+    
+    /// Encode a mouse event for the active mouse protocol and write it to the PTY
+    ///
+    /// Dropped unless the application enabled mouse reporting.
+    Mouse(MouseEvent),
+    
+    /// Write pasted text to the PTY, bracketed if the application enabled
+    /// bracketed paste
+    Paste(String),
+    
     /// Resize the terminal
-    Resize(Size),
+    ///
+    /// `pixels` is the text area size in pixels, if the frontend knows it;
+    /// the cell metrics are derived from it.
+    Resize { size: Size, pixels: Option<PixelSize> },
+    
+    /// The frontend window gained (`true`) or lost (`false`) focus
+    Focus(bool),
+    
+    /// The frontend's cell size in pixels changed, e.g. after a font change
+    SetCellMetrics(CellMetrics),
+    
+    /// Answer an `Event::ClipboardQuery` with the clipboard's contents
+    ClipboardReply { clipboard: ClipboardType, text: String },
+    
+    /// Ask for the cells in view, answered with `Event::GridSnapshot`
+    RequestGridSnapshot,
     
     /// Close the terminal
     Close,
@@ -25,6 +65,65 @@ pub enum Event {
     /// Terminal was resized
     Resized(Size),
     
+    /// The application switched the grid to a new size (DECCOLM)
+    ///
+    /// The grid has already been resized; frontends may resize the window
+    /// to match and follow up with `Command::Resize`.
+    ResizeRequested(Size),
+    
+    /// The application rang the bell
+    ///
+    /// `action` comes from the session's bell policy and is never `Silent`.
+    /// `urgent` asks the frontend to draw attention to the window.
+    Bell { action: BellAction, urgent: bool },
+    
+    /// The application set a clipboard (OSC 52)
+    ClipboardSet { clipboard: ClipboardType, text: String },
+    
+    /// The application asked to read a clipboard (OSC 52)
+    ///
+    /// Frontends that allow this answer with `Command::ClipboardReply`;
+    /// ignoring the query leaves the application without a reply.
+    ClipboardQuery(ClipboardType),
+    
+    /// The shell reported that a command finished (OSC 133;D)
+    CommandFinished { exit_code: Option<i32> },
+    
+    /// The application changed the window title (OSC 0/2, or a title pop)
+    TitleChanged(String),
+    
+    /// The application changed the icon name (OSC 0/1, or a title pop)
+    IconNameChanged(String),
+    
+    /// The application asked to minimize (`true`) or restore (`false`) the window
+    Iconify(bool),
+    
+    /// The application asked for a desktop notification (OSC 9 / OSC 777)
+    Notification { title: Option<String>, body: String },
+    
+    /// The application sent an APC string (e.g. kitty graphics, tmux passthrough)
+    ///
+    /// Passed through undecoded for integrations to inspect.
+    ApcString(Vec<u8>),
+    
+    /// The application sent a PM (privacy message) string
+    PmString(Vec<u8>),
+    
+    /// The application sent an SOS string
+    SosString(Vec<u8>),
+    
+    /// A trigger matched a completed output line
+    ///
+    /// `captures` holds the match followed by each capture group, `None`
+    /// for a group that didn't take part.
+    TriggerMatched { name: String, captures: Vec<Option<String>> },
+    
+    /// The cells in view, in answer to `Command::RequestGridSnapshot`
+    GridSnapshot(Arc<GridSnapshot>),
+    
+    /// The session exceeded a rate limit and is paused for `paused`
+    LimitExceeded { kind: LimitKind, limit: u64, paused: std::time::Duration },
+    
     /// Terminal closed
     Closed,
     
@@ -0,0 +1,15 @@
+use phosphor_common::mouse;
+use phosphor_common::types::MouseEvent;
+
+pub use phosphor_common::keyboard::{
+    encode_key, encode_paste, AltKey, InputModes, KITTY_DISAMBIGUATE, KITTY_REPORT_ALL_KEYS, KITTY_REPORT_ALTERNATE_KEYS,
+    KITTY_REPORT_EVENT_TYPES, KITTY_REPORT_TEXT,
+};
+pub use phosphor_common::mouse::{MouseEncoding, MouseTracking};
+
+/// Encode a mouse event for the active mouse protocol
+///
+/// See `phosphor_common::mouse::encode_mouse` for what is reported.
+pub fn encode_mouse(event: MouseEvent, modes: InputModes) -> Vec<u8> {
+    mouse::encode_mouse_with(event, modes.mouse_tracking, modes.mouse_encoding)
+}
@@ -1,16 +1,120 @@
 pub mod ansi;
+pub mod capabilities;
 pub mod events;
+pub mod input;
+pub mod limits;
+pub mod metrics;
+pub mod printer;
 pub mod pty;
+#[cfg(feature = "render")]
+pub mod render;
 pub mod session;
+pub mod tap;
 pub mod terminal;
+pub mod triggers;
 
-use phosphor_common::{error::Result, types::Size, traits::{TerminalBackend, TerminalParser}};
+use phosphor_common::{error::{PhosphorError, Result}, geometry::CellMetrics, types::Size, traits::TerminalBackend};
 use phosphor_parser::VteParser;
+use std::sync::Arc;
 use tracing::{debug, info, error, instrument};
 
+/// Largest single write to the PTY; bigger writes, such as long pastes, are
+/// split so the application can drain its input between them
+const WRITE_CHUNK_SIZE: usize = 1024;
+
+pub use capabilities::{capabilities, Capabilities};
 pub use events::EventBus;
-pub use pty::PtyManager;
-pub use terminal::TerminalState;
+pub use input::{encode_key, encode_mouse, encode_paste, InputModes, MouseEncoding, MouseTracking};
+pub use limits::{LimitKind, RateLimiter};
+pub use metrics::{WakeupMetrics, WakeupStats};
+pub use printer::{CallbackPrinter, FilePrinter, PrinterSink};
+pub use pty::{InputJournal, Locale, PtyManager, SpawnOptions};
+pub use tap::{ByteTaps, TapChunk, TapDirection};
+pub use terminal::{LineDamage, TerminalConfig, TerminalState};
+pub use triggers::{TriggerMatch, TriggerRegistry};
+
+/// Write all of `data`, at most `WRITE_CHUNK_SIZE` bytes at a time
+///
+/// Short writes are retried with the rest. The task yields after each
+/// write, so other commands and the read loop draining the application's
+/// output get to run during a long paste.
+async fn write_chunked(backend: &mut impl TerminalBackend, mut data: &[u8]) -> Result<()> {
+    while !data.is_empty() {
+        let len = data.len().min(WRITE_CHUNK_SIZE);
+        let written = backend.write(&data[..len]).await?;
+        if written == 0 {
+            return Err(PhosphorError::Pty("PTY accepted no input".to_string()));
+        }
+        data = &data[written..];
+        tokio::task::yield_now().await;
+    }
+    Ok(())
+}
+
+/// Apply commands sent to the terminal until the channel closes or `Close` arrives
+///
+/// Input is written to `backend`. Commands that need the terminal state are
+/// forwarded to the read loop on `state_cmd_tx`.
+async fn process_commands(
+    mut command_rx: tokio::sync::mpsc::Receiver<events::Command>,
+    mut backend: impl TerminalBackend,
+    mut journal: Option<InputJournal>,
+    metrics: Arc<WakeupMetrics>,
+    taps: ByteTaps,
+    state_cmd_tx: tokio::sync::mpsc::UnboundedSender<events::Command>,
+) {
+    debug!("Command processor started");
+    while let Some(cmd) = command_rx.recv().await {
+        use events::Command;
+        metrics.record_wakeup();
+        match cmd {
+            Command::Write(data) => {
+                debug!("Processing write command: {} bytes", data.len());
+                if let Some(journal) = journal.as_mut() {
+                    if let Err(e) = journal.record(&data) {
+                        error!("Input journal write error: {}", e);
+                    }
+                }
+                taps.send(TapDirection::Input, &data);
+                if let Err(e) = write_chunked(&mut backend, &data).await {
+                    error!("PTY write error: {}", e);
+                    break;
+                }
+            }
+            Command::Reply(data) => {
+                debug!("Processing reply: {} bytes", data.len());
+                taps.send(TapDirection::Input, &data);
+                if let Err(e) = write_chunked(&mut backend, &data).await {
+                    error!("PTY write error: {}", e);
+                    break;
+                }
+            }
+            Command::Resize { size, pixels } => {
+                debug!("Processing resize command: {:?} ({:?})", size, pixels);
+                if let Err(e) = backend.resize_with_pixels(size, pixels.unwrap_or_default()).await {
+                    error!("PTY resize error: {}", e);
+                }
+                if pixels.is_some() {
+                    let _ = state_cmd_tx.send(Command::Resize { size, pixels });
+                }
+            }
+            Command::Key(_)
+            | Command::Mouse(_)
+            | Command::Paste(_)
+            | Command::Focus(_)
+            | Command::SetCellMetrics(_)
+            | Command::ClipboardReply { .. }
+            | Command::RequestGridSnapshot => {
+                let _ = state_cmd_tx.send(cmd);
+            }
+            Command::Close => {
+                info!("Received close command");
+                break;
+            }
+        }
+    }
+    debug!("Command processor exiting");
+}
 
 /// Main terminal structure that coordinates all components
 pub struct Terminal {
@@ -19,20 +123,96 @@ pub struct Terminal {
     parser: VteParser,
     event_bus: EventBus,
     size: Size,
+    journal: Option<InputJournal>,
+    metrics: Arc<WakeupMetrics>,
+    taps: ByteTaps,
+    output_limiter: Option<RateLimiter>,
+    event_limiter: Option<RateLimiter>,
+    triggers: TriggerRegistry,
+    bell_child: Option<tokio::process::Child>,
+    /// PTY reads are paused until then while over a rate limit
+    throttled_until: Option<tokio::time::Instant>,
 }
 
 impl Terminal {
     /// Create a new terminal with the specified size
-    #[instrument]
     pub fn new(size: Size) -> Result<Self> {
+        Self::with_options(size, SpawnOptions::default())
+    }
+    
+    /// Create a new terminal with the specified size and spawn options
+    pub fn with_options(size: Size, options: SpawnOptions) -> Result<Self> {
+        Self::with_config(size, options, TerminalConfig::default())
+    }
+    
+    /// Create a new terminal with the specified size, spawn options and configuration
+    #[instrument]
+    pub fn with_config(size: Size, mut options: SpawnOptions, mut config: TerminalConfig) -> Result<Self> {
         info!("Creating new Terminal with size: {:?}", size);
-        let pty = PtyManager::spawn_shell(size)?;
-        let state = TerminalState::new(size);
-        let parser = VteParser::new();
+        let metrics = Arc::new(WakeupMetrics::new());
+        // Character widths follow the locale the child is given
+        let locale = options.locale.get_or_insert_with(Locale::detect);
+        config.wide_ambiguous.get_or_insert(locale.prefers_wide_ambiguous());
+        let mut pty = PtyManager::spawn_shell_with_options(size, &options)?;
+        pty.set_wakeup_metrics(metrics.clone());
+        let output_limiter = config.limits.max_output_bytes_per_sec.map(RateLimiter::new);
+        let event_limiter = config.limits.max_events_per_sec.map(RateLimiter::new);
+        let mut parser = VteParser::new();
+        parser.set_c1_controls(config.c1_controls);
+        let state = TerminalState::with_config(size, config);
         let event_bus = EventBus::new();
         
         info!("Terminal created successfully");
-        Ok(Self { pty, state, parser, event_bus, size })
+        Ok(Self {
+            pty,
+            state,
+            parser,
+            event_bus,
+            size,
+            journal: None,
+            metrics,
+            taps: ByteTaps::new(),
+            output_limiter,
+            event_limiter,
+            triggers: TriggerRegistry::new(),
+            bell_child: None,
+            throttled_until: None,
+        })
+    }
+    
+    /// Journal input written to the PTY once the terminal is running
+    ///
+    /// Writes, keys, mouse reports and pastes are recorded; the terminal's
+    /// own replies to the application are not.
+    pub fn set_input_journal(&mut self, journal: InputJournal) {
+        self.journal = Some(journal);
+    }
+    
+    /// Send what applications print (media copy) to `printer`
+    pub fn set_printer(&mut self, printer: Box<dyn PrinterSink>) {
+        self.state.set_printer(Some(printer));
+    }
+    
+    /// Patterns run over output lines as they complete
+    ///
+    /// Register triggers before `run`; matches are sent as
+    /// `Event::TriggerMatched`.
+    pub fn triggers_mut(&mut self) -> &mut TriggerRegistry {
+        &mut self.triggers
+    }
+    
+    /// Wakeup counters for auditing idle CPU use
+    ///
+    /// Counting is off until enabled with `WakeupMetrics::set_enabled`.
+    pub fn wakeup_metrics(&self) -> Arc<WakeupMetrics> {
+        self.metrics.clone()
+    }
+    
+    /// Taps on the raw bytes written to and read from the PTY
+    ///
+    /// The returned handle can register taps before or while the terminal runs.
+    pub fn byte_taps(&self) -> ByteTaps {
+        self.taps.clone()
     }
     
     /// Get a command sender for external control
@@ -50,37 +230,21 @@ impl Terminal {
     pub async fn run(mut self) -> Result<()> {
         info!("Starting Terminal run loop");
         let mut buffer = vec![0u8; 4096];
+        self.state.collect_completed_lines(!self.triggers.is_empty());
         let event_tx = self.event_bus.event_sender();
         
+        // Commands that need the terminal state are forwarded to the read loop
+        let (state_cmd_tx, mut state_cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+        
         // Spawn command processor
-        let mut command_rx = self.event_bus.take_command_receiver();
-        let mut pty_writer = self.pty.clone();
-        let cmd_processor = tokio::spawn(async move {
-            debug!("Command processor started");
-            while let Some(cmd) = command_rx.recv().await {
-                use events::Command;
-                match cmd {
-                    Command::Write(data) => {
-                        debug!("Processing write command: {} bytes", data.len());
-                        if let Err(e) = pty_writer.write(&data).await {
-                            error!("PTY write error: {}", e);
-                            break;
-                        }
-                    }
-                    Command::Resize(size) => {
-                        debug!("Processing resize command: {:?}", size);
-                        if let Err(e) = pty_writer.resize(size).await {
-                            error!("PTY resize error: {}", e);
-                        }
-                    }
-                    Command::Close => {
-                        info!("Received close command");
-                        break;
-                    }
-                }
-            }
-            debug!("Command processor exiting");
-        });
+        let cmd_processor = tokio::spawn(process_commands(
+            self.event_bus.take_command_receiver(),
+            self.pty.clone(),
+            self.journal.take(),
+            self.metrics.clone(),
+            self.taps.clone(),
+            state_cmd_tx,
+        ));
         
         // Initial PTY alive check
         if !self.pty.is_alive().await {
@@ -98,7 +262,7 @@ impl Terminal {
             // First ensure the PTY has the right size
             tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
             info!("Sending resize command to ensure proper size");
-            if let Err(e) = test_sender.send(events::Command::Resize(test_size)).await {
+            if let Err(e) = test_sender.send(events::Command::Resize { size: test_size, pixels: None }).await {
                 error!("Failed to send resize: {}", e);
             }
             
@@ -122,10 +286,11 @@ impl Terminal {
         loop {
             iteration += 1;
             debug!("Read loop iteration: {}", iteration);
+            self.metrics.record_wakeup();
             
             tokio::select! {
-                // Read from PTY
-                result = self.pty.read(&mut buffer) => {
+                // Read from PTY unless throttled
+                result = self.pty.read(&mut buffer), if self.throttled_until.is_none() => {
                     match result {
                         Ok(0) => {
                             info!("PTY read returned 0 bytes (EOF)");
@@ -134,10 +299,14 @@ impl Terminal {
                         Ok(n) => {
                             info!("PTY read successful: {} bytes", n);
                             let data = &buffer[..n];
-                            self.process_output(data)?;
+                            self.taps.send(TapDirection::Output, data);
+                            let parsed = self.process_output(data)?;
+                            self.flush_responses().await;
                             
                             // Send event
                             let _ = event_tx.send(events::Event::OutputReady(data.to_vec()));
+                            
+                            self.enforce_limits(n, parsed);
                         }
                         Err(e) => {
                             error!("PTY read error: {}", e);
@@ -146,9 +315,21 @@ impl Terminal {
                     }
                 }
                 
+                // Resume reading once the throttle pause is over
+                _ = tokio::time::sleep_until(self.throttled_until.unwrap_or_else(tokio::time::Instant::now)), if self.throttled_until.is_some() => {
+                    debug!("Rate limit pause over, resuming reads");
+                    self.throttled_until = None;
+                }
+                
+                // Apply commands forwarded by the command processor
+                Some(cmd) = state_cmd_rx.recv() => {
+                    self.apply_state_command(cmd).await;
+                }
+                
                 // Check if PTY is still alive
                 _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
                     debug!("Checking PTY alive status");
+                    self.metrics.record_timer_fire();
                     if !self.pty.is_alive().await {
                         info!("PTY process ended (detected in alive check)");
                         break;
@@ -167,17 +348,177 @@ impl Terminal {
         Ok(())
     }
     
-    fn process_output(&mut self, data: &[u8]) -> Result<()> {
-        // Parse the data and process events
-        let events = self.parser.parse(data);
-        for event in events {
-            ansi::AnsiProcessor::process_event(&mut self.state, event);
+    /// Parse and apply PTY output, returning the number of parsed events
+    ///
+    /// In printer controller mode the raw bytes go to the printer instead,
+    /// bypassing the parser until MC 4.
+    fn process_output(&mut self, mut data: &[u8]) -> Result<usize> {
+        let mut parsed = 0;
+        while !data.is_empty() {
+            if self.state.printer_controller() {
+                data = &data[self.state.print_raw(data)..];
+                continue;
+            }
+            
+            // Parse the data and process events as they are parsed, up to
+            // the end of a sequence that starts printer controller mode
+            let state = &mut self.state;
+            let mut printing_from = None;
+            self.parser.parse_with_ranges(data, |event, range| {
+                if printing_from.is_some() {
+                    return;
+                }
+                parsed += 1;
+                ansi::AnsiProcessor::process_event(state, event);
+                if state.printer_controller() {
+                    printing_from = Some(range.end);
+                }
+            });
+            let Some(from) = printing_from else { break };
+            // The parser has seen the printer data too; start afresh at MC 4
+            self.parser.reset();
+            data = &data[from..];
+        }
+        
+        for event in self.state.take_events() {
+            if let events::Event::Bell { action: terminal::BellAction::Command(argv), .. } = &event {
+                Self::run_bell_command(&mut self.bell_child, argv);
+            }
+            let _ = self.event_bus.event_sender().send(event);
+        }
+        for line in self.state.take_completed_lines() {
+            for found in self.triggers.evaluate(&line) {
+                let _ = self.event_bus.event_sender().send(events::Event::TriggerMatched {
+                    name: found.name,
+                    captures: found.captures,
+                });
+            }
         }
         
         // Send state changed event
         let _ = self.event_bus.event_sender().send(events::Event::StateChanged);
         
-        Ok(())
+        Ok(parsed)
+    }
+    
+    /// Pause reading while the session is over its rate limits
+    ///
+    /// Only PTY reads are held back; input and state commands are still
+    /// handled during the pause.
+    fn enforce_limits(&mut self, bytes: usize, parsed: usize) {
+        let checks = [
+            (LimitKind::OutputBytes, self.output_limiter.as_mut(), bytes),
+            (LimitKind::ParsedEvents, self.event_limiter.as_mut(), parsed),
+        ];
+        let mut pause = None;
+        for (kind, limiter, amount) in checks {
+            let Some(limiter) = limiter else { continue };
+            if let Some(paused) = limiter.record(amount as u64) {
+                info!("Session over {:?} limit, pausing for {:?}", kind, paused);
+                let _ = self.event_bus.event_sender().send(events::Event::LimitExceeded {
+                    kind,
+                    limit: limiter.limit(),
+                    paused,
+                });
+                pause = pause.max(Some(paused));
+            }
+        }
+        if let Some(paused) = pause {
+            let until = tokio::time::Instant::now() + paused;
+            self.throttled_until = self.throttled_until.max(Some(until));
+        }
+    }
+    
+    async fn apply_state_command(&mut self, cmd: events::Command) {
+        match cmd {
+            events::Command::Key(key) => {
+                let bytes = input::encode_key(key, self.state.input_modes());
+                debug!("Key {:?} encoded as {} bytes", key, bytes.len());
+                if !bytes.is_empty() {
+                    if let Err(e) = self.event_bus.send_command(events::Command::Write(bytes)).await {
+                        error!("Failed to send key input: {}", e);
+                    }
+                }
+            }
+            events::Command::Mouse(mouse) => {
+                let bytes = input::encode_mouse(mouse, self.state.input_modes());
+                if !bytes.is_empty() {
+                    if let Err(e) = self.event_bus.send_command(events::Command::Write(bytes)).await {
+                        error!("Failed to send mouse input: {}", e);
+                    }
+                }
+            }
+            events::Command::Paste(text) => {
+                let bytes = input::encode_paste(&text, self.state.input_modes());
+                debug!("Paste of {} bytes", bytes.len());
+                if let Err(e) = self.event_bus.send_command(events::Command::Write(bytes)).await {
+                    error!("Failed to send paste: {}", e);
+                }
+            }
+            events::Command::Focus(focused) => {
+                debug!("Focus changed: {}", focused);
+                self.state.set_focused(focused);
+            }
+            events::Command::SetCellMetrics(metrics) => {
+                debug!("Cell metrics changed: {:?}", metrics);
+                self.state.set_cell_metrics(metrics);
+            }
+            events::Command::Resize { size, pixels: Some(pixels) } => {
+                if let Some(metrics) = CellMetrics::from_text_area(pixels, size) {
+                    debug!("Cell metrics from resize: {:?}", metrics);
+                    self.state.set_cell_metrics(metrics);
+                }
+            }
+            events::Command::ClipboardReply { clipboard, text } => {
+                debug!("Clipboard reply for {:?}: {} bytes", clipboard, text.len());
+                self.state.reply_clipboard(clipboard, &text);
+            }
+            events::Command::RequestGridSnapshot => {
+                let snapshot = Arc::new(self.state.grid_snapshot());
+                let _ = self.event_bus.send_event(events::Event::GridSnapshot(snapshot));
+            }
+            _ => {}
+        }
+        self.flush_responses().await;
+    }
+    
+    /// Write replies queued by the terminal state back to the application
+    async fn flush_responses(&mut self) {
+        for response in self.state.take_responses() {
+            if let Err(e) = self.event_bus.send_command(events::Command::Reply(response)).await {
+                error!("Failed to send terminal response: {}", e);
+            }
+        }
+    }
+    
+    /// Run the bell command hook without waiting for it to finish
+    ///
+    /// Only one run is kept in flight: bells rung while the previous command
+    /// is still running are dropped, so a flood of BELs cannot fork a
+    /// process each.
+    fn run_bell_command(running: &mut Option<tokio::process::Child>, argv: &[String]) {
+        if let Some(child) = running {
+            if matches!(child.try_wait(), Ok(None)) {
+                debug!("Bell command still running, skipping");
+                return;
+            }
+        }
+        let Some((program, args)) = argv.split_first() else {
+            return;
+        };
+        let spawned = tokio::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => *running = Some(child),
+            Err(e) => {
+                *running = None;
+                error!("Failed to run bell command {:?}: {}", program, e);
+            }
+        }
     }
     
     /// Get the current terminal state
@@ -189,4 +530,206 @@ impl Terminal {
     pub fn size(&self) -> Size {
         self.size
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    
+    /// Takes at most `accept` bytes per write and keeps them
+    struct ShortWriter {
+        accept: usize,
+        writes: Vec<usize>,
+        received: Vec<u8>,
+    }
+    
+    #[async_trait]
+    impl TerminalBackend for ShortWriter {
+        async fn write(&mut self, data: &[u8]) -> Result<usize> {
+            let n = data.len().min(self.accept);
+            self.writes.push(data.len());
+            self.received.extend_from_slice(&data[..n]);
+            Ok(n)
+        }
+        
+        async fn read(&mut self, _buf: &mut [u8]) -> Result<usize> {
+            Ok(0)
+        }
+        
+        async fn resize(&mut self, _size: Size) -> Result<()> {
+            Ok(())
+        }
+        
+        async fn is_alive(&self) -> bool {
+            true
+        }
+    }
+    
+    /// Keeps everything written, shared with the test
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<std::sync::Mutex<Vec<u8>>>);
+    
+    #[async_trait]
+    impl TerminalBackend for SharedWriter {
+        async fn write(&mut self, data: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+        
+        async fn read(&mut self, _buf: &mut [u8]) -> Result<usize> {
+            Ok(0)
+        }
+        
+        async fn resize(&mut self, _size: Size) -> Result<()> {
+            Ok(())
+        }
+        
+        async fn is_alive(&self) -> bool {
+            true
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_large_paste_arrives_complete() {
+        let text: String = (0..20_000).map(|i| format!("{} ", i)).collect();
+        let paste = input::encode_paste(&text, InputModes { bracketed_paste: true, ..Default::default() });
+        
+        let mut writer = ShortWriter { accept: 700, writes: Vec::new(), received: Vec::new() };
+        write_chunked(&mut writer, &paste).await.unwrap();
+        assert_eq!(writer.received, paste);
+        assert!(writer.writes.len() > paste.len() / WRITE_CHUNK_SIZE);
+        assert!(writer.writes.iter().all(|&len| len <= WRITE_CHUNK_SIZE));
+        
+        let mut stuck = ShortWriter { accept: 0, ..writer };
+        assert!(write_chunked(&mut stuck, b"x").await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_replies_reach_the_pty_but_not_the_journal() {
+        use events::Command;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.jsonl");
+        let journal = InputJournal::create(&path).unwrap();
+        let pty = SharedWriter::default();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(8);
+        let (state_cmd_tx, _state_cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+        
+        // The application asks for device attributes and the cursor position
+        let mut state = TerminalState::new(Size::new(80, 24));
+        let mut parser = VteParser::new();
+        parser.parse_with(b"\x1b[c\x1b[6n", |event| ansi::AnsiProcessor::process_event(&mut state, event));
+        let replies = state.take_responses();
+        assert_eq!(replies.len(), 2);
+        
+        command_tx.send(Command::Write(b"ls\r".to_vec())).await.unwrap();
+        for reply in &replies {
+            command_tx.send(Command::Reply(reply.clone())).await.unwrap();
+        }
+        drop(command_tx);
+        process_commands(command_rx, pty.clone(), Some(journal), Arc::new(WakeupMetrics::new()), ByteTaps::new(), state_cmd_tx).await;
+        
+        let written = pty.0.lock().unwrap().clone();
+        assert_eq!(written, [b"ls\r".to_vec(), replies.concat()].concat());
+        let journaled = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(journaled.lines().count(), 1);
+        assert!(journaled.contains(r#""ls\r""#));
+    }
+    
+    #[tokio::test]
+    async fn test_resize_pixels_reach_pty_and_cell_metrics() {
+        use events::Command;
+        use phosphor_common::types::PixelSize;
+        
+        let mut terminal = Terminal::new(Size::new(80, 24)).unwrap();
+        let (command_tx, command_rx) = tokio::sync::mpsc::channel(8);
+        let (state_cmd_tx, mut state_cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+        
+        let size = Size::new(100, 30);
+        command_tx.send(Command::Resize { size, pixels: Some(PixelSize::new(900, 540)) }).await.unwrap();
+        drop(command_tx);
+        process_commands(command_rx, terminal.pty.clone(), None, terminal.wakeup_metrics(), ByteTaps::new(), state_cmd_tx).await;
+        
+        assert_eq!(terminal.pty.window_size().await.unwrap(), (size, PixelSize::new(900, 540)));
+        while let Ok(cmd) = state_cmd_rx.try_recv() {
+            terminal.apply_state_command(cmd).await;
+        }
+        assert_eq!(terminal.state().cell_metrics(), Some(CellMetrics::new(9, 18)));
+    }
+    
+    #[tokio::test]
+    async fn test_bell_command_runs_one_at_a_time() {
+        let argv = vec!["sleep".to_string(), "5".to_string()];
+        let mut running = None;
+        Terminal::run_bell_command(&mut running, &argv);
+        let first = running.as_ref().and_then(|child| child.id()).expect("bell command spawned");
+        
+        // A second bell while the first command runs spawns nothing
+        Terminal::run_bell_command(&mut running, &argv);
+        assert_eq!(running.as_ref().and_then(|child| child.id()), Some(first));
+        
+        // Once it exits the next bell runs the command again
+        let mut child = running.take().unwrap();
+        child.kill().await.unwrap();
+        running = Some(child);
+        Terminal::run_bell_command(&mut running, &argv);
+        let second = running.as_mut().and_then(|child| child.id()).expect("bell command respawned");
+        assert_ne!(second, first);
+        running.unwrap().kill().await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_rate_limit_pauses_reads_only() {
+        let config = TerminalConfig {
+            limits: terminal::SessionLimits { max_output_bytes_per_sec: Some(1000), ..Default::default() },
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(Size::new(80, 24), SpawnOptions::default(), config).unwrap();
+        let mut events = terminal.event_bus.event_receiver();
+        
+        // A whole read over the budget is paid for before reading again
+        let start = tokio::time::Instant::now();
+        terminal.enforce_limits(4096, 0);
+        let until = terminal.throttled_until.expect("reads paused");
+        assert!(until - start >= std::time::Duration::from_millis(3096));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(events::Event::LimitExceeded { kind: LimitKind::OutputBytes, limit: 1000, .. })
+        ));
+        
+        // State commands are still applied while reads are paused
+        terminal.apply_state_command(events::Command::Focus(false)).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert!(!terminal.state().is_focused());
+    }
+    
+    #[tokio::test]
+    async fn test_printer_controller_passes_raw_bytes() {
+        use std::sync::Mutex;
+        
+        let mut terminal = Terminal::new(Size::new(10, 3)).unwrap();
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let sink = printed.clone();
+        terminal.set_printer(Box::new(CallbackPrinter::new(move |data: &[u8]| {
+            sink.lock().unwrap().extend_from_slice(data)
+        })));
+        let row = |terminal: &Terminal| -> String {
+            terminal.state().screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        // Sequences are printed as they came, up to MC 4 split across reads
+        terminal.process_output(b"ab\x1b[5ipage\x1b[1m\x1b[4").unwrap();
+        terminal.process_output(b"x\x1b]0;t\x07\x1b[").unwrap();
+        terminal.process_output(b"4icd").unwrap();
+        assert_eq!(std::mem::take(&mut *printed.lock().unwrap()), b"page\x1b[1m\x1b[4x\x1b]0;t\x07");
+        assert_eq!(row(&terminal), "abcd      ");
+        assert!(!terminal.state().printer_controller());
+        
+        // Bytes that only start like MC 4 are printer data
+        terminal.process_output(b"\x1b[5i\x1b[3\x1b[4ief").unwrap();
+        assert_eq!(std::mem::take(&mut *printed.lock().unwrap()), b"\x1b[3");
+        assert_eq!(row(&terminal), "abcdef    ");
+        assert!(terminal.state().snapshot().title.is_empty());
+    }
+}
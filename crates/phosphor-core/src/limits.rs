@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+/// Which per-session limit was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// Output bytes processed per second
+    OutputBytes,
+    /// Parsed events per second
+    ParsedEvents,
+}
+
+/// Token bucket enforcing a per-second budget
+///
+/// The bucket holds up to one second's budget and refills continuously.
+/// Work is always admitted, but work beyond what the bucket holds is carried
+/// as debt, so a single large read still has to be paid for in full.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: u64,
+    /// Units available, scaled by `NANOS_PER_SEC` so refills stay exact;
+    /// negative while in debt
+    tokens: i128,
+    refilled_at: Instant,
+}
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+impl RateLimiter {
+    pub fn new(limit: u64) -> Self {
+        Self::starting_at(limit, Instant::now())
+    }
+    
+    fn starting_at(limit: u64, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit as i128 * NANOS_PER_SEC,
+            refilled_at: now,
+        }
+    }
+    
+    /// The per-second budget
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+    
+    /// Units owed beyond the budget, as of the last `record`
+    pub fn debt(&self) -> u64 {
+        ((-self.tokens).max(0) as u128).div_ceil(NANOS_PER_SEC as u128) as u64
+    }
+    
+    /// Record `amount` units of work
+    ///
+    /// Returns how long to pause, until the debt is paid off at the budgeted
+    /// rate, if the bucket is overdrawn.
+    pub fn record(&mut self, amount: u64) -> Option<Duration> {
+        self.record_at(amount, Instant::now())
+    }
+    
+    fn record_at(&mut self, amount: u64, now: Instant) -> Option<Duration> {
+        let limit = self.limit as i128;
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_nanos() as i128;
+        self.refilled_at = now;
+        self.tokens = (self.tokens + elapsed * limit).min(limit * NANOS_PER_SEC);
+        self.tokens -= amount as i128 * NANOS_PER_SEC;
+        
+        if self.tokens < 0 {
+            let nanos = (-self.tokens as u128).div_ceil(self.limit.max(1) as u128);
+            Some(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_within_budget() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::starting_at(100, start);
+        assert_eq!(limiter.record_at(100, start), None);
+        assert_eq!(limiter.record_at(50, start + Duration::from_millis(500)), None);
+        assert_eq!(limiter.debt(), 0);
+    }
+    
+    #[test]
+    fn test_over_budget_waits_for_refill() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::starting_at(100, start);
+        assert_eq!(limiter.record_at(80, start), None);
+        assert_eq!(
+            limiter.record_at(30, start + Duration::from_millis(50)),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(limiter.debt(), 5);
+        
+        // Paying off the debt and waiting a full second refills the bucket
+        assert_eq!(limiter.record_at(90, start + Duration::from_millis(1100)), None);
+        assert_eq!(limiter.debt(), 0);
+    }
+    
+    #[test]
+    fn test_large_read_carries_debt() {
+        // A single 4096 byte read against a 1000 B/s budget pays for all of it
+        let start = Instant::now();
+        let mut limiter = RateLimiter::starting_at(1000, start);
+        let paused = limiter.record_at(4096, start).unwrap();
+        assert_eq!(paused, Duration::from_millis(3096));
+        assert_eq!(limiter.debt(), 3096);
+        
+        // Reading again before the pause is over deepens the debt
+        let later = start + Duration::from_millis(1000);
+        assert_eq!(limiter.record_at(4096, later), Some(Duration::from_millis(6192)));
+        
+        // Over any stretch the admitted total stays within the budget
+        let after_pause = later + Duration::from_millis(6192);
+        assert_eq!(limiter.record_at(0, after_pause), None);
+        assert_eq!(limiter.debt(), 0);
+    }
+}
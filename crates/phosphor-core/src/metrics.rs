@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Wakeups per second an idle terminal should stay under
+pub const IDLE_WAKEUP_TARGET: f64 = 5.0;
+
+/// Wakeup accounting for auditing idle CPU use
+///
+/// Counts run-loop wakeups, timer fires and PTY lock acquisitions while the
+/// audit is enabled. Counting is off by default; when off, each record call
+/// is a single relaxed atomic load.
+#[derive(Debug)]
+pub struct WakeupMetrics {
+    enabled: AtomicBool,
+    wakeups: AtomicU64,
+    timer_fires: AtomicU64,
+    lock_acquisitions: AtomicU64,
+    since: Mutex<Instant>,
+}
+
+/// Counters accumulated over a measurement window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WakeupStats {
+    pub wakeups: u64,
+    pub timer_fires: u64,
+    pub lock_acquisitions: u64,
+    pub elapsed: Duration,
+}
+
+impl WakeupMetrics {
+    /// Create a new, disabled set of counters
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            wakeups: AtomicU64::new(0),
+            timer_fires: AtomicU64::new(0),
+            lock_acquisitions: AtomicU64::new(0),
+            since: Mutex::new(Instant::now()),
+        }
+    }
+    
+    /// Turn the audit on or off; turning it on starts a fresh window
+    pub fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            self.reset();
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Whether the audit is counting
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+    
+    /// Zero the counters and start a new measurement window
+    pub fn reset(&self) {
+        self.wakeups.store(0, Ordering::Relaxed);
+        self.timer_fires.store(0, Ordering::Relaxed);
+        self.lock_acquisitions.store(0, Ordering::Relaxed);
+        *self.since.lock().unwrap() = Instant::now();
+    }
+    
+    /// Record a task waking up to do work
+    pub fn record_wakeup(&self) {
+        self.increment(&self.wakeups);
+    }
+    
+    /// Record a timer firing
+    pub fn record_timer_fire(&self) {
+        self.increment(&self.timer_fires);
+    }
+    
+    /// Record acquiring the PTY lock
+    pub fn record_lock(&self) {
+        self.increment(&self.lock_acquisitions);
+    }
+    
+    fn increment(&self, counter: &AtomicU64) {
+        if self.is_enabled() {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    
+    /// Read the counters for the current window
+    pub fn stats(&self) -> WakeupStats {
+        WakeupStats {
+            wakeups: self.wakeups.load(Ordering::Relaxed),
+            timer_fires: self.timer_fires.load(Ordering::Relaxed),
+            lock_acquisitions: self.lock_acquisitions.load(Ordering::Relaxed),
+            elapsed: self.since.lock().unwrap().elapsed(),
+        }
+    }
+}
+
+impl Default for WakeupMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakeupStats {
+    fn per_second(&self, count: u64) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { count as f64 / secs } else { 0.0 }
+    }
+    
+    pub fn wakeups_per_sec(&self) -> f64 {
+        self.per_second(self.wakeups)
+    }
+    
+    pub fn timer_fires_per_sec(&self) -> f64 {
+        self.per_second(self.timer_fires)
+    }
+    
+    pub fn lock_acquisitions_per_sec(&self) -> f64 {
+        self.per_second(self.lock_acquisitions)
+    }
+    
+    /// Whether an idle window stayed under `IDLE_WAKEUP_TARGET`
+    pub fn meets_idle_target(&self) -> bool {
+        self.wakeups_per_sec() < IDLE_WAKEUP_TARGET
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_counts_only_while_enabled() {
+        let metrics = WakeupMetrics::new();
+        metrics.record_wakeup();
+        assert_eq!(metrics.stats().wakeups, 0);
+        
+        metrics.set_enabled(true);
+        metrics.record_wakeup();
+        metrics.record_wakeup();
+        metrics.record_timer_fire();
+        metrics.record_lock();
+        let stats = metrics.stats();
+        assert_eq!((stats.wakeups, stats.timer_fires, stats.lock_acquisitions), (2, 1, 1));
+        
+        metrics.reset();
+        assert_eq!(metrics.stats().wakeups, 0);
+    }
+    
+    #[test]
+    fn test_rates() {
+        let stats = WakeupStats {
+            wakeups: 6,
+            timer_fires: 2,
+            lock_acquisitions: 4,
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(stats.wakeups_per_sec(), 3.0);
+        assert_eq!(stats.lock_acquisitions_per_sec(), 2.0);
+        assert!(stats.meets_idle_target());
+        
+        let busy = WakeupStats { wakeups: 100, ..stats };
+        assert!(!busy.meets_idle_target());
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use tracing::debug;
+
+/// Destination for what applications send to the printer (media copy)
+///
+/// Receives the text and controls written in printer controller mode, and
+/// the lines copied by autoprint, print line and print screen.
+pub trait PrinterSink: Send {
+    /// Write printer data
+    fn write(&mut self, data: &[u8]) -> io::Result<()>;
+    
+    /// Called when the application leaves printer controller mode
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends printer output to a file
+///
+/// The file is written on a background thread, so a slow disk never holds
+/// up the terminal; write errors are logged there. Dropping the printer
+/// waits for queued output to be written.
+pub struct FilePrinter {
+    jobs: Option<mpsc::Sender<PrintJob>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+enum PrintJob {
+    Write(Vec<u8>),
+    Flush,
+}
+
+impl FilePrinter {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (jobs, queue) = mpsc::channel();
+        let writer = std::thread::Builder::new().name("phosphor-printer".to_string()).spawn(move || {
+            for job in queue {
+                let result = match job {
+                    PrintJob::Write(data) => file.write_all(&data),
+                    PrintJob::Flush => file.flush(),
+                };
+                if let Err(e) = result {
+                    debug!("Printer file write failed: {}", e);
+                }
+            }
+        })?;
+        Ok(Self { jobs: Some(jobs), writer: Some(writer) })
+    }
+    
+    fn queue(&self, job: PrintJob) -> io::Result<()> {
+        let sent = self.jobs.as_ref().map(|jobs| jobs.send(job));
+        match sent {
+            Some(Ok(())) => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::BrokenPipe, "printer writer thread exited")),
+        }
+    }
+}
+
+impl PrinterSink for FilePrinter {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.queue(PrintJob::Write(data.to_vec()))
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.queue(PrintJob::Flush)
+    }
+}
+
+impl Drop for FilePrinter {
+    fn drop(&mut self) {
+        // Closing the queue ends the writer once it has caught up
+        self.jobs.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Hands printer output to a closure
+pub struct CallbackPrinter<F> {
+    callback: F,
+}
+
+impl<F: FnMut(&[u8]) + Send> CallbackPrinter<F> {
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F: FnMut(&[u8]) + Send> PrinterSink for CallbackPrinter<F> {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        (self.callback)(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_file_printer_appends() {
+        let path = std::env::temp_dir().join(format!("phosphor-printer-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        
+        let mut printer = FilePrinter::open(&path).unwrap();
+        printer.write(b"one\n").unwrap();
+        printer.flush().unwrap();
+        drop(printer);
+        let mut printer = FilePrinter::open(&path).unwrap();
+        printer.write(b"two\n").unwrap();
+        printer.flush().unwrap();
+        drop(printer);
+        
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\ntwo\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Write-ahead journal of input sent to the PTY
+///
+/// Each write is recorded before it reaches the child as an asciinema v2
+/// input event, `[elapsed, "i", data]`, one per line. The elapsed time is in
+/// seconds since the journal was created. Bytes that are not valid UTF-8 are
+/// stored with replacement characters, as the asciinema format requires strings.
+pub struct InputJournal {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl InputJournal {
+    /// Create a journal that writes to the given sink
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            start: Instant::now(),
+        }
+    }
+    
+    /// Create a journal appending to the file at `path`
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+    
+    /// Record input about to be written to the PTY
+    ///
+    /// The entry is flushed before returning so it survives a crash during the write.
+    pub fn record(&mut self, data: &[u8]) -> io::Result<()> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let text = serde_json::to_string(&String::from_utf8_lossy(data))?;
+        writeln!(self.writer, "[{:.6}, \"i\", {}]", elapsed, text)?;
+        self.writer.flush()
+    }
+}
+
+impl std::fmt::Debug for InputJournal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InputJournal").field("start", &self.start).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_records_asciinema_input_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.jsonl");
+        
+        let mut journal = InputJournal::create(&path).unwrap();
+        journal.record(b"ls -l\r").unwrap();
+        journal.record(b"\x1b[A\"quoted\"").unwrap();
+        
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert!(event[0].as_f64().unwrap() >= 0.0);
+        assert_eq!(event[1], "i");
+        assert_eq!(event[2], "ls -l\r");
+        
+        let event: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(event[2], "\u{1b}[A\"quoted\"");
+    }
+}
@@ -0,0 +1,139 @@
+/// Locale propagated to child processes
+///
+/// Children get an explicit LANG (and any LC_* categories) instead of
+/// inheriting whatever the embedding process happened to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// Value exported as LANG
+    pub lang: String,
+    /// Explicit LC_* category values, e.g. `("LC_TIME", "de_DE.UTF-8")`
+    pub categories: Vec<(String, String)>,
+}
+
+/// Locale used when the host has none, or only the non-UTF-8 C/POSIX locale
+const FALLBACK_LANG: &str = "C.UTF-8";
+
+pub(crate) const LC_CATEGORIES: &[&str] = &[
+    "LC_ALL",
+    "LC_CTYPE",
+    "LC_COLLATE",
+    "LC_MESSAGES",
+    "LC_MONETARY",
+    "LC_NUMERIC",
+    "LC_TIME",
+];
+
+impl Locale {
+    /// Create a locale with the given LANG and no category overrides
+    pub fn new(lang: impl Into<String>) -> Self {
+        Self {
+            lang: lang.into(),
+            categories: Vec::new(),
+        }
+    }
+    
+    /// Add an LC_* category override
+    pub fn with_category(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.categories.push((name.into(), value.into()));
+        self
+    }
+    
+    /// Detect the host locale from the environment
+    pub fn detect() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+    
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |name: &str| lookup(name).filter(|v| !v.is_empty());
+        
+        let lang = get("LANG")
+            .or_else(|| get("LC_ALL"))
+            .or_else(|| get("LC_CTYPE"))
+            .filter(|v| v != "C" && v != "POSIX")
+            .unwrap_or_else(|| FALLBACK_LANG.to_string());
+        
+        let categories = LC_CATEGORIES
+            .iter()
+            .filter_map(|&name| get(name).map(|value| (name.to_string(), value)))
+            .filter(|(_, value)| value != "C" && value != "POSIX")
+            .collect();
+        
+        Self { lang, categories }
+    }
+    
+    /// The locale that governs character classification (LC_ALL > LC_CTYPE > LANG)
+    pub fn ctype(&self) -> &str {
+        ["LC_ALL", "LC_CTYPE"]
+            .iter()
+            .find_map(|name| {
+                self.categories
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, v)| v.as_str())
+            })
+            .unwrap_or(&self.lang)
+    }
+    
+    /// Whether the character encoding is UTF-8
+    pub fn is_utf8(&self) -> bool {
+        let ctype = self.ctype().to_ascii_lowercase();
+        ctype.contains("utf-8") || ctype.contains("utf8")
+    }
+    
+    /// Whether East Asian ambiguous-width characters should be treated as wide
+    ///
+    /// CJK locales conventionally render them double width.
+    pub fn prefers_wide_ambiguous(&self) -> bool {
+        let ctype = self.ctype();
+        ["ja", "ko", "zh"].iter().any(|prefix| ctype.starts_with(prefix))
+    }
+    
+    /// Environment variables to export to the child
+    pub fn env_vars(&self) -> impl Iterator<Item = (&str, &str)> {
+        std::iter::once(("LANG", self.lang.as_str()))
+            .chain(self.categories.iter().map(|(n, v)| (n.as_str(), v.as_str())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+    
+    #[test]
+    fn test_detect_from_lang() {
+        let locale = Locale::from_lookup(lookup(&[("LANG", "de_DE.UTF-8"), ("LC_TIME", "en_GB.UTF-8")]));
+        assert_eq!(locale.lang, "de_DE.UTF-8");
+        assert_eq!(locale.categories, vec![("LC_TIME".to_string(), "en_GB.UTF-8".to_string())]);
+        assert!(locale.is_utf8());
+    }
+    
+    #[test]
+    fn test_fallback_for_c_locale() {
+        let locale = Locale::from_lookup(lookup(&[("LANG", "C")]));
+        assert_eq!(locale.lang, FALLBACK_LANG);
+        assert!(locale.is_utf8());
+        
+        let locale = Locale::from_lookup(lookup(&[]));
+        assert_eq!(locale.lang, FALLBACK_LANG);
+    }
+    
+    #[test]
+    fn test_ctype_precedence() {
+        let locale = Locale::new("en_US.UTF-8").with_category("LC_CTYPE", "ja_JP.eucJP");
+        assert_eq!(locale.ctype(), "ja_JP.eucJP");
+        assert!(!locale.is_utf8());
+        assert!(locale.prefers_wide_ambiguous());
+        
+        let env: Vec<_> = locale.env_vars().collect();
+        assert_eq!(env, vec![("LANG", "en_US.UTF-8"), ("LC_CTYPE", "ja_JP.eucJP")]);
+    }
+}
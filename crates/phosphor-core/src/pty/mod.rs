@@ -1,9 +1,13 @@
 use async_trait::async_trait;
-use phosphor_common::{error::{PhosphorError, Result}, traits::TerminalBackend, types::Size};
+use crate::metrics::WakeupMetrics;
+use phosphor_common::{error::{PhosphorError, Result}, traits::TerminalBackend, types::{PixelSize, Size}};
 use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, error, info, instrument};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{debug, error, info, instrument, warn};
+
+mod journal;
+mod locale;
 
 #[cfg(unix)]
 mod unix;
@@ -18,10 +22,21 @@ use unix::AsyncPtyIo;
 #[cfg(windows)]
 use windows::AsyncPtyIo;
 
+pub use journal::InputJournal;
+pub use locale::Locale;
+
+/// Options for spawning the child shell
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// Locale exported to the child; detected from the host when `None`
+    pub locale: Option<Locale>,
+}
+
 /// PTY manager that handles process spawning and I/O
 #[derive(Clone)]
 pub struct PtyManager {
     inner: Arc<Mutex<PtyManagerInner>>,
+    metrics: Arc<WakeupMetrics>,
 }
 
 struct PtyManagerInner {
@@ -33,10 +48,21 @@ struct PtyManagerInner {
 
 impl PtyManager {
     /// Spawn a shell process with the given terminal size
-    #[instrument]
     pub fn spawn_shell(size: Size) -> Result<Self> {
+        Self::spawn_shell_with_options(size, &SpawnOptions::default())
+    }
+    
+    /// Spawn a shell process with the given terminal size and spawn options
+    #[instrument]
+    pub fn spawn_shell_with_options(size: Size, options: &SpawnOptions) -> Result<Self> {
         info!("Starting PTY spawn_shell with size: {:?}", size);
         
+        let locale = options.locale.clone().unwrap_or_else(Locale::detect);
+        info!("Using locale: {:?}", locale);
+        if !locale.is_utf8() {
+            warn!("Locale {} is not UTF-8; the child's output is still decoded as UTF-8", locale.ctype());
+        }
+        
         let pty_system = native_pty_system();
         let pty_size = PtySize {
             rows: size.rows,
@@ -66,15 +92,19 @@ impl PtyManager {
         
         // Check if we should use minimal environment
         let use_minimal_env = std::env::var("PHOSPHOR_MINIMAL_ENV").is_ok();
+        let caps = crate::capabilities();
         
         let mut cmd = if use_minimal_env {
             info!("Using minimal environment with env -i");
             let mut env_cmd = CommandBuilder::new("env");
             env_cmd.arg("-i");
             env_cmd.arg(format!("PATH={}", std::env::var("PATH").unwrap_or_else(|_| "/usr/local/bin:/usr/bin:/bin".to_string())));
-            env_cmd.arg("TERM=xterm-256color");
+            env_cmd.arg(format!("TERM={}", caps.term));
             env_cmd.arg("HOME=/tmp");
             env_cmd.arg("USER=user");
+            for (name, value) in locale.env_vars() {
+                env_cmd.arg(format!("{}={}", name, value));
+            }
             env_cmd.arg(&shell);
             env_cmd
         } else {
@@ -100,13 +130,23 @@ impl PtyManager {
         
         // Set up environment for interactive shell (unless using minimal env)
         if !use_minimal_env {
-            cmd.env("TERM", "xterm-256color");
-            cmd.env("COLORTERM", "truecolor");
+            cmd.env("TERM", caps.term);
+            if caps.truecolor {
+                cmd.env("COLORTERM", "truecolor");
+            }
             cmd.env("PS1", "\\u@\\h:\\w\\$ ");  // Set a proper prompt
             cmd.env("SHELL", &shell);  // Ensure SHELL is set
             cmd.env("USER", std::env::var("USER").unwrap_or_else(|_| "user".to_string()));
             cmd.env("HOME", std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string()));
             cmd.env("PATH", std::env::var("PATH").unwrap_or_else(|_| "/usr/local/bin:/usr/bin:/bin".to_string()));
+            
+            // Export the locale explicitly instead of inheriting the host's LC_* values
+            for name in locale::LC_CATEGORIES {
+                cmd.env_remove(name);
+            }
+            for (name, value) in locale.env_vars() {
+                cmd.env(name, value);
+            }
         }
         
         // Set current directory
@@ -150,7 +190,7 @@ impl PtyManager {
             
         // Create async I/O wrapper
         debug!("Creating async I/O wrapper");
-        let io = AsyncPtyIo::new(&pair.master)?;
+        let io = AsyncPtyIo::new(pair.master.as_ref())?;
         info!("Async I/O wrapper created");
         
         let inner = PtyManagerInner {
@@ -162,8 +202,27 @@ impl PtyManager {
         info!("PtyManager initialized successfully");
         Ok(Self {
             inner: Arc::new(Mutex::new(inner)),
+            metrics: Arc::new(WakeupMetrics::new()),
         })
     }
+    
+    /// Count lock acquisitions in the given wakeup metrics
+    pub fn set_wakeup_metrics(&mut self, metrics: Arc<WakeupMetrics>) {
+        self.metrics = metrics;
+    }
+    
+    /// The window size the child sees via TIOCGWINSZ, in cells and pixels
+    pub async fn window_size(&self) -> Result<(Size, PixelSize)> {
+        let inner = self.lock_inner().await;
+        let size = inner.master.get_size()
+            .map_err(|e| PhosphorError::Pty(format!("Failed to get PTY size: {}", e)))?;
+        Ok((Size::new(size.cols, size.rows), PixelSize::new(size.pixel_width, size.pixel_height)))
+    }
+    
+    async fn lock_inner(&self) -> MutexGuard<'_, PtyManagerInner> {
+        self.metrics.record_lock();
+        self.inner.lock().await
+    }
 }
 
 #[async_trait]
@@ -171,7 +230,7 @@ impl TerminalBackend for PtyManager {
     #[instrument(skip(self, data))]
     async fn write(&mut self, data: &[u8]) -> Result<usize> {
         debug!("PTY write called with {} bytes", data.len());
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.lock_inner().await;
         match inner.io.write(data).await {
             Ok(n) => {
                 debug!("PTY write successful: {} bytes written", n);
@@ -187,7 +246,7 @@ impl TerminalBackend for PtyManager {
     #[instrument(skip(self, buf))]
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         debug!("PTY read called with buffer size: {}", buf.len());
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.lock_inner().await;
         match inner.io.read(buf).await {
             Ok(0) => {
                 info!("PTY read returned 0 bytes (EOF)");
@@ -207,25 +266,34 @@ impl TerminalBackend for PtyManager {
         }
     }
     
-    #[instrument(skip(self))]
     async fn resize(&mut self, size: Size) -> Result<()> {
-        let inner = self.inner.lock().await;
+        // Pixel size unknown
+        self.resize_with_pixels(size, PixelSize::default()).await
+    }
+    
+    /// Resize the PTY, also reporting the text area size in pixels
+    ///
+    /// The pixel size is what the child sees in `ws_xpixel`/`ws_ypixel` via
+    /// TIOCGWINSZ; graphics-aware programs use it to size images.
+    #[instrument(skip(self))]
+    async fn resize_with_pixels(&mut self, size: Size, pixels: PixelSize) -> Result<()> {
+        let inner = self.lock_inner().await;
         let pty_size = PtySize {
             rows: size.rows,
             cols: size.cols,
-            pixel_width: 0,
-            pixel_height: 0,
+            pixel_width: pixels.width,
+            pixel_height: pixels.height,
         };
         
         inner.master.resize(pty_size)
             .map_err(|e| PhosphorError::Pty(format!("Failed to resize PTY: {}", e)))?;
             
-        debug!("PTY resized to {:?}", size);
+        debug!("PTY resized to {:?} ({:?})", size, pixels);
         Ok(())
     }
     
     async fn is_alive(&self) -> bool {
-        let mut inner = self.inner.lock().await;
+        let mut inner = self.lock_inner().await;
         match inner.child.try_wait() {
             Ok(None) => {
                 debug!("PTY process is still running");
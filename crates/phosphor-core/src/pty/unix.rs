@@ -11,7 +11,7 @@ pub struct AsyncPtyIo {
 }
 
 impl AsyncPtyIo {
-    pub fn new(master: &Box<dyn MasterPty + Send>) -> Result<Self> {
+    pub fn new(master: &dyn MasterPty) -> Result<Self> {
         info!("Creating AsyncPtyIo wrapper");
         
         // Get reader and writer from the master PTY
@@ -5,7 +5,7 @@ use portable_pty::MasterPty;
 pub struct AsyncPtyIo;
 
 impl AsyncPtyIo {
-    pub fn new(_master: &Box<dyn MasterPty + Send>) -> Result<Self> {
+    pub fn new(_master: &dyn MasterPty) -> Result<Self> {
         Err(PhosphorError::Platform(
             "Windows PTY support not yet implemented".to_string()
         ))
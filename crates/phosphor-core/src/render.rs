@@ -0,0 +1,312 @@
+//! Draw a `GridSnapshot` as an image, for headless screenshot tests and CI
+//! artifacts showing what the terminal displayed
+//!
+//! SVG leaves glyphs to the viewer's monospace font, so needs no
+//! rasterizer. Every character is placed at its cell's column, so the grid
+//! lines up whatever the font's advance width. With the `png` feature the
+//! same SVG is rasterized with resvg, using the system's fonts.
+
+use phosphor_common::color::default_rgb;
+#[cfg(feature = "png")]
+use phosphor_common::error::{PhosphorError, Result};
+use phosphor_common::types::{AttributeFlags, Color, CursorStyle, GridCell, GridSnapshot, Rgb, TerminalMode};
+
+/// Cell geometry and font for `render_svg`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub cell_width: f32,
+    pub cell_height: f32,
+    /// CSS font family list for the text
+    pub font_family: String,
+    pub font_size: f32,
+    /// Draw the cursor when the application has it visible
+    pub draw_cursor: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            cell_width: 9.0,
+            cell_height: 18.0,
+            font_family: "monospace".to_string(),
+            font_size: 15.0,
+            draw_cursor: true,
+        }
+    }
+}
+
+/// How one cell is drawn, after reverse video, hidden text and the cursor
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CellPaint {
+    fg: Rgb,
+    bg: Rgb,
+    flags: AttributeFlags,
+}
+
+/// Render the rows of a snapshot as a standalone SVG document
+///
+/// Lines with a double-width rendition are stretched horizontally; the
+/// halves of double-height lines are drawn the same way.
+pub fn render_svg(snapshot: &GridSnapshot, options: &RenderOptions) -> String {
+    let terminal = &snapshot.terminal;
+    let cols = snapshot.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let (width, height) = (cols as f32 * options.cell_width, snapshot.rows.len() as f32 * options.cell_height);
+    let colors = terminal.display_colors();
+    let (default_fg, default_bg) = (resolve(snapshot, colors.foreground), resolve(snapshot, colors.background));
+    
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\
+         <rect width=\"{width}\" height=\"{height}\" fill=\"{}\"/>",
+        css_color(default_bg)
+    );
+    let cursor = visible_cursor(snapshot, options);
+    for (row, cells) in snapshot.rows.iter().enumerate() {
+        let top = row as f32 * options.cell_height;
+        let paints: Vec<_> = cells.iter().enumerate()
+            .map(|(col, cell)| {
+                let block = cursor.is_some_and(|(cursor_row, cursor_col, style)| {
+                    (cursor_row, cursor_col) == (row, col) && matches!(style, CursorStyle::Block | CursorStyle::BlinkingBlock)
+                });
+                paint(snapshot, cell, default_fg, default_bg, block)
+            })
+            .collect();
+        
+        let double = terminal.line_renditions.get(row).is_some_and(|rendition| rendition.is_double_width());
+        if double {
+            svg.push_str("<g transform=\"scale(2 1)\">");
+        }
+        let mut col = 0;
+        while col < cells.len() {
+            let bg = paints[col].bg;
+            let run = paints[col..].iter().take_while(|paint| paint.bg == bg).count();
+            if bg != default_bg {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{top}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    col as f32 * options.cell_width,
+                    run as f32 * options.cell_width,
+                    options.cell_height,
+                    css_color(bg)
+                ));
+            }
+            col += run;
+        }
+        
+        let baseline = top + options.cell_height * 0.8;
+        let mut col = 0;
+        while col < cells.len() {
+            let text_paint = |paint: &CellPaint| (paint.fg, paint.flags);
+            let key = text_paint(&paints[col]);
+            let run = paints[col..].iter().take_while(|paint| text_paint(paint) == key).count();
+            let glyphs: Vec<_> = (col..col + run)
+                .filter(|&i| !cells[i].ch.is_whitespace() && cells[i].width != 0)
+                .collect();
+            if !glyphs.is_empty() {
+                let xs: Vec<_> = glyphs.iter().map(|&i| (i as f32 * options.cell_width).to_string()).collect();
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{baseline}\" font-family=\"{}\" font-size=\"{}\" fill=\"{}\"{}>",
+                    xs.join(" "),
+                    escape(&options.font_family),
+                    options.font_size,
+                    css_color(key.0),
+                    text_style(key.1)
+                ));
+                for &i in &glyphs {
+                    svg.push_str(&escape(cells[i].ch.encode_utf8(&mut [0; 4])));
+                }
+                svg.push_str("</text>");
+            }
+            col += run;
+        }
+        
+        if let Some((_, cursor_col, style)) = cursor.filter(|(cursor_row, _, _)| *cursor_row == row) {
+            let x = cursor_col as f32 * options.cell_width;
+            let (y, width, height) = match style {
+                CursorStyle::Underline | CursorStyle::BlinkingUnderline => {
+                    (top + options.cell_height - 2.0, options.cell_width, 2.0)
+                }
+                CursorStyle::Bar | CursorStyle::BlinkingBar => (top, 2.0, options.cell_height),
+                CursorStyle::Block | CursorStyle::BlinkingBlock => (top, 0.0, 0.0),
+            };
+            if width > 0.0 {
+                let color = resolve(snapshot, terminal.dynamic_colors.cursor);
+                svg.push_str(&format!(
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{width}\" height=\"{height}\" fill=\"{}\"/>",
+                    css_color(color)
+                ));
+            }
+        }
+        if double {
+            svg.push_str("</g>");
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Render the rows of a snapshot as a PNG image
+///
+/// Draws the document `render_svg` produces. System fonts are loaded on
+/// first use and kept for the rest of the process; text is left out if no
+/// font matches `options.font_family`.
+#[cfg(feature = "png")]
+pub fn render_png(snapshot: &GridSnapshot, options: &RenderOptions) -> Result<Vec<u8>> {
+    use resvg::{tiny_skia, usvg};
+    use std::sync::{Arc, OnceLock};
+    
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    let fontdb = FONTS.get_or_init(|| {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        Arc::new(fonts)
+    });
+    let usvg_options = usvg::Options { fontdb: fontdb.clone(), ..usvg::Options::default() };
+    
+    let svg = render_svg(snapshot, options);
+    let tree = usvg::Tree::from_str(&svg, &usvg_options)
+        .map_err(|err| PhosphorError::State(format!("Can't render snapshot: {}", err)))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| PhosphorError::State("Can't render an empty snapshot".to_string()))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|err| PhosphorError::State(format!("Can't encode snapshot: {}", err)))
+}
+
+/// The cursor's row and column in view with its style, if it is drawn
+fn visible_cursor(snapshot: &GridSnapshot, options: &RenderOptions) -> Option<(usize, usize, CursorStyle)> {
+    let terminal = &snapshot.terminal;
+    if !options.draw_cursor || !terminal.mode.contains(TerminalMode::CURSOR_VISIBLE) {
+        return None;
+    }
+    let row = terminal.cursor.row as usize + snapshot.display_offset;
+    (row < snapshot.rows.len()).then_some((row, terminal.cursor.col as usize, terminal.cursor_style))
+}
+
+fn paint(snapshot: &GridSnapshot, cell: &GridCell, default_fg: Rgb, default_bg: Rgb, block_cursor: bool) -> CellPaint {
+    let attrs = &cell.attrs;
+    let mut fg = match attrs.fg_color {
+        Color::Default => default_fg,
+        color => resolve(snapshot, color),
+    };
+    let mut bg = match attrs.bg_color {
+        Color::Default => default_bg,
+        color => resolve(snapshot, color),
+    };
+    if attrs.flags.contains(AttributeFlags::REVERSE) {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.flags.contains(AttributeFlags::HIDDEN) {
+        fg = bg;
+    }
+    if block_cursor {
+        fg = bg;
+        bg = resolve(snapshot, snapshot.terminal.dynamic_colors.cursor);
+    }
+    CellPaint { fg, bg, flags: attrs.flags }
+}
+
+/// Resolve a color against the snapshot's palette
+fn resolve(snapshot: &GridSnapshot, color: Color) -> Rgb {
+    match color {
+        Color::Rgb(r, g, b) => Rgb::new(r, g, b),
+        color => color.palette_index().map_or(Rgb::default(), |index| {
+            snapshot.terminal.palette.get(index as usize).copied().unwrap_or_else(|| default_rgb(index))
+        }),
+    }
+}
+
+/// Presentation attributes for bold, dim, italic and text decorations
+fn text_style(flags: AttributeFlags) -> String {
+    let mut style = String::new();
+    if flags.contains(AttributeFlags::BOLD) {
+        style.push_str(" font-weight=\"bold\"");
+    }
+    if flags.contains(AttributeFlags::DIM) {
+        style.push_str(" opacity=\"0.5\"");
+    }
+    if flags.contains(AttributeFlags::ITALIC) {
+        style.push_str(" font-style=\"italic\"");
+    }
+    let underline = AttributeFlags::UNDERLINE
+        | AttributeFlags::DOUBLE_UNDERLINE
+        | AttributeFlags::CURLY_UNDERLINE
+        | AttributeFlags::DOTTED_UNDERLINE
+        | AttributeFlags::DASHED_UNDERLINE;
+    let decorations: Vec<_> = [
+        (underline, "underline"),
+        (AttributeFlags::STRIKETHROUGH, "line-through"),
+        (AttributeFlags::OVERLINE, "overline"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags.intersects(*flag))
+    .map(|(_, name)| name)
+    .collect();
+    if !decorations.is_empty() {
+        style.push_str(&format!(" text-decoration=\"{}\"", decorations.join(" ")));
+    }
+    style
+}
+
+fn css_color(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::TerminalState;
+    use phosphor_common::types::Size;
+    
+    #[test]
+    fn test_render_svg() {
+        let mut state = TerminalState::new(Size::new(4, 2));
+        state.set_background_color(Color::Blue);
+        state.write_str("a<");
+        state.reset_attributes();
+        state.set_attribute_flag(AttributeFlags::BOLD, true);
+        state.write_str(" b");
+        state.reset_attributes();
+        state.write_str("\r\n");
+        
+        let options = RenderOptions { cell_width: 10.0, cell_height: 20.0, ..RenderOptions::default() };
+        let svg = render_svg(&state.grid_snapshot(), &options);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"40\" height=\"40\""));
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"20\" height=\"20\" fill=\"#0000ee\"/>"));
+        assert!(svg.contains(
+            "<text x=\"0 10\" y=\"16\" font-family=\"monospace\" font-size=\"15\" fill=\"#e5e5e5\">a&lt;</text>"
+        ));
+        assert!(svg.contains("fill=\"#e5e5e5\" font-weight=\"bold\">b</text>"));
+        // Block cursor at the start of the second row
+        assert!(svg.contains("<rect x=\"0\" y=\"20\" width=\"10\" height=\"20\" fill=\"#e5e5e5\"/>"));
+        
+        let hidden = RenderOptions { draw_cursor: false, ..options };
+        assert!(!render_svg(&state.grid_snapshot(), &hidden).contains("y=\"20\""));
+    }
+    
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_render_png() {
+        use resvg::tiny_skia::Pixmap;
+        
+        let mut state = TerminalState::new(Size::new(4, 2));
+        state.set_background_color(Color::Blue);
+        state.write_str("ab");
+        
+        let options = RenderOptions { cell_width: 10.0, cell_height: 20.0, draw_cursor: false, ..RenderOptions::default() };
+        let png = render_png(&state.grid_snapshot(), &options).unwrap();
+        let image = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (40, 40));
+        let rgb = |x, y| {
+            let pixel = image.pixel(x, y).unwrap();
+            Rgb::new(pixel.red(), pixel.green(), pixel.blue())
+        };
+        // Backgrounds fill whole cells; glyphs only need a font to be found
+        assert_eq!(rgb(0, 0), Rgb::new(0, 0, 0xee));
+        assert_eq!(rgb(19, 19), Rgb::new(0, 0, 0xee));
+        let snapshot = state.grid_snapshot();
+        assert_eq!(rgb(39, 39), resolve(&snapshot, snapshot.terminal.display_colors().background));
+    }
+}
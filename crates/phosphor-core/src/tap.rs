@@ -0,0 +1,108 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Which side of the PTY a chunk of bytes came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapDirection {
+    /// Bytes written to the PTY (keyboard input, pastes, terminal replies)
+    Input,
+    /// Bytes read from the PTY (application output)
+    Output,
+}
+
+/// Raw bytes seen by a tap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapChunk {
+    pub direction: TapDirection,
+    pub data: Vec<u8>,
+}
+
+struct Tap {
+    directions: Vec<TapDirection>,
+    tx: mpsc::UnboundedSender<TapChunk>,
+}
+
+/// Registry of taps on a session's raw byte stream
+///
+/// Cloning shares the registry, so taps can be added while the terminal
+/// runs. A tap is removed once its receiver is dropped. Channels are
+/// unbounded; a tap that stops reading keeps its chunks in memory.
+#[derive(Clone, Default)]
+pub struct ByteTaps {
+    taps: Arc<Mutex<Vec<Tap>>>,
+}
+
+impl ByteTaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Register a tap receiving the given directions
+    pub fn register(&self, directions: &[TapDirection]) -> mpsc::UnboundedReceiver<TapChunk> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.taps.lock().unwrap().push(Tap {
+            directions: directions.to_vec(),
+            tx,
+        });
+        rx
+    }
+    
+    /// Number of registered taps
+    pub fn len(&self) -> usize {
+        self.taps.lock().unwrap().len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Copy bytes to every tap listening to `direction`
+    pub fn send(&self, direction: TapDirection, data: &[u8]) {
+        let mut taps = self.taps.lock().unwrap();
+        taps.retain(|tap| {
+            if !tap.directions.contains(&direction) {
+                return !tap.tx.is_closed();
+            }
+            tap.tx
+                .send(TapChunk { direction, data: data.to_vec() })
+                .is_ok()
+        });
+    }
+}
+
+impl std::fmt::Debug for ByteTaps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ByteTaps").field("taps", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_tap_directions() {
+        let taps = ByteTaps::new();
+        let mut output = taps.register(&[TapDirection::Output]);
+        let mut both = taps.register(&[TapDirection::Input, TapDirection::Output]);
+        
+        taps.send(TapDirection::Input, b"ls\n");
+        taps.send(TapDirection::Output, b"a b\n");
+        
+        assert_eq!(output.try_recv().unwrap(), TapChunk { direction: TapDirection::Output, data: b"a b\n".to_vec() });
+        assert!(output.try_recv().is_err());
+        assert_eq!(both.try_recv().unwrap().direction, TapDirection::Input);
+        assert_eq!(both.try_recv().unwrap().direction, TapDirection::Output);
+    }
+    
+    #[test]
+    fn test_dropped_tap_is_removed() {
+        let taps = ByteTaps::new();
+        let rx = taps.register(&[TapDirection::Input]);
+        assert_eq!(taps.len(), 1);
+        
+        drop(rx);
+        taps.send(TapDirection::Output, b"x");
+        assert!(taps.is_empty());
+    }
+}
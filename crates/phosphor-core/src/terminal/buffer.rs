@@ -1,9 +1,79 @@
-use phosphor_common::types::{Cell, Position, Size};
-use std::collections::VecDeque;
+use phosphor_common::types::{Cell, CellAttributes, CellTags, Hyperlink, LineRendition, Position, Size};
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Columns of a row changed since damage was last taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineDamage {
+    pub row: u16,
+    /// First changed column (inclusive)
+    pub left: u16,
+    /// Last changed column (inclusive)
+    pub right: u16,
+}
+
+/// Join cells into text, skipping the spacers of wide characters
+pub fn cells_text(cells: &[Cell]) -> String {
+    cells.iter().filter(|cell| !cell.is_spacer()).map(|cell| cell.ch).collect()
+}
+
+/// Whether a line autowrapped onto the next one
+pub fn is_wrapped(line: &[Cell]) -> bool {
+    line.last().is_some_and(|cell| cell.tags.contains(CellTags::WRAPPED))
+}
+
+/// Take the given column range of each line for copying
+///
+/// Returns the cells of each line and whether a newline follows it. A
+/// newline follows every line but the last, except with `join_wrapped`
+/// lines that autowrapped and whose range reaches the end. Trailing blanks
+/// are dropped before each newline and at the end.
+pub fn copy_lines<'a>(lines: impl IntoIterator<Item = (&'a [Cell], Range<usize>)>, join_wrapped: bool) -> Vec<(&'a [Cell], bool)> {
+    let mut copied: Vec<(&[Cell], bool)> = Vec::new();
+    for (line, range) in lines {
+        let end = range.end.min(line.len());
+        let mut cells = &line[range.start.min(end)..end];
+        let newline = !(join_wrapped && is_wrapped(line) && end == line.len());
+        if newline {
+            while let [rest @ .., last] = cells {
+                if last.ch != ' ' {
+                    break;
+                }
+                cells = rest;
+            }
+        }
+        copied.push((cells, newline));
+    }
+    if let Some((_, newline)) = copied.last_mut() {
+        *newline = false;
+    }
+    copied
+}
+
+/// Join lines from `copy_lines` into text
+pub fn lines_text(lines: &[(&[Cell], bool)]) -> String {
+    let mut text = String::new();
+    for (cells, newline) in lines {
+        text.push_str(&cells_text(cells));
+        if *newline {
+            text.push('\n');
+        }
+    }
+    text
+}
 
 /// Screen buffer that holds the visible terminal content
 pub struct ScreenBuffer {
     lines: Vec<Vec<Cell>>,
+    /// Rendition of each line, kept in step with `lines`
+    renditions: Vec<LineRendition>,
+    /// Changed columns of each row as `(left, right)`, kept in step with `lines`
+    damage: Vec<Option<(u16, u16)>>,
     size: Size,
 }
 
@@ -13,14 +83,133 @@ impl ScreenBuffer {
         let lines = (0..size.rows)
             .map(|_| vec![Cell::blank(); size.cols as usize])
             .collect();
+        let renditions = vec![LineRendition::default(); size.rows as usize];
+        let damage = vec![None; size.rows as usize];
         
-        Self { lines, size }
+        let mut buffer = Self { lines, renditions, damage, size };
+        buffer.damage_all();
+        buffer
+    }
+    
+    /// Rebuild a buffer from saved lines and renditions, fitting them to `size`
+    pub(crate) fn from_lines(size: Size, mut lines: Vec<Vec<Cell>>, mut renditions: Vec<LineRendition>) -> Self {
+        lines.resize_with(size.rows as usize, Vec::new);
+        for line in &mut lines {
+            line.resize(size.cols as usize, Cell::blank());
+        }
+        renditions.resize(size.rows as usize, LineRendition::default());
+        let damage = vec![None; size.rows as usize];
+        
+        let mut buffer = Self { lines, renditions, damage, size };
+        buffer.damage_all();
+        buffer
+    }
+    
+    /// Mark columns `left..=right` of a row as changed
+    pub fn damage_span(&mut self, row: u16, left: u16, right: u16) {
+        let right = right.min(self.size.cols.saturating_sub(1));
+        if left > right {
+            return;
+        }
+        if let Some(span) = self.damage.get_mut(row as usize) {
+            *span = Some(match *span {
+                Some((l, r)) => (l.min(left), r.max(right)),
+                None => (left, right),
+            });
+        }
+    }
+    
+    /// Mark whole rows as changed
+    pub fn damage_rows(&mut self, rows: Range<u16>) {
+        for row in rows {
+            self.damage_span(row, 0, self.size.cols);
+        }
+    }
+    
+    /// Mark the whole buffer as changed, e.g. after a color change
+    pub fn damage_all(&mut self) {
+        self.damage.resize(self.lines.len(), None);
+        self.damage_rows(0..self.lines.len() as u16);
+    }
+    
+    /// Take the rows changed since the last call, top to bottom
+    pub fn take_damage(&mut self) -> Vec<LineDamage> {
+        self.damage.iter_mut()
+            .enumerate()
+            .filter_map(|(row, span)| {
+                let (left, right) = span.take()?;
+                Some(LineDamage { row: row as u16, left, right })
+            })
+            .collect()
+    }
+    
+    /// Get the rendition of a line
+    pub fn line_rendition(&self, row: u16) -> LineRendition {
+        self.renditions.get(row as usize).copied().unwrap_or_default()
+    }
+    
+    /// Set the rendition of a line
+    pub fn set_line_rendition(&mut self, row: u16, rendition: LineRendition) {
+        if let Some(line) = self.renditions.get_mut(row as usize) {
+            *line = rendition;
+            self.damage_rows(row..row + 1);
+        }
+    }
+    
+    /// Get the rendition of every line, top to bottom
+    pub fn line_renditions(&self) -> &[LineRendition] {
+        &self.renditions
+    }
+    
+    /// Whether a row autowrapped onto the next one, rather than ending in a
+    /// line feed
+    pub fn is_wrapped(&self, row: u16) -> bool {
+        self.lines.get(row as usize).is_some_and(|line| is_wrapped(line))
+    }
+    
+    /// Mark a row as continuing on the next one, or clear the mark
+    ///
+    /// The mark is `CellTags::WRAPPED` on the row's last cell, so it moves
+    /// with the row on scrolling and into scrollback.
+    pub fn set_wrapped(&mut self, row: u16, wrapped: bool) {
+        if let Some(cell) = self.lines.get_mut(row as usize).and_then(|line| line.last_mut()) {
+            cell.tags.set(CellTags::WRAPPED, wrapped);
+        }
+    }
+    
+    /// Put a row's wrap mark back on its last cell after cells shifted
+    fn restore_wrapped(&mut self, row: u16, wrapped: bool) {
+        if let Some(line) = self.lines.get_mut(row as usize) {
+            for cell in line.iter_mut() {
+                cell.tags.remove(CellTags::WRAPPED);
+            }
+        }
+        self.set_wrapped(row, wrapped);
     }
     
     /// Set a cell at the given position
+    ///
+    /// Overwriting either half of a wide character blanks the other half.
     pub fn set_cell(&mut self, pos: Position, cell: Cell) {
         if pos.row < self.size.rows && pos.col < self.size.cols {
-            self.lines[pos.row as usize][pos.col as usize] = cell;
+            let line = &mut self.lines[pos.row as usize];
+            let col = pos.col as usize;
+            let other = match line[col].width {
+                0 => col.checked_sub(1),
+                2 => Some(col + 1),
+                _ => None,
+            };
+            if let Some(other) = other.and_then(|other| line.get_mut(other)) {
+                other.ch = ' ';
+                other.width = 1;
+            }
+            line[col] = cell;
+            
+            let (left, right) = match other {
+                Some(other) => (col.min(other), col.max(other)),
+                None => (col, col),
+            };
+            self.damage_span(pos.row, left as u16, right as u16);
         }
     }
     
@@ -45,7 +234,10 @@ impl ScreenBuffer {
     /// Remove the top line and return it
     pub fn remove_top_line(&mut self) -> Option<Vec<Cell>> {
         if !self.lines.is_empty() {
-            Some(self.lines.remove(0))
+            self.renditions.remove(0);
+            let line = self.lines.remove(0);
+            self.damage_all();
+            Some(line)
         } else {
             None
         }
@@ -54,15 +246,21 @@ impl ScreenBuffer {
     /// Add a blank line at the bottom
     pub fn add_blank_line(&mut self) {
         self.lines.push(vec![Cell::blank(); self.size.cols as usize]);
+        self.renditions.push(LineRendition::default());
+        self.damage_all();
     }
     
     /// Clear the entire buffer
+    ///
+    /// Every line returns to single width.
     pub fn clear(&mut self) {
+        self.renditions.fill(LineRendition::default());
         for line in &mut self.lines {
             for cell in line {
                 *cell = Cell::blank();
             }
         }
+        self.damage_all();
     }
     
     /// Clear a line
@@ -71,6 +269,7 @@ impl ScreenBuffer {
             for cell in &mut self.lines[row as usize] {
                 *cell = Cell::blank();
             }
+            self.damage_rows(row..row + 1);
         }
     }
     
@@ -79,31 +278,251 @@ impl ScreenBuffer {
         self.set_cell(pos, Cell::blank());
     }
     
+    /// Insert blank cells at the given position, shifting the rest of the line right
+    ///
+    /// Cells pushed past the right edge are discarded.
+    pub fn insert_blank_cells(&mut self, pos: Position, count: u16) {
+        self.insert_blank_cells_in(pos, count, self.size.cols.saturating_sub(1));
+    }
+    
+    /// Insert blank cells at the given position, shifting cells up to column `right` right
+    ///
+    /// Cells pushed past `right` are discarded; cells after it are untouched.
+    pub fn insert_blank_cells_in(&mut self, pos: Position, count: u16, right: u16) {
+        let wrapped = self.is_wrapped(pos.row);
+        let Some(len) = self.span_mut(pos, right).map(|span| span.len()) else {
+            return;
+        };
+        let count = (count as usize).min(len);
+        
+        // Wide characters cut by the cursor, the cells pushed out or the right
+        // edge can't be shifted whole
+        let col = pos.col as usize;
+        for boundary in [col, col + len - count, col + len] {
+            self.split_wide_char(pos.row, boundary);
+        }
+        let Some(span) = self.span_mut(pos, right) else {
+            return;
+        };
+        span.rotate_right(count);
+        for cell in &mut span[..count] {
+            *cell = Cell::blank();
+        }
+        self.restore_wrapped(pos.row, wrapped);
+        self.damage_span(pos.row, pos.col, right);
+    }
+    
+    /// Delete cells at the given position, shifting the rest of the line left
+    ///
+    /// Blank cells are inserted at the right edge to fill the gap.
+    pub fn delete_cells(&mut self, pos: Position, count: u16) {
+        self.delete_cells_in(pos, count, self.size.cols.saturating_sub(1));
+    }
+    
+    /// Delete cells at the given position, shifting cells up to column `right` left
+    ///
+    /// Blank cells fill the gap at `right`; cells after it are untouched.
+    pub fn delete_cells_in(&mut self, pos: Position, count: u16, right: u16) {
+        let wrapped = self.is_wrapped(pos.row);
+        let Some(len) = self.span_mut(pos, right).map(|span| span.len()) else {
+            return;
+        };
+        let count = (count as usize).min(len);
+        
+        // Wide characters cut by the cursor, the end of the deleted cells or
+        // the right edge can't be shifted whole
+        let col = pos.col as usize;
+        for boundary in [col, col + count, col + len] {
+            self.split_wide_char(pos.row, boundary);
+        }
+        let Some(span) = self.span_mut(pos, right) else {
+            return;
+        };
+        span.rotate_left(count);
+        for cell in &mut span[len - count..] {
+            *cell = Cell::blank();
+        }
+        self.restore_wrapped(pos.row, wrapped);
+        self.damage_span(pos.row, pos.col, right);
+    }
+    
+    /// Blank both halves of a wide character whose spacer is in column `col`
+    fn split_wide_char(&mut self, row: u16, col: usize) {
+        let line = &mut self.lines[row as usize];
+        if col == 0 || !line.get(col).is_some_and(Cell::is_spacer) {
+            return;
+        }
+        for cell in &mut line[col - 1..=col] {
+            cell.ch = ' ';
+            cell.width = 1;
+        }
+        self.damage_span(row, col as u16 - 1, col as u16);
+    }
+    
+    /// Cells of row `pos.row` from `pos.col` to `right` inclusive
+    fn span_mut(&mut self, pos: Position, right: u16) -> Option<&mut [Cell]> {
+        let right = right.min(self.size.cols.saturating_sub(1));
+        if pos.row >= self.size.rows || pos.col > right {
+            return None;
+        }
+        Some(&mut self.lines[pos.row as usize][pos.col as usize..=right as usize])
+    }
+    
+    /// Insert blank columns at `col` in each of the given rows (DECIC)
+    ///
+    /// Only columns up to `right` shift.
+    pub fn insert_blank_columns(&mut self, col: u16, count: u16, rows: Range<u16>, right: u16) {
+        for row in rows {
+            self.insert_blank_cells_in(Position::new(row, col), count, right);
+        }
+    }
+    
+    /// Delete columns at `col` in each of the given rows (DECDC)
+    ///
+    /// Only columns up to `right` shift.
+    pub fn delete_columns(&mut self, col: u16, count: u16, rows: Range<u16>, right: u16) {
+        for row in rows {
+            self.delete_cells_in(Position::new(row, col), count, right);
+        }
+    }
+    
+    /// Add or remove tags on the cells from `start` to `end` inclusive, in reading order
+    pub fn set_tags(&mut self, start: Position, end: Position, tags: CellTags, enabled: bool) {
+        let cols = usize::from(self.size.cols);
+        let first = usize::from(start.row) * cols + usize::from(start.col);
+        let last = usize::from(end.row) * cols + usize::from(end.col);
+        let total = self.lines.len() * cols;
+        if first > last || first >= total {
+            return;
+        }
+        
+        let last = last.min(total - 1);
+        for index in first..=last {
+            self.lines[index / cols][index % cols].tags.set(tags, enabled);
+        }
+        for row in first / cols..=last / cols {
+            let left = if row == first / cols { first % cols } else { 0 };
+            let right = if row == last / cols { last % cols } else { cols - 1 };
+            self.damage_span(row as u16, left as u16, right as u16);
+        }
+    }
+    
+    /// Remove tags from every cell, e.g. to clear all search matches
+    pub fn clear_tags(&mut self, tags: CellTags) {
+        for row in 0..self.lines.len() {
+            let line = &mut self.lines[row];
+            if line.iter().any(|cell| cell.tags.intersects(tags)) {
+                for cell in line {
+                    cell.tags.remove(tags);
+                }
+                self.damage_rows(row as u16..row as u16 + 1);
+            }
+        }
+    }
+    
     /// Insert a blank line at the specified row
     pub fn insert_blank_line(&mut self, row: u16) {
         if row <= self.size.rows {
             let row_idx = row as usize;
             if row_idx < self.lines.len() {
                 self.lines.insert(row_idx, vec![Cell::blank(); self.size.cols as usize]);
+                self.renditions.insert(row_idx, LineRendition::default());
                 // Limit to screen size
                 if self.lines.len() > self.size.rows as usize {
                     self.lines.truncate(self.size.rows as usize);
+                    self.renditions.truncate(self.size.rows as usize);
                 }
+                self.damage_rows(row..self.size.rows);
             }
         }
     }
     
+    /// Scroll the rows `top..=bottom` up by one line
+    ///
+    /// The line scrolled off the top of the region is returned and a blank
+    /// line is inserted at `bottom`. Rows outside the region are untouched.
+    pub fn scroll_region_up(&mut self, top: u16, bottom: u16) -> Option<Vec<Cell>> {
+        if top > bottom || bottom >= self.size.rows {
+            return None;
+        }
+        
+        let line = self.lines.remove(top as usize);
+        self.lines.insert(bottom as usize, vec![Cell::blank(); self.size.cols as usize]);
+        self.renditions.remove(top as usize);
+        self.renditions.insert(bottom as usize, LineRendition::default());
+        self.damage_rows(top..bottom + 1);
+        Some(line)
+    }
+    
+    /// Scroll the rows `top..=bottom` down by one line
+    ///
+    /// The bottom line of the region is discarded and a blank line is inserted at `top`.
+    pub fn scroll_region_down(&mut self, top: u16, bottom: u16) {
+        if top > bottom || bottom >= self.size.rows {
+            return;
+        }
+        
+        self.lines.remove(bottom as usize);
+        self.lines.insert(top as usize, vec![Cell::blank(); self.size.cols as usize]);
+        self.renditions.remove(bottom as usize);
+        self.renditions.insert(top as usize, LineRendition::default());
+        self.damage_rows(top..bottom + 1);
+    }
+    
+    /// Scroll the rectangle `top..=bottom` x `left..=right` up by one line
+    ///
+    /// Used when left/right margins are set; cells outside the columns stay put
+    /// and the top row of the rectangle is discarded.
+    pub fn scroll_rect_up(&mut self, top: u16, bottom: u16, left: u16, right: u16) {
+        if top > bottom || bottom >= self.size.rows || left > right || right >= self.size.cols {
+            return;
+        }
+        
+        let cols = left as usize..=right as usize;
+        for row in top as usize..bottom as usize {
+            let below = self.lines[row + 1][cols.clone()].to_vec();
+            self.lines[row][cols.clone()].clone_from_slice(&below);
+        }
+        self.lines[bottom as usize][cols].fill(Cell::blank());
+        for row in top..=bottom {
+            self.damage_span(row, left, right);
+        }
+    }
+    
+    /// Scroll the rectangle `top..=bottom` x `left..=right` down by one line
+    pub fn scroll_rect_down(&mut self, top: u16, bottom: u16, left: u16, right: u16) {
+        if top > bottom || bottom >= self.size.rows || left > right || right >= self.size.cols {
+            return;
+        }
+        
+        let cols = left as usize..=right as usize;
+        for row in (top as usize + 1..=bottom as usize).rev() {
+            let above = self.lines[row - 1][cols.clone()].to_vec();
+            self.lines[row][cols.clone()].clone_from_slice(&above);
+        }
+        self.lines[top as usize][cols].fill(Cell::blank());
+        for row in top..=bottom {
+            self.damage_span(row, left, right);
+        }
+    }
+    
     /// Remove the bottom line
     pub fn remove_bottom_line(&mut self) {
         if !self.lines.is_empty() {
             self.lines.pop();
+            self.renditions.pop();
+            self.damage.pop();
         }
     }
     
     /// Resize the buffer
     pub fn resize(&mut self, new_size: Size) {
-        // First resize columns for existing rows
+        // First resize columns for existing rows, keeping wrap marks on the last cell
         for line in &mut self.lines {
+            let wrapped = is_wrapped(line);
+            if let Some(cell) = line.last_mut() {
+                cell.tags.remove(CellTags::WRAPPED);
+            }
             if new_size.cols > self.size.cols {
                 // Add blank cells
                 line.extend((self.size.cols..new_size.cols).map(|_| Cell::blank()));
@@ -111,6 +530,9 @@ impl ScreenBuffer {
                 // Remove excess cells
                 line.truncate(new_size.cols as usize);
             }
+            if let Some(cell) = line.last_mut().filter(|_| wrapped) {
+                cell.tags.insert(CellTags::WRAPPED);
+            }
         }
         
         // Then resize rows
@@ -123,8 +545,10 @@ impl ScreenBuffer {
             // Remove excess lines
             self.lines.truncate(new_size.rows as usize);
         }
+        self.renditions.resize(self.lines.len(), LineRendition::default());
         
         self.size = new_size;
+        self.damage_all();
     }
     
     /// Get the buffer size
@@ -138,52 +562,337 @@ impl ScreenBuffer {
     }
 }
 
+/// Everything about a cell but its character, shared by a run of cells
+#[derive(Debug, Clone, PartialEq)]
+struct CellStyle {
+    attrs: CellAttributes,
+    hyperlink: Option<Arc<Hyperlink>>,
+    tags: CellTags,
+    protected: bool,
+}
+
+impl CellStyle {
+    fn matches(&self, cell: &Cell) -> bool {
+        self.attrs == cell.attrs
+            && self.hyperlink == cell.hyperlink
+            && self.tags == cell.tags
+            && self.protected == cell.protected
+    }
+}
+
+/// A scrollback line stored compactly
+///
+/// Trailing blank cells are dropped and the characters are kept as UTF-8,
+/// with NUL standing for the spacer after a wide character. The other cell
+/// fields are run-length encoded.
+#[derive(Debug, Clone)]
+struct CompactLine {
+    text: Box<str>,
+    runs: Box<[(u16, CellStyle)]>,
+    /// Width of the line before trimming
+    cols: u16,
+}
+
+impl CompactLine {
+    fn new(line: &[Cell], links: &mut HashSet<Arc<Hyperlink>>) -> Self {
+        let blank = Cell::blank();
+        let len = line.iter().rposition(|cell| *cell != blank).map_or(0, |last| last + 1);
+        let mut text = String::with_capacity(len);
+        let mut runs: Vec<(u16, CellStyle)> = Vec::new();
+        for cell in &line[..len] {
+            text.push(if cell.is_spacer() { '\0' } else { cell.ch });
+            match runs.last_mut() {
+                Some((count, style)) if style.matches(cell) => *count += 1,
+                _ => runs.push((1, CellStyle {
+                    attrs: cell.attrs,
+                    hyperlink: cell.hyperlink.as_ref().map(|link| intern(links, link)),
+                    tags: cell.tags,
+                    protected: cell.protected,
+                })),
+            }
+        }
+        Self { text: text.into(), runs: runs.into(), cols: line.len() as u16 }
+    }
+    
+    fn cells(&self) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(self.cols as usize);
+        let mut chars = self.text.chars().peekable();
+        for (count, style) in self.runs.iter() {
+            for _ in 0..*count {
+                let (ch, width) = match chars.next() {
+                    Some('\0') => (' ', 0),
+                    Some(ch) if chars.peek() == Some(&'\0') => (ch, 2),
+                    ch => (ch.unwrap_or(' '), 1),
+                };
+                cells.push(Cell {
+                    ch,
+                    attrs: style.attrs,
+                    hyperlink: style.hyperlink.clone(),
+                    tags: style.tags,
+                    protected: style.protected,
+                    width,
+                });
+            }
+        }
+        cells.resize(self.cols as usize, Cell::blank());
+        cells
+    }
+    
+    /// Same as `is_wrapped` on the full line
+    fn is_wrapped(&self) -> bool {
+        let stored: usize = self.runs.iter().map(|(count, _)| *count as usize).sum();
+        stored == self.cols as usize && self.runs.last().is_some_and(|(_, style)| style.tags.contains(CellTags::WRAPPED))
+    }
+    
+    fn heap_size(&self) -> usize {
+        self.text.len() + self.runs.len() * std::mem::size_of::<(u16, CellStyle)>()
+    }
+    
+    fn encoded(&self) -> EncodedLine {
+        let runs = self.runs.iter()
+            .map(|(count, style)| (*count, style.attrs, style.hyperlink.as_deref().cloned(), style.tags, style.protected))
+            .collect();
+        (self.cols, self.text.to_string(), runs)
+    }
+    
+    fn from_encoded((cols, text, runs): EncodedLine) -> Self {
+        let runs = runs.into_iter()
+            .map(|(count, attrs, hyperlink, tags, protected)| {
+                (count, CellStyle { attrs, hyperlink: hyperlink.map(Arc::new), tags, protected })
+            })
+            .collect();
+        Self { text: text.into(), runs, cols }
+    }
+}
+
+/// A line in serializable form: its width, its text as stored by
+/// `CompactLine` and its style runs
+pub(crate) type EncodedLine = (u16, String, Vec<(u16, CellAttributes, Option<Hyperlink>, CellTags, bool)>);
+
+/// Encode a line of cells compactly, for saving
+pub(crate) fn encode_line(line: &[Cell]) -> EncodedLine {
+    CompactLine::new(line, &mut HashSet::new()).encoded()
+}
+
+/// Expand a line saved by `encode_line`
+pub(crate) fn decode_line(line: EncodedLine) -> Vec<Cell> {
+    CompactLine::from_encoded(line).cells()
+}
+
+/// Append-only file holding the lines that overflowed the in-memory scrollback
+struct SpillFile {
+    /// Unnamed temporary file, deleted when closed
+    file: File,
+    /// Where each line starts in the file, oldest first
+    offsets: Vec<u64>,
+    wrapped: Vec<bool>,
+    end: u64,
+}
+
+impl SpillFile {
+    fn create_in(dir: &Path) -> io::Result<Self> {
+        Ok(Self { file: tempfile::tempfile_in(dir)?, offsets: Vec::new(), wrapped: Vec::new(), end: 0 })
+    }
+    
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+    
+    fn append(&mut self, line: &CompactLine) -> io::Result<()> {
+        let mut data = serde_json::to_vec(&line.encoded())?;
+        data.push(b'\n');
+        // Reads move the file position
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&data)?;
+        self.offsets.push(self.end);
+        self.wrapped.push(line.is_wrapped());
+        self.end += data.len() as u64;
+        Ok(())
+    }
+    
+    fn read(&self, index: usize) -> io::Result<CompactLine> {
+        let start = self.offsets[index];
+        let end = self.offsets.get(index + 1).copied().unwrap_or(self.end);
+        let mut data = vec![0; (end - start) as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut data)?;
+        Ok(CompactLine::from_encoded(serde_json::from_slice(&data)?))
+    }
+    
+    fn clear(&mut self) -> io::Result<()> {
+        self.offsets.clear();
+        self.wrapped.clear();
+        self.end = 0;
+        self.file.set_len(0)
+    }
+}
+
+/// The shared copy of a hyperlink, so identical links in scrollback are stored once
+fn intern(links: &mut HashSet<Arc<Hyperlink>>, link: &Arc<Hyperlink>) -> Arc<Hyperlink> {
+    if let Some(existing) = links.get(link) {
+        return existing.clone();
+    }
+    links.insert(link.clone());
+    link.clone()
+}
+
 /// Scrollback buffer that holds historical terminal content
+///
+/// Lines are stored compactly and expanded back into cells when read. With
+/// a spill file, lines overflowing `max_lines` move to disk instead of being
+/// dropped, and are read back from there as needed.
 pub struct ScrollbackBuffer {
-    lines: VecDeque<Vec<Cell>>,
+    lines: VecDeque<CompactLine>,
     max_lines: usize,
+    /// Older lines, before those in `lines`
+    spill: Option<SpillFile>,
+    /// Hyperlinks used in the scrollback
+    links: HashSet<Arc<Hyperlink>>,
+    /// Size of `links` at which unused links are next pruned
+    links_limit: usize,
+    /// Lines dropped from the front or cleared, over the buffer's lifetime
+    dropped: usize,
 }
 
 impl ScrollbackBuffer {
     /// Create a new scrollback buffer with a maximum number of lines
     pub fn new(max_lines: usize) -> Self {
         Self {
-            lines: VecDeque::with_capacity(max_lines.min(100_000)), // Cap capacity
+            lines: VecDeque::with_capacity(max_lines.min(1_024)), // Grows as needed
             max_lines,
+            spill: None,
+            links: HashSet::new(),
+            links_limit: 64,
+            dropped: 0,
         }
     }
     
+    /// Create a scrollback buffer that keeps `max_lines` in memory and spills
+    /// older lines to a temporary file in `dir`, for unlimited history
+    pub fn with_spill_dir(max_lines: usize, dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut buffer = Self::new(max_lines);
+        buffer.spill = Some(SpillFile::create_in(dir.as_ref())?);
+        Ok(buffer)
+    }
+    
+    /// Lines held on disk, which come first
+    fn spilled(&self) -> usize {
+        self.spill.as_ref().map_or(0, SpillFile::len)
+    }
+    
     /// Push a new line to the scrollback
-    pub fn push(&mut self, line: Vec<Cell>) {
-        if self.lines.len() >= self.max_lines {
-            self.lines.pop_front();
+    ///
+    /// Returns whether the oldest line was dropped to make room.
+    pub fn push(&mut self, line: Vec<Cell>) -> bool {
+        if self.links.len() >= self.links_limit {
+            // Only the set still holds links that left the scrollback
+            self.links.retain(|link| Arc::strong_count(link) > 1);
+            self.links_limit = (self.links.len() * 2).max(64);
+        }
+        self.lines.push_back(CompactLine::new(&line, &mut self.links));
+        self.evict() > 0
+    }
+    
+    /// The most lines kept in memory
+    pub fn max_lines(&self) -> usize {
+        self.max_lines
+    }
+    
+    /// Change the most lines kept in memory
+    ///
+    /// Returns how many of the oldest lines were dropped to fit; with a
+    /// spill file they move to disk instead.
+    pub fn set_max_lines(&mut self, max_lines: usize) -> usize {
+        self.max_lines = max_lines;
+        self.evict()
+    }
+    
+    /// Spill or drop the oldest lines over the limit, returning how many were dropped
+    fn evict(&mut self) -> usize {
+        let mut dropped = 0;
+        while self.lines.len() > self.max_lines {
+            let Some(oldest) = self.lines.pop_front() else { break };
+            let spilled = self.spill.as_mut().is_some_and(|spill| {
+                spill.append(&oldest).inspect_err(|err| warn!("Failed to spill scrollback: {}", err)).is_ok()
+            });
+            if !spilled {
+                dropped += 1;
+            }
         }
-        self.lines.push_back(line);
+        self.dropped += dropped;
+        dropped
+    }
+    
+    /// How many lines have left the buffer, dropped over the limit or
+    /// cleared, since it was created
+    ///
+    /// Adding it to a line index gives a number that stays with the line
+    /// while older lines go.
+    pub fn dropped(&self) -> usize {
+        self.dropped
     }
     
     /// Get the number of lines in scrollback
     pub fn len(&self) -> usize {
-        self.lines.len()
+        self.spilled() + self.lines.len()
     }
     
     /// Check if scrollback is empty
     pub fn is_empty(&self) -> bool {
-        self.lines.is_empty()
+        self.len() == 0
     }
     
     /// Get a line from scrollback (0 is oldest)
-    pub fn get_line(&self, index: usize) -> Option<&Vec<Cell>> {
-        self.lines.get(index)
+    ///
+    /// Returns `None` for a spilled line that can't be read back.
+    pub fn get_line(&self, index: usize) -> Option<Vec<Cell>> {
+        let spilled = self.spilled();
+        match &self.spill {
+            Some(spill) if index < spilled => spill.read(index)
+                .inspect_err(|err| warn!("Failed to read spilled scrollback: {}", err))
+                .ok()
+                .map(|line| line.cells()),
+            _ => self.lines.get(index - spilled).map(CompactLine::cells),
+        }
+    }
+    
+    /// Whether a line autowrapped onto the next one, without expanding it
+    pub fn is_wrapped(&self, index: usize) -> bool {
+        let spilled = self.spilled();
+        match &self.spill {
+            Some(spill) if index < spilled => spill.wrapped[index],
+            _ => self.lines.get(index - spilled).is_some_and(CompactLine::is_wrapped),
+        }
     }
     
     /// Clear the scrollback buffer
     pub fn clear(&mut self) {
+        self.dropped += self.len();
         self.lines.clear();
+        self.links.clear();
+        if let Some(spill) = &mut self.spill {
+            if let Err(err) = spill.clear() {
+                warn!("Failed to truncate spilled scrollback: {}", err);
+            }
+        }
     }
     
-    /// Get all lines as a slice
-    pub fn lines(&self) -> &VecDeque<Vec<Cell>> {
-        &self.lines
+    /// Iterate over the lines, oldest first
+    pub fn lines(&self) -> impl Iterator<Item = Vec<Cell>> + '_ {
+        (0..self.len()).filter_map(|index| self.get_line(index))
+    }
+    
+    /// Approximate bytes held by the stored lines
+    pub fn memory_usage(&self) -> usize {
+        let lines: usize = self.lines.iter().map(CompactLine::heap_size).sum();
+        let links: usize = self.links.iter().map(|link| {
+            std::mem::size_of::<Hyperlink>() + link.uri.len() + link.id.as_ref().map_or(0, String::len)
+        }).sum();
+        let spill = self.spill.as_ref().map_or(0, |spill| {
+            spill.offsets.capacity() * std::mem::size_of::<u64>() + spill.wrapped.capacity()
+        });
+        self.lines.capacity() * std::mem::size_of::<CompactLine>() + lines + links + spill
     }
 }
 
@@ -226,6 +935,87 @@ mod tests {
         assert_eq!(buffer.size(), Size::new(3, 2));
     }
     
+    #[test]
+    fn test_insert_and_delete_cells() {
+        let mut buffer = ScreenBuffer::new(Size::new(5, 1));
+        for (col, ch) in "ABCDE".chars().enumerate() {
+            buffer.set_cell(Position::new(0, col as u16), Cell::new(ch));
+        }
+        let row_text = |buffer: &ScreenBuffer| -> String {
+            buffer.get_line(0).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        // Insert shifts right and drops cells past the edge
+        buffer.insert_blank_cells(Position::new(0, 1), 2);
+        assert_eq!(row_text(&buffer), "A  BC");
+        
+        // Delete shifts left and fills the edge with blanks
+        buffer.delete_cells(Position::new(0, 1), 2);
+        assert_eq!(row_text(&buffer), "ABC  ");
+        
+        // Counts larger than the remaining line are clamped
+        buffer.delete_cells(Position::new(0, 1), 100);
+        assert_eq!(row_text(&buffer), "A    ");
+    }
+    
+    #[test]
+    fn test_set_tags_across_lines() {
+        let mut buffer = ScreenBuffer::new(Size::new(3, 3));
+        buffer.set_tags(Position::new(0, 2), Position::new(1, 1), CellTags::SEARCH_MATCH, true);
+        
+        let tagged: Vec<bool> = buffer.lines().iter().flatten().map(|c| c.tags.contains(CellTags::SEARCH_MATCH)).collect();
+        assert_eq!(tagged, vec![false, false, true, true, true, false, false, false, false]);
+        
+        // Ranges past the end are clamped
+        buffer.set_tags(Position::new(2, 2), Position::new(9, 9), CellTags::SELECTED, true);
+        assert!(buffer.get_cell(Position::new(2, 2)).tags.contains(CellTags::SELECTED));
+    }
+    
+    #[test]
+    fn test_scroll_region() {
+        let mut buffer = ScreenBuffer::new(Size::new(1, 4));
+        for (row, ch) in "ABCD".chars().enumerate() {
+            buffer.set_cell(Position::new(row as u16, 0), Cell::new(ch));
+        }
+        let column = |buffer: &ScreenBuffer| -> String {
+            buffer.lines().iter().map(|l| l[0].ch).collect()
+        };
+        
+        let removed = buffer.scroll_region_up(1, 2);
+        assert_eq!(removed.unwrap()[0].ch, 'B');
+        assert_eq!(column(&buffer), "AC D");
+        
+        buffer.scroll_region_down(0, 2);
+        assert_eq!(column(&buffer), " ACD");
+        
+        // Degenerate regions are ignored
+        assert!(buffer.scroll_region_up(3, 2).is_none());
+        assert!(buffer.scroll_region_up(0, 4).is_none());
+        assert_eq!(column(&buffer), " ACD");
+    }
+    
+    #[test]
+    fn test_damage_spans() {
+        let mut buffer = ScreenBuffer::new(Size::new(8, 3));
+        assert_eq!(buffer.take_damage().len(), 3);
+        
+        // Writes on a row merge into one span
+        buffer.set_cell(Position::new(1, 5), Cell::new('A'));
+        buffer.set_cell(Position::new(1, 2), Cell::new('B'));
+        buffer.delete_cells_in(Position::new(2, 3), 1, 4);
+        assert_eq!(buffer.take_damage(), vec![
+            LineDamage { row: 1, left: 2, right: 5 },
+            LineDamage { row: 2, left: 3, right: 4 },
+        ]);
+        
+        buffer.scroll_rect_up(0, 1, 1, 3);
+        assert_eq!(buffer.take_damage(), vec![
+            LineDamage { row: 0, left: 1, right: 3 },
+            LineDamage { row: 1, left: 1, right: 3 },
+        ]);
+        assert!(buffer.take_damage().is_empty());
+    }
+    
     #[test]
     fn test_scrollback_buffer() {
         let mut scrollback = ScrollbackBuffer::new(3);
@@ -244,4 +1034,96 @@ mod tests {
         assert_eq!(scrollback.get_line(0).unwrap()[0].ch, '2');
         assert_eq!(scrollback.get_line(2).unwrap()[0].ch, '4');
     }
+    
+    #[test]
+    fn test_scrollback_round_trip() {
+        let mut scrollback = ScrollbackBuffer::new(10);
+        let link = Arc::new(Hyperlink { id: None, uri: "https://example.com".to_string() });
+        let mut line = vec![Cell::blank(); 8];
+        line[0] = Cell::new('a');
+        line[1] = Cell { hyperlink: Some(link.clone()), ..Cell::new('b') };
+        line[2] = Cell { width: 2, ..Cell::new('大') };
+        line[3] = Cell { ch: ' ', width: 0, ..line[2].clone() };
+        line[4].attrs.bg_color = phosphor_common::types::Color::Red;
+        scrollback.push(line.clone());
+        assert_eq!(scrollback.get_line(0).unwrap(), line);
+        assert!(!scrollback.is_wrapped(0));
+        
+        // Trailing blanks aren't stored, but wrapped lines keep their last cell
+        line[7].tags = CellTags::WRAPPED;
+        scrollback.push(line.clone());
+        assert_eq!(scrollback.get_line(1).unwrap(), line);
+        assert!(scrollback.is_wrapped(1));
+        
+        // Equal links from different sequences share one copy
+        let again = Arc::new(Hyperlink { id: None, uri: "https://example.com".to_string() });
+        scrollback.push(vec![Cell { hyperlink: Some(again), ..Cell::new('c') }]);
+        let stored = |index: usize, col: usize| scrollback.get_line(index).unwrap()[col].hyperlink.clone().unwrap();
+        assert!(Arc::ptr_eq(&stored(0, 1), &stored(2, 0)));
+    }
+    
+    #[test]
+    fn test_scrollback_spill() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut scrollback = ScrollbackBuffer::with_spill_dir(2, dir.path()).unwrap();
+        let link = Arc::new(Hyperlink { id: Some("a".to_string()), uri: "file:///tmp".to_string() });
+        let mut first = vec![Cell { hyperlink: Some(link), ..Cell::new('1') }, Cell::new('x')];
+        first[1].tags = CellTags::WRAPPED;
+        
+        assert!(!scrollback.push(first.clone()));
+        for ch in ['2', '3', '4'] {
+            assert!(!scrollback.push(vec![Cell::new(ch)]));
+        }
+        assert_eq!(scrollback.len(), 4);
+        assert_eq!(scrollback.get_line(0).unwrap(), first);
+        assert!(scrollback.is_wrapped(0));
+        assert!(!scrollback.is_wrapped(1));
+        let chars: String = scrollback.lines().map(|line| line[0].ch).collect();
+        assert_eq!(chars, "1234");
+        
+        scrollback.clear();
+        assert!(scrollback.is_empty());
+        scrollback.push(vec![Cell::new('5')]);
+        assert_eq!(scrollback.get_line(0).unwrap()[0].ch, '5');
+    }
+    
+    #[test]
+    fn test_scrollback_limits() {
+        let mut scrollback = ScrollbackBuffer::new(0);
+        assert!(scrollback.push(vec![Cell::new('1')]));
+        assert!(scrollback.is_empty());
+        
+        let mut scrollback = ScrollbackBuffer::new(usize::MAX);
+        for ch in "12345".chars() {
+            assert!(!scrollback.push(vec![Cell::new(ch)]));
+        }
+        assert_eq!(scrollback.set_max_lines(2), 3);
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(scrollback.get_line(0).unwrap()[0].ch, '4');
+    }
+    
+    #[test]
+    fn test_wrap_marks() {
+        let mut buffer = ScreenBuffer::new(Size::new(4, 2));
+        buffer.set_wrapped(0, true);
+        assert!(buffer.is_wrapped(0));
+        assert!(!buffer.is_wrapped(1));
+        
+        // The mark stays on the last cell as cells shift in and out
+        buffer.insert_blank_cells(Position::new(0, 0), 2);
+        assert!(buffer.is_wrapped(0));
+        buffer.delete_cells(Position::new(0, 1), 1);
+        assert!(buffer.is_wrapped(0));
+        let marked = buffer.get_line(0).unwrap().iter().filter(|cell| cell.tags.contains(CellTags::WRAPPED)).count();
+        assert_eq!(marked, 1);
+        
+        buffer.resize(Size::new(6, 2));
+        assert!(buffer.is_wrapped(0));
+        buffer.resize(Size::new(3, 2));
+        assert!(buffer.is_wrapped(0));
+        assert!(!buffer.is_wrapped(1));
+        
+        buffer.set_wrapped(0, false);
+        assert!(!buffer.is_wrapped(0));
+    }
 }
\ No newline at end of file
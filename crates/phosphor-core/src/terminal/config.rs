@@ -0,0 +1,236 @@
+use phosphor_common::keyboard::AltKey;
+use phosphor_common::types::DynamicColors;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Construction-time configuration for a terminal
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TerminalConfig {
+    /// Character classes used for word selection and path detection
+    pub word_boundaries: WordBoundaries,
+    /// What to do when the application rings the bell
+    pub bell: BellPolicy,
+    /// Keep lines scrolled off the alternate screen in scrollback
+    ///
+    /// Off by default: full-screen programs redraw the alternate screen
+    /// constantly and would flood the history.
+    pub alt_screen_scrollback: bool,
+    /// Default foreground, background and cursor colors
+    ///
+    /// Applications can change them with OSC 10/11/12 until the next reset.
+    pub colors: DynamicColors,
+    /// Per-session rate limits enforced by the run loop
+    pub limits: SessionLimits,
+    /// Interpret 8-bit C1 control bytes (e.g. 0x9B as CSI) from legacy
+    /// applications
+    ///
+    /// Off by default, as in other UTF-8 terminals.
+    pub c1_controls: bool,
+    /// Keep the scrollback when the application resets the terminal (RIS)
+    ///
+    /// Off by default: a reset clears the history along with the screen.
+    pub keep_scrollback_on_reset: bool,
+    /// How many scrollback lines to keep in memory
+    ///
+    /// Can be changed later with `TerminalState::set_scrollback_limit`.
+    pub scrollback_limit: ScrollbackLimit,
+    /// Directory for a temporary file that takes scrollback lines overflowing
+    /// the in-memory limit, for unlimited history
+    ///
+    /// Lines there are read back for the viewport, selection and search.
+    /// `None` by default: overflowing lines are dropped.
+    pub scrollback_spill_dir: Option<PathBuf>,
+    /// How long a blinking cursor stays on, and then off
+    pub cursor_blink_interval: CursorBlinkInterval,
+    /// What Alt does to key presses
+    pub alt_key: AltKey,
+    /// Give East Asian ambiguous-width characters (e.g. `±`, `°`, Greek and
+    /// Cyrillic letters) two columns
+    ///
+    /// `None` by default: `Terminal` follows the child's locale, which makes
+    /// them wide for CJK locales; a bare `TerminalState` keeps them narrow.
+    pub wide_ambiguous: Option<bool>,
+}
+
+/// Half period of a blinking cursor, for frontends to drive the blink
+///
+/// `Duration::ZERO` keeps the cursor steady even when the application asks
+/// for a blinking one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorBlinkInterval(pub Duration);
+
+impl Default for CursorBlinkInterval {
+    fn default() -> Self {
+        Self(Duration::from_millis(500))
+    }
+}
+
+/// How many lines of scrollback to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollbackLimit {
+    /// Keep up to this many lines; 0 disables scrollback
+    Lines(usize),
+    /// Keep every line
+    Unlimited,
+}
+
+impl ScrollbackLimit {
+    /// The limit as a line count, `usize::MAX` when unlimited
+    pub fn max_lines(self) -> usize {
+        match self {
+            Self::Lines(lines) => lines,
+            Self::Unlimited => usize::MAX,
+        }
+    }
+}
+
+impl Default for ScrollbackLimit {
+    fn default() -> Self {
+        Self::Lines(10_000)
+    }
+}
+
+/// Per-session rate limits
+///
+/// A session over a limit has its PTY reads paused until the excess has been
+/// paid off at the limited rate, which in turn blocks the child's writes.
+/// Input keeps flowing meanwhile. `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Maximum output bytes processed per second
+    pub max_output_bytes_per_sec: Option<u64>,
+    /// Maximum parsed events (text runs, controls, sequences) per second
+    pub max_events_per_sec: Option<u64>,
+}
+
+/// Action taken when the application rings the bell (BEL)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BellAction {
+    /// Ignore the bell
+    Silent,
+    /// Play the system beep
+    #[default]
+    Audible,
+    /// Flash the terminal window
+    Visual,
+    /// Show a desktop notification
+    Notify,
+    /// Run a program; the first element is the executable, the rest its arguments
+    Command(Vec<String>),
+}
+
+/// Bell behaviour for a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BellPolicy {
+    pub action: BellAction,
+    /// Mark the bell urgent (e.g. set the window urgency hint) only while the
+    /// terminal is unfocused
+    pub urgent_when_unfocused: bool,
+}
+
+impl Default for BellPolicy {
+    fn default() -> Self {
+        Self {
+            action: BellAction::default(),
+            urgent_when_unfocused: true,
+        }
+    }
+}
+
+/// Character classes that split words and paths
+///
+/// Whitespace always breaks both. Users disagree on the rest (e.g. whether
+/// `.` or `-` ends a word), so the separator sets are configurable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordBoundaries {
+    /// Characters that end a word for word selection and word-wise motion
+    pub word_separators: String,
+    /// Characters that end a path when detecting file paths in hint mode
+    pub path_separators: String,
+}
+
+impl Default for WordBoundaries {
+    fn default() -> Self {
+        Self {
+            word_separators: ",│`|:\"'()[]{}<>".to_string(),
+            path_separators: "\"'`()[]{}<>|;,".to_string(),
+        }
+    }
+}
+
+impl WordBoundaries {
+    /// Check whether a character belongs to a word
+    pub fn is_word_char(&self, ch: char) -> bool {
+        !ch.is_whitespace() && !self.word_separators.contains(ch)
+    }
+    
+    /// Check whether a character can be part of a path
+    pub fn is_path_char(&self, ch: char) -> bool {
+        !ch.is_whitespace() && !self.path_separators.contains(ch)
+    }
+    
+    /// Find the inclusive column range of the word containing `col`
+    ///
+    /// Returns `None` if the character at `col` is not a word character.
+    pub fn word_range(&self, line: &[char], col: usize) -> Option<(usize, usize)> {
+        Self::range_matching(line, col, |ch| self.is_word_char(ch))
+    }
+    
+    /// Find the inclusive column range of the path containing `col`
+    pub fn path_range(&self, line: &[char], col: usize) -> Option<(usize, usize)> {
+        Self::range_matching(line, col, |ch| self.is_path_char(ch))
+    }
+    
+    fn range_matching(line: &[char], col: usize, matches: impl Fn(char) -> bool) -> Option<(usize, usize)> {
+        if !matches(*line.get(col)?) {
+            return None;
+        }
+        let start = line[..col]
+            .iter()
+            .rposition(|&ch| !matches(ch))
+            .map_or(0, |i| i + 1);
+        let end = line[col..]
+            .iter()
+            .position(|&ch| !matches(ch))
+            .map_or(line.len() - 1, |i| col + i - 1);
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+    
+    #[test]
+    fn test_default_word_range() {
+        let boundaries = WordBoundaries::default();
+        let line = chars("ls (foo-bar.txt) baz");
+        
+        assert_eq!(boundaries.word_range(&line, 6), Some((4, 14)));
+        assert_eq!(boundaries.word_range(&line, 0), Some((0, 1)));
+        assert_eq!(boundaries.word_range(&line, 19), Some((17, 19)));
+        assert_eq!(boundaries.word_range(&line, 3), None);
+        assert_eq!(boundaries.word_range(&line, 100), None);
+    }
+    
+    #[test]
+    fn test_custom_separators() {
+        let boundaries = WordBoundaries {
+            word_separators: ".-".to_string(),
+            ..WordBoundaries::default()
+        };
+        let line = chars("foo-bar.txt");
+        assert_eq!(boundaries.word_range(&line, 5), Some((4, 6)));
+    }
+    
+    #[test]
+    fn test_path_range() {
+        let boundaries = WordBoundaries::default();
+        let line = chars("error in \"src/main.rs:12\"");
+        assert_eq!(boundaries.path_range(&line, 12), Some((10, 23)));
+    }
+}
@@ -1,11 +1,15 @@
 use phosphor_common::types::Position;
+use serde::{Deserialize, Serialize};
 
 /// Cursor state and operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cursor {
     position: Position,
     saved_position: Option<Position>,
     visible: bool,
+    /// A character was written in the last column; the line wraps before
+    /// the next printable character (xterm's "wrap pending")
+    pending_wrap: bool,
 }
 
 impl Cursor {
@@ -15,6 +19,7 @@ impl Cursor {
             position: Position::new(0, 0),
             saved_position: None,
             visible: true,
+            pending_wrap: false,
         }
     }
     
@@ -25,46 +30,55 @@ impl Cursor {
     
     /// Set the cursor position
     pub fn set_position(&mut self, pos: Position) {
+        self.pending_wrap = false;
         self.position = pos;
     }
     
     /// Set the cursor row
     pub fn set_row(&mut self, row: u16) {
+        self.pending_wrap = false;
         self.position.row = row;
     }
     
     /// Set the cursor column
     pub fn set_col(&mut self, col: u16) {
+        self.pending_wrap = false;
         self.position.col = col;
     }
     
     /// Set the cursor column (alias for set_col)
     pub fn set_column(&mut self, col: u16) {
+        self.pending_wrap = false;
         self.position.col = col;
     }
     
     /// Move cursor up by n rows
     pub fn move_up(&mut self, n: u16) {
+        self.pending_wrap = false;
         self.position.row = self.position.row.saturating_sub(n);
     }
     
     /// Move cursor down by n rows
     pub fn move_down(&mut self, n: u16) {
+        self.pending_wrap = false;
         self.position.row = self.position.row.saturating_add(n);
     }
     
     /// Move cursor left by n columns
     pub fn move_left(&mut self, n: u16) {
+        self.pending_wrap = false;
         self.position.col = self.position.col.saturating_sub(n);
     }
     
     /// Move cursor right by n columns
     pub fn move_right(&mut self, n: u16) {
+        self.pending_wrap = false;
         self.position.col = self.position.col.saturating_add(n);
     }
     
     /// Move cursor left by 1 column (saturating)
     pub fn saturating_left(&mut self) {
+        self.pending_wrap = false;
         self.position.col = self.position.col.saturating_sub(1);
     }
     
@@ -76,10 +90,22 @@ impl Cursor {
     /// Restore the saved cursor position
     pub fn restore(&mut self) {
         if let Some(pos) = self.saved_position {
-            self.position = pos;
+            self.set_position(pos);
         }
     }
     
+    /// Whether the next printable character wraps to the next line first
+    pub fn pending_wrap(&self) -> bool {
+        self.pending_wrap
+    }
+    
+    /// Mark that the next printable character wraps first
+    ///
+    /// Any cursor movement clears the flag.
+    pub fn set_pending_wrap(&mut self, pending: bool) {
+        self.pending_wrap = pending;
+    }
+    
     /// Check if cursor is visible
     pub fn is_visible(&self) -> bool {
         self.visible
@@ -145,4 +171,17 @@ mod tests {
         cursor.move_up(10);
         assert_eq!(cursor.position(), Position::new(0, 0));
     }
+    
+    #[test]
+    fn test_movement_clears_pending_wrap() {
+        let mut cursor = Cursor::new();
+        cursor.set_pending_wrap(true);
+        assert!(cursor.pending_wrap());
+        cursor.move_left(1);
+        assert!(!cursor.pending_wrap());
+        
+        cursor.set_pending_wrap(true);
+        cursor.set_column(0);
+        assert!(!cursor.pending_wrap());
+    }
 }
\ No newline at end of file
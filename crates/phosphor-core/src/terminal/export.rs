@@ -0,0 +1,323 @@
+use phosphor_common::types::{AttributeFlags, Cell, CellAttributes, Color, Hyperlink, Rgb, TerminalMode};
+use std::ops::Range;
+
+use super::buffer;
+use super::state::TerminalState;
+use crate::ansi::sgr_string;
+
+/// Join lines from `buffer::copy_lines` or `styled_lines` into text with
+/// SGR sequences
+///
+/// A sequence is only written where the attributes change. They are reset
+/// before each newline, so every line can be pasted or paged on its own.
+pub fn lines_ansi(lines: &[(&[Cell], bool)]) -> String {
+    let default = CellAttributes::default();
+    let mut current = default;
+    let mut text = String::new();
+    for (i, (cells, newline)) in lines.iter().enumerate() {
+        for cell in cells.iter().filter(|cell| !cell.is_spacer()) {
+            if cell.attrs != current {
+                current = cell.attrs;
+                text.push_str(&format!("\x1b[{}m", sgr_string(&current)));
+            }
+            text.push(cell.ch);
+        }
+        if current != default && (*newline || i + 1 == lines.len()) {
+            current = default;
+            text.push_str("\x1b[0m");
+        }
+        if *newline {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// History lines (the scrollback followed by the screen) as plain text,
+/// for logs and saving output to a file
+///
+/// Attributes are dropped, autowrapped lines joined and trailing whitespace
+/// trimmed from each line. Trailing empty lines are dropped and the text
+/// ends in a newline unless it is empty.
+pub fn to_text(state: &TerminalState, lines: Range<usize>) -> String {
+    let lines: Vec<_> = lines.filter_map(|line| state.history_line(line)).collect();
+    let copied = buffer::copy_lines(lines.iter().map(|line| (line.as_ref(), 0..line.len())), true);
+    let mut text = String::new();
+    for line in buffer::lines_text(&copied).lines() {
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text.truncate(text.trim_end_matches('\n').len());
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// Take whole lines for `lines_ansi`, to replay them in another terminal
+///
+/// Unlike `buffer::copy_lines`, trailing blanks are only dropped when
+/// nothing of them shows, and every line that didn't autowrap ends in a
+/// newline. Trailing empty lines are dropped.
+pub fn styled_lines<'a>(lines: impl IntoIterator<Item = &'a [Cell]>) -> Vec<(&'a [Cell], bool)> {
+    let invisible = |cell: &Cell| {
+        cell.ch == ' ' && cell.attrs.bg_color == Color::Default && !cell.attrs.flags.contains(AttributeFlags::REVERSE)
+    };
+    let mut styled: Vec<(&[Cell], bool)> = lines.into_iter()
+        .map(|line| {
+            if buffer::is_wrapped(line) {
+                (line, false)
+            } else {
+                let end = line.iter().rposition(|cell| !invisible(cell)).map_or(0, |last| last + 1);
+                (&line[..end], true)
+            }
+        })
+        .collect();
+    while styled.last().is_some_and(|(cells, newline)| cells.is_empty() && *newline) {
+        styled.pop();
+    }
+    styled
+}
+
+/// History lines (the scrollback followed by the screen) as a standalone
+/// HTML fragment, for sharing command output
+///
+/// See `lines_html`; blanks that show a background are kept as in
+/// `styled_lines`.
+pub fn to_html(state: &TerminalState, lines: Range<usize>) -> String {
+    let lines: Vec<_> = lines.filter_map(|line| state.history_line(line)).collect();
+    lines_html(state, &styled_lines(lines.iter().map(|line| line.as_ref())))
+}
+
+/// Join lines from `buffer::copy_lines` or `styled_lines` into an HTML
+/// `<pre>` block with inline styles
+///
+/// Colors are resolved against the terminal's palette and default colors.
+/// OSC 8 hyperlinks become links when they point at the web or a mailbox;
+/// other schemes are left as plain text.
+pub fn lines_html(state: &TerminalState, lines: &[(&[Cell], bool)]) -> String {
+    let (foreground, background) = default_colors(state);
+    let mut html = format!(
+        "<pre style=\"color: {}; background-color: {};\">",
+        css_color(foreground),
+        css_color(background)
+    );
+    let mut open_link: Option<&Hyperlink> = None;
+    let mut open_style = String::new();
+    for (cells, newline) in lines {
+        for cell in cells.iter().filter(|cell| !cell.is_spacer()) {
+            let link = cell.hyperlink.as_deref().filter(|link| is_safe_link(&link.uri));
+            let style = cell_style(state, &cell.attrs);
+            if link != open_link || style != open_style {
+                if !open_style.is_empty() {
+                    html.push_str("</span>");
+                    open_style.clear();
+                }
+                if link != open_link {
+                    if open_link.is_some() {
+                        html.push_str("</a>");
+                    }
+                    if let Some(link) = link {
+                        html.push_str("<a href=\"");
+                        push_escaped(&mut html, &link.uri);
+                        html.push_str("\">");
+                    }
+                    open_link = link;
+                }
+                if !style.is_empty() {
+                    html.push_str(&format!("<span style=\"{}\">", style));
+                }
+                open_style = style;
+            }
+            push_escaped(&mut html, cell.ch.encode_utf8(&mut [0; 4]));
+        }
+        if *newline {
+            html.push('\n');
+        }
+    }
+    if !open_style.is_empty() {
+        html.push_str("</span>");
+    }
+    if open_link.is_some() {
+        html.push_str("</a>");
+    }
+    html.push_str("</pre>");
+    html
+}
+
+fn push_escaped(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            ch => html.push(ch),
+        }
+    }
+}
+
+/// Whether a link can be followed from a shared page without running script
+fn is_safe_link(uri: &str) -> bool {
+    let scheme = uri.split_once(':').map_or("", |(scheme, _)| scheme);
+    ["http", "https", "ftp", "mailto"].iter().any(|safe| scheme.eq_ignore_ascii_case(safe))
+}
+
+/// Default foreground and background as drawn, swapped under reverse video
+fn default_colors(state: &TerminalState) -> (Rgb, Rgb) {
+    let foreground = state.resolve_color(Color::Default);
+    let background = state.resolve_background(Color::Default);
+    if state.mode().contains(TerminalMode::REVERSE_VIDEO) {
+        (background, foreground)
+    } else {
+        (foreground, background)
+    }
+}
+
+/// Inline CSS for a cell's attributes, empty for the default rendition
+fn cell_style(state: &TerminalState, attrs: &CellAttributes) -> String {
+    let (default_fg, default_bg) = default_colors(state);
+    let mut fg = match attrs.fg_color {
+        Color::Default => default_fg,
+        color => state.resolve_color(color),
+    };
+    let mut bg = match attrs.bg_color {
+        Color::Default => default_bg,
+        color => state.resolve_background(color),
+    };
+    if attrs.flags.contains(AttributeFlags::REVERSE) {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.flags.contains(AttributeFlags::HIDDEN) {
+        fg = bg;
+    }
+    
+    let mut style = Vec::new();
+    if fg != default_fg {
+        style.push(format!("color: {}", css_color(fg)));
+    }
+    if bg != default_bg {
+        style.push(format!("background-color: {}", css_color(bg)));
+    }
+    let flags = [
+        (AttributeFlags::BOLD, "font-weight: bold"),
+        (AttributeFlags::DIM, "opacity: 0.5"),
+        (AttributeFlags::ITALIC, "font-style: italic"),
+    ];
+    for (flag, css) in flags {
+        if attrs.flags.contains(flag) {
+            style.push(css.to_string());
+        }
+    }
+    
+    let underline = [
+        (AttributeFlags::UNDERLINE, "solid"),
+        (AttributeFlags::DOUBLE_UNDERLINE, "double"),
+        (AttributeFlags::CURLY_UNDERLINE, "wavy"),
+        (AttributeFlags::DOTTED_UNDERLINE, "dotted"),
+        (AttributeFlags::DASHED_UNDERLINE, "dashed"),
+    ]
+    .into_iter()
+    .find(|(flag, _)| attrs.flags.contains(*flag));
+    let mut lines = Vec::new();
+    if underline.is_some() {
+        lines.push("underline");
+    }
+    if attrs.flags.contains(AttributeFlags::STRIKETHROUGH) {
+        lines.push("line-through");
+    }
+    if attrs.flags.contains(AttributeFlags::OVERLINE) {
+        lines.push("overline");
+    }
+    if !lines.is_empty() {
+        style.push(format!("text-decoration-line: {}", lines.join(" ")));
+    }
+    if let Some((_, kind)) = underline {
+        if kind != "solid" {
+            style.push(format!("text-decoration-style: {}", kind));
+        }
+        if let Some(color) = attrs.underline_color {
+            style.push(format!("text-decoration-color: {}", css_color(state.resolve_color(color))));
+        }
+    }
+    style.join("; ")
+}
+
+fn css_color(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::buffer::copy_lines;
+    use phosphor_common::types::{Position, Size};
+    
+    #[test]
+    fn test_lines_ansi() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.write_str("a");
+        state.set_foreground_color(Color::Red);
+        state.set_attribute_flag(AttributeFlags::BOLD, true);
+        state.write_str("bc\r\n");
+        state.write_str("d");
+        
+        let lines = state.screen_buffer().lines();
+        let copied = copy_lines(lines.iter().map(|line| (line.as_slice(), 0..line.len())), true);
+        assert_eq!(lines_ansi(&copied), "a\x1b[0;1;31mbc\x1b[0m\n\x1b[0;1;31md\x1b[0m");
+    }
+    
+    #[test]
+    fn test_lines_html() {
+        let mut state = TerminalState::new(Size::new(10, 1));
+        state.write_str("<a> ");
+        state.set_foreground_color(Color::Rgb(255, 128, 0));
+        state.set_attribute_flag(AttributeFlags::ITALIC, true);
+        state.write_str("&");
+        state.set_cursor_position(Position::new(0, 0));
+        
+        let line = state.screen_buffer().get_line(0).unwrap();
+        let copied = copy_lines([(line.as_slice(), 0..line.len())], true);
+        assert_eq!(
+            lines_html(&state, &copied),
+            "<pre style=\"color: #e5e5e5; background-color: #000000;\">&lt;a&gt; \
+             <span style=\"color: #ff8000; font-style: italic\">&amp;</span></pre>"
+        );
+    }
+    
+    #[test]
+    fn test_to_html() {
+        let mut state = TerminalState::new(Size::new(8, 2));
+        state.write_str("old\r\n");
+        state.set_hyperlink(Some(Hyperlink { id: None, uri: "https://a.test/?x=1&y".into() }));
+        state.write_str("li");
+        state.set_attribute_flag(AttributeFlags::BOLD, true);
+        state.write_str("nk");
+        state.set_hyperlink(Some(Hyperlink { id: None, uri: "javascript:alert(1)".into() }));
+        state.write_str("!\r\n");
+        state.set_hyperlink(None);
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        
+        assert_eq!(
+            to_html(&state, 0..3),
+            "<pre style=\"color: #e5e5e5; background-color: #000000;\">old\n\
+             <a href=\"https://a.test/?x=1&amp;y\">li<span style=\"font-weight: bold\">nk</span></a>\
+             <span style=\"font-weight: bold\">!\n</span></pre>"
+        );
+    }
+    
+    #[test]
+    fn test_to_text() {
+        let mut state = TerminalState::new(Size::new(5, 4));
+        state.write_str("one  \r\n");
+        state.set_attribute_flag(AttributeFlags::BOLD, true);
+        state.write_str("wrapped\u{a0}\r\n\r\n");
+        state.write_str("x\r\n");
+        assert_eq!(state.scrollback_buffer().len(), 2);
+        
+        assert_eq!(state.export_text(true), "one\nwrapped\n\nx\n");
+        assert_eq!(state.export_text(false), "ed\n\nx\n");
+        assert_eq!(to_text(&state, 0..1), "one\n");
+        assert_eq!(TerminalState::new(Size::new(5, 2)).export_text(true), "");
+    }
+}
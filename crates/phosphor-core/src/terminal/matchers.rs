@@ -0,0 +1,122 @@
+use phosphor_common::types::Position;
+use regex::Regex;
+
+/// A named pattern to look for in the visible text
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pub name: String,
+    pub(crate) regex: Regex,
+}
+
+/// Patterns such as URLs and hashes, evaluated over the visible rows on request
+///
+/// Matchers are tried in registration order; where hits overlap, the
+/// earlier matcher wins.
+#[derive(Debug, Clone, Default)]
+pub struct MatcherRegistry {
+    matchers: Vec<Matcher>,
+}
+
+impl MatcherRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// A registry with `url`, `ipv4`, `git-hash` and `path` matchers
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        let defaults = [
+            ("url", r#"\b(?:https?|ftp|file)://[^\s<>"']*[^\s<>"'.,;:!?)\]}]"#),
+            ("ipv4", r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b"),
+            ("git-hash", r"\b[0-9a-f]{7,40}\b"),
+            ("path", r"(?:~|\.\.?)?/[\w.-]+(?:/[\w.-]+)*/?"),
+        ];
+        for (name, pattern) in defaults {
+            registry.register(name, pattern).expect("default matchers are valid patterns");
+        }
+        registry
+    }
+    
+    /// Add a matcher, replacing any with the same name in place
+    pub fn register(&mut self, name: impl Into<String>, pattern: &str) -> Result<(), regex::Error> {
+        let matcher = Matcher { name: name.into(), regex: Regex::new(pattern)? };
+        match self.matchers.iter_mut().find(|existing| existing.name == matcher.name) {
+            Some(existing) => *existing = matcher,
+            None => self.matchers.push(matcher),
+        }
+        Ok(())
+    }
+    
+    /// Remove a matcher, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let len = self.matchers.len();
+        self.matchers.retain(|matcher| matcher.name != name);
+        self.matchers.len() != len
+    }
+    
+    /// The registered matchers, in order
+    pub fn matchers(&self) -> &[Matcher] {
+        &self.matchers
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.matchers.is_empty()
+    }
+}
+
+/// Text found by a matcher, with the inclusive range of screen cells it covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternHit {
+    /// Name of the matcher that found it
+    pub matcher: String,
+    pub text: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl PatternHit {
+    /// Whether the hit covers a screen cell
+    pub fn contains(&self, pos: Position) -> bool {
+        let key = |pos: Position| (pos.row, pos.col);
+        key(self.start) <= key(pos) && key(pos) <= key(self.end)
+    }
+    
+    /// Whether two hits share any cell
+    pub fn overlaps(&self, other: &PatternHit) -> bool {
+        self.contains(other.start) || other.contains(self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_default_matchers() {
+        let registry = MatcherRegistry::with_defaults();
+        let find = |name: &str, text: &str| -> Vec<String> {
+            let matcher = registry.matchers().iter().find(|m| m.name == name).unwrap();
+            matcher.regex.find_iter(text).map(|m| m.as_str().to_string()).collect()
+        };
+        
+        assert_eq!(find("url", "see (https://example.com/a?b=1)."), vec!["https://example.com/a?b=1"]);
+        assert_eq!(find("ipv4", "from 10.0.0.255 not 256.1.1.1"), vec!["10.0.0.255"]);
+        assert_eq!(find("git-hash", "commit 7bb7680 and deadbeefcafe"), vec!["7bb7680", "deadbeefcafe"]);
+        assert_eq!(find("path", "open ~/src/main.rs or ./a/b"), vec!["~/src/main.rs", "./a/b"]);
+    }
+    
+    #[test]
+    fn test_register() {
+        let mut registry = MatcherRegistry::new();
+        registry.register("ticket", r"#\d+").unwrap();
+        registry.register("word", r"\w+").unwrap();
+        registry.register("ticket", r"[A-Z]+-\d+").unwrap();
+        let names: Vec<_> = registry.matchers().iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["ticket", "word"]);
+        
+        assert!(registry.register("bad", "(").is_err());
+        assert!(registry.unregister("word"));
+        assert!(!registry.unregister("word"));
+    }
+}
@@ -1,5 +1,18 @@
 pub mod buffer;
+pub mod config;
 pub mod cursor;
+pub mod export;
+pub mod matchers;
+pub mod persist;
+pub mod search;
+pub mod selection;
+pub mod semantic;
 pub mod state;
 
+pub use buffer::LineDamage;
+pub use config::{BellAction, BellPolicy, CursorBlinkInterval, ScrollbackLimit, SessionLimits, TerminalConfig, WordBoundaries};
+pub use matchers::{Matcher, MatcherRegistry, PatternHit};
+pub use search::{SearchDirection, SearchMatch, SearchQuery};
+pub use selection::{Selection, SelectionMode};
+pub use semantic::{CommandBlock, HistoryPoint, ZoneRange};
 pub use state::TerminalState;
\ No newline at end of file
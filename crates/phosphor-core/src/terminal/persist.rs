@@ -0,0 +1,92 @@
+use phosphor_common::geometry::CellMetrics;
+use phosphor_common::types::{
+    CellAttributes, CellTags, Charset, Color, CursorStyle, DynamicColors, Hyperlink, LineRendition, Size,
+    TerminalMode,
+};
+use serde::{Deserialize, Serialize};
+
+use super::buffer::{self, EncodedLine, ScreenBuffer};
+use super::cursor::Cursor;
+use super::state::SavedCursor;
+
+/// Version of the format written by `TerminalState::save`
+///
+/// Bump it whenever `SavedState` changes incompatibly; `restore` rejects
+/// any other version.
+pub const STATE_VERSION: u32 = 1;
+
+/// Just the version, read before the rest so a mismatch is reported as such
+#[derive(Deserialize)]
+pub(crate) struct SavedVersion {
+    pub version: u32,
+}
+
+/// The lines and renditions of a screen buffer
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedScreen {
+    pub lines: Vec<EncodedLine>,
+    pub renditions: Vec<LineRendition>,
+}
+
+impl SavedScreen {
+    pub fn new(buffer: &ScreenBuffer) -> Self {
+        Self {
+            lines: buffer.lines().iter().map(|line| buffer::encode_line(line)).collect(),
+            renditions: buffer.line_renditions().to_vec(),
+        }
+    }
+    
+    pub fn into_buffer(self, size: Size) -> ScreenBuffer {
+        let lines = self.lines.into_iter().map(buffer::decode_line).collect();
+        ScreenBuffer::from_lines(size, lines, self.renditions)
+    }
+}
+
+/// Everything `TerminalState::save` keeps
+///
+/// Left out are the configuration, which the embedder supplies again, the
+/// printer and pattern matchers, undelivered events and responses, the
+/// exit codes and timings of past commands, and view state such as the
+/// selection and display offset.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedState {
+    pub version: u32,
+    pub size: Size,
+    pub cursor: Cursor,
+    pub saved_cursor: Option<SavedCursor>,
+    /// DECSC state of the screen not being shown
+    #[serde(default)]
+    pub inactive_saved_cursor: Option<SavedCursor>,
+    /// The screen being shown
+    pub screen: SavedScreen,
+    /// The main screen while the alternate screen is shown
+    pub alternate: Option<SavedScreen>,
+    /// The alternate screen's contents while the main screen is shown
+    pub inactive_alternate: Option<SavedScreen>,
+    /// Scrollback lines, oldest first
+    pub scrollback: Vec<EncodedLine>,
+    pub mode: TerminalMode,
+    pub cursor_style: CursorStyle,
+    pub active_attributes: CellAttributes,
+    pub charsets: [Charset; 4],
+    pub active_charset: usize,
+    pub semantic_zone: CellTags,
+    pub protected: bool,
+    pub hyperlink: Option<Hyperlink>,
+    pub color_palette: Vec<Color>,
+    pub dynamic_colors: DynamicColors,
+    pub tab_stops: Vec<u16>,
+    pub scroll_top: u16,
+    pub scroll_bottom: u16,
+    pub left_margin: u16,
+    pub right_margin: u16,
+    pub focused: bool,
+    pub cell_metrics: Option<CellMetrics>,
+    pub title: String,
+    pub icon_name: String,
+    pub title_stack: Vec<(String, String)>,
+    pub modify_other_keys: u8,
+    pub keyboard_flags: [Vec<u8>; 2],
+    pub printer_controller: bool,
+    pub autoprint: bool,
+}
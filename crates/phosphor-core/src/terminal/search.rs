@@ -0,0 +1,114 @@
+use phosphor_common::types::Cell;
+use regex::{Regex, RegexBuilder};
+
+use super::semantic::HistoryPoint;
+
+/// Which way to step through matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    /// Towards the bottom of the screen
+    Forward,
+    /// Towards the oldest scrollback line
+    Backward,
+}
+
+/// A compiled search pattern
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    regex: Regex,
+}
+
+impl SearchQuery {
+    /// Search for literal text
+    pub fn text(text: &str, case_sensitive: bool) -> Self {
+        Self::regex(&regex::escape(text), case_sensitive).expect("escaped text is a valid pattern")
+    }
+    
+    /// Search for a regular expression
+    pub fn regex(pattern: &str, case_sensitive: bool) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern).case_insensitive(!case_sensitive).build()?;
+        Ok(Self { regex })
+    }
+    
+    /// Matches in one logical line, given its rows starting at history line `first`
+    pub(crate) fn find_in(&self, rows: &[&[Cell]], first: usize) -> Vec<SearchMatch> {
+        LineText::new(rows, first).find_iter(&self.regex).map(|(found, _)| found).collect()
+    }
+}
+
+/// The text of a logical line, with the cells each character covers
+///
+/// Rows are joined without newlines, so matches can span autowrapped rows.
+pub(crate) struct LineText {
+    text: String,
+    /// Byte offset of each character with its first and last cell
+    cells: Vec<(usize, HistoryPoint, HistoryPoint)>,
+}
+
+impl LineText {
+    /// Join the rows of a logical line, the first being history line `first`
+    pub(crate) fn new(rows: &[&[Cell]], first: usize) -> Self {
+        let mut text = String::new();
+        let mut cells = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            for (col, cell) in row.iter().enumerate().filter(|(_, cell)| !cell.is_spacer()) {
+                let start = HistoryPoint::new(first + i, col as u16);
+                let end = HistoryPoint::new(first + i, (col + cell.width.max(1) as usize - 1) as u16);
+                cells.push((text.len(), start, end));
+                text.push(cell.ch);
+            }
+        }
+        while text.ends_with(' ') {
+            text.pop();
+            cells.pop();
+        }
+        Self { text, cells }
+    }
+    
+    /// Non-empty matches of `regex` with the cells they cover
+    pub(crate) fn find_iter<'a>(&'a self, regex: &'a Regex) -> impl Iterator<Item = (SearchMatch, &'a str)> + 'a {
+        regex
+            .find_iter(&self.text)
+            .filter(|found| !found.is_empty())
+            .map(|found| {
+                let start = self.cells.partition_point(|(offset, _, _)| *offset < found.start());
+                let end = self.cells.partition_point(|(offset, _, _)| *offset < found.end()) - 1;
+                let span = SearchMatch { start: self.cells[start].1, end: self.cells[end].2 };
+                (span, found.as_str())
+            })
+    }
+}
+
+/// Inclusive range of cells covered by a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: HistoryPoint,
+    pub end: HistoryPoint,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn row(text: &str) -> Vec<Cell> {
+        text.chars().map(Cell::new).collect()
+    }
+    
+    #[test]
+    fn test_find_in() {
+        let rows = [row("a.b A.B"), row("a.b  ")];
+        let rows: Vec<&[Cell]> = rows.iter().map(Vec::as_slice).collect();
+        let found = |query: SearchQuery| -> Vec<(usize, u16, usize, u16)> {
+            query.find_in(&rows, 3).iter()
+                .map(|m| (m.start.line, m.start.col, m.end.line, m.end.col))
+                .collect()
+        };
+        
+        assert_eq!(found(SearchQuery::text("a.b", true)), vec![(3, 0, 3, 2), (4, 0, 4, 2)]);
+        assert_eq!(found(SearchQuery::text("a.b", false)).len(), 3);
+        assert_eq!(found(SearchQuery::text("Ba.", true)), vec![(3, 6, 4, 1)]);
+        assert_eq!(found(SearchQuery::regex(r"a\.b\s*$", true).unwrap()), vec![(4, 0, 4, 2)]);
+        assert!(found(SearchQuery::regex("x*", true).unwrap()).is_empty());
+        assert!(SearchQuery::regex("(", true).is_err());
+    }
+}
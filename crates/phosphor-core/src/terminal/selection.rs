@@ -0,0 +1,77 @@
+use super::semantic::HistoryPoint;
+
+/// How a selection grows from its anchor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionMode {
+    /// Cell by cell, in reading order
+    #[default]
+    Character,
+    /// Whole words, e.g. after a double click
+    Word,
+    /// Whole lines, following autowrapped lines, e.g. after a triple click
+    Line,
+    /// A rectangle of columns, e.g. with Alt held
+    Block,
+}
+
+/// A selection in history coordinates, so it stays on the same text as
+/// lines scroll into scrollback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub mode: SelectionMode,
+    /// Where the selection started
+    pub anchor: HistoryPoint,
+    /// Where the selection was last extended to
+    pub head: HistoryPoint,
+}
+
+impl Selection {
+    /// Start a selection at a point
+    pub fn new(mode: SelectionMode, point: HistoryPoint) -> Self {
+        Self { mode, anchor: point, head: point }
+    }
+    
+    /// The selected cells from the first to the last, before word and line expansion
+    ///
+    /// For block selections these are the top-left and bottom-right corners.
+    pub fn bounds(&self) -> (HistoryPoint, HistoryPoint) {
+        match self.mode {
+            SelectionMode::Block => (
+                HistoryPoint::new(self.anchor.line.min(self.head.line), self.anchor.col.min(self.head.col)),
+                HistoryPoint::new(self.anchor.line.max(self.head.line), self.anchor.col.max(self.head.col)),
+            ),
+            _ => (self.anchor.min(self.head), self.anchor.max(self.head)),
+        }
+    }
+    
+    /// Move the selection `count` lines up, as when history lines are dropped
+    ///
+    /// Returns false if the selection starts on a dropped line.
+    pub fn shift_up(&mut self, count: usize) -> bool {
+        if self.anchor.line < count || self.head.line < count {
+            return false;
+        }
+        self.anchor.line -= count;
+        self.head.line -= count;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_selection_bounds() {
+        let mut selection = Selection::new(SelectionMode::Character, HistoryPoint::new(5, 3));
+        selection.head = HistoryPoint::new(2, 7);
+        assert_eq!(selection.bounds(), (HistoryPoint::new(2, 7), HistoryPoint::new(5, 3)));
+        
+        selection.mode = SelectionMode::Block;
+        assert_eq!(selection.bounds(), (HistoryPoint::new(2, 3), HistoryPoint::new(5, 7)));
+        
+        assert!(selection.shift_up(2));
+        assert_eq!(selection.anchor, HistoryPoint::new(3, 3));
+        assert!(!selection.shift_up(3));
+    }
+}
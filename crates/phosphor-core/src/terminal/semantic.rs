@@ -0,0 +1,199 @@
+use phosphor_common::types::{Cell, CellTags};
+use std::time::{Duration, Instant};
+
+use super::buffer;
+
+/// A cell position in history coordinates
+///
+/// Line 0 is the oldest scrollback line; the screen follows the scrollback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HistoryPoint {
+    pub line: usize,
+    pub col: u16,
+}
+
+impl HistoryPoint {
+    pub fn new(line: usize, col: u16) -> Self {
+        Self { line, col }
+    }
+}
+
+/// Inclusive range of cells covered by one semantic zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZoneRange {
+    pub start: HistoryPoint,
+    pub end: HistoryPoint,
+}
+
+/// One prompt/command/output cycle marked by OSC 133
+///
+/// Zones the shell never marked (e.g. a command with no output) are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandBlock {
+    pub prompt: Option<ZoneRange>,
+    pub command: Option<ZoneRange>,
+    pub output: Option<ZoneRange>,
+    /// The command line as entered, with trailing blanks trimmed
+    pub text: String,
+    /// Whether the shell reported the command finished (OSC 133;D)
+    pub finished: bool,
+    /// Exit status sent with OSC 133;D
+    pub exit_code: Option<i32>,
+    /// Time from the start of the output (OSC 133;C) to OSC 133;D
+    pub duration: Option<Duration>,
+}
+
+impl CommandBlock {
+    /// The first history line the block covers
+    pub fn first_line(&self) -> Option<usize> {
+        self.zones().map(|zone| zone.start.line).min()
+    }
+    
+    /// The last history line the block covers
+    pub fn last_line(&self) -> Option<usize> {
+        self.zones().map(|zone| zone.end.line).max()
+    }
+    
+    fn zones(&self) -> impl Iterator<Item = ZoneRange> {
+        [self.prompt, self.command, self.output].into_iter().flatten()
+    }
+    
+    fn zone_mut(&mut self, zone: CellTags) -> Option<&mut Option<ZoneRange>> {
+        if zone == CellTags::PROMPT {
+            Some(&mut self.prompt)
+        } else if zone == CellTags::COMMAND {
+            Some(&mut self.command)
+        } else if zone == CellTags::OUTPUT {
+            Some(&mut self.output)
+        } else {
+            None
+        }
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.prompt.is_none() && self.command.is_none() && self.output.is_none()
+    }
+}
+
+/// Group the zone tags of a run of lines into command blocks
+///
+/// A block starts at each prompt that follows a command or output zone.
+/// Untagged cells (unmarked output, unwritten blanks) are skipped.
+pub fn command_blocks(lines: impl IntoIterator<Item = impl AsRef<[Cell]>>) -> Vec<CommandBlock> {
+    let mut blocks = Vec::new();
+    let mut current = CommandBlock::default();
+    let mut last_zone = CellTags::empty();
+    let mut last_wrapped = false;
+    
+    for (line, cells) in lines.into_iter().enumerate() {
+        let cells = cells.as_ref();
+        for (col, cell) in cells.iter().enumerate() {
+            let zone = cell.tags & CellTags::ZONES;
+            let point = HistoryPoint::new(line, col as u16);
+            
+            if zone == CellTags::PROMPT && last_zone != CellTags::PROMPT && !current.is_empty() {
+                finish_text(&mut current);
+                blocks.push(std::mem::take(&mut current));
+            }
+            
+            if zone == CellTags::COMMAND && !cell.is_spacer() {
+                // Rows the command wrapped onto join up; otherwise it spans lines
+                if let Some(range) = current.command.filter(|range| range.end.line < line) {
+                    if !(last_wrapped && range.end.line + 1 == line) {
+                        current.text.push('\n');
+                    }
+                }
+                current.text.push(cell.ch);
+            }
+            
+            if let Some(range) = current.zone_mut(zone) {
+                match range {
+                    Some(range) => range.end = point,
+                    None => *range = Some(ZoneRange { start: point, end: point }),
+                }
+                last_zone = zone;
+            }
+        }
+        last_wrapped = buffer::is_wrapped(cells);
+    }
+    
+    if !current.is_empty() {
+        finish_text(&mut current);
+        blocks.push(current);
+    }
+    blocks
+}
+
+fn finish_text(block: &mut CommandBlock) {
+    block.text.truncate(block.text.trim_end().len());
+}
+
+/// A command the shell ran, recorded from its OSC 133 marks
+///
+/// Exit codes and timings aren't held in cells, so they are kept here and
+/// matched to `CommandBlock`s by line.
+#[derive(Debug, Clone)]
+pub(crate) struct CommandRun {
+    /// Line the command was entered on, as a history line plus
+    /// `ScrollbackBuffer::dropped`
+    pub line: usize,
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
+    pub exit_code: Option<i32>,
+}
+
+impl CommandRun {
+    /// Copy the run's status into the block it belongs to
+    pub fn apply_to(&self, block: &mut CommandBlock) {
+        block.finished = self.finished.is_some();
+        block.exit_code = self.exit_code;
+        block.duration = self.started.zip(self.finished).map(|(started, finished)| finished - started);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn line(text: &str, zones: &[CellTags]) -> Vec<Cell> {
+        text.chars()
+            .zip(zones)
+            .map(|(ch, &tags)| Cell { tags, ..Cell::new(ch) })
+            .collect()
+    }
+    
+    #[test]
+    fn test_command_blocks() {
+        let p = CellTags::PROMPT;
+        let c = CellTags::COMMAND;
+        let o = CellTags::OUTPUT;
+        let n = CellTags::empty();
+        let lines = [
+            line("$ ls ", &[p, p, c, c, n]),
+            line("a b", &[o, o, o]),
+            line("$ true", &[p, p, c, c, c, c]),
+            line("$ ", &[p, p]),
+        ];
+        
+        let blocks = command_blocks(lines.iter().map(|l| l.as_slice()));
+        assert_eq!(blocks.len(), 3);
+        
+        let range = |l1, c1, l2, c2| Some(ZoneRange {
+            start: HistoryPoint::new(l1, c1),
+            end: HistoryPoint::new(l2, c2),
+        });
+        assert_eq!(blocks[0].prompt, range(0, 0, 0, 1));
+        assert_eq!(blocks[0].command, range(0, 2, 0, 3));
+        assert_eq!(blocks[0].output, range(1, 0, 1, 2));
+        assert_eq!(blocks[1].command, range(2, 2, 2, 5));
+        assert_eq!(blocks[1].output, None);
+        assert_eq!(blocks[2].prompt, range(3, 0, 3, 1));
+        assert_eq!(blocks[2].command, None);
+    }
+    
+    #[test]
+    fn test_untagged_history() {
+        let lines = [line("plain", &[CellTags::empty(); 5])];
+        assert!(command_blocks(lines.iter().map(|l| l.as_slice())).is_empty());
+    }
+}
@@ -1,44 +1,231 @@
 use phosphor_common::types::{
-    Cell, Position, Size, TerminalMode, TerminalSnapshot, 
-    CellAttributes, Color, CursorStyle, AttributeFlags
+    Cell, GridCell, GridSnapshot, Hyperlink, Position, Size, TerminalMode, TerminalSnapshot, 
+    CellAttributes, CellTags, Charset, Color, CursorStyle, AttributeFlags,
+    DynamicColor, DynamicColors, LineRendition, Rgb
 };
-use phosphor_common::traits::Mode;
-use tracing::{debug, instrument};
+use phosphor_common::color::default_rgb;
+use phosphor_common::error::{PhosphorError, Result};
+use phosphor_common::geometry::CellMetrics;
+use phosphor_common::traits::{ClipboardType, KeyboardFlagsMode, Mode, TitleKind};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
+use unicode_width::UnicodeWidthChar;
 
-use super::buffer::{ScreenBuffer, ScrollbackBuffer};
+use super::buffer::{self, LineDamage, ScreenBuffer, ScrollbackBuffer};
+use super::config::{BellAction, CursorBlinkInterval, ScrollbackLimit, TerminalConfig};
+use super::export;
 use super::cursor::Cursor;
+use super::matchers::{MatcherRegistry, PatternHit};
+use super::persist::{SavedScreen, SavedState, SavedVersion, STATE_VERSION};
+use super::search::{LineText, SearchDirection, SearchMatch, SearchQuery};
+use super::selection::{Selection, SelectionMode};
+use super::semantic::{self, CommandBlock, CommandRun, HistoryPoint, ZoneRange};
+use crate::events::Event;
+use crate::printer::PrinterSink;
+use crate::input::{InputModes, MouseEncoding, MouseTracking};
+
+/// State saved by DECSC and restored by DECRC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedCursor {
+    /// Position and pending wrap
+    cursor: Cursor,
+    attributes: CellAttributes,
+    charsets: [Charset; 4],
+    active_charset: usize,
+    origin_mode: bool,
+    protected: bool,
+}
 
 /// Terminal state machine that manages the display buffer and cursor
 pub struct TerminalState {
+    config: TerminalConfig,
     size: Size,
     cursor: Cursor,
-    saved_cursor: Option<Cursor>,
+    saved_cursor: Option<SavedCursor>,
+    /// DECSC state of the screen not being shown; each screen has its own
+    inactive_saved_cursor: Option<SavedCursor>,
     screen_buffer: ScreenBuffer,
     alternate_buffer: Option<ScreenBuffer>,
+    /// Contents of the alternate screen while the main screen is shown
+    inactive_alternate: Option<ScreenBuffer>,
     scrollback_buffer: ScrollbackBuffer,
     mode: TerminalMode,
     cursor_style: CursorStyle,
     active_attributes: CellAttributes,
+    /// Character sets designated into G0-G3
+    charsets: [Charset; 4],
+    /// Index of the set invoked into GL by SO/SI (0 = G0, 1 = G1)
+    active_charset: usize,
+    /// Semantic zone stamped on written cells
+    semantic_zone: CellTags,
+    /// Written cells are protected from selective erase (DECSCA)
+    protected: bool,
+    /// Hyperlink stamped on written cells (OSC 8)
+    hyperlink: Option<Arc<Hyperlink>>,
     color_palette: Vec<Color>,
+    /// Current default foreground, background and cursor colors
+    dynamic_colors: DynamicColors,
     tab_stops: Vec<u16>,
+    /// Top margin of the scroll region (inclusive, 0-based)
+    scroll_top: u16,
+    /// Bottom margin of the scroll region (inclusive, 0-based)
+    scroll_bottom: u16,
+    /// Left margin (inclusive, 0-based), only set while DECLRMM is enabled
+    left_margin: u16,
+    /// Right margin (inclusive, 0-based), only set while DECLRMM is enabled
+    right_margin: u16,
+    /// Whether the frontend window has focus
+    focused: bool,
+    /// Events raised while processing output, drained by the terminal loop
+    pending_events: Vec<Event>,
+    /// Replies to the application, drained by the terminal loop and written to the PTY
+    responses: Vec<Vec<u8>>,
+    /// Pixel size of a cell as reported by the frontend
+    cell_metrics: Option<CellMetrics>,
+    /// Window title set by the application
+    title: String,
+    /// Icon name set by the application
+    icon_name: String,
+    /// Titles saved by XTWINOPS 22, as (title, icon name)
+    title_stack: Vec<(String, String)>,
+    /// XTMODKEYS modifyOtherKeys level
+    modify_other_keys: u8,
+    /// Kitty keyboard flag stacks for the main and alternate screens
+    keyboard_flags: [Vec<u8>; 2],
+    /// Receives printer output; discarded when unset
+    printer: Option<Box<dyn PrinterSink>>,
+    /// Output goes to the printer instead of the screen (MC 5)
+    printer_controller: bool,
+    /// Bytes of MC 4 matched at the end of the raw printer output so far
+    printer_exit_matched: usize,
+    /// Lines are printed as the cursor leaves them (MC ? 5)
+    autoprint: bool,
+    /// Cursor position and visibility when damage was last taken
+    damaged_cursor: Option<(Position, bool)>,
+    /// Text selected by the user
+    selection: Option<Selection>,
+    /// Matches tagged by `highlight_search`
+    search_highlights: Vec<SearchMatch>,
+    /// Patterns found on request by `pattern_hits`
+    matchers: MatcherRegistry,
+    /// How many lines the view is scrolled back into history
+    display_offset: usize,
+    /// Keep the view where it is on new output instead of snapping to the bottom
+    display_pinned: bool,
+    /// Exit status and timing of recent commands, oldest first
+    command_runs: VecDeque<CommandRun>,
+    /// Text of lines completed by a line feed, while collecting for triggers
+    completed_lines: Option<Vec<String>>,
 }
 
+/// Deepest title stack kept; older entries are dropped (as in xterm)
+const MAX_TITLE_STACK: usize = 10;
+
+/// Deepest kitty keyboard flag stack kept; older entries are dropped
+const MAX_KEYBOARD_FLAGS_STACK: usize = 16;
+
 impl TerminalState {
     /// Create a new terminal state with the given size
     pub fn new(size: Size) -> Self {
+        Self::with_config(size, TerminalConfig::default())
+    }
+    
+    /// Create a new terminal state with the given size and configuration
+    pub fn with_config(size: Size, config: TerminalConfig) -> Self {
         debug!("Creating terminal state with size {:?}", size);
+        let dynamic_colors = config.colors;
+        let scrollback_buffer = Self::new_scrollback(&config);
         Self {
+            config,
             size,
             cursor: Cursor::new(),
             saved_cursor: None,
+            inactive_saved_cursor: None,
             screen_buffer: ScreenBuffer::new(size),
             alternate_buffer: None,
-            scrollback_buffer: ScrollbackBuffer::new(10_000), // 10k lines
+            inactive_alternate: None,
+            scrollback_buffer,
             mode: TerminalMode::default(),
             cursor_style: CursorStyle::default(),
             active_attributes: CellAttributes::default(),
+            charsets: [Charset::Ascii; 4],
+            active_charset: 0,
+            semantic_zone: CellTags::empty(),
+            protected: false,
+            hyperlink: None,
             color_palette: Self::default_palette(),
+            dynamic_colors,
             tab_stops: Self::default_tab_stops(size.cols),
+            scroll_top: 0,
+            scroll_bottom: size.rows.saturating_sub(1),
+            left_margin: 0,
+            right_margin: size.cols.saturating_sub(1),
+            focused: true,
+            pending_events: Vec::new(),
+            responses: Vec::new(),
+            cell_metrics: None,
+            title: String::new(),
+            icon_name: String::new(),
+            title_stack: Vec::new(),
+            modify_other_keys: 0,
+            keyboard_flags: [Vec::new(), Vec::new()],
+            printer: None,
+            printer_controller: false,
+            printer_exit_matched: 0,
+            autoprint: false,
+            damaged_cursor: None,
+            selection: None,
+            search_highlights: Vec::new(),
+            matchers: MatcherRegistry::new(),
+            display_offset: 0,
+            display_pinned: false,
+            command_runs: VecDeque::new(),
+            completed_lines: None,
+        }
+    }
+    
+    /// Reset to the initial state (RIS)
+    ///
+    /// The configuration, palette overrides (OSC 4), frontend state (focus,
+    /// cell metrics and the window titles), the printer and any undelivered
+    /// events or responses survive the reset. So do the scrollback and the commands recorded
+    /// in it if `keep_scrollback_on_reset` is set.
+    pub fn reset(&mut self) {
+        let mut fresh = Self::with_config(self.size, self.config.clone());
+        fresh.focused = self.focused;
+        fresh.display_pinned = self.display_pinned;
+        fresh.cell_metrics = self.cell_metrics;
+        fresh.color_palette = std::mem::take(&mut self.color_palette);
+        fresh.title = std::mem::take(&mut self.title);
+        fresh.icon_name = std::mem::take(&mut self.icon_name);
+        fresh.pending_events = std::mem::take(&mut self.pending_events);
+        fresh.responses = std::mem::take(&mut self.responses);
+        fresh.printer = self.printer.take();
+        fresh.matchers = std::mem::take(&mut self.matchers);
+        fresh.completed_lines = self.completed_lines.take();
+        if self.config.keep_scrollback_on_reset {
+            std::mem::swap(&mut fresh.scrollback_buffer, &mut self.scrollback_buffer);
+            fresh.command_runs = std::mem::take(&mut self.command_runs);
+        }
+        *self = fresh;
+    }
+    
+    /// The scrollback for a configuration, falling back to memory only if
+    /// the spill file can't be created
+    fn new_scrollback(config: &TerminalConfig) -> ScrollbackBuffer {
+        let max_lines = config.scrollback_limit.max_lines();
+        match &config.scrollback_spill_dir {
+            Some(dir) => ScrollbackBuffer::with_spill_dir(max_lines, dir).unwrap_or_else(|err| {
+                warn!("Failed to create scrollback spill file in {}: {}", dir.display(), err);
+                ScrollbackBuffer::new(max_lines)
+            }),
+            None => ScrollbackBuffer::new(max_lines),
         }
     }
     
@@ -51,22 +238,9 @@ impl TerminalState {
             palette.push(Color::from_ansi(i));
         }
         
-        // 16-231: 6x6x6 color cube
-        for r in 0..6 {
-            for g in 0..6 {
-                for b in 0..6 {
-                    let red = if r == 0 { 0 } else { 55 + r * 40 };
-                    let green = if g == 0 { 0 } else { 55 + g * 40 };
-                    let blue = if b == 0 { 0 } else { 55 + b * 40 };
-                    palette.push(Color::Rgb(red, green, blue));
-                }
-            }
-        }
-        
-        // 232-255: Grayscale
-        for i in 0..24 {
-            let gray = 8 + i * 10;
-            palette.push(Color::Rgb(gray, gray, gray));
+        // 16-255: 6x6x6 color cube and grayscale
+        for i in 16..=255 {
+            palette.push(default_rgb(i).into());
         }
         
         palette
@@ -80,6 +254,9 @@ impl TerminalState {
     /// Write a character to the terminal
     #[instrument(skip(self))]
     pub fn write_char(&mut self, ch: char) {
+        if self.display_offset != 0 && !self.display_pinned {
+            self.scroll_display_to_bottom();
+        }
         match ch {
             '\n' => self.new_line(),
             '\r' => self.carriage_return(),
@@ -98,10 +275,37 @@ impl TerminalState {
                     self.cursor.set_row(self.size.rows.saturating_sub(1));
                 }
                 
+                // A character left in the last column wraps only now
+                if self.cursor.pending_wrap() {
+                    let (left, _) = self.horizontal_bounds(self.cursor.position().col);
+                    self.wrap_line(left);
+                }
+                
                 // Write character at cursor position with current attributes
+                let ch = self.charsets[self.active_charset].map(ch);
+                let width = if self.config.wide_ambiguous == Some(true) { ch.width_cjk() } else { ch.width() };
+                let wide = width == Some(2);
+                if wide && !self.make_room_for_wide_char() {
+                    return;
+                }
+                // IRM: shift the rest of the line right to make room
+                if self.mode.contains(TerminalMode::INSERT_MODE) {
+                    self.insert_characters(if wide { 2 } else { 1 });
+                }
                 let pos = self.cursor.position();
-                let cell = Cell::with_attrs(ch, self.active_attributes);
-                self.screen_buffer.set_cell(pos, cell);
+                let mut cell = Cell::with_attrs(ch, self.active_attributes);
+                cell.tags = self.semantic_zone;
+                cell.protected = self.protected;
+                cell.hyperlink = self.hyperlink.clone();
+                if wide {
+                    cell.width = 2;
+                    let spacer = Cell { ch: ' ', width: 0, ..cell.clone() };
+                    self.screen_buffer.set_cell(pos, cell);
+                    self.screen_buffer.set_cell(Position::new(pos.row, pos.col + 1), spacer);
+                    self.advance_cursor();
+                } else {
+                    self.screen_buffer.set_cell(pos, cell);
+                }
                 
                 // Advance cursor
                 self.advance_cursor();
@@ -116,6 +320,34 @@ impl TerminalState {
         }
     }
     
+    /// Write a character repeatedly (REP)
+    ///
+    /// Repeats beyond one full screen are indistinguishable on the display, so the
+    /// count is capped there to keep huge parameters from stalling the terminal.
+    pub fn repeat_char(&mut self, ch: char, count: u16) {
+        let max = usize::from(self.size.rows) * usize::from(self.size.cols);
+        for _ in 0..usize::from(count).min(max) {
+            self.write_char(ch);
+        }
+    }
+    
+    /// Designate a character set into G0-G3 (SCS)
+    pub fn designate_charset(&mut self, slot: u8, charset: Charset) {
+        if let Some(entry) = self.charsets.get_mut(slot as usize) {
+            *entry = charset;
+        }
+    }
+    
+    /// Invoke G0 (SI) or G1 (SO) as the active character set
+    pub fn shift_charset(&mut self, slot: usize) {
+        self.active_charset = slot.min(1);
+    }
+    
+    /// Get the character set currently used for written text
+    pub fn active_charset(&self) -> Charset {
+        self.charsets[self.active_charset]
+    }
+    
     /// Set the active text attributes
     pub fn set_attributes(&mut self, attrs: CellAttributes) {
         self.active_attributes = attrs;
@@ -150,6 +382,368 @@ impl TerminalState {
         self.active_attributes = CellAttributes::default();
     }
     
+    /// Set the semantic zone (prompt, command or output) for text written from now on
+    ///
+    /// Overlay tags are not zones and are ignored here.
+    pub fn set_semantic_zone(&mut self, zone: CellTags) {
+        self.semantic_zone = zone & CellTags::ZONES;
+    }
+    
+    /// Get the semantic zone applied to written text
+    pub fn semantic_zone(&self) -> CellTags {
+        self.semantic_zone
+    }
+    
+    /// Protect subsequently written cells from selective erase (DECSCA)
+    pub fn set_character_protection(&mut self, protected: bool) {
+        self.protected = protected;
+    }
+    
+    /// Whether written cells are protected from selective erase
+    pub fn character_protection(&self) -> bool {
+        self.protected
+    }
+    
+    /// Link text written from now on to `uri` (OSC 8), or end the link with `None`
+    pub fn set_hyperlink(&mut self, hyperlink: Option<Hyperlink>) {
+        self.hyperlink = hyperlink.map(Arc::new);
+    }
+    
+    /// Get the hyperlink applied to written text
+    pub fn hyperlink(&self) -> Option<&Hyperlink> {
+        self.hyperlink.as_deref()
+    }
+    
+    /// Get the hyperlink of the cell at `pos`, e.g. to open it on click
+    pub fn hyperlink_at(&self, pos: Position) -> Option<&Hyperlink> {
+        self.screen_buffer.get_line(pos.row)?.get(pos.col as usize)?.hyperlink.as_deref()
+    }
+    
+    /// Attach the sink that receives printer output, or detach it
+    ///
+    /// Without a sink, printed output is discarded.
+    pub fn set_printer(&mut self, printer: Option<Box<dyn PrinterSink>>) {
+        self.printer = printer;
+    }
+    
+    /// Whether output goes to the printer instead of the screen
+    pub fn printer_controller(&self) -> bool {
+        self.printer_controller
+    }
+    
+    /// Enter or leave printer controller mode (MC 5 / MC 4)
+    pub fn set_printer_controller(&mut self, enabled: bool) {
+        if self.printer_controller && !enabled {
+            if let Some(Err(e)) = self.printer.as_mut().map(|printer| printer.flush()) {
+                debug!("Printer flush failed: {}", e);
+            }
+        }
+        self.printer_controller = enabled;
+        self.printer_exit_matched = 0;
+    }
+    
+    /// Pass raw output to the printer in printer controller mode
+    ///
+    /// Everything up to MC 4 (`CSI 4 i`) is forwarded untouched, escape
+    /// sequences included, and MC 4 leaves the mode. Returns how many bytes
+    /// were consumed: all of `data`, unless the mode ended part way through.
+    pub fn print_raw(&mut self, data: &[u8]) -> usize {
+        const EXIT: &[u8] = b"\x1b[4i";
+        let mut out = Vec::with_capacity(data.len());
+        for (i, &byte) in data.iter().enumerate() {
+            if self.printer_exit_matched > 0 && byte != EXIT[self.printer_exit_matched] {
+                // Not MC 4 after all; the held back bytes were printer data
+                out.extend_from_slice(&EXIT[..self.printer_exit_matched]);
+                self.printer_exit_matched = 0;
+            }
+            if byte != EXIT[self.printer_exit_matched] {
+                out.push(byte);
+                continue;
+            }
+            self.printer_exit_matched += 1;
+            if self.printer_exit_matched == EXIT.len() {
+                self.print(&out);
+                self.set_printer_controller(false);
+                return i + 1;
+            }
+        }
+        self.print(&out);
+        data.len()
+    }
+    
+    /// Print each line as the cursor leaves it (MC ? 5 / MC ? 4)
+    pub fn set_autoprint(&mut self, enabled: bool) {
+        self.autoprint = enabled;
+    }
+    
+    /// Send data to the printer
+    pub fn print(&mut self, data: &[u8]) {
+        if let Some(Err(e)) = self.printer.as_mut().map(|printer| printer.write(data)) {
+            debug!("Printer write failed: {}", e);
+        }
+    }
+    
+    /// Print a screen line without its trailing blanks (MC ? 1)
+    pub fn print_line(&mut self, row: u16) {
+        if self.printer.is_none() {
+            return;
+        }
+        let Some(line) = self.screen_buffer.get_line(row) else { return };
+        let mut text: String = line.iter().filter(|cell| !cell.is_spacer()).map(|cell| cell.ch).collect();
+        text.truncate(text.trim_end().len());
+        text.push('\n');
+        self.print(text.as_bytes());
+    }
+    
+    /// Print the whole screen (MC 0)
+    pub fn print_screen(&mut self) {
+        for row in 0..self.size.rows {
+            self.print_line(row);
+        }
+    }
+    
+    /// Mark the start of the command line (OSC 133;B)
+    ///
+    /// Records a run on the cursor's line, which the command's exit status
+    /// and timing are kept with.
+    pub fn start_command(&mut self) {
+        self.semantic_zone = CellTags::COMMAND;
+        let base = self.scrollback_buffer.dropped();
+        while self.command_runs.front().is_some_and(|run| run.line < base) {
+            self.command_runs.pop_front();
+        }
+        let line = base + self.scrollback_buffer.len() + self.cursor_position().row as usize;
+        // A prompt redrawn on the same line before anything ran replaces its run
+        if self.command_runs.back().is_some_and(|run| run.line == line && run.started.is_none()) {
+            self.command_runs.pop_back();
+        }
+        self.command_runs.push_back(CommandRun { line, started: None, finished: None, exit_code: None });
+    }
+    
+    /// Mark the start of command output (OSC 133;C), when the command runs
+    pub fn start_command_output(&mut self) {
+        self.semantic_zone = CellTags::OUTPUT;
+        if let Some(run) = self.command_runs.back_mut().filter(|run| run.started.is_none()) {
+            run.started = Some(Instant::now());
+        }
+    }
+    
+    /// Mark the end of a command (OSC 133;D)
+    pub fn finish_command(&mut self, exit_code: Option<i32>) {
+        self.semantic_zone = CellTags::empty();
+        if let Some(run) = self.command_runs.back_mut().filter(|run| run.finished.is_none()) {
+            run.finished = Some(Instant::now());
+            run.exit_code = exit_code;
+        }
+        self.pending_events.push(Event::CommandFinished { exit_code });
+    }
+    
+    /// Enumerate the commands marked by shell integration
+    ///
+    /// Covers the scrollback and the main screen, even while the alternate
+    /// screen is active. Ranges are in history coordinates. Exit codes and
+    /// durations are known for commands run since the terminal was created
+    /// or restored.
+    pub fn commands(&self) -> Vec<CommandBlock> {
+        let main = self.alternate_buffer.as_ref().unwrap_or(&self.screen_buffer);
+        let lines = self.scrollback_buffer.lines()
+            .map(Cow::Owned)
+            .chain(main.lines().iter().map(|line| Cow::Borrowed(line.as_slice())));
+        let mut blocks = semantic::command_blocks(lines);
+        
+        let base = self.scrollback_buffer.dropped();
+        for block in &mut blocks {
+            let (Some(first), Some(last)) = (block.first_line(), block.last_line()) else { continue };
+            let after = self.command_runs.partition_point(|run| run.line <= base + last);
+            if let Some(run) = after.checked_sub(1).map(|i| &self.command_runs[i]).filter(|run| run.line >= base + first) {
+                run.apply_to(block);
+            }
+        }
+        blocks
+    }
+    
+    /// Text of a zone from `commands`, e.g. to copy a command's output, with
+    /// autowrapped lines joined
+    pub fn zone_text(&self, zone: ZoneRange) -> String {
+        self.with_history_lines(zone.start, zone.end, false, buffer::lines_text)
+    }
+    
+    /// Start of each prompt marked by shell integration, oldest first
+    ///
+    /// Follows `commands`, so a prompt re-drawn before any command is
+    /// entered counts once.
+    pub fn prompts(&self) -> Vec<HistoryPoint> {
+        self.commands().iter().filter_map(|block| block.prompt).map(|prompt| prompt.start).collect()
+    }
+    
+    /// The last prompt starting before `from`, to jump to the previous command
+    ///
+    /// Agrees with `prompts`, but only reads history back to the prompt found.
+    pub fn prev_prompt(&self, from: HistoryPoint) -> Option<HistoryPoint> {
+        // A prompt starts where the zone before it isn't a prompt
+        let mut start = None;
+        for line in (0..=from.line.min(self.zone_lines())).rev() {
+            for (col, zone) in self.line_zones(line).into_iter().rev() {
+                let point = HistoryPoint::new(line, col);
+                if point >= from {
+                    continue;
+                }
+                if zone == CellTags::PROMPT {
+                    start = Some(point);
+                } else if start.is_some() {
+                    return start;
+                }
+            }
+        }
+        start
+    }
+    
+    /// The first prompt starting after `from`
+    ///
+    /// Agrees with `prompts`, but only reads history up to the prompt found.
+    pub fn next_prompt(&self, from: HistoryPoint) -> Option<HistoryPoint> {
+        // A prompt carries on past untagged cells, so start from the zone before `from`
+        let mut in_prompt = (0..=from.line.min(self.zone_lines())).rev().find_map(|line| {
+            self.line_zones(line).into_iter().rev().find(|&(col, _)| HistoryPoint::new(line, col) < from)
+        }).is_some_and(|(_, zone)| zone == CellTags::PROMPT);
+        
+        for line in from.line..=self.zone_lines() {
+            for (col, zone) in self.line_zones(line) {
+                let point = HistoryPoint::new(line, col);
+                if point < from {
+                    continue;
+                }
+                if zone == CellTags::PROMPT && !in_prompt && point > from {
+                    return Some(point);
+                }
+                in_prompt = zone == CellTags::PROMPT;
+            }
+        }
+        None
+    }
+    
+    /// Last history line of the main screen, which `commands` reads
+    fn zone_lines(&self) -> usize {
+        let main = self.alternate_buffer.as_ref().unwrap_or(&self.screen_buffer);
+        (self.scrollback_buffer.len() + main.lines().len()).saturating_sub(1)
+    }
+    
+    /// Columns and semantic zones of the zone-tagged cells of a history line,
+    /// reading the main screen as `commands` does
+    fn line_zones(&self, line: usize) -> Vec<(u16, CellTags)> {
+        let main = self.alternate_buffer.as_ref().unwrap_or(&self.screen_buffer);
+        let scrollback = self.scrollback_buffer.len();
+        let cells = if line < scrollback {
+            self.scrollback_buffer.get_line(line).map(Cow::Owned)
+        } else {
+            main.lines().get(line - scrollback).map(|cells| Cow::Borrowed(cells.as_slice()))
+        };
+        let zones = [CellTags::PROMPT, CellTags::COMMAND, CellTags::OUTPUT];
+        cells.iter()
+            .flat_map(|cells| cells.iter().enumerate())
+            .map(|(col, cell)| (col as u16, cell.tags & CellTags::ZONES))
+            .filter(|(_, zone)| zones.contains(zone))
+            .collect()
+    }
+    
+    /// Erase characters starting at the cursor (ECH)
+    ///
+    /// Erased cells take the current background color; the cursor does not move.
+    pub fn erase_characters(&mut self, count: u16) {
+        let pos = self.cursor_position();
+        let (_, right) = self.horizontal_bounds(pos.col);
+        let end = pos.col.saturating_add(count).min(right + 1);
+        let blank = self.erased_cell();
+        for col in pos.col..end {
+            self.screen_buffer.set_cell(Position::new(pos.row, col), blank.clone());
+        }
+    }
+    
+    /// Insert blank characters at the cursor (ICH)
+    ///
+    /// Only characters up to the right margin shift; outside the margins this
+    /// does nothing.
+    pub fn insert_characters(&mut self, count: u16) {
+        let pos = self.cursor_position();
+        if self.within_margins(pos.col) {
+            self.screen_buffer.insert_blank_cells_in(pos, count, self.right_margin);
+        }
+    }
+    
+    /// Delete characters at the cursor (DCH)
+    ///
+    /// Only characters up to the right margin shift; outside the margins this
+    /// does nothing.
+    pub fn delete_characters(&mut self, count: u16) {
+        let pos = self.cursor_position();
+        if self.within_margins(pos.col) {
+            self.screen_buffer.delete_cells_in(pos, count, self.right_margin);
+        }
+    }
+    
+    /// Insert blank columns at the cursor column (DECIC)
+    pub fn insert_columns(&mut self, count: u16) {
+        let col = self.cursor_position().col;
+        if self.within_margins(col) {
+            let rows = self.scroll_top..self.scroll_bottom + 1;
+            self.screen_buffer.insert_blank_columns(col, count, rows, self.right_margin);
+        }
+    }
+    
+    /// Delete columns at the cursor column (DECDC)
+    pub fn delete_columns(&mut self, count: u16) {
+        let col = self.cursor_position().col;
+        if self.within_margins(col) {
+            let rows = self.scroll_top..self.scroll_bottom + 1;
+            self.screen_buffer.delete_columns(col, count, rows, self.right_margin);
+        }
+    }
+    
+    /// Blank cell carrying the current background color, used by erase operations
+    fn erased_cell(&self) -> Cell {
+        let attrs = CellAttributes {
+            bg_color: self.active_attributes.bg_color,
+            ..CellAttributes::default()
+        };
+        Cell::with_attrs(' ', attrs)
+    }
+    
+    /// Make sure both halves of a wide character fit before the right margin
+    ///
+    /// With autowrap the cursor wraps early, leaving the last column blank;
+    /// without it the character goes in the last two columns. Returns false
+    /// if the line is too narrow to hold it.
+    fn make_room_for_wide_char(&mut self) -> bool {
+        let (left, right) = self.horizontal_bounds(self.cursor.position().col);
+        if self.cursor.position().col < right {
+            return true;
+        }
+        if right == left {
+            return false;
+        }
+        if self.mode.contains(TerminalMode::LINE_WRAP) {
+            self.wrap_line(left);
+        } else {
+            self.cursor.set_column(right - 1);
+        }
+        true
+    }
+    
+    /// Continue on the next line at column `left`, as autowrap does
+    fn wrap_line(&mut self, left: u16) {
+        // Mark the line as continued, so copying and search rejoin it. Only a
+        // wrap at the screen edge continues the whole row
+        let pos = self.cursor.position();
+        if pos.col + 1 >= self.size.cols {
+            self.screen_buffer.set_wrapped(pos.row, true);
+        }
+        if self.autoprint {
+            self.print_line(self.cursor.position().row);
+        }
+        self.cursor.set_column(left);
+        self.index();
+    }
+    
     /// Advance cursor position after writing a character
     fn advance_cursor(&mut self) {
         // Skip if terminal has no size
@@ -157,39 +751,181 @@ impl TerminalState {
             return;
         }
         
-        self.cursor.move_right(1);
+        let (_, right) = self.horizontal_bounds(self.cursor.position().col);
         
-        // Check for line wrap
-        if self.cursor.position().col >= self.size.cols {
+        // At the right margin the cursor stays put; with autowrap the line
+        // wraps when the next character is written
+        if self.cursor.position().col >= right {
+            self.cursor.set_column(right);
             if self.mode.contains(TerminalMode::LINE_WRAP) {
-                self.cursor.set_column(0);
-                self.cursor.move_down(1);
-                
-                // Check if we need to scroll
-                if self.cursor.position().row >= self.size.rows {
-                    self.scroll_up();
-                    self.cursor.set_row(self.size.rows.saturating_sub(1));
-                }
-            } else {
-                // Stay at the last column
-                self.cursor.set_column(self.size.cols.saturating_sub(1));
+                self.cursor.set_pending_wrap(true);
             }
+        } else {
+            self.cursor.move_right(1);
         }
     }
     
-    /// Handle newline
+    /// Handle newline; in LNM mode it also returns the carriage
     fn new_line(&mut self) {
         debug!("New line at cursor position {:?}", self.cursor.position());
-        self.cursor.move_down(1);
-        
-        // Allow cursor to be on virtual row for proper newline handling
-        // Scrolling only happens when writing text to out-of-bounds position
+        if self.autoprint {
+            self.print_line(self.cursor.position().row);
+        }
+        if self.completed_lines.is_some() {
+            let text = self.completed_line_text(self.cursor_position().row);
+            self.completed_lines.get_or_insert_with(Vec::new).push(text);
+        }
+        self.index();
+        if self.mode.contains(TerminalMode::LINE_FEED) {
+            self.carriage_return();
+        }
+    }
+    
+    /// Start or stop collecting the text of lines completed by a line feed,
+    /// for `take_completed_lines`
+    pub fn collect_completed_lines(&mut self, enabled: bool) {
+        self.completed_lines = enabled.then(Vec::new);
+    }
+    
+    /// Take the lines completed since the last call, oldest first
+    ///
+    /// Each is the logical line ending at the row the line feed left, with
+    /// the rows it autowrapped from joined and trailing blanks trimmed.
+    pub fn take_completed_lines(&mut self) -> Vec<String> {
+        self.completed_lines.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+    
+    fn completed_line_text(&self, row: u16) -> String {
+        let line = self.scrollback_buffer.len() + row as usize;
+        let start = self.logical_line(line).start;
+        let mut text: String = (start..=line)
+            .filter_map(|line| self.history_line(line))
+            .map(|cells| buffer::cells_text(&cells))
+            .collect();
+        text.truncate(text.trim_end().len());
+        text
+    }
+    
+    /// Move the cursor down one line, scrolling the region at the bottom margin (IND)
+    pub fn index(&mut self) {
+        let row = self.cursor.position().row;
+        if row == self.scroll_bottom {
+            self.scroll_up();
+        } else if row + 1 < self.size.rows {
+            self.cursor.move_down(1);
+        }
+    }
+    
+    /// Move the cursor up one line, scrolling the region at the top margin (RI)
+    pub fn reverse_index(&mut self) {
+        let row = self.cursor.position().row;
+        if row == self.scroll_top {
+            self.scroll_down();
+        } else {
+            self.cursor.move_up(1);
+        }
     }
     
     /// Handle carriage return
     fn carriage_return(&mut self) {
         debug!("Carriage return");
-        self.cursor.set_column(0);
+        let (left, _) = self.horizontal_bounds(self.cursor.position().col);
+        self.cursor.set_column(left);
+    }
+    
+    /// Move the cursor right, stopping at the right margin (CUF)
+    pub fn cursor_forward(&mut self, count: u16) {
+        let col = self.cursor.position().col;
+        let (_, right) = self.horizontal_bounds(col);
+        self.cursor.set_column(col.saturating_add(count).min(right.max(col)));
+    }
+    
+    /// Move the cursor left, stopping at the left margin (CUB)
+    pub fn cursor_back(&mut self, count: u16) {
+        let col = self.cursor.position().col;
+        let (left, _) = self.horizontal_bounds(col);
+        self.cursor.set_column(col.saturating_sub(count).max(left.min(col)));
+    }
+    
+    /// Move the cursor to an absolute column on its line (CHA/HPA)
+    ///
+    /// Columns past the right edge stop on the last column, or on the right
+    /// margin when the cursor is between the margins.
+    pub fn set_cursor_column(&mut self, col: u16) {
+        let (_, right) = self.horizontal_bounds(self.cursor.position().col);
+        self.cursor.set_column(col.min(right));
+    }
+    
+    /// Whether `col` lies between the left and right margins
+    fn within_margins(&self, col: u16) -> bool {
+        (self.left_margin..=self.right_margin).contains(&col)
+    }
+    
+    /// Whether left/right margins narrower than the screen are set
+    fn has_side_margins(&self) -> bool {
+        self.left_margin > 0 || self.right_margin + 1 < self.size.cols
+    }
+    
+    /// Horizontal limits for a cursor in column `col`
+    ///
+    /// A cursor between the left and right margins is confined to them; one
+    /// outside the margins may use the full width of the screen.
+    fn horizontal_bounds(&self, col: u16) -> (u16, u16) {
+        let (left, right) = if self.within_margins(col) {
+            (self.left_margin, self.right_margin)
+        } else {
+            (0, self.size.cols.saturating_sub(1))
+        };
+        // A double-width line only holds half as many characters
+        if self.screen_buffer.line_rendition(self.cursor.position().row).is_double_width() {
+            let last = (self.size.cols / 2).saturating_sub(1);
+            (left.min(last), right.min(last))
+        } else {
+            (left, right)
+        }
+    }
+    
+    /// Set the rendition of the cursor line (DECSWL/DECDWL/DECDHL)
+    ///
+    /// On a double-width line the cursor is kept within the first half of
+    /// the screen.
+    pub fn set_line_rendition(&mut self, rendition: LineRendition) {
+        let pos = self.cursor.position();
+        self.screen_buffer.set_line_rendition(pos.row, rendition);
+        if rendition.is_double_width() {
+            let last = (self.size.cols / 2).saturating_sub(1);
+            self.cursor.set_column(pos.col.min(last));
+        }
+    }
+    
+    /// Set the left and right margins (DECSLRM), 0-based and inclusive
+    ///
+    /// Only takes effect while DECLRMM is enabled. `right` is clamped to the last
+    /// column and margins narrower than two columns are ignored. The cursor
+    /// moves to the home position.
+    pub fn set_left_right_margins(&mut self, left: u16, right: u16) {
+        if !self.mode.contains(TerminalMode::LEFT_RIGHT_MARGIN) {
+            return;
+        }
+        let right = right.min(self.size.cols.saturating_sub(1));
+        if left >= right {
+            return;
+        }
+        
+        self.left_margin = left;
+        self.right_margin = right;
+        
+        let home = if self.mode.contains(TerminalMode::ORIGIN_MODE) {
+            Position::new(self.scroll_top, left)
+        } else {
+            Position::new(0, 0)
+        };
+        self.cursor.set_position(home);
+    }
+    
+    /// Get the left and right margins as (left, right), 0-based and inclusive
+    pub fn left_right_margins(&self) -> (u16, u16) {
+        (self.left_margin, self.right_margin)
     }
     
     /// Perform a tab operation
@@ -232,29 +968,92 @@ impl TerminalState {
         self.cursor.saturating_left();
     }
     
-    /// Scroll the terminal up by one line
+    /// Scroll the scroll region up by one line
+    ///
+    /// The line scrolled off is kept in scrollback if `keeps_scrollback` allows it.
     pub fn scroll_up(&mut self) {
-        debug!("Scrolling up");
+        debug!("Scrolling up rows {}..={}", self.scroll_top, self.scroll_bottom);
+        
+        if self.has_side_margins() {
+            let (left, right) = (self.left_margin, self.right_margin);
+            self.screen_buffer.scroll_rect_up(self.scroll_top, self.scroll_bottom, left, right);
+            return;
+        }
         
-        // Move the first line to scrollback
-        if let Some(line) = self.screen_buffer.remove_top_line() {
-            self.scrollback_buffer.push(line);
+        if let Some(mut line) = self.screen_buffer.scroll_region_up(self.scroll_top, self.scroll_bottom) {
+            if self.keeps_scrollback() {
+                // Overlays are drawn from the selection and search state, not stored
+                for cell in &mut line {
+                    cell.tags.remove(CellTags::OVERLAYS);
+                }
+                let dropped = self.scrollback_buffer.push(line);
+                // A pinned view stays on the same lines
+                if self.display_offset != 0 {
+                    self.display_offset = (self.display_offset + 1).min(self.scrollback_buffer.len());
+                }
+                // The oldest line was dropped, so history coordinates shift
+                if dropped {
+                    self.shift_search_highlights(1);
+                    if self.selection.as_mut().is_some_and(|selection| !selection.shift_up(1)) {
+                        self.clear_selection();
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Whether a line scrolled off the current region belongs in scrollback
+    ///
+    /// Only whole lines leaving the top of the main screen are kept. Lines
+    /// leaving a region with a lower top margin are discarded, as are lines
+    /// leaving the alternate screen unless `alt_screen_scrollback` is configured.
+    /// With left/right margins set, partial lines scroll and nothing is kept.
+    fn keeps_scrollback(&self) -> bool {
+        self.scroll_top == 0
+            && (!self.mode.contains(TerminalMode::ALTERNATE_SCREEN) || self.config.alt_screen_scrollback)
+    }
+    
+    /// Set the scroll region margins (DECSTBM), 0-based and inclusive
+    ///
+    /// `bottom` is clamped to the last row and regions of fewer than two lines
+    /// are ignored. The cursor moves to the home position.
+    pub fn set_scroll_region(&mut self, top: u16, bottom: u16) {
+        let bottom = bottom.min(self.size.rows.saturating_sub(1));
+        if top >= bottom {
+            return;
         }
         
-        // Add a new blank line at the bottom
-        self.screen_buffer.add_blank_line();
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        
+        let home_row = if self.mode.contains(TerminalMode::ORIGIN_MODE) { top } else { 0 };
+        self.cursor.set_position(Position::new(home_row, 0));
+    }
+    
+    /// Get the scroll region margins as (top, bottom), 0-based and inclusive
+    pub fn scroll_region(&self) -> (u16, u16) {
+        (self.scroll_top, self.scroll_bottom)
     }
     
     /// Resize the terminal
     pub fn resize(&mut self, new_size: Size) {
         debug!("Resizing terminal from {:?} to {:?}", self.size, new_size);
         
+        self.clear_selection();
+        self.clear_search_highlights();
+        self.display_offset = self.display_offset.min(self.scrollback_buffer.len());
         self.size = new_size;
         self.screen_buffer.resize(new_size);
         
         // Update tab stops for new width
         self.tab_stops = Self::default_tab_stops(new_size.cols);
         
+        // Reset the scroll region and margins to the full screen
+        self.scroll_top = 0;
+        self.scroll_bottom = new_size.rows.saturating_sub(1);
+        self.left_margin = 0;
+        self.right_margin = new_size.cols.saturating_sub(1);
+        
         // Clamp cursor position
         let pos = self.cursor.position();
         self.cursor.set_position(Position::new(
@@ -263,6 +1062,19 @@ impl TerminalState {
         ));
     }
     
+    /// Switch between 132 and 80 columns (DECCOLM)
+    ///
+    /// Resizes the grid, clears the screen and homes the cursor, then asks
+    /// the frontend to resize its window to match.
+    pub fn set_column_mode(&mut self, wide: bool) {
+        let size = Size::new(if wide { 132 } else { 80 }, self.size.rows);
+        self.resize(size);
+        self.screen_buffer.clear();
+        self.cursor.set_position(Position::new(0, 0));
+        self.mode.set(TerminalMode::COLUMN_132, wide);
+        self.pending_events.push(Event::ResizeRequested(size));
+    }
+    
     /// Get the cursor position
     pub fn cursor_position(&self) -> Position {
         // Clamp position for external callers
@@ -278,63 +1090,668 @@ impl TerminalState {
         self.size
     }
     
-    /// Get a reference to the screen buffer
-    pub fn screen_buffer(&self) -> &ScreenBuffer {
-        &self.screen_buffer
+    /// Get the terminal configuration
+    pub fn config(&self) -> &TerminalConfig {
+        &self.config
     }
     
-    /// Get a reference to the scrollback buffer
-    pub fn scrollback_buffer(&self) -> &ScrollbackBuffer {
-        &self.scrollback_buffer
+    /// Get a mutable reference to the terminal configuration
+    pub fn config_mut(&mut self) -> &mut TerminalConfig {
+        &mut self.config
     }
     
-    /// Get a mutable reference to the screen buffer
-    pub fn screen_buffer_mut(&mut self) -> &mut ScreenBuffer {
-        &mut self.screen_buffer
+    /// Find the word under a screen position using the configured word boundaries
+    ///
+    /// Returns the first and last cell of the word, or `None` on a separator.
+    pub fn word_range_at(&self, pos: Position) -> Option<(Position, Position)> {
+        let (start, end) = self.word_range_in(self.screen_buffer.get_line(pos.row)?, pos.col)?;
+        Some((Position::new(pos.row, start), Position::new(pos.row, end)))
     }
     
-    /// Get a mutable reference to the scrollback buffer
-    pub fn scrollback_buffer_mut(&mut self) -> &mut ScrollbackBuffer {
-        &mut self.scrollback_buffer
+    /// Inclusive column range of the word containing `col` in a line
+    fn word_range_in(&self, cells: &[Cell], col: u16) -> Option<(u16, u16)> {
+        // A wide character's spacer belongs to the same word
+        let line: Vec<char> = cells
+            .iter()
+            .scan(' ', |lead, cell| {
+                if !cell.is_spacer() {
+                    *lead = cell.ch;
+                }
+                Some(*lead)
+            })
+            .collect();
+        let (start, end) = self.config.word_boundaries.word_range(&line, col as usize)?;
+        Some((start as u16, end as u16))
     }
     
-    /// Get a mutable reference to the cursor
-    pub fn cursor_mut(&mut self) -> &mut Cursor {
-        &mut self.cursor
+    /// Convert a screen position to history coordinates
+    pub fn history_point(&self, pos: Position) -> HistoryPoint {
+        HistoryPoint::new(self.scrollback_buffer.len() + pos.row as usize, pos.col)
     }
     
-    /// Set cursor position
-    pub fn set_cursor_position(&mut self, pos: Position) {
-        self.cursor.set_position(pos);
+    /// A line of history: the scrollback, oldest first, followed by the screen
+    ///
+    /// Scrollback lines are expanded from their compact form, so prefer
+    /// `history_line_wrapped` when only the wrap flag is needed.
+    pub fn history_line(&self, line: usize) -> Option<Cow<'_, [Cell]>> {
+        let scrollback = self.scrollback_buffer.len();
+        if line < scrollback {
+            self.scrollback_buffer.get_line(line).map(Cow::Owned)
+        } else {
+            let line = self.screen_buffer.get_line(u16::try_from(line - scrollback).ok()?)?;
+            Some(Cow::Borrowed(line.as_slice()))
+        }
     }
     
-    /// Set underline color
-    pub fn set_underline_color(&mut self, color: Option<Color>) {
-        self.active_attributes.underline_color = color;
+    /// Whether a history line autowrapped onto the next one
+    pub fn history_line_wrapped(&self, line: usize) -> bool {
+        let scrollback = self.scrollback_buffer.len();
+        if line < scrollback {
+            self.scrollback_buffer.is_wrapped(line)
+        } else {
+            u16::try_from(line - scrollback).ok()
+                .and_then(|row| self.screen_buffer.get_line(row))
+                .is_some_and(|line| buffer::is_wrapped(line))
+        }
     }
     
-    /// Scroll down (reverse scroll)
-    pub fn scroll_down(&mut self) {
-        debug!("Scrolling down");
-        // Insert blank line at top
-        self.screen_buffer.insert_blank_line(0);
-        // Remove bottom line
-        self.screen_buffer.remove_bottom_line();
+    /// Number of history lines: the scrollback followed by the screen
+    pub fn history_len(&self) -> usize {
+        self.scrollback_buffer.len() + self.size.rows as usize
     }
     
-    /// Set a terminal mode flag
-    pub fn set_mode_flag(&mut self, mode: Mode, enabled: bool) {
-        match mode {
-            Mode::Insert => {
-                if enabled {
-                    self.mode.insert(TerminalMode::INSERT_MODE);
-                } else {
-                    self.mode.remove(TerminalMode::INSERT_MODE);
-                }
-            }
-            Mode::AutoWrap => {
-                if enabled {
-                    self.mode.insert(TerminalMode::LINE_WRAP);
+    /// The history lines joined by autowrap into the logical line containing `line`
+    fn logical_line(&self, line: usize) -> Range<usize> {
+        let mut start = line;
+        while start > 0 && self.history_line_wrapped(start - 1) {
+            start -= 1;
+        }
+        let mut end = line;
+        while end + 1 < self.history_len() && self.history_line_wrapped(end) {
+            end += 1;
+        }
+        start..end + 1
+    }
+    
+    /// All matches in the scrollback and screen, oldest first
+    pub fn search(&self, query: &SearchQuery) -> Vec<SearchMatch> {
+        self.matches_from(query, HistoryPoint::new(0, 0), SearchDirection::Forward, true).collect()
+    }
+    
+    /// Matches starting after `from`, or before it going backward, nearest first
+    ///
+    /// Pass the start of the current match to step to the next one.
+    pub fn search_from<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+        from: HistoryPoint,
+        direction: SearchDirection,
+    ) -> impl Iterator<Item = SearchMatch> + 'a {
+        self.matches_from(query, from, direction, false)
+    }
+    
+    /// Search and tag every match with `CellTags::SEARCH_MATCH`, replacing
+    /// earlier highlights
+    ///
+    /// Returns the matches, oldest first. They stay highlighted until
+    /// `clear_search_highlights`; matches in the scrollback are tagged as
+    /// `visible_rows` reads them.
+    pub fn highlight_search(&mut self, query: &SearchQuery) -> Vec<SearchMatch> {
+        self.screen_buffer.clear_tags(CellTags::SEARCH_MATCH);
+        self.search_highlights = self.search(query);
+        for found in self.search_highlights.clone() {
+            self.tag_screen_span((found.start, found.end), false, CellTags::SEARCH_MATCH);
+        }
+        self.search_highlights.clone()
+    }
+    
+    /// Remove the tags set by `highlight_search`
+    pub fn clear_search_highlights(&mut self) {
+        if !std::mem::take(&mut self.search_highlights).is_empty() {
+            self.screen_buffer.clear_tags(CellTags::SEARCH_MATCH);
+        }
+    }
+    
+    /// Keep highlights on their text when the oldest history lines are dropped
+    fn shift_search_highlights(&mut self, count: usize) {
+        self.search_highlights.retain_mut(|found| {
+            let keep = found.start.line >= count;
+            if keep {
+                found.start.line -= count;
+                found.end.line -= count;
+            }
+            keep
+        });
+    }
+    
+    fn matches_from<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+        from: HistoryPoint,
+        direction: SearchDirection,
+        inclusive: bool,
+    ) -> impl Iterator<Item = SearchMatch> + 'a {
+        let total = self.history_len();
+        let mut next = Some(self.logical_line(from.line.min(total - 1)));
+        let mut pending: Vec<SearchMatch> = Vec::new();
+        std::iter::from_fn(move || loop {
+            if let Some(found) = pending.pop() {
+                return Some(found);
+            }
+            let lines = next.take()?;
+            next = match direction {
+                SearchDirection::Forward => (lines.end < total).then(|| self.logical_line(lines.end)),
+                SearchDirection::Backward => (lines.start > 0).then(|| self.logical_line(lines.start - 1)),
+            };
+            let rows: Vec<_> = lines.clone().filter_map(|line| self.history_line(line)).collect();
+            let rows: Vec<&[Cell]> = rows.iter().map(|row| row.as_ref()).collect();
+            pending = query.find_in(&rows, lines.start);
+            pending.retain(|found| match direction {
+                SearchDirection::Forward => found.start > from || (inclusive && found.start == from),
+                SearchDirection::Backward => found.start < from || (inclusive && found.start == from),
+            });
+            // Popped from the end, so the nearest match goes last
+            if direction == SearchDirection::Forward {
+                pending.reverse();
+            }
+        })
+    }
+    
+    /// How many lines the view is scrolled back into history, 0 at the bottom
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+    
+    /// Scroll the view back `lines` into the scrollback, e.g. for Shift+PageUp
+    pub fn scroll_display_up(&mut self, lines: usize) {
+        self.set_display_offset(self.display_offset.saturating_add(lines));
+    }
+    
+    /// Scroll the view `lines` towards the live screen
+    pub fn scroll_display_down(&mut self, lines: usize) {
+        self.set_display_offset(self.display_offset.saturating_sub(lines));
+    }
+    
+    /// Scroll the view to the oldest scrollback line
+    pub fn scroll_display_to_top(&mut self) {
+        self.set_display_offset(self.scrollback_buffer.len());
+    }
+    
+    /// Scroll the view back to the live screen
+    pub fn scroll_display_to_bottom(&mut self) {
+        self.set_display_offset(0);
+    }
+    
+    /// Scroll the view so history line `line` is at the top, or as close as
+    /// the scrollback allows
+    pub fn scroll_display_to_line(&mut self, line: usize) {
+        self.set_display_offset(self.scrollback_buffer.len().saturating_sub(line));
+    }
+    
+    fn set_display_offset(&mut self, offset: usize) {
+        let offset = offset.min(self.scrollback_buffer.len());
+        if offset != self.display_offset {
+            self.display_offset = offset;
+            self.screen_buffer.damage_all();
+        }
+    }
+    
+    /// Whether the view stays put on new output instead of snapping to the bottom
+    pub fn display_pinned(&self) -> bool {
+        self.display_pinned
+    }
+    
+    /// Keep a scrolled-back view in place on new output, following its
+    /// lines as they move into the scrollback
+    pub fn set_display_pinned(&mut self, pinned: bool) {
+        self.display_pinned = pinned;
+    }
+    
+    /// The rows in view: the end of the scrollback followed by the top of the
+    /// screen when scrolled back, otherwise the screen
+    pub fn visible_rows(&self) -> Vec<Cow<'_, [Cell]>> {
+        let scrollback = self.scrollback_buffer.len();
+        let top = scrollback - self.display_offset;
+        let selection = self.selection_range();
+        let block = self.selection.is_some_and(|selection| selection.mode == SelectionMode::Block);
+        (top..top + self.size.rows as usize)
+            .filter_map(|line| {
+                let mut row = self.history_line(line)?;
+                if line >= scrollback {
+                    return Some(row);
+                }
+                // Scrollback doesn't keep overlay tags, so they are added here
+                let matches = self.search_highlights.iter().map(|found| (found.start, found.end, false, CellTags::SEARCH_MATCH));
+                let spans = selection.map(|(start, end)| (start, end, block, CellTags::SELECTED)).into_iter().chain(matches);
+                for (start, end, block, tag) in spans {
+                    let Some((from, to)) = self.span_columns((start, end), block, line) else { continue };
+                    let cells = row.to_mut();
+                    let to = usize::from(to).min(cells.len().saturating_sub(1));
+                    for cell in cells.iter_mut().take(to + 1).skip(usize::from(from)) {
+                        cell.tags.insert(tag);
+                    }
+                }
+                Some(row)
+            })
+            .collect()
+    }
+    
+    /// Convert a position in the view to history coordinates
+    pub fn viewport_point(&self, pos: Position) -> HistoryPoint {
+        HistoryPoint::new(self.scrollback_buffer.len() - self.display_offset + pos.row as usize, pos.col)
+    }
+    
+    /// The patterns looked for by `pattern_hits`
+    pub fn matchers(&self) -> &MatcherRegistry {
+        &self.matchers
+    }
+    
+    /// Register or remove patterns
+    pub fn matchers_mut(&mut self) -> &mut MatcherRegistry {
+        &mut self.matchers
+    }
+    
+    /// Text found by the registered matchers on the visible screen, in reading order
+    ///
+    /// Autowrapped rows are matched as one line. Matchers run on each call,
+    /// so nothing is spent on output nobody looks at.
+    pub fn pattern_hits(&self) -> Vec<PatternHit> {
+        self.pattern_hits_in(0..self.size.rows)
+    }
+    
+    /// The hit covering a screen cell, e.g. for click-to-open
+    ///
+    /// Only the logical line containing the cell is matched.
+    pub fn pattern_hit_at(&self, pos: Position) -> Option<PatternHit> {
+        let mut row = pos.row;
+        while row > 0 && self.screen_buffer.get_line(row - 1).is_some_and(|line| buffer::is_wrapped(line)) {
+            row -= 1;
+        }
+        self.pattern_hits_in(row..pos.row + 1).into_iter().find(|hit| hit.contains(pos))
+    }
+    
+    /// Hits on the logical lines starting in `rows`
+    fn pattern_hits_in(&self, rows: Range<u16>) -> Vec<PatternHit> {
+        let mut hits = Vec::new();
+        let mut row = rows.start;
+        while row < rows.end.min(self.size.rows) {
+            let mut end = row;
+            while end + 1 < self.size.rows && self.screen_buffer.get_line(end).is_some_and(|line| buffer::is_wrapped(line)) {
+                end += 1;
+            }
+            let cells: Vec<&[Cell]> = (row..=end).filter_map(|row| self.screen_buffer.get_line(row).map(Vec::as_slice)).collect();
+            let text = LineText::new(&cells, row as usize);
+            
+            let mut line_hits: Vec<PatternHit> = Vec::new();
+            for matcher in self.matchers.matchers() {
+                for (span, found) in text.find_iter(&matcher.regex) {
+                    let hit = PatternHit {
+                        matcher: matcher.name.clone(),
+                        text: found.to_string(),
+                        start: Position::new(span.start.line as u16, span.start.col),
+                        end: Position::new(span.end.line as u16, span.end.col),
+                    };
+                    if !line_hits.iter().any(|earlier| earlier.overlaps(&hit)) {
+                        line_hits.push(hit);
+                    }
+                }
+            }
+            line_hits.sort_by_key(|hit| (hit.start.row, hit.start.col));
+            hits.extend(line_hits);
+            row = end + 1;
+        }
+        hits
+    }
+    
+    /// Start selecting at a point, replacing any selection
+    pub fn start_selection(&mut self, mode: SelectionMode, point: HistoryPoint) {
+        self.selection = Some(Selection::new(mode, point));
+        self.update_selection_tags();
+    }
+    
+    /// Extend the selection to a point
+    pub fn extend_selection(&mut self, point: HistoryPoint) {
+        if let Some(selection) = &mut self.selection {
+            selection.head = point;
+            self.update_selection_tags();
+        }
+    }
+    
+    /// Clear the selection
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.update_selection_tags();
+        }
+    }
+    
+    /// Get the current selection
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+    
+    /// First and last selected cell, with word and line selections expanded
+    ///
+    /// For block selections these are the top-left and bottom-right corners.
+    pub fn selection_range(&self) -> Option<(HistoryPoint, HistoryPoint)> {
+        let selection = self.selection?;
+        let (mut start, mut end) = selection.bounds();
+        match selection.mode {
+            SelectionMode::Word => {
+                let word = |point: HistoryPoint| self.word_range_in(&self.history_line(point.line)?, point.col);
+                if let Some((first, _)) = word(start) {
+                    start.col = first;
+                }
+                if let Some((_, last)) = word(end) {
+                    end.col = last;
+                }
+            }
+            SelectionMode::Line => {
+                // Take in the whole of lines that autowrapped
+                while start.line > 0 && self.history_line_wrapped(start.line - 1) {
+                    start.line -= 1;
+                }
+                while self.history_line_wrapped(end.line) && end.line + 1 < self.history_len() {
+                    end.line += 1;
+                }
+                start.col = 0;
+                end.col = self.size.cols.saturating_sub(1);
+            }
+            SelectionMode::Character | SelectionMode::Block => {}
+        }
+        Some((start, end))
+    }
+    
+    /// Whether a cell is selected
+    pub fn is_selected(&self, point: HistoryPoint) -> bool {
+        let Some((start, end)) = self.selection_range() else { return false };
+        if self.selection.is_some_and(|selection| selection.mode == SelectionMode::Block) {
+            (start.line..=end.line).contains(&point.line) && (start.col..=end.col).contains(&point.col)
+        } else {
+            (start..=end).contains(&point)
+        }
+    }
+    
+    /// Text of the selection, with autowrapped lines joined
+    pub fn selected_text(&self) -> Option<String> {
+        self.with_selected_lines(buffer::lines_text)
+    }
+    
+    /// The screen, and optionally the scrollback above it, as plain text
+    ///
+    /// See `export::to_text`.
+    pub fn export_text(&self, include_scrollback: bool) -> String {
+        let first = if include_scrollback { 0 } else { self.scrollback_buffer.len() };
+        export::to_text(self, first..self.history_len())
+    }
+    
+    /// The selection as text with SGR sequences for its colors and attributes
+    pub fn selection_to_ansi(&self) -> Option<String> {
+        self.with_selected_lines(export::lines_ansi)
+    }
+    
+    /// The screen as text with SGR sequences, e.g. to `cat` into another
+    /// terminal or page with `less -R`
+    pub fn screen_to_ansi(&self) -> String {
+        let first = self.scrollback_buffer.len();
+        self.history_to_ansi(first..first + self.size.rows as usize)
+    }
+    
+    /// History lines (the scrollback followed by the screen) as text with
+    /// SGR sequences
+    ///
+    /// Autowrapped lines are joined and trailing empty lines dropped.
+    pub fn history_to_ansi(&self, lines: Range<usize>) -> String {
+        let lines: Vec<_> = lines.filter_map(|line| self.history_line(line)).collect();
+        export::lines_ansi(&export::styled_lines(lines.iter().map(|line| line.as_ref())))
+    }
+    
+    /// The selection as an HTML `<pre>` block, with colors resolved
+    /// against the current palette
+    pub fn selection_to_html(&self) -> Option<String> {
+        self.with_selected_lines(|lines| export::lines_html(self, lines))
+    }
+    
+    /// Pass the selected cells of each line, as from `buffer::copy_lines`, to `f`
+    fn with_selected_lines<T>(&self, f: impl FnOnce(&[(&[Cell], bool)]) -> T) -> Option<T> {
+        let (start, end) = self.selection_range()?;
+        let block = self.selection?.mode == SelectionMode::Block;
+        Some(self.with_history_lines(start, end, block, f))
+    }
+    
+    /// Pass the cells from `start` to `end` inclusive, or the rectangle
+    /// they span if `block`, to `f` as from `buffer::copy_lines`
+    fn with_history_lines<T>(
+        &self,
+        start: HistoryPoint,
+        end: HistoryPoint,
+        block: bool,
+        f: impl FnOnce(&[(&[Cell], bool)]) -> T,
+    ) -> T {
+        let lines = (start.line..=end.line).filter_map(|line| {
+            let cells = self.history_line(line)?;
+            let range = if block {
+                start.col as usize..end.col as usize + 1
+            } else {
+                let from = if line == start.line { start.col as usize } else { 0 };
+                let to = if line == end.line { end.col as usize + 1 } else { cells.len() };
+                from..to
+            };
+            Some((cells, range))
+        });
+        let lines: Vec<_> = lines.collect();
+        let copied = buffer::copy_lines(lines.iter().map(|(cells, range)| (cells.as_ref(), range.clone())), !block);
+        f(&copied)
+    }
+    
+    /// Tag the selected cells on screen so renderers can highlight them
+    fn update_selection_tags(&mut self) {
+        self.screen_buffer.clear_tags(CellTags::SELECTED);
+        let Some(range) = self.selection_range() else { return };
+        let block = self.selection.is_some_and(|selection| selection.mode == SelectionMode::Block);
+        self.tag_screen_span(range, block, CellTags::SELECTED);
+    }
+    
+    /// Tag the part of a span of history that is on the screen
+    fn tag_screen_span(&mut self, range: (HistoryPoint, HistoryPoint), block: bool, tag: CellTags) {
+        let first = self.scrollback_buffer.len();
+        for line in range.0.line.max(first)..=range.1.line {
+            let Ok(row) = u16::try_from(line - first) else { break };
+            if row >= self.size.rows {
+                break;
+            }
+            if let Some((from, to)) = self.span_columns(range, block, line) {
+                self.screen_buffer.set_tags(Position::new(row, from), Position::new(row, to), tag, true);
+            }
+        }
+    }
+    
+    /// First and last column of a history line within a span, read as a
+    /// rectangle if `block` is set
+    fn span_columns(&self, (start, end): (HistoryPoint, HistoryPoint), block: bool, line: usize) -> Option<(u16, u16)> {
+        if !(start.line..=end.line).contains(&line) {
+            return None;
+        }
+        let last_col = self.size.cols.saturating_sub(1);
+        let (from, to) = if block {
+            (start.col, end.col)
+        } else {
+            (if line == start.line { start.col } else { 0 }, if line == end.line { end.col } else { last_col })
+        };
+        Some((from, to.min(last_col)))
+    }
+    
+    /// Change how many scrollback lines are kept, dropping the oldest to fit
+    ///
+    /// The new limit also survives a reset.
+    pub fn set_scrollback_limit(&mut self, limit: ScrollbackLimit) {
+        self.config.scrollback_limit = limit;
+        let dropped = self.scrollback_buffer.set_max_lines(limit.max_lines());
+        if dropped > 0 {
+            self.shift_search_highlights(dropped);
+            if self.selection.as_mut().is_some_and(|selection| !selection.shift_up(dropped)) {
+                self.clear_selection();
+            }
+            self.set_display_offset(self.display_offset);
+        }
+    }
+    
+    /// Clear the scrollback (ED 3)
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback_buffer.clear();
+        self.clear_selection();
+        self.clear_search_highlights();
+        self.scroll_display_to_bottom();
+    }
+    
+    /// Text of a visible row, without trailing blanks
+    pub fn row_text(&self, row: u16) -> String {
+        self.text_in(Position::new(row, 0), Position::new(row, self.size.cols.saturating_sub(1)))
+    }
+    
+    /// Text of the visible screen from `start` to `end` inclusive, in reading order
+    ///
+    /// Rows end in a newline unless they wrapped onto the next row, and
+    /// trailing blanks are dropped from rows that didn't wrap.
+    pub fn text_in(&self, start: Position, end: Position) -> String {
+        let lines = self.screen_buffer.lines().iter()
+            .enumerate()
+            .take(end.row as usize + 1)
+            .skip(start.row as usize)
+            .map(|(row, line)| {
+                let from = if row == start.row as usize { start.col as usize } else { 0 };
+                let to = if row == end.row as usize { end.col as usize + 1 } else { line.len() };
+                (line.as_slice(), from..to)
+            });
+        buffer::lines_text(&buffer::copy_lines(lines, true))
+    }
+    
+    /// Text of the whole visible screen, without trailing blank lines
+    pub fn contents(&self) -> String {
+        let end = Position::new(self.size.rows.saturating_sub(1), self.size.cols.saturating_sub(1));
+        let mut text = self.text_in(Position::new(0, 0), end);
+        text.truncate(text.trim_end_matches('\n').len());
+        text
+    }
+    
+    /// Get a reference to the screen buffer
+    pub fn screen_buffer(&self) -> &ScreenBuffer {
+        &self.screen_buffer
+    }
+    
+    /// Get a reference to the scrollback buffer
+    pub fn scrollback_buffer(&self) -> &ScrollbackBuffer {
+        &self.scrollback_buffer
+    }
+    
+    /// Get a mutable reference to the screen buffer
+    pub fn screen_buffer_mut(&mut self) -> &mut ScreenBuffer {
+        &mut self.screen_buffer
+    }
+    
+    /// Get a mutable reference to the scrollback buffer
+    pub fn scrollback_buffer_mut(&mut self) -> &mut ScrollbackBuffer {
+        &mut self.scrollback_buffer
+    }
+    
+    /// Get a mutable reference to the cursor
+    pub fn cursor_mut(&mut self) -> &mut Cursor {
+        &mut self.cursor
+    }
+    
+    /// Set cursor position
+    pub fn set_cursor_position(&mut self, pos: Position) {
+        self.cursor.set_position(pos);
+    }
+    
+    /// Set underline color
+    pub fn set_underline_color(&mut self, color: Option<Color>) {
+        self.active_attributes.underline_color = color;
+    }
+    
+    /// Scroll the scroll region down by one line (reverse scroll)
+    pub fn scroll_down(&mut self) {
+        debug!("Scrolling down rows {}..={}", self.scroll_top, self.scroll_bottom);
+        if self.has_side_margins() {
+            let (left, right) = (self.left_margin, self.right_margin);
+            self.screen_buffer.scroll_rect_down(self.scroll_top, self.scroll_bottom, left, right);
+        } else {
+            self.screen_buffer.scroll_region_down(self.scroll_top, self.scroll_bottom);
+        }
+    }
+    
+    /// Set the modifyOtherKeys level (XTMODKEYS 4), clamped to 2
+    pub fn set_modify_other_keys(&mut self, level: u8) {
+        self.modify_other_keys = level.min(2);
+    }
+    
+    /// Kitty keyboard flag stack of the active screen
+    fn keyboard_flags_stack(&mut self) -> &mut Vec<u8> {
+        &mut self.keyboard_flags[self.alternate_buffer.is_some() as usize]
+    }
+    
+    /// Current kitty keyboard flags of the active screen
+    pub fn keyboard_flags(&self) -> u8 {
+        let stack = &self.keyboard_flags[self.alternate_buffer.is_some() as usize];
+        stack.last().copied().unwrap_or(0)
+    }
+    
+    /// Push kitty keyboard flags (`CSI > flags u`)
+    pub fn push_keyboard_flags(&mut self, flags: u8) {
+        let stack = self.keyboard_flags_stack();
+        if stack.len() == MAX_KEYBOARD_FLAGS_STACK {
+            stack.remove(0);
+        }
+        stack.push(flags);
+    }
+    
+    /// Pop `count` kitty keyboard flag entries (`CSI < count u`)
+    pub fn pop_keyboard_flags(&mut self, count: u16) {
+        let stack = self.keyboard_flags_stack();
+        stack.truncate(stack.len().saturating_sub(count as usize));
+    }
+    
+    /// Update the current kitty keyboard flags (`CSI = flags ; mode u`)
+    pub fn set_keyboard_flags(&mut self, flags: u8, mode: KeyboardFlagsMode) {
+        let stack = self.keyboard_flags_stack();
+        if stack.is_empty() {
+            stack.push(0);
+        }
+        let current = stack.last_mut().unwrap();
+        match mode {
+            KeyboardFlagsMode::Replace => *current = flags,
+            KeyboardFlagsMode::Set => *current |= flags,
+            KeyboardFlagsMode::Clear => *current &= !flags,
+        }
+    }
+    
+    /// Modes the key encoder needs to translate key presses
+    pub fn input_modes(&self) -> InputModes {
+        InputModes {
+            application_cursor: self.mode.contains(TerminalMode::APPLICATION_CURSOR),
+            application_keypad: self.mode.contains(TerminalMode::APPLICATION_KEYPAD),
+            line_feed: self.mode.contains(TerminalMode::LINE_FEED),
+            modify_other_keys: self.modify_other_keys,
+            keyboard_flags: self.keyboard_flags(),
+            mouse_tracking: MouseTracking::from_mode(&self.mode),
+            mouse_encoding: MouseEncoding::from_mode(&self.mode),
+            alt_key: self.config.alt_key,
+            bracketed_paste: self.mode.contains(TerminalMode::BRACKETED_PASTE),
+        }
+    }
+    
+    /// Set a terminal mode flag
+    pub fn set_mode_flag(&mut self, mode: Mode, enabled: bool) {
+        match mode {
+            Mode::Insert => {
+                if enabled {
+                    self.mode.insert(TerminalMode::INSERT_MODE);
+                } else {
+                    self.mode.remove(TerminalMode::INSERT_MODE);
+                }
+            }
+            Mode::AutoWrap => {
+                if enabled {
+                    self.mode.insert(TerminalMode::LINE_WRAP);
                 } else {
                     self.mode.remove(TerminalMode::LINE_WRAP);
                 }
@@ -353,11 +1770,33 @@ impl TerminalState {
                     self.mode.remove(TerminalMode::FOCUS_REPORTING);
                 }
             }
-            Mode::MouseReporting => {
+            Mode::MouseReporting | Mode::MouseDrag | Mode::MouseMotion => {
+                let flag = match mode {
+                    Mode::MouseReporting => TerminalMode::MOUSE_REPORTING,
+                    Mode::MouseDrag => TerminalMode::MOUSE_DRAG,
+                    _ => TerminalMode::MOUSE_MOTION,
+                };
+                // Setting a tracking mode replaces the active one; resetting
+                // only turns tracking off if that mode is the active one
+                if enabled {
+                    self.mode.remove(TerminalMode::MOUSE_TRACKING);
+                    self.mode.insert(flag);
+                } else {
+                    self.mode.remove(flag);
+                }
+            }
+            Mode::MouseUtf8 | Mode::MouseSgr | Mode::MouseUrxvt => {
+                let flag = match mode {
+                    Mode::MouseUtf8 => TerminalMode::MOUSE_UTF8,
+                    Mode::MouseSgr => TerminalMode::MOUSE_SGR,
+                    _ => TerminalMode::MOUSE_URXVT,
+                };
+                // Encodings replace each other the same way
                 if enabled {
-                    self.mode.insert(TerminalMode::MOUSE_REPORTING);
+                    self.mode.remove(TerminalMode::MOUSE_ENCODINGS);
+                    self.mode.insert(flag);
                 } else {
-                    self.mode.remove(TerminalMode::MOUSE_REPORTING);
+                    self.mode.remove(flag);
                 }
             }
             Mode::ApplicationCursor => {
@@ -381,68 +1820,403 @@ impl TerminalState {
                     self.mode.remove(TerminalMode::ORIGIN_MODE);
                 }
             }
+            Mode::ScreenMode => {
+                if enabled {
+                    self.mode.insert(TerminalMode::REVERSE_VIDEO);
+                } else {
+                    self.mode.remove(TerminalMode::REVERSE_VIDEO);
+                }
+                self.screen_buffer.damage_all();
+            }
+            Mode::LineFeed => {
+                if enabled {
+                    self.mode.insert(TerminalMode::LINE_FEED);
+                } else {
+                    self.mode.remove(TerminalMode::LINE_FEED);
+                }
+            }
+            Mode::LeftRightMargin => {
+                if enabled {
+                    self.mode.insert(TerminalMode::LEFT_RIGHT_MARGIN);
+                } else {
+                    // Leaving DECLRMM restores the full-width margins
+                    self.mode.remove(TerminalMode::LEFT_RIGHT_MARGIN);
+                    self.left_margin = 0;
+                    self.right_margin = self.size.cols.saturating_sub(1);
+                }
+            }
             _ => {
                 debug!("Unhandled mode flag: {:?}", mode);
             }
         }
     }
     
+    /// Whether a mode is enabled, or `None` if the mode is not tracked
+    pub fn mode_flag(&self, mode: Mode) -> Option<bool> {
+        let flag = match mode {
+            // 1048 is an action rather than a setting, so it always reads
+            // as reset, as in xterm
+            Mode::SaveCursor => return Some(false),
+            Mode::Insert => TerminalMode::INSERT_MODE,
+            Mode::LineFeed => TerminalMode::LINE_FEED,
+            Mode::ColumnMode => TerminalMode::COLUMN_132,
+            Mode::ScreenMode => TerminalMode::REVERSE_VIDEO,
+            Mode::AutoWrap => TerminalMode::LINE_WRAP,
+            Mode::CursorVisible => TerminalMode::CURSOR_VISIBLE,
+            Mode::CursorBlink => TerminalMode::CURSOR_BLINKING,
+            Mode::AlternateScreen
+            | Mode::AlternateScreenClear
+            | Mode::AlternateScreenSaveCursor => TerminalMode::ALTERNATE_SCREEN,
+            Mode::BracketedPaste => TerminalMode::BRACKETED_PASTE,
+            Mode::FocusReporting => TerminalMode::FOCUS_REPORTING,
+            Mode::MouseReporting => TerminalMode::MOUSE_REPORTING,
+            Mode::MouseDrag => TerminalMode::MOUSE_DRAG,
+            Mode::MouseMotion => TerminalMode::MOUSE_MOTION,
+            Mode::MouseUtf8 => TerminalMode::MOUSE_UTF8,
+            Mode::MouseSgr => TerminalMode::MOUSE_SGR,
+            Mode::MouseUrxvt => TerminalMode::MOUSE_URXVT,
+            Mode::ApplicationCursor => TerminalMode::APPLICATION_CURSOR,
+            Mode::ApplicationKeypad => TerminalMode::APPLICATION_KEYPAD,
+            Mode::OriginMode => TerminalMode::ORIGIN_MODE,
+            Mode::LeftRightMargin => TerminalMode::LEFT_RIGHT_MARGIN,
+            _ => return None,
+        };
+        Some(self.mode.contains(flag))
+    }
+    
     /// Get the terminal mode
     pub fn mode(&self) -> TerminalMode {
         self.mode
     }
     
-    /// Set terminal mode
-    pub fn set_mode(&mut self, mode: TerminalMode) {
-        self.mode = mode;
+    /// Set terminal mode
+    pub fn set_mode(&mut self, mode: TerminalMode) {
+        self.mode = mode;
+    }
+    
+    /// Enable alternate screen buffer
+    ///
+    /// The alternate screen keeps what was left on it the last time it was
+    /// shown; use `clear_alternate_screen` for a blank one.
+    pub fn enable_alternate_screen(&mut self) {
+        if self.alternate_buffer.is_none() {
+            self.clear_selection();
+            self.clear_search_highlights();
+            let mut alt_buffer = self.inactive_alternate.take().unwrap_or_else(|| ScreenBuffer::new(self.size));
+            if alt_buffer.size() != self.size {
+                alt_buffer.resize(self.size);
+            }
+            self.display_offset = 0;
+            self.alternate_buffer = Some(std::mem::replace(&mut self.screen_buffer, alt_buffer));
+            std::mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+            self.mode.insert(TerminalMode::ALTERNATE_SCREEN);
+            self.screen_buffer.damage_all();
+        }
+    }
+    
+    /// Disable alternate screen buffer
+    pub fn disable_alternate_screen(&mut self) {
+        if let Some(mut main_buffer) = self.alternate_buffer.take() {
+            self.clear_selection();
+            self.clear_search_highlights();
+            // The main screen missed any resize while it was hidden
+            if main_buffer.size() != self.size {
+                main_buffer.resize(self.size);
+            }
+            self.display_offset = 0;
+            self.inactive_alternate = Some(std::mem::replace(&mut self.screen_buffer, main_buffer));
+            std::mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+            self.mode.remove(TerminalMode::ALTERNATE_SCREEN);
+            self.screen_buffer.damage_all();
+        }
+    }
+    
+    /// Clear the alternate screen if it is shown
+    pub fn clear_alternate_screen(&mut self) {
+        if self.alternate_buffer.is_some() {
+            self.screen_buffer.clear();
+        }
+    }
+    
+    /// Save cursor position and attributes (DECSC)
+    ///
+    /// Also saves the character sets, origin mode, selective erase
+    /// protection and whether a wrap is pending. The main and alternate
+    /// screens each keep their own saved state.
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            cursor: self.cursor.clone(),
+            attributes: self.active_attributes,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+            origin_mode: self.mode.contains(TerminalMode::ORIGIN_MODE),
+            protected: self.protected,
+        });
+    }
+    
+    /// Restore cursor position and attributes (DECRC)
+    ///
+    /// The saved state can be restored more than once. Without one the
+    /// cursor goes home with default attributes, as in xterm.
+    pub fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.clone().unwrap_or_else(|| SavedCursor {
+            cursor: Cursor::new(),
+            attributes: CellAttributes::default(),
+            charsets: [Charset::Ascii; 4],
+            active_charset: 0,
+            origin_mode: false,
+            protected: false,
+        });
+        self.cursor = saved.cursor;
+        self.active_attributes = saved.attributes;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.mode.set(TerminalMode::ORIGIN_MODE, saved.origin_mode);
+        self.protected = saved.protected;
+        
+        // The screen may have shrunk since the save
+        let pos = self.cursor.position();
+        if pos.row >= self.size.rows || pos.col >= self.size.cols {
+            self.cursor.set_position(Position::new(
+                pos.row.min(self.size.rows.saturating_sub(1)),
+                pos.col.min(self.size.cols.saturating_sub(1)),
+            ));
+        }
+    }
+    
+    /// Set cursor style, which also sets whether the cursor blinks
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+        self.mode.set(TerminalMode::CURSOR_BLINKING, style.is_blinking());
+    }
+    
+    /// Start or stop the cursor blinking, keeping its shape (DECSET 12)
+    pub fn set_cursor_blinking(&mut self, blinking: bool) {
+        self.set_cursor_style(self.cursor_style.with_blinking(blinking));
+    }
+    
+    /// Whether the application asked for a blinking cursor
+    pub fn cursor_blinking(&self) -> bool {
+        self.mode.contains(TerminalMode::CURSOR_BLINKING)
+    }
+    
+    /// How long the cursor stays on and then off, or `None` when it should
+    /// be drawn steady
+    pub fn cursor_blink_interval(&self) -> Option<Duration> {
+        let CursorBlinkInterval(interval) = self.config.cursor_blink_interval;
+        (self.cursor_blinking() && !interval.is_zero()).then_some(interval)
+    }
+    
+    /// Get cursor style
+    pub fn cursor_style(&self) -> CursorStyle {
+        self.cursor_style
+    }
+    
+    /// Set cursor visibility
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        if visible {
+            self.mode.insert(TerminalMode::CURSOR_VISIBLE);
+        } else {
+            self.mode.remove(TerminalMode::CURSOR_VISIBLE);
+        }
+    }
+    
+    /// Ring the bell according to the configured bell policy
+    pub fn ring_bell(&mut self) {
+        let policy = &self.config.bell;
+        if policy.action == BellAction::Silent {
+            return;
+        }
+        let urgent = policy.urgent_when_unfocused && !self.focused;
+        self.pending_events.push(Event::Bell { action: policy.action.clone(), urgent });
+    }
+    
+    /// Hand text the application copied to the frontend (OSC 52)
+    pub fn set_clipboard(&mut self, clipboard: ClipboardType, text: String) {
+        self.pending_events.push(Event::ClipboardSet { clipboard, text });
+    }
+    
+    /// Ask the frontend for a clipboard's contents (OSC 52 query)
+    pub fn request_clipboard(&mut self, clipboard: ClipboardType) {
+        self.pending_events.push(Event::ClipboardQuery(clipboard));
+    }
+    
+    /// Queue the reply to an OSC 52 query
+    pub fn reply_clipboard(&mut self, clipboard: ClipboardType, text: &str) {
+        let selection = match clipboard {
+            ClipboardType::Clipboard => 'c',
+            ClipboardType::Primary => 'p',
+            ClipboardType::Secondary => 'q',
+        };
+        let data = BASE64_STANDARD.encode(text);
+        self.push_response(format!("\x1b]52;{};{}\x1b\\", selection, data));
+    }
+    
+    /// Ask the frontend to show a desktop notification
+    pub fn notify(&mut self, title: Option<String>, body: String) {
+        self.pending_events.push(Event::Notification { title, body });
+    }
+    
+    /// Set the window title (OSC 2)
+    pub fn set_title(&mut self, title: String) {
+        self.title = title.clone();
+        self.pending_events.push(Event::TitleChanged(title));
+    }
+    
+    /// Set the icon name (OSC 1)
+    pub fn set_icon_name(&mut self, icon_name: String) {
+        self.icon_name = icon_name.clone();
+        self.pending_events.push(Event::IconNameChanged(icon_name));
+    }
+    
+    /// Get the window title
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    
+    /// Get the icon name
+    pub fn icon_name(&self) -> &str {
+        &self.icon_name
+    }
+    
+    /// Save the current titles on the title stack (XTWINOPS 22)
+    ///
+    /// Both titles are always saved; `kind` only matters when popping.
+    pub fn push_title(&mut self, _kind: TitleKind) {
+        if self.title_stack.len() == MAX_TITLE_STACK {
+            self.title_stack.remove(0);
+        }
+        self.title_stack.push((self.title.clone(), self.icon_name.clone()));
+    }
+    
+    /// Restore titles from the title stack (XTWINOPS 23)
+    pub fn pop_title(&mut self, kind: TitleKind) {
+        let Some((title, icon_name)) = self.title_stack.pop() else {
+            return;
+        };
+        if kind != TitleKind::IconName {
+            self.set_title(title);
+        }
+        if kind != TitleKind::WindowTitle {
+            self.set_icon_name(icon_name);
+        }
+    }
+    
+    /// Pass an event through to the frontend as is
+    pub fn emit(&mut self, event: Event) {
+        self.pending_events.push(event);
+    }
+    
+    /// Ask the frontend to minimize or restore the window
+    pub fn request_iconify(&mut self, iconify: bool) {
+        self.pending_events.push(Event::Iconify(iconify));
+    }
+    
+    /// Update the focus state reported by the frontend
+    ///
+    /// Queues a focus report for the application when focus reporting
+    /// (DECSET 1004) is enabled and the focus actually changed.
+    pub fn set_focused(&mut self, focused: bool) {
+        let changed = self.focused != focused;
+        self.focused = focused;
+        
+        if changed && self.mode.contains(TerminalMode::FOCUS_REPORTING) {
+            self.push_response(if focused { "\x1b[I" } else { "\x1b[O" });
+        }
+    }
+    
+    /// Whether the frontend window has focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+    
+    /// Take the events raised since the last call
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.pending_events)
+    }
+    
+    /// Queue a reply to be written back to the application
+    pub fn push_response(&mut self, bytes: impl Into<Vec<u8>>) {
+        self.responses.push(bytes.into());
+    }
+    
+    /// Take the replies queued since the last call
+    pub fn take_responses(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.responses)
+    }
+    
+    /// Set the pixel size of a cell, as reported by the frontend
+    pub fn set_cell_metrics(&mut self, metrics: CellMetrics) {
+        self.cell_metrics = Some(metrics);
+    }
+    
+    /// Get the pixel size of a cell, if the frontend has reported one
+    pub fn cell_metrics(&self) -> Option<CellMetrics> {
+        self.cell_metrics
+    }
+    
+    /// Replace a palette entry (OSC 4)
+    pub fn set_palette_color(&mut self, index: u8, color: Color) {
+        self.color_palette[index as usize] = color;
+        self.screen_buffer.damage_all();
     }
     
-    /// Enable alternate screen buffer
-    pub fn enable_alternate_screen(&mut self) {
-        if self.alternate_buffer.is_none() {
-            let alt_buffer = ScreenBuffer::new(self.size);
-            self.alternate_buffer = Some(std::mem::replace(&mut self.screen_buffer, alt_buffer));
-            self.mode.insert(TerminalMode::ALTERNATE_SCREEN);
-        }
+    /// Get the color of a palette entry
+    pub fn palette_color(&self, index: u8) -> Color {
+        self.color_palette[index as usize]
     }
     
-    /// Disable alternate screen buffer
-    pub fn disable_alternate_screen(&mut self) {
-        if let Some(main_buffer) = self.alternate_buffer.take() {
-            self.screen_buffer = main_buffer;
-            self.mode.remove(TerminalMode::ALTERNATE_SCREEN);
-        }
+    /// Change a default color (OSC 10/11/12)
+    pub fn set_dynamic_color(&mut self, target: DynamicColor, color: Color) {
+        self.dynamic_colors.set(target, color);
+        self.screen_buffer.damage_all();
     }
     
-    /// Save cursor position and attributes
-    pub fn save_cursor(&mut self) {
-        self.saved_cursor = Some(self.cursor.clone());
+    /// Get the current default colors
+    pub fn dynamic_colors(&self) -> DynamicColors {
+        self.dynamic_colors
     }
     
-    /// Restore cursor position and attributes
-    pub fn restore_cursor(&mut self) {
-        if let Some(saved) = self.saved_cursor.take() {
-            self.cursor = saved;
-        }
+    /// Resolve a foreground color to RGB using the live palette
+    ///
+    /// `Color::Default` is the current default foreground.
+    pub fn resolve_color(&self, color: Color) -> Rgb {
+        self.resolve(color, self.dynamic_colors.foreground)
     }
     
-    /// Set cursor style
-    pub fn set_cursor_style(&mut self, style: CursorStyle) {
-        self.cursor_style = style;
+    /// Resolve a background color to RGB; `Color::Default` is the current default background
+    pub fn resolve_background(&self, color: Color) -> Rgb {
+        self.resolve(color, self.dynamic_colors.background)
     }
     
-    /// Get cursor style
-    pub fn cursor_style(&self) -> CursorStyle {
-        self.cursor_style
+    fn resolve(&self, color: Color, default: Color) -> Rgb {
+        let color = match color {
+            Color::Default => default,
+            color => color,
+        };
+        let color = color.palette_index().map_or(color, |index| self.color_palette[index as usize]);
+        match color {
+            Color::Rgb(r, g, b) => Rgb::new(r, g, b),
+            // Entries still holding a named color use the built-in value
+            color => color.palette_index().map_or(Rgb::default(), default_rgb),
+        }
     }
     
-    /// Set cursor visibility
-    pub fn set_cursor_visible(&mut self, visible: bool) {
-        if visible {
-            self.mode.insert(TerminalMode::CURSOR_VISIBLE);
-        } else {
-            self.mode.remove(TerminalMode::CURSOR_VISIBLE);
+    /// Take the cells changed since the last call, top to bottom
+    ///
+    /// Covers writes, erases, scrolling and cursor movement, and the whole
+    /// screen after a resize, screen switch or color change, so a frontend
+    /// only needs to redraw these spans on `StateChanged`.
+    pub fn take_damage(&mut self) -> Vec<LineDamage> {
+        let cursor = (self.cursor_position(), self.mode.contains(TerminalMode::CURSOR_VISIBLE));
+        if self.damaged_cursor != Some(cursor) {
+            // A cursor on a wide character covers the next cell too
+            for (pos, _) in self.damaged_cursor.into_iter().chain([cursor]) {
+                self.screen_buffer.damage_span(pos.row, pos.col, pos.col.saturating_add(1));
+            }
+            self.damaged_cursor = Some(cursor);
         }
+        self.screen_buffer.take_damage()
     }
     
     /// Get a snapshot of the terminal state
@@ -454,18 +2228,183 @@ impl TerminalState {
             cursor_style: self.cursor_style,
             active_attributes: self.active_attributes,
             alternate_screen_active: self.alternate_buffer.is_some(),
+            dynamic_colors: self.dynamic_colors,
+            line_renditions: self.screen_buffer.line_renditions().to_vec(),
+            reverse_video: self.mode.contains(TerminalMode::REVERSE_VIDEO),
+            cursor_blinking: self.cursor_blinking(),
+            palette: (0..=255).map(|index| self.resolve_color(Color::Indexed(index))).collect(),
+            title: self.title.clone(),
+            icon_name: self.icon_name.clone(),
         }
     }
     
-    /// Ensure cursor is within bounds
-    fn clamp_cursor(&mut self) {
-        let pos = self.cursor.position();
-        if pos.row >= self.size.rows {
-            self.cursor.set_row(self.size.rows - 1);
+    /// Capture the cells in view along with `snapshot`, for frontends to
+    /// render from one consistent structure
+    ///
+    /// This copies every visible cell, so it is only built on request.
+    pub fn grid_snapshot(&self) -> GridSnapshot {
+        let mut terminal = self.snapshot();
+        let mut hyperlinks: Vec<Hyperlink> = Vec::new();
+        let rows = self.visible_rows().iter().map(|row| {
+            row.iter().map(|cell| GridCell {
+                ch: cell.ch,
+                attrs: cell.attrs,
+                tags: cell.tags,
+                width: cell.width,
+                hyperlink: cell.hyperlink.as_ref().map(|link| {
+                    hyperlinks.iter().position(|known| known == link.as_ref()).unwrap_or_else(|| {
+                        hyperlinks.push(Hyperlink::clone(link));
+                        hyperlinks.len() - 1
+                    })
+                }),
+            }).collect()
+        }).collect();
+        
+        // Scrollback lines don't keep their rendition
+        let scrolled = self.display_offset.min(self.size.rows as usize);
+        let mut renditions = vec![LineRendition::default(); scrolled];
+        renditions.extend(terminal.line_renditions.iter().take(self.size.rows as usize - scrolled));
+        terminal.line_renditions = renditions;
+        
+        GridSnapshot { terminal, rows, display_offset: self.display_offset, hyperlinks }
+    }
+    
+    /// Serialize the whole state, for detach/reattach and crash recovery
+    ///
+    /// See `restore_with_config` for what is kept.
+    pub fn save(&self) -> Vec<u8> {
+        let saved = SavedState {
+            version: STATE_VERSION,
+            size: self.size,
+            cursor: self.cursor.clone(),
+            saved_cursor: self.saved_cursor.clone(),
+            inactive_saved_cursor: self.inactive_saved_cursor.clone(),
+            screen: SavedScreen::new(&self.screen_buffer),
+            alternate: self.alternate_buffer.as_ref().map(SavedScreen::new),
+            inactive_alternate: self.inactive_alternate.as_ref().map(SavedScreen::new),
+            scrollback: self.scrollback_buffer.lines().map(|line| buffer::encode_line(&line)).collect(),
+            mode: self.mode,
+            cursor_style: self.cursor_style,
+            active_attributes: self.active_attributes,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+            semantic_zone: self.semantic_zone,
+            protected: self.protected,
+            hyperlink: self.hyperlink.as_deref().cloned(),
+            color_palette: self.color_palette.clone(),
+            dynamic_colors: self.dynamic_colors,
+            tab_stops: self.tab_stops.clone(),
+            scroll_top: self.scroll_top,
+            scroll_bottom: self.scroll_bottom,
+            left_margin: self.left_margin,
+            right_margin: self.right_margin,
+            focused: self.focused,
+            cell_metrics: self.cell_metrics,
+            title: self.title.clone(),
+            icon_name: self.icon_name.clone(),
+            title_stack: self.title_stack.clone(),
+            modify_other_keys: self.modify_other_keys,
+            keyboard_flags: self.keyboard_flags.clone(),
+            printer_controller: self.printer_controller,
+            autoprint: self.autoprint,
+        };
+        serde_json::to_vec(&saved).expect("terminal state serializes")
+    }
+    
+    /// Rebuild a terminal saved by `save`, with the default configuration
+    pub fn restore(bytes: &[u8]) -> Result<Self> {
+        Self::restore_with_config(bytes, TerminalConfig::default())
+    }
+    
+    /// Rebuild a terminal saved by `save`
+    ///
+    /// The screens, scrollback, cursor, modes, colors, titles and keyboard
+    /// modes come back as saved. The configuration isn't saved, so the
+    /// scrollback is cut to its limit. The printer, pattern matchers,
+    /// selection and view start out empty, and events or responses that
+    /// were never taken are lost.
+    pub fn restore_with_config(bytes: &[u8], config: TerminalConfig) -> Result<Self> {
+        let invalid = |err: serde_json::Error| PhosphorError::State(format!("Invalid saved state: {}", err));
+        let SavedVersion { version } = serde_json::from_slice(bytes).map_err(invalid)?;
+        if version != STATE_VERSION {
+            return Err(PhosphorError::State(format!(
+                "Unsupported saved state version {} (expected {})",
+                version, STATE_VERSION
+            )));
+        }
+        let saved: SavedState = serde_json::from_slice(bytes).map_err(invalid)?;
+        Self::check_saved(&saved).map_err(|err| PhosphorError::State(format!("Invalid saved state: {}", err)))?;
+        
+        let size = saved.size;
+        let mut state = Self::with_config(size, config);
+        state.cursor = saved.cursor;
+        state.saved_cursor = saved.saved_cursor;
+        state.inactive_saved_cursor = saved.inactive_saved_cursor;
+        state.screen_buffer = saved.screen.into_buffer(size);
+        state.alternate_buffer = saved.alternate.map(|screen| screen.into_buffer(size));
+        state.inactive_alternate = saved.inactive_alternate.map(|screen| screen.into_buffer(size));
+        for line in saved.scrollback {
+            state.scrollback_buffer.push(buffer::decode_line(line));
+        }
+        state.mode = saved.mode;
+        state.cursor_style = saved.cursor_style;
+        state.active_attributes = saved.active_attributes;
+        state.charsets = saved.charsets;
+        state.active_charset = saved.active_charset;
+        state.semantic_zone = saved.semantic_zone;
+        state.protected = saved.protected;
+        state.hyperlink = saved.hyperlink.map(Arc::new);
+        state.color_palette = saved.color_palette;
+        state.dynamic_colors = saved.dynamic_colors;
+        state.tab_stops = saved.tab_stops;
+        state.scroll_top = saved.scroll_top;
+        state.scroll_bottom = saved.scroll_bottom;
+        state.left_margin = saved.left_margin;
+        state.right_margin = saved.right_margin;
+        state.focused = saved.focused;
+        state.cell_metrics = saved.cell_metrics;
+        state.title = saved.title;
+        state.icon_name = saved.icon_name;
+        state.title_stack = saved.title_stack;
+        state.modify_other_keys = saved.modify_other_keys;
+        state.keyboard_flags = saved.keyboard_flags;
+        state.printer_controller = saved.printer_controller;
+        state.autoprint = saved.autoprint;
+        Ok(state)
+    }
+    
+    /// Check the values a restored state indexes with, so a tampered or
+    /// corrupt save is rejected instead of panicking later
+    fn check_saved(saved: &SavedState) -> std::result::Result<(), String> {
+        let Size { cols, rows } = saved.size;
+        if cols == 0 || rows == 0 {
+            return Err(format!("size {}x{}", cols, rows));
         }
-        if pos.col >= self.size.cols {
-            self.cursor.set_col(self.size.cols - 1);
+        if saved.color_palette.len() != 256 {
+            return Err(format!("palette of {} colors", saved.color_palette.len()));
         }
+        if saved.active_charset >= saved.charsets.len() {
+            return Err(format!("active charset {}", saved.active_charset));
+        }
+        if saved.scroll_top > saved.scroll_bottom || saved.scroll_bottom >= rows {
+            return Err(format!("scroll region {}-{}", saved.scroll_top, saved.scroll_bottom));
+        }
+        if saved.left_margin > saved.right_margin || saved.right_margin >= cols {
+            return Err(format!("margins {}-{}", saved.left_margin, saved.right_margin));
+        }
+        let on_screen = |pos: Position| pos.row < rows && pos.col < cols;
+        if !on_screen(saved.cursor.position()) {
+            return Err(format!("cursor at {:?}", saved.cursor.position()));
+        }
+        for cursor in saved.saved_cursor.iter().chain(&saved.inactive_saved_cursor) {
+            if !on_screen(cursor.cursor.position()) || cursor.active_charset >= cursor.charsets.len() {
+                return Err(format!("saved cursor at {:?}", cursor.cursor.position()));
+            }
+        }
+        if let Some(stop) = saved.tab_stops.iter().find(|&&stop| stop >= cols) {
+            return Err(format!("tab stop {}", stop));
+        }
+        Ok(())
     }
 }
 
@@ -516,6 +2455,69 @@ mod tests {
         assert_eq!(state.cursor_position(), Position::new(0, 16));
     }
     
+    #[test]
+    fn test_erase_characters() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.write_str("abcdefgh");
+        state.set_cursor_position(Position::new(0, 2));
+        state.set_background_color(Color::Blue);
+        state.erase_characters(3);
+        
+        let row: String = state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect();
+        assert_eq!(row, "ab   fgh  ");
+        assert_eq!(state.screen_buffer().get_cell(Position::new(0, 2)).attrs.bg_color, Color::Blue);
+        assert_eq!(state.screen_buffer().get_cell(Position::new(0, 5)).attrs.bg_color, Color::Default);
+        assert_eq!(state.cursor_position(), Position::new(0, 2));
+        
+        // Erasing past the right edge is clamped
+        state.set_cursor_position(Position::new(0, 8));
+        state.erase_characters(100);
+        assert_eq!(state.screen_buffer().get_cell(Position::new(0, 9)).ch, ' ');
+    }
+    
+    #[test]
+    fn test_insert_delete_columns() {
+        let mut state = TerminalState::new(Size::new(5, 3));
+        state.write_str("abcdefghij");
+        let rows = |state: &TerminalState| -> Vec<String> {
+            state.screen_buffer().lines().iter().map(|l| l.iter().map(|c| c.ch).collect()).collect()
+        };
+        
+        state.set_cursor_position(Position::new(0, 1));
+        state.insert_columns(2);
+        assert_eq!(rows(&state), vec!["a  bc", "f  gh", "     "]);
+        
+        state.delete_columns(3);
+        assert_eq!(rows(&state), vec!["ac   ", "fh   ", "     "]);
+    }
+    
+    #[test]
+    fn test_repeat_char() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        state.write_char('-');
+        state.repeat_char('-', 4);
+        assert_eq!(state.cursor_position(), Position::new(0, 5));
+        
+        // Huge counts are capped at one screen
+        state.repeat_char('x', u16::MAX);
+        assert_eq!(state.screen_buffer().get_cell(Position::new(1, 9)).ch, 'x');
+    }
+    
+    #[test]
+    fn test_word_range_at() {
+        let mut state = TerminalState::new(Size::new(20, 2));
+        state.write_str("cd ~/my-project");
+        
+        let word = state.word_range_at(Position::new(0, 6));
+        assert_eq!(word, Some((Position::new(0, 3), Position::new(0, 14))));
+        
+        // Making '-' and '/' separators narrows the word
+        state.config_mut().word_boundaries.word_separators.push_str("-/");
+        let word = state.word_range_at(Position::new(0, 6));
+        assert_eq!(word, Some((Position::new(0, 5), Position::new(0, 6))));
+        assert_eq!(state.word_range_at(Position::new(0, 2)), None);
+    }
+    
     #[test]
     fn test_scroll() {
         let mut state = TerminalState::new(Size::new(80, 3));
@@ -527,7 +2529,154 @@ mod tests {
         
         // Should have scrolled
         assert_eq!(state.cursor_position().row, 2);
+        assert_eq!(state.scrollback_buffer().len(), 2);
+    }
+    
+    #[test]
+    fn test_scroll_region() {
+        let mut state = TerminalState::new(Size::new(10, 4));
+        state.write_str("top\r\n");
+        state.set_scroll_region(1, 2);
+        assert_eq!(state.cursor_position(), Position::new(0, 0));
+        
+        // Newlines at the bottom margin scroll only the region
+        state.set_cursor_position(Position::new(1, 0));
+        state.write_str("a\r\nb\r\nc");
+        assert_eq!(state.screen_buffer().get_cell(Position::new(0, 0)).ch, 't');
+        assert_eq!(state.screen_buffer().get_cell(Position::new(1, 0)).ch, 'b');
+        assert_eq!(state.screen_buffer().get_cell(Position::new(2, 0)).ch, 'c');
+        assert_eq!(state.cursor_position(), Position::new(2, 1));
+        
+        // Lines leaving a region that doesn't start at the top are not saved
+        assert!(state.scrollback_buffer().is_empty());
+        
+        // Below the region the cursor stops at the last row without scrolling
+        state.set_cursor_position(Position::new(3, 0));
+        state.write_str("\n\n");
+        assert_eq!(state.cursor_position(), Position::new(3, 0));
+        assert_eq!(state.screen_buffer().get_cell(Position::new(1, 0)).ch, 'b');
+        
+        // Invalid regions are ignored
+        state.set_scroll_region(2, 2);
+        assert_eq!(state.scroll_region(), (1, 2));
+    }
+    
+    #[test]
+    fn test_semantic_zone_tags() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.set_semantic_zone(CellTags::PROMPT | CellTags::SELECTED);
+        state.write_str("$ ");
+        state.set_semantic_zone(CellTags::COMMAND);
+        state.write_str("ls");
+        
+        let tags = |state: &TerminalState, col: u16| state.screen_buffer().get_cell(Position::new(0, col)).tags;
+        assert_eq!(tags(&state, 0), CellTags::PROMPT);
+        assert_eq!(tags(&state, 2), CellTags::COMMAND);
+        assert_eq!(tags(&state, 4), CellTags::empty());
+        
+        // Overlays are set on existing cells and cleared independently of zones
+        state.screen_buffer_mut().set_tags(Position::new(0, 1), Position::new(0, 2), CellTags::SELECTED, true);
+        assert_eq!(tags(&state, 2), CellTags::COMMAND | CellTags::SELECTED);
+        state.screen_buffer_mut().clear_tags(CellTags::OVERLAYS);
+        assert_eq!(tags(&state, 2), CellTags::COMMAND);
+    }
+    
+    #[test]
+    fn test_alt_screen_scrollback() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.enable_alternate_screen();
+        state.write_str("a\r\nb\r\nc\r\n");
+        assert!(state.scrollback_buffer().is_empty());
+        
+        state.config_mut().alt_screen_scrollback = true;
+        state.write_str("d\r\n");
         assert_eq!(state.scrollback_buffer().len(), 1);
+        
+        // The main screen always keeps its history
+        state.disable_alternate_screen();
+        state.config_mut().alt_screen_scrollback = false;
+        state.write_str("e\r\nf\r\n");
+        assert_eq!(state.scrollback_buffer().len(), 3);
+    }
+    
+    #[test]
+    fn test_left_right_margins() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        
+        // Ignored unless DECLRMM is enabled
+        state.set_left_right_margins(2, 5);
+        assert_eq!(state.left_right_margins(), (0, 9));
+        
+        state.set_mode_flag(Mode::LeftRightMargin, true);
+        state.set_left_right_margins(2, 5);
+        assert_eq!(state.left_right_margins(), (2, 5));
+        
+        // Text wraps from the right margin back to the left margin
+        state.set_cursor_position(Position::new(0, 2));
+        state.write_str("abcdef");
+        let row = |state: &TerminalState, row: u16| -> String {
+            state.screen_buffer().get_line(row).unwrap().iter().map(|c| c.ch).collect()
+        };
+        assert_eq!(row(&state, 0), "  abcd    ");
+        assert_eq!(row(&state, 1), "  ef      ");
+        
+        // Cursor movement and carriage return stop at the margins
+        state.cursor_forward(10);
+        assert_eq!(state.cursor_position(), Position::new(1, 5));
+        state.cursor_back(10);
+        assert_eq!(state.cursor_position(), Position::new(1, 2));
+        state.cursor_forward(1);
+        state.write_char('\r');
+        assert_eq!(state.cursor_position(), Position::new(1, 2));
+        
+        // Erasing stops at the right margin
+        state.set_cursor_position(Position::new(0, 3));
+        state.erase_characters(10);
+        assert_eq!(row(&state, 0), "  a       ");
+        
+        // Outside the margins the full width is available
+        state.set_cursor_position(Position::new(2, 7));
+        state.cursor_forward(10);
+        assert_eq!(state.cursor_position(), Position::new(2, 9));
+        
+        state.set_mode_flag(Mode::LeftRightMargin, false);
+        assert_eq!(state.left_right_margins(), (0, 9));
+    }
+    
+    #[test]
+    fn test_bell_policy() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.ring_bell();
+        let events = state.take_events();
+        assert!(matches!(events[..], [Event::Bell { action: BellAction::Audible, urgent: false }]));
+        assert!(state.take_events().is_empty());
+        
+        // Urgency only applies while unfocused
+        state.config_mut().bell.action = BellAction::Command(vec!["true".to_string()]);
+        state.set_focused(false);
+        state.ring_bell();
+        assert!(matches!(state.take_events()[..], [Event::Bell { action: BellAction::Command(_), urgent: true }]));
+        
+        state.config_mut().bell.action = BellAction::Silent;
+        state.ring_bell();
+        assert!(state.take_events().is_empty());
+    }
+    
+    #[test]
+    fn test_focus_reporting() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.set_focused(false);
+        assert!(state.take_responses().is_empty());
+        
+        state.set_mode_flag(Mode::FocusReporting, true);
+        state.set_focused(true);
+        state.set_focused(true);
+        state.set_focused(false);
+        assert_eq!(state.take_responses(), vec![b"\x1b[I".to_vec(), b"\x1b[O".to_vec()]);
+        
+        // Focus is window state and survives a reset
+        state.reset();
+        assert!(!state.is_focused());
     }
     
     #[test]
@@ -543,4 +2692,700 @@ mod tests {
                      i, state.cursor_position(), state.scrollback_buffer().len());
         }
     }
+    
+    #[test]
+    fn test_wide_characters() {
+        let mut state = TerminalState::new(Size::new(6, 3));
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        state.write_str("a中b");
+        assert_eq!(state.cursor_position(), Position::new(0, 4));
+        let line = state.screen_buffer().get_line(0).unwrap();
+        assert_eq!((line[1].ch, line[1].width), ('中', 2));
+        assert!(line[2].is_spacer());
+        assert_eq!(line[3].width, 1);
+        
+        // A wide character that doesn't fit wraps early
+        state.write_str("c😀");
+        assert_eq!(row(&state, 0), "a中 bc ");
+        assert_eq!(row(&state, 1), "😀     ");
+        assert_eq!(state.cursor_position(), Position::new(1, 2));
+        
+        // Overwriting or erasing half of a wide character blanks the other half
+        state.cursor_mut().set_position(Position::new(0, 2));
+        state.write_char('x');
+        assert_eq!(row(&state, 0), "a xbc ");
+        state.cursor_mut().set_position(Position::new(1, 0));
+        state.erase_characters(1);
+        assert_eq!(row(&state, 1), "      ");
+        assert!(state.screen_buffer().get_line(1).unwrap().iter().all(|c| c.width == 1));
+        
+        // Without autowrap it takes the last two columns
+        state.set_mode_flag(Mode::AutoWrap, false);
+        state.cursor_mut().set_position(Position::new(2, 5));
+        state.write_char('中');
+        assert_eq!(row(&state, 2), "    中 ");
+        assert_eq!(state.cursor_position(), Position::new(2, 5));
+        
+        // Ambiguous-width characters are narrow unless configured wide
+        state.set_mode_flag(Mode::AutoWrap, true);
+        state.set_cursor_position(Position::new(0, 0));
+        state.write_str("±a");
+        assert_eq!(state.cursor_position(), Position::new(0, 2));
+        let config = TerminalConfig { wide_ambiguous: Some(true), ..TerminalConfig::default() };
+        let mut state = TerminalState::with_config(Size::new(6, 3), config);
+        state.write_str("±a");
+        assert_eq!(row(&state, 0), "± a   ");
+        assert_eq!(state.cursor_position(), Position::new(0, 3));
+    }
+    
+    #[test]
+    fn test_take_damage() {
+        let mut state = TerminalState::new(Size::new(10, 4));
+        // A new screen is damaged in full
+        assert_eq!(state.take_damage().len(), 4);
+        assert!(state.take_damage().is_empty());
+        
+        state.set_cursor_position(Position::new(1, 2));
+        state.write_str("ab");
+        let damage = state.take_damage();
+        assert_eq!(damage, vec![
+            LineDamage { row: 0, left: 0, right: 1 },
+            LineDamage { row: 1, left: 2, right: 5 },
+        ]);
+        
+        // Scrolling damages every row of the region
+        state.set_scroll_region(1, 2);
+        state.take_damage();
+        state.scroll_up();
+        let rows: Vec<u16> = state.take_damage().iter().map(|d| d.row).collect();
+        assert_eq!(rows, vec![1, 2]);
+        
+        // Color changes redraw everything
+        state.set_palette_color(1, Color::Rgb(1, 2, 3));
+        assert_eq!(state.take_damage().len(), 4);
+    }
+    
+    #[test]
+    fn test_resolve_color() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        assert_eq!(state.resolve_color(Color::Red), Rgb::new(205, 0, 0));
+        assert_eq!(state.resolve_color(Color::Indexed(1)), Rgb::new(205, 0, 0));
+        assert_eq!(state.resolve_color(Color::Indexed(21)), Rgb::new(0, 0, 255));
+        assert_eq!(state.resolve_color(Color::Rgb(1, 2, 3)), Rgb::new(1, 2, 3));
+        assert_eq!(state.resolve_color(Color::Default), Rgb::new(229, 229, 229));
+        assert_eq!(state.resolve_background(Color::Default), Rgb::new(0, 0, 0));
+        
+        // OSC 4 and OSC 11 changes are picked up
+        state.set_palette_color(1, Color::Rgb(10, 20, 30));
+        state.set_dynamic_color(DynamicColor::Background, Color::Rgb(40, 50, 60));
+        assert_eq!(state.resolve_color(Color::Red), Rgb::new(10, 20, 30));
+        assert_eq!(state.resolve_background(Color::Default), Rgb::new(40, 50, 60));
+    }
+    
+    #[test]
+    fn test_insert_mode() {
+        let mut state = TerminalState::new(Size::new(8, 2));
+        let row = |state: &TerminalState| -> String {
+            state.screen_buffer().get_line(0).unwrap().iter().map(|c| c.ch).collect()
+        };
+        state.write_str("abcdefg");
+        state.set_cursor_position(Position::new(0, 1));
+        state.set_mode_flag(Mode::Insert, true);
+        state.write_str("XY");
+        assert_eq!(row(&state), "aXYbcdef");
+        assert_eq!(state.cursor_position(), Position::new(0, 3));
+        
+        // Cells pushed past the right margin are dropped
+        state.set_mode_flag(Mode::LeftRightMargin, true);
+        state.set_left_right_margins(0, 4);
+        state.set_cursor_position(Position::new(0, 0));
+        state.write_char('Z');
+        assert_eq!(row(&state), "ZaXYbdef");
+        
+        // Without IRM characters overwrite again
+        state.set_mode_flag(Mode::Insert, false);
+        state.write_char('W');
+        assert_eq!(row(&state), "ZWXYbdef");
+    }
+    
+    #[test]
+    fn test_pending_wrap() {
+        let mut state = TerminalState::new(Size::new(5, 3));
+        let row = |state: &TerminalState, r: u16| -> String {
+            state.screen_buffer().get_line(r).unwrap().iter().map(|c| c.ch).collect()
+        };
+        
+        // Filling the line leaves the cursor on the last column
+        state.write_str("abcde");
+        assert_eq!(state.cursor_position(), Position::new(0, 4));
+        
+        // A full line followed by CR LF doesn't leave a blank line
+        state.write_str("\r\nxy");
+        assert_eq!(row(&state, 1), "xy   ");
+        
+        // The next printable character wraps first
+        state.write_str("zuv");
+        assert_eq!(state.cursor_position(), Position::new(1, 4));
+        state.write_char('!');
+        assert_eq!(row(&state, 2), "!    ");
+        
+        // Cursor movement clears the pending wrap
+        state.write_str("\r\nzzzzz");
+        state.cursor_back(1);
+        state.write_char('?');
+        assert_eq!(row(&state, 2), "zzz?z");
+        assert_eq!(state.cursor_position(), Position::new(2, 4));
+    }
+    
+    #[test]
+    fn test_save_restore_cursor_state() {
+        let mut state = TerminalState::new(Size::new(5, 4));
+        state.set_cursor_position(Position::new(1, 0));
+        state.write_str("abcde");
+        state.set_foreground_color(Color::Red);
+        state.designate_charset(0, Charset::DecSpecialGraphics);
+        state.set_mode_flag(Mode::OriginMode, true);
+        state.set_character_protection(true);
+        state.save_cursor();
+        
+        state.reset_attributes();
+        state.designate_charset(0, Charset::Ascii);
+        state.set_mode_flag(Mode::OriginMode, false);
+        state.set_character_protection(false);
+        state.set_cursor_position(Position::new(3, 0));
+        
+        // Restoring twice gives the same state both times
+        for _ in 0..2 {
+            state.restore_cursor();
+            assert_eq!(state.cursor_position(), Position::new(1, 4));
+            assert_eq!(state.attributes().fg_color, Color::Red);
+            assert_eq!(state.active_charset(), Charset::DecSpecialGraphics);
+            assert_eq!(state.mode_flag(Mode::OriginMode), Some(true));
+            assert!(state.character_protection());
+            state.set_cursor_position(Position::new(0, 0));
+        }
+        
+        // The pending wrap is restored too
+        state.restore_cursor();
+        state.write_char('a');
+        assert_eq!(state.screen_buffer().get_cell(Position::new(2, 0)).ch, '\u{2592}');
+    }
+    
+    #[test]
+    fn test_reset_keeps_configuration() {
+        let config = TerminalConfig {
+            colors: DynamicColors { background: Color::Rgb(1, 2, 3), ..DynamicColors::default() },
+            ..TerminalConfig::default()
+        };
+        let mut state = TerminalState::with_config(Size::new(10, 2), config.clone());
+        state.write_str("a\r\nb\r\nc");
+        state.set_palette_color(1, Color::Rgb(9, 9, 9));
+        state.set_dynamic_color(DynamicColor::Background, Color::Rgb(7, 7, 7));
+        state.set_mode_flag(Mode::Insert, true);
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        
+        // Modes, dynamic colors changed by the application and the history
+        // go; palette overrides stay
+        state.reset();
+        assert_eq!(state.config(), &config);
+        assert_eq!(state.dynamic_colors().background, Color::Rgb(1, 2, 3));
+        assert_eq!(state.palette_color(1), Color::Rgb(9, 9, 9));
+        assert_eq!(state.mode_flag(Mode::Insert), Some(false));
+        assert!(state.scrollback_buffer().is_empty());
+        
+        // The history can be kept
+        state.config_mut().keep_scrollback_on_reset = true;
+        state.write_str("a\r\nb\r\nc");
+        state.reset();
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        assert_eq!(state.screen_buffer().get_cell(Position::new(0, 0)).ch, ' ');
+    }
+    
+    #[test]
+    fn test_text_extraction() {
+        let mut state = TerminalState::new(Size::new(6, 4));
+        state.write_str("ls\r\nabcdefgh\r\n日本");
+        
+        assert_eq!(state.row_text(0), "ls");
+        assert_eq!(state.row_text(1), "abcdef");
+        assert_eq!(state.row_text(3), "日本");
+        // Wrapped rows join without a newline
+        assert_eq!(state.contents(), "ls\nabcdefgh\n日本");
+        assert_eq!(state.text_in(Position::new(0, 1), Position::new(1, 2)), "s\nabc");
+        assert_eq!(state.text_in(Position::new(1, 4), Position::new(2, 1)), "efgh");
+        
+        // Rewriting the last column clears the wrap
+        state.set_cursor_position(Position::new(1, 5));
+        state.write_char('F');
+        assert_eq!(state.contents(), "ls\nabcdeF\ngh\n日本");
+    }
+    
+    #[test]
+    fn test_selection() {
+        let mut state = TerminalState::new(Size::new(8, 4));
+        state.write_str("one two\r\nthree-four\r\n");
+        let selected = |state: &TerminalState, row: u16| -> String {
+            state.screen_buffer().get_line(row).unwrap().iter()
+                .map(|c| if c.tags.contains(CellTags::SELECTED) { '#' } else { '.' })
+                .collect()
+        };
+        
+        let point = |state: &TerminalState, row, col| state.history_point(Position::new(row, col));
+        state.start_selection(SelectionMode::Character, point(&state, 0, 4));
+        state.extend_selection(point(&state, 1, 2));
+        assert_eq!(state.selected_text().as_deref(), Some("two\nthr"));
+        assert_eq!(selected(&state, 0), "....####");
+        assert_eq!(selected(&state, 1), "###.....");
+        
+        // Word selection expands both ends
+        state.start_selection(SelectionMode::Word, point(&state, 0, 5));
+        assert_eq!(state.selected_text().as_deref(), Some("two"));
+        
+        // Line selection follows the autowrapped line
+        state.start_selection(SelectionMode::Line, point(&state, 2, 0));
+        assert_eq!(state.selected_text().as_deref(), Some("three-four"));
+        
+        state.start_selection(SelectionMode::Block, point(&state, 0, 1));
+        state.extend_selection(point(&state, 1, 3));
+        assert_eq!(state.selected_text().as_deref(), Some("ne\nhre"));
+        
+        // The selection stays on its text as it scrolls into scrollback
+        state.start_selection(SelectionMode::Word, point(&state, 0, 0));
+        state.write_str("\r\nfive");
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        assert_eq!(state.selected_text().as_deref(), Some("one"));
+        assert!(state.is_selected(HistoryPoint::new(0, 2)));
+        
+        // Scrollback shows the selection without keeping it once cleared
+        let top_row = |state: &mut TerminalState| -> String {
+            state.scroll_display_up(1);
+            let row = state.visible_rows()[0].iter()
+                .map(|c| if c.tags.contains(CellTags::SELECTED) { '#' } else { '.' })
+                .collect();
+            state.scroll_display_down(1);
+            row
+        };
+        assert_eq!(top_row(&mut state), "###.....");
+        
+        state.clear_selection();
+        assert_eq!(state.selected_text(), None);
+        assert_eq!(selected(&state, 0), "........");
+        assert_eq!(top_row(&mut state), "........");
+    }
+    
+    #[test]
+    fn test_selection_with_formatting() {
+        let mut state = TerminalState::new(Size::new(10, 2));
+        state.set_foreground_color(Color::Blue);
+        state.write_str("a<b");
+        state.reset_attributes();
+        state.write_str(" c");
+        
+        let point = |row, col| HistoryPoint::new(row, col);
+        state.start_selection(SelectionMode::Character, point(0, 1));
+        state.extend_selection(point(0, 4));
+        assert_eq!(state.selection_to_ansi().as_deref(), Some("\x1b[0;34m<b\x1b[0m c"));
+        assert_eq!(
+            state.selection_to_html().as_deref(),
+            Some("<pre style=\"color: #e5e5e5; background-color: #000000;\">\
+                  <span style=\"color: #0000ee\">&lt;b</span> c</pre>")
+        );
+        
+        state.clear_selection();
+        assert_eq!(state.selection_to_ansi(), None);
+    }
+    
+    #[test]
+    fn test_search() {
+        let mut state = TerminalState::new(Size::new(8, 3));
+        state.write_str("Error one\r\nok\r\nerror two\r\nlast");
+        assert_eq!(state.scrollback_buffer().len(), 3);
+        
+        // Both lines wrapped onto a second row; the first scrolled off
+        let found = state.search(&SearchQuery::text("error", false));
+        let starts: Vec<_> = found.iter().map(|m| (m.start.line, m.start.col)).collect();
+        assert_eq!(starts, vec![(0, 0), (3, 0)]);
+        assert_eq!(state.search(&SearchQuery::text("error", true)).len(), 1);
+        
+        let spanning = state.search(&SearchQuery::text("r one", true));
+        assert_eq!(spanning, vec![SearchMatch { start: HistoryPoint::new(0, 4), end: HistoryPoint::new(1, 0) }]);
+        
+        let words = SearchQuery::regex(r"\b[a-z]+\b", true).unwrap();
+        let forward: Vec<_> = state.search_from(&words, HistoryPoint::new(2, 0), SearchDirection::Forward)
+            .map(|m| (m.start.line, m.start.col))
+            .collect();
+        assert_eq!(forward, vec![(3, 0), (3, 6), (5, 0)]);
+        let backward: Vec<_> = state.search_from(&words, HistoryPoint::new(2, 0), SearchDirection::Backward)
+            .map(|m| (m.start.line, m.start.col))
+            .collect();
+        assert_eq!(backward, vec![(0, 6)]);
+        
+        // Highlighted matches are tagged on screen and in the scrollback
+        let tagged = |row: &[Cell]| -> String {
+            row.iter().map(|c| if c.tags.contains(CellTags::SEARCH_MATCH) { '#' } else { '.' }).collect()
+        };
+        let highlights = |state: &mut TerminalState| -> (String, String) {
+            let screen = tagged(state.screen_buffer().get_line(0).unwrap());
+            state.scroll_display_up(3);
+            let scrollback = tagged(&state.visible_rows()[0]);
+            state.scroll_display_to_bottom();
+            (screen, scrollback)
+        };
+        assert_eq!(state.highlight_search(&SearchQuery::text("error", false)), found);
+        assert_eq!(highlights(&mut state), ("#####...".to_string(), "#####...".to_string()));
+        
+        state.clear_search_highlights();
+        assert_eq!(highlights(&mut state), ("........".to_string(), "........".to_string()));
+    }
+    
+    #[test]
+    fn test_pattern_hits() {
+        let mut state = TerminalState::new(Size::new(16, 3));
+        state.write_str("go https://a.io/x 7bb7680\r\nhost 10.0.0.1");
+        assert!(state.pattern_hits().is_empty());
+        
+        *state.matchers_mut() = MatcherRegistry::with_defaults();
+        let hits: Vec<_> = state.pattern_hits().into_iter().map(|hit| (hit.matcher, hit.text)).collect();
+        assert_eq!(hits, vec![
+            ("url".to_string(), "https://a.io/x".to_string()),
+            ("git-hash".to_string(), "7bb7680".to_string()),
+            ("ipv4".to_string(), "10.0.0.1".to_string()),
+        ]);
+        
+        // The URL wraps from the first row onto the second
+        let hit = state.pattern_hit_at(Position::new(1, 0)).unwrap();
+        assert_eq!((hit.start, hit.end), (Position::new(0, 3), Position::new(1, 0)));
+        assert_eq!(state.pattern_hit_at(Position::new(0, 1)), None);
+    }
+    
+    #[test]
+    fn test_display_offset() {
+        let mut state = TerminalState::new(Size::new(4, 2));
+        state.write_str("a\r\nb\r\nc\r\nd");
+        assert_eq!(state.scrollback_buffer().len(), 2);
+        let view = |state: &TerminalState| -> Vec<String> {
+            state.visible_rows().iter().map(|row| buffer::cells_text(row).trim_end().to_string()).collect()
+        };
+        assert_eq!(view(&state), vec!["c", "d"]);
+        
+        state.scroll_display_up(1);
+        assert_eq!(view(&state), vec!["b", "c"]);
+        assert_eq!(state.viewport_point(Position::new(0, 0)), HistoryPoint::new(1, 0));
+        state.scroll_display_up(10);
+        assert_eq!(state.display_offset(), 2);
+        assert_eq!(view(&state), vec!["a", "b"]);
+        state.scroll_display_down(1);
+        assert_eq!(view(&state), vec!["b", "c"]);
+        
+        // Pinned, the view follows its lines as output scrolls
+        state.set_display_pinned(true);
+        state.write_str("\r\ne");
+        assert_eq!(state.display_offset(), 2);
+        assert_eq!(view(&state), vec!["b", "c"]);
+        
+        // Unpinned, new output snaps back to the bottom
+        state.set_display_pinned(false);
+        state.scroll_display_to_top();
+        state.write_str("f");
+        assert_eq!(state.display_offset(), 0);
+        assert_eq!(view(&state), vec!["d", "ef"]);
+    }
+    
+    #[test]
+    fn test_scrollback_limit() {
+        let config = TerminalConfig { scrollback_limit: ScrollbackLimit::Lines(0), ..Default::default() };
+        let mut state = TerminalState::with_config(Size::new(4, 2), config);
+        state.write_str("1\r\n2\r\n3\r\n4");
+        assert!(state.scrollback_buffer().is_empty());
+        
+        state.set_scrollback_limit(ScrollbackLimit::Unlimited);
+        state.write_str("\r\n5\r\n6\r\n7");
+        assert_eq!(state.scrollback_buffer().len(), 3);
+        
+        // Shrinking drops the oldest lines, and the view and selection with them
+        state.scroll_display_to_top();
+        state.start_selection(SelectionMode::Line, HistoryPoint::new(2, 0));
+        state.set_scrollback_limit(ScrollbackLimit::Lines(1));
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        assert_eq!(state.display_offset(), 1);
+        assert_eq!(state.selected_text().as_deref(), Some("5"));
+        
+        state.reset();
+        assert_eq!(state.scrollback_buffer().max_lines(), 1);
+    }
+    
+    #[test]
+    fn test_grid_snapshot() {
+        let mut state = TerminalState::new(Size::new(4, 2));
+        state.set_hyperlink(Some(Hyperlink { id: None, uri: "https://a.io".to_string() }));
+        state.write_str("ab");
+        state.set_hyperlink(None);
+        state.set_foreground_color(Color::Green);
+        state.write_str("c\r\nd");
+        state.screen_buffer_mut().set_line_rendition(1, LineRendition::DoubleWidth);
+        
+        let grid = state.grid_snapshot();
+        assert_eq!(grid.rows.len(), 2);
+        assert_eq!(grid.rows[0][2].ch, 'c');
+        assert_eq!(grid.rows[0][2].attrs.fg_color, Color::Green);
+        assert_eq!(grid.rows[0][0].hyperlink, Some(0));
+        assert_eq!(grid.rows[0][1].hyperlink, Some(0));
+        assert_eq!(grid.rows[0][2].hyperlink, None);
+        assert_eq!(grid.hyperlinks.len(), 1);
+        assert_eq!(grid.hyperlinks[0].uri, "https://a.io");
+        assert_eq!(grid.terminal.line_renditions[1], LineRendition::DoubleWidth);
+        
+        // Scrolled back, the view starts in the scrollback
+        state.write_str("\r\ne");
+        state.scroll_display_up(1);
+        let grid = state.grid_snapshot();
+        assert_eq!(grid.display_offset, 1);
+        assert_eq!(grid.rows[0][1].ch, 'b');
+        assert_eq!(grid.rows[1][0].ch, 'd');
+        assert_eq!(grid.terminal.line_renditions[0], LineRendition::SingleWidth);
+    }
+    
+    #[test]
+    fn test_grid_snapshot_diff() {
+        let mut state = TerminalState::new(Size::new(6, 3));
+        state.write_str("abc\r\ndef");
+        let first = state.grid_snapshot();
+        assert_eq!(first.diff(&first), vec![]);
+        
+        state.set_cursor_position(Position::new(1, 1));
+        state.write_str("X");
+        state.set_cursor_position(Position::new(1, 4));
+        state.write_str("Y");
+        let second = state.grid_snapshot();
+        let deltas = second.diff(&first);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!((deltas[0].row, deltas[0].start), (1, 1));
+        let text: String = deltas[0].cells.iter().map(|cell| cell.ch).collect();
+        assert_eq!(text, "Xf Y");
+        
+        // A new hyperlink on the same text is a change
+        state.set_hyperlink(Some(Hyperlink { id: None, uri: "https://a.io".to_string() }));
+        state.set_cursor_position(Position::new(0, 0));
+        state.write_str("a");
+        let deltas = state.grid_snapshot().diff(&second);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!((deltas[0].row, deltas[0].start, deltas[0].cells.len()), (0, 0, 1));
+        
+        // After a resize every row is sent whole
+        state.resize(Size::new(8, 3));
+        let deltas = state.grid_snapshot().diff(&second);
+        assert_eq!(deltas.len(), 3);
+        assert!(deltas.iter().all(|delta| delta.start == 0 && delta.cells.len() == 8));
+    }
+    
+    #[test]
+    fn test_save_restore() {
+        let mut state = TerminalState::new(Size::new(6, 3));
+        state.set_hyperlink(Some(Hyperlink { id: None, uri: "https://a.io".to_string() }));
+        state.set_foreground_color(Color::Indexed(200));
+        state.write_str("one\r\ntwo\r\nthree\r\n大");
+        state.set_title("saved".to_string());
+        state.set_scroll_region(0, 1);
+        state.save_cursor();
+        state.enable_alternate_screen();
+        state.write_str("alt");
+        
+        let restored = TerminalState::restore(&state.save()).unwrap();
+        assert_eq!(restored.save(), state.save());
+        assert_eq!(restored.scrollback_buffer().get_line(0), state.scrollback_buffer().get_line(0));
+        assert_eq!(restored.contents(), "alt");
+        assert_eq!(restored.title(), "saved");
+        assert_eq!(restored.hyperlink(), state.hyperlink());
+        assert_eq!(restored.snapshot().cursor, state.snapshot().cursor);
+        
+        let mut restored = restored;
+        restored.disable_alternate_screen();
+        state.disable_alternate_screen();
+        assert_eq!(restored.contents(), state.contents());
+        assert_eq!(restored.screen_buffer().get_line(2), state.screen_buffer().get_line(2));
+        
+        let mut bytes = state.save();
+        let version = format!("\"version\":{}", STATE_VERSION);
+        let at = bytes.windows(version.len()).position(|window| window == version.as_bytes()).unwrap();
+        bytes[at + version.len() - 1] += 1;
+        assert!(TerminalState::restore(&bytes).is_err());
+        assert!(TerminalState::restore(b"{}").is_err());
+    }
+    
+    #[test]
+    fn test_restore_rejects_out_of_range_values() {
+        let mut state = TerminalState::new(Size::new(6, 3));
+        state.save_cursor();
+        let saved: serde_json::Value = serde_json::from_slice(&state.save()).unwrap();
+        
+        let tampered = [
+            ("/active_charset", serde_json::json!(9)),
+            ("/color_palette", serde_json::json!([])),
+            ("/size/cols", serde_json::json!(0)),
+            ("/scroll_bottom", serde_json::json!(3)),
+            ("/scroll_top", serde_json::json!(3)),
+            ("/right_margin", serde_json::json!(6)),
+            ("/cursor/position/row", serde_json::json!(3)),
+            ("/saved_cursor/cursor/position/col", serde_json::json!(6)),
+            ("/tab_stops", serde_json::json!([8])),
+        ];
+        for (path, value) in tampered {
+            let mut bad = saved.clone();
+            *bad.pointer_mut(path).unwrap_or_else(|| panic!("no {}", path)) = value;
+            let result = TerminalState::restore(&serde_json::to_vec(&bad).unwrap());
+            assert!(matches!(result, Err(PhosphorError::State(_))), "{} was accepted", path);
+        }
+        assert!(TerminalState::restore(&serde_json::to_vec(&saved).unwrap()).is_ok());
+    }
+    
+    #[test]
+    fn test_history_to_ansi() {
+        let mut state = TerminalState::new(Size::new(6, 3));
+        state.write_str("plain\r\n");
+        state.set_foreground_color(Color::Red);
+        state.write_str("wrapped");
+        state.reset_attributes();
+        state.write_str("\r\n");
+        state.set_background_color(Color::Blue);
+        state.write_str("  ");
+        state.reset_attributes();
+        state.write_str("  ");
+        assert_eq!(state.scrollback_buffer().len(), 1);
+        
+        assert_eq!(state.screen_to_ansi(), "\x1b[0;31mwrapped\x1b[0m\n\x1b[0;44m  \x1b[0m\n");
+        assert_eq!(state.history_to_ansi(0..1), "plain\n");
+        assert_eq!(state.history_to_ansi(0..3), "plain\n\x1b[0;31mwrapped\x1b[0m\n");
+    }
+    
+    #[test]
+    fn test_prompt_navigation() {
+        let mut state = TerminalState::new(Size::new(10, 3));
+        for command in ["ls", "pwd", "true"] {
+            state.set_semantic_zone(CellTags::PROMPT);
+            state.write_str("$ ");
+            state.set_semantic_zone(CellTags::COMMAND);
+            state.write_str(command);
+            state.set_semantic_zone(CellTags::OUTPUT);
+            state.write_str("\r\nout\r\n");
+            state.finish_command(Some(0));
+        }
+        state.set_semantic_zone(CellTags::PROMPT);
+        state.write_str("$ ");
+        
+        let prompts: Vec<_> = state.prompts().iter().map(|prompt| prompt.line).collect();
+        assert_eq!(prompts, vec![0, 2, 4, 6]);
+        assert_eq!(state.scrollback_buffer().len(), 4);
+        
+        let cursor = state.viewport_point(state.cursor_position());
+        let prev = state.prev_prompt(cursor).unwrap();
+        assert_eq!(prev, HistoryPoint::new(6, 0));
+        let prev = state.prev_prompt(prev).unwrap();
+        assert_eq!(prev, HistoryPoint::new(4, 0));
+        assert_eq!(state.next_prompt(prev), Some(HistoryPoint::new(6, 0)));
+        assert_eq!(state.prev_prompt(HistoryPoint::new(0, 0)), None);
+        assert_eq!(state.next_prompt(cursor), None);
+        
+        state.scroll_display_to_line(2);
+        assert_eq!(state.display_offset(), 2);
+        assert_eq!(state.viewport_point(Position::new(0, 0)), HistoryPoint::new(2, 0));
+        state.scroll_display_to_line(6);
+        assert_eq!(state.display_offset(), 0);
+        
+        // A prompt drawn again before any command counts once, and scanning
+        // from any cell finds the same prompts as `prompts`
+        state.write_str("\r\n$ ");
+        let all = state.prompts();
+        assert_eq!(all.len(), 4);
+        for line in 0..9 {
+            for col in 0..10 {
+                let from = HistoryPoint::new(line, col);
+                assert_eq!(state.prev_prompt(from), all.iter().rev().copied().find(|&p| p < from));
+                assert_eq!(state.next_prompt(from), all.iter().copied().find(|&p| p > from));
+            }
+        }
+    }
+    
+    #[test]
+    fn test_command_runs() {
+        let mut state = TerminalState::new(Size::new(10, 4));
+        state.set_scrollback_limit(ScrollbackLimit::Lines(2));
+        let run = |state: &mut TerminalState, command: &str, output: &str, exit_code: Option<i32>| {
+            state.set_semantic_zone(CellTags::PROMPT);
+            state.write_str("$ ");
+            state.start_command();
+            state.write_str(command);
+            state.write_str("\r\n");
+            state.start_command_output();
+            state.write_str(output);
+            state.finish_command(exit_code);
+        };
+        run(&mut state, "ls", "a b\r\n", Some(0));
+        run(&mut state, "make all", "", Some(2));
+        state.set_semantic_zone(CellTags::PROMPT);
+        state.write_str("$ ");
+        state.start_command();
+        state.write_str("sleep");
+        
+        let commands = state.commands();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0].text, "ls");
+        assert_eq!((commands[0].finished, commands[0].exit_code), (true, Some(0)));
+        assert!(commands[0].duration.is_some());
+        assert_eq!(state.zone_text(commands[0].output.unwrap()), "a b");
+        assert_eq!((commands[1].text.as_str(), commands[1].exit_code), ("make all", Some(2)));
+        assert_eq!(commands[1].output, None);
+        assert_eq!((commands[2].text.as_str(), commands[2].finished), ("sleep", false));
+        assert_eq!(commands[2].duration, None);
+        assert_eq!((commands[2].first_line(), commands[2].last_line()), (Some(3), Some(3)));
+        
+        // Once the first command scrolls out of the history its run goes too,
+        // leaving its output with no status
+        state.write_str("\r\n\r\n\r\n");
+        let commands = state.commands();
+        assert_eq!((commands[0].text.as_str(), commands[0].finished), ("", false));
+        assert_eq!((commands[1].text.as_str(), commands[1].exit_code), ("make all", Some(2)));
+        state.start_command();
+        let lines: Vec<_> = state.command_runs.iter().map(|run| run.line).collect();
+        assert_eq!(lines, vec![2, 3, 6]);
+    }
+    
+    #[test]
+    fn test_completed_lines() {
+        let mut state = TerminalState::new(Size::new(5, 2));
+        state.write_str("skip\r\n");
+        assert!(state.take_completed_lines().is_empty());
+        
+        state.collect_completed_lines(true);
+        state.write_str("wrapped line\r\nok  \r\npartial");
+        assert_eq!(state.take_completed_lines(), vec!["wrapped line", "ok"]);
+        assert!(state.take_completed_lines().is_empty());
+        
+        state.collect_completed_lines(false);
+        state.write_str("\r\n");
+        assert!(state.take_completed_lines().is_empty());
+    }
+    
+    #[test]
+    fn test_wrap_mark_on_shift() {
+        let mut state = TerminalState::new(Size::new(4, 3));
+        state.write_str("abcdef");
+        assert!(state.screen_buffer().is_wrapped(0));
+        
+        // Characters shifted through the last column leave the row joined
+        state.set_cursor_position(Position::new(0, 0));
+        state.insert_characters(1);
+        assert!(state.screen_buffer().is_wrapped(0));
+        assert_eq!(state.export_text(false), " abcef\n");
+        state.delete_characters(1);
+        assert_eq!(state.export_text(false), "abc ef\n");
+        
+        // Erasing the last column ends the row
+        state.set_cursor_position(Position::new(0, 2));
+        state.erase_characters(2);
+        assert!(!state.screen_buffer().is_wrapped(0));
+        assert_eq!(state.export_text(false), "ab\nef\n");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,134 @@
+use regex::Regex;
+
+/// Called with each match of a trigger
+pub type TriggerCallback = Box<dyn FnMut(&TriggerMatch) + Send>;
+
+/// A named pattern evaluated on each line of output as it completes
+struct Trigger {
+    name: String,
+    regex: Regex,
+    callback: Option<TriggerCallback>,
+}
+
+/// A match of a trigger on a completed line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerMatch {
+    /// Name of the trigger that matched
+    pub name: String,
+    /// The whole logical line, with autowrapped rows joined
+    pub line: String,
+    /// The match followed by each capture group; `None` for a group that
+    /// didn't take part
+    pub captures: Vec<Option<String>>,
+}
+
+/// Patterns run over output lines when they complete, e.g. to highlight
+/// errors or raise a notification on "BUILD FAILED"
+///
+/// A line completes when the application moves to the next one with a line
+/// feed. Every match is sent as `Event::TriggerMatched`; a trigger can also
+/// have a callback, which could answer a prompt by sending
+/// `Command::Write` through a `Terminal::command_sender`.
+#[derive(Default)]
+pub struct TriggerRegistry {
+    triggers: Vec<Trigger>,
+}
+
+impl TriggerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add a trigger that only emits events, replacing any with the same name
+    pub fn register(&mut self, name: impl Into<String>, pattern: &str) -> Result<(), regex::Error> {
+        self.insert(name.into(), pattern, None)
+    }
+    
+    /// Add a trigger that also calls `callback` on each match
+    pub fn register_with(
+        &mut self,
+        name: impl Into<String>,
+        pattern: &str,
+        callback: impl FnMut(&TriggerMatch) + Send + 'static,
+    ) -> Result<(), regex::Error> {
+        self.insert(name.into(), pattern, Some(Box::new(callback)))
+    }
+    
+    fn insert(&mut self, name: String, pattern: &str, callback: Option<TriggerCallback>) -> Result<(), regex::Error> {
+        let trigger = Trigger { name, regex: Regex::new(pattern)?, callback };
+        match self.triggers.iter_mut().find(|existing| existing.name == trigger.name) {
+            Some(existing) => *existing = trigger,
+            None => self.triggers.push(trigger),
+        }
+        Ok(())
+    }
+    
+    /// Remove a trigger, returning whether it was registered
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let len = self.triggers.len();
+        self.triggers.retain(|trigger| trigger.name != name);
+        self.triggers.len() != len
+    }
+    
+    /// Names of the registered triggers, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.triggers.iter().map(|trigger| trigger.name.as_str())
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+    
+    /// Run every trigger over a completed line, calling callbacks, and
+    /// return the matches in trigger order
+    pub(crate) fn evaluate(&mut self, line: &str) -> Vec<TriggerMatch> {
+        let mut matches = Vec::new();
+        for trigger in &mut self.triggers {
+            for captures in trigger.regex.captures_iter(line) {
+                let found = TriggerMatch {
+                    name: trigger.name.clone(),
+                    line: line.to_string(),
+                    captures: captures.iter().map(|group| group.map(|group| group.as_str().to_string())).collect(),
+                };
+                if let Some(callback) = &mut trigger.callback {
+                    callback(&found);
+                }
+                matches.push(found);
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    
+    #[test]
+    fn test_evaluate() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = TriggerRegistry::new();
+        registry.register("error", r"error(?:\[(E\d+)\])?:").unwrap();
+        let callback_seen = seen.clone();
+        registry
+            .register_with("failed", "BUILD FAILED", move |found| {
+                callback_seen.lock().unwrap().push(found.line.clone());
+            })
+            .unwrap();
+        assert!(registry.register("bad", "(").is_err());
+        
+        let matches = registry.evaluate("error[E0308]: mismatched types; error: BUILD FAILED");
+        let captures: Vec<_> = matches.iter().map(|found| (found.name.as_str(), found.captures.clone())).collect();
+        assert_eq!(captures, vec![
+            ("error", vec![Some("error[E0308]:".to_string()), Some("E0308".to_string())]),
+            ("error", vec![Some("error:".to_string()), None]),
+            ("failed", vec![Some("BUILD FAILED".to_string())]),
+        ]);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert!(registry.evaluate("all good").is_empty());
+        
+        assert!(registry.unregister("error"));
+        assert_eq!(registry.names().collect::<Vec<_>>(), vec!["failed"]);
+    }
+}
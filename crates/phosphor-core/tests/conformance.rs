@@ -0,0 +1,362 @@
+//! Escape sequence conformance, in the style of vttest and esctest
+//!
+//! Each case drives a fresh `TerminalState` with a canned byte stream and
+//! compares the screen and cursor with what a VT520/xterm shows. Results
+//! are reported per capability, so one regression doesn't hide others.
+//!
+//! Cases for sequences not handled yet are listed in `KNOWN_FAILURES`. They
+//! are reported but don't fail the test; one that starts passing does, so
+//! the list stays accurate.
+
+use phosphor_common::traits::TerminalParser;
+use phosphor_common::types::{Position, Size};
+use phosphor_core::ansi::AnsiProcessor;
+use phosphor_core::TerminalState;
+use phosphor_parser::VteParser;
+use std::collections::BTreeMap;
+
+struct Case {
+    capability: &'static str,
+    name: &'static str,
+    input: &'static [u8],
+    /// Screen rows from the top, trailing blanks trimmed; rows not listed
+    /// must be blank
+    screen: &'static [&'static str],
+    /// Zero-based row and column
+    cursor: (u16, u16),
+}
+
+/// Every case runs on a 10x5 screen
+const SIZE: Size = Size { cols: 10, rows: 5 };
+
+const CASES: &[Case] = &[
+    // Cursor movement
+    Case {
+        capability: "cursor",
+        name: "CUP is one-based and clamps to the screen",
+        input: b"\x1b[2;3HA\x1b[99;99HB",
+        screen: &["", "  A", "", "", "         B"],
+        cursor: (4, 9),
+    },
+    Case {
+        capability: "cursor",
+        name: "CUU/CUD/CUF/CUB stop at the edges",
+        input: b"\x1b[3;5H\x1b[9A\x1b[2B\x1b[3C\x1b[20D",
+        screen: &[],
+        cursor: (2, 0),
+    },
+    Case {
+        capability: "cursor",
+        name: "CNL/CPL move to the first column",
+        input: b"\x1b[3;5H\x1b[E\x1b[3;5H\x1b[2F",
+        screen: &[],
+        cursor: (0, 0),
+    },
+    Case {
+        capability: "cursor",
+        name: "CHA and VPA set one coordinate",
+        input: b"\x1b[2;2H\x1b[7G\x1b[4d",
+        screen: &[],
+        cursor: (3, 6),
+    },
+    Case {
+        capability: "cursor",
+        name: "BS stops at the left margin, CR returns to it",
+        input: b"ab\x08\x08\x08c\rd",
+        screen: &["db"],
+        cursor: (0, 1),
+    },
+    // Autowrap
+    Case {
+        capability: "autowrap",
+        name: "writing the last column leaves the cursor pending",
+        input: b"0123456789",
+        screen: &["0123456789"],
+        cursor: (0, 9),
+    },
+    Case {
+        capability: "autowrap",
+        name: "the next character wraps",
+        input: b"0123456789X",
+        screen: &["0123456789", "X"],
+        cursor: (1, 1),
+    },
+    Case {
+        capability: "autowrap",
+        name: "DECAWM off overwrites the last column",
+        input: b"\x1b[?7l0123456789XY",
+        screen: &["012345678Y"],
+        cursor: (0, 9),
+    },
+    Case {
+        capability: "autowrap",
+        name: "cursor movement cancels a pending wrap",
+        input: b"0123456789\x1b[DX",
+        screen: &["01234567X9"],
+        cursor: (0, 9),
+    },
+    // Erasing
+    Case {
+        capability: "erase",
+        name: "EL 0/1/2",
+        input: b"abcdef\r\nabcdef\r\nabcdef\x1b[1;3H\x1b[K\x1b[2;3H\x1b[1K\x1b[3;3H\x1b[2K",
+        screen: &["ab", "   def"],
+        cursor: (2, 2),
+    },
+    Case {
+        capability: "erase",
+        name: "ED 0 erases from the cursor",
+        input: b"aaa\r\nbbb\r\nccc\x1b[2;2H\x1b[J",
+        screen: &["aaa", "b"],
+        cursor: (1, 1),
+    },
+    Case {
+        capability: "erase",
+        name: "ED 1 erases to the cursor",
+        input: b"aaa\r\nbbb\r\nccc\x1b[2;2H\x1b[1J",
+        screen: &["", "  b", "ccc"],
+        cursor: (1, 1),
+    },
+    Case {
+        capability: "erase",
+        name: "ED 2 keeps the cursor",
+        input: b"aaa\r\nbbb\x1b[2J",
+        screen: &[],
+        cursor: (1, 3),
+    },
+    Case {
+        capability: "erase",
+        name: "ECH blanks without shifting",
+        input: b"abcdef\x1b[1;2H\x1b[3X",
+        screen: &["a   ef"],
+        cursor: (0, 1),
+    },
+    // Insert and delete
+    Case {
+        capability: "insert-delete",
+        name: "ICH shifts right and drops off the edge",
+        input: b"abcdefghij\x1b[1;3H\x1b[2@",
+        screen: &["ab  cdefgh"],
+        cursor: (0, 2),
+    },
+    Case {
+        capability: "insert-delete",
+        name: "DCH shifts left",
+        input: b"abcdef\x1b[1;2H\x1b[2P",
+        screen: &["adef"],
+        cursor: (0, 1),
+    },
+    Case {
+        capability: "insert-delete",
+        name: "IL inserts blank lines at the cursor",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4H\x1b[2L",
+        screen: &["1", "", "", "2", "3"],
+        cursor: (1, 0),
+    },
+    Case {
+        capability: "insert-delete",
+        name: "DL pulls lines up",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\x1b[2H\x1b[2M",
+        screen: &["1", "4", "5"],
+        cursor: (1, 0),
+    },
+    Case {
+        capability: "insert-delete",
+        name: "IRM inserts characters",
+        input: b"abc\r\x1b[4hXY\x1b[4lZ",
+        screen: &["XYZbc"],
+        cursor: (0, 3),
+    },
+    // Scrolling
+    Case {
+        capability: "scrolling",
+        name: "LF at the bottom scrolls",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\r\n6",
+        screen: &["2", "3", "4", "5", "6"],
+        cursor: (4, 1),
+    },
+    Case {
+        capability: "scrolling",
+        name: "DECSTBM confines IND to the region",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[4H\nX",
+        screen: &["1", "3", "4", "X", "5"],
+        cursor: (3, 1),
+    },
+    Case {
+        capability: "scrolling",
+        name: "RI at the region top scrolls down",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[2H\x1bMX",
+        screen: &["1", "X", "2", "3", "5"],
+        cursor: (1, 1),
+    },
+    Case {
+        capability: "scrolling",
+        name: "SU and SD",
+        input: b"1\r\n2\r\n3\r\n4\r\n5\x1b[2S\x1b[1T",
+        screen: &["", "3", "4", "5"],
+        cursor: (4, 1),
+    },
+    Case {
+        capability: "scrolling",
+        name: "DECOM makes CUP relative to the region",
+        input: b"\x1b[2;4r\x1b[?6h\x1b[1;1HA\x1b[9;1HB",
+        screen: &["", "A", "", "B"],
+        cursor: (3, 1),
+    },
+    Case {
+        capability: "scrolling",
+        name: "DECLRMM margins confine scrolling",
+        input: b"abcdefghij\r\n0123456789\x1b[?69h\x1b[3;6s\x1b[2;6H\x1b[2;1H\x1b[1S",
+        screen: &["ab2345ghij", "01    6789"],
+        cursor: (1, 0),
+    },
+    // Tabs
+    Case {
+        capability: "tabs",
+        name: "default stops every eight columns",
+        input: b"\tA",
+        screen: &["        A"],
+        cursor: (0, 9),
+    },
+    Case {
+        capability: "tabs",
+        name: "HTS, TBC, CHT and CBT",
+        input: b"\x1b[3g\x1b[1;3H\x1bH\x1b[1;6H\x1bH\r\x1b[2IA\x1b[ZB",
+        screen: &["  B  A"],
+        cursor: (0, 3),
+    },
+    // Save and restore
+    Case {
+        capability: "save-restore",
+        name: "DECSC/DECRC restore the position",
+        input: b"\x1b[2;3H\x1b7\x1b[5;5HX\x1b8Y",
+        screen: &["", "  Y", "", "", "    X"],
+        cursor: (1, 3),
+    },
+    Case {
+        capability: "save-restore",
+        name: "SCOSC/SCORC without margins",
+        input: b"\x1b[3;4H\x1b[s\x1b[H\x1b[uZ",
+        screen: &["", "", "   Z"],
+        cursor: (2, 4),
+    },
+    // Character sets
+    Case {
+        capability: "charsets",
+        name: "DEC special graphics in G0",
+        input: b"\x1b(0lqk\x1b(Bq",
+        screen: &["┌─┐q"],
+        cursor: (0, 4),
+    },
+    Case {
+        capability: "charsets",
+        name: "SO and SI switch to G1",
+        input: b"\x1b)0x\x0ex\x0fx",
+        screen: &["x│x"],
+        cursor: (0, 3),
+    },
+    // Screen alignment and repetition
+    Case {
+        capability: "misc",
+        name: "DECALN fills with E and homes the cursor",
+        input: b"\x1b[2;4r\x1b[3;3H\x1b#8",
+        screen: &["EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE", "EEEEEEEEEE"],
+        cursor: (0, 0),
+    },
+    Case {
+        capability: "misc",
+        name: "REP repeats the last character",
+        input: b"a\x1b[3b",
+        screen: &["aaaa"],
+        cursor: (0, 4),
+    },
+    Case {
+        capability: "misc",
+        name: "RIS clears everything",
+        input: b"abc\x1b[2;4r\x1bcX",
+        screen: &["X"],
+        cursor: (0, 1),
+    },
+    Case {
+        capability: "misc",
+        name: "wide characters take two columns",
+        input: "a界b".as_bytes(),
+        screen: &["a界b"],
+        cursor: (0, 4),
+    },
+];
+
+/// Cases expected to fail, by name, with the reason
+const KNOWN_FAILURES: &[(&str, &str)] = &[
+    ("CUP is one-based and clamps to the screen", "CUP doesn't clamp, so writing there scrolls"),
+    ("BS stops at the left margin, CR returns to it", "BS blanks the cell it leaves"),
+    ("IL inserts blank lines at the cursor", "IL (CSI L) isn't implemented"),
+    ("DL pulls lines up", "DL (CSI M) isn't implemented"),
+    ("DECOM makes CUP relative to the region", "origin mode doesn't offset CUP"),
+    ("HTS, TBC, CHT and CBT", "CHT and CBT (CSI I, CSI Z) aren't implemented"),
+    ("DECALN fills with E and homes the cursor", "DECALN (ESC # 8) isn't implemented"),
+];
+
+/// What went wrong in a case, or `None` if it passed
+fn check(case: &Case) -> Option<String> {
+    let mut state = TerminalState::new(SIZE);
+    let mut parser = VteParser::new();
+    for event in parser.parse(case.input) {
+        AnsiProcessor::process_event(&mut state, event);
+    }
+    
+    let screen: Vec<String> = (0..SIZE.rows).map(|row| state.row_text(row).trim_end().to_string()).collect();
+    let expected: Vec<String> = (0..SIZE.rows as usize)
+        .map(|row| case.screen.get(row).copied().unwrap_or("").to_string())
+        .collect();
+    let cursor = state.cursor_position();
+    let mut problems = Vec::new();
+    if screen != expected {
+        problems.push(format!("screen {:?}, expected {:?}", screen, expected));
+    }
+    if cursor != Position::new(case.cursor.0, case.cursor.1) {
+        problems.push(format!("cursor at {:?}, expected {:?}", (cursor.row, cursor.col), case.cursor));
+    }
+    (!problems.is_empty()).then(|| problems.join("; "))
+}
+
+#[derive(Default)]
+struct Results {
+    passed: usize,
+    known: Vec<String>,
+    failures: Vec<String>,
+}
+
+#[test]
+fn test_conformance() {
+    let mut results: BTreeMap<&str, Results> = BTreeMap::new();
+    for case in CASES {
+        let results = results.entry(case.capability).or_default();
+        let known = KNOWN_FAILURES.iter().find(|(name, _)| *name == case.name).map(|(_, reason)| reason);
+        match (check(case), known) {
+            (None, None) => results.passed += 1,
+            (None, Some(_)) => results.failures.push(format!("    {}: passes now, remove it from KNOWN_FAILURES", case.name)),
+            (Some(_), Some(reason)) => results.known.push(format!("    {}: known, {}", case.name, reason)),
+            (Some(problem), None) => results.failures.push(format!("    {}: {}", case.name, problem)),
+        }
+    }
+    
+    let mut report = String::new();
+    for (capability, results) in &results {
+        let total = results.passed + results.known.len() + results.failures.len();
+        let status = if !results.failures.is_empty() {
+            "FAILED"
+        } else if !results.known.is_empty() {
+            "known gaps"
+        } else {
+            "ok"
+        };
+        report.push_str(&format!("{:<14} {:>2}/{:<2} {}\n", capability, results.passed, total, status));
+        for line in results.known.iter().chain(&results.failures) {
+            report.push_str(line);
+            report.push('\n');
+        }
+    }
+    println!("{}", report);
+    assert!(results.values().all(|results| results.failures.is_empty()), "conformance failures:\n{}", report);
+}
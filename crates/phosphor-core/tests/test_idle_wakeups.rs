@@ -0,0 +1,48 @@
+use phosphor_common::types::Size;
+use phosphor_core::metrics::IDLE_WAKEUP_TARGET;
+use phosphor_core::Terminal;
+use std::time::Duration;
+use tokio::time;
+
+#[tokio::test]
+async fn test_idle_wakeups() -> Result<(), Box<dyn std::error::Error>> {
+    println!("\n=== Testing Idle Wakeup Rate ===");
+    
+    let size = Size::new(80, 24);
+    let terminal = Terminal::new(size)?;
+    let cmd_sender = terminal.command_sender();
+    let metrics = terminal.wakeup_metrics();
+    metrics.set_enabled(true);
+    
+    let terminal_handle = tokio::spawn(async move {
+        terminal.run().await
+    });
+    
+    // Let the shell start and the startup input settle
+    time::sleep(Duration::from_millis(2500)).await;
+    
+    // Measure an idle window
+    metrics.reset();
+    time::sleep(Duration::from_secs(3)).await;
+    let stats = metrics.stats();
+    
+    cmd_sender.send(phosphor_core::events::Command::Close).await?;
+    let _ = time::timeout(Duration::from_secs(1), terminal_handle).await;
+    
+    println!("Idle window: {:?}", stats.elapsed);
+    println!("Wakeups/s: {:.2}", stats.wakeups_per_sec());
+    println!("Timer fires/s: {:.2}", stats.timer_fires_per_sec());
+    println!("Lock acquisitions/s: {:.2}", stats.lock_acquisitions_per_sec());
+    
+    assert!(
+        stats.meets_idle_target(),
+        "Idle terminal woke {:.2} times/s, target is under {}",
+        stats.wakeups_per_sec(),
+        IDLE_WAKEUP_TARGET
+    );
+    assert!(stats.timer_fires > 0, "Alive check never fired, run loop not running!");
+    
+    println!("✅ Idle wakeups within target");
+    
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Parser throughput on typical terminal output
+//!
+//! Run with `cargo bench -p phosphor-parser`. Reports MB/s for each input.
+
+use phosphor_parser::VteParser;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Input size for each case
+const SIZE: usize = 4 << 20;
+
+/// Repeat `chunk` up to `SIZE` bytes
+fn repeated(chunk: &[u8]) -> Vec<u8> {
+    chunk.iter().copied().cycle().take(SIZE).collect()
+}
+
+/// Plain source text, as from `cat` of a file
+fn plain_text() -> Vec<u8> {
+    repeated(b"fn main() {\n    println!(\"hello, world\");\n}\n\n// the quick brown fox jumps over the lazy dog\r\n")
+}
+
+/// Colored listing, as from `ls --color` or a compiler
+fn colored_text() -> Vec<u8> {
+    repeated(b"\x1b[0m\x1b[01;34mtarget\x1b[0m  \x1b[01;32mbuild.sh\x1b[0m  Cargo.toml  \x1b[38;2;255;128;0mwarning\x1b[39m: unused\r\n")
+}
+
+/// Non-ASCII text
+fn unicode_text() -> Vec<u8> {
+    repeated("Größe → 大きさ — размер ✓ 😀\r\n".as_bytes())
+}
+
+fn bench(name: &str, data: &[u8]) {
+    let mut parser = VteParser::new();
+    // Warm up
+    parser.parse_with(data, |event| {
+        black_box(event);
+    });
+    
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while start.elapsed() < Duration::from_secs(2) {
+        parser.parse_with(data, |event| {
+            black_box(event);
+        });
+        runs += 1;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let mb = (data.len() as f64 * runs as f64) / (1024.0 * 1024.0);
+    println!("{:<10} {:>8.1} MB/s", name, mb / elapsed);
+}
+
+fn main() {
+    bench("plain", &plain_text());
+    bench("colored", &colored_text());
+    bench("unicode", &unicode_text());
+}
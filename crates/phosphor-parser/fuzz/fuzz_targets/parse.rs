@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use phosphor_common::traits::{ParsedEvent, TerminalParser};
+use phosphor_parser::VteParser;
+
+// The first byte picks where to split the input, so state carried
+// between reads (partial sequences and UTF-8) is exercised too
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else { return };
+    let (first, second) = data.split_at((split as usize).min(data.len()));
+    
+    let mut parser = VteParser::new();
+    parser.parse(first);
+    parser.parse_with(second, |_| {});
+    
+    // CAN aborts whatever was left in progress
+    parser.parse(b"\x18");
+    let events = parser.parse(b"ok");
+    assert!(
+        matches!(&events[..], [ParsedEvent::Text(text)] if text == "ok"),
+        "parser did not recover: {:?}",
+        events
+    );
+});
@@ -1,8 +1,13 @@
 use phosphor_common::traits::{
     ControlEvent, ParsedEvent, TerminalParser, CsiSequence, OscSequence, EscSequence,
-    EraseMode, SgrParameter
+    DcsSequence, EraseMode, SgrParameter, Mode, ClipboardType, SemanticMark, TitleKind,
+    KeyboardFlagsMode, SequenceKind, MediaCopy, SgrParameters
 };
-use phosphor_common::types::Color;
+use phosphor_common::color::parse_color_spec;
+use phosphor_common::types::{Charset, Color, CursorStyle, DynamicColor, LineRendition};
+use smallvec::SmallVec;
+use std::borrow::Cow;
+use std::ops::Range;
 use tracing::{trace, debug};
 use vte::{Parser, Perform, Params};
 
@@ -10,37 +15,259 @@ use vte::{Parser, Perform, Params};
 pub struct VteParser {
     parser: Parser,
     performer: TerminalPerformer,
+    /// Whether the previous byte was ESC
+    after_esc: bool,
+    /// APC/PM/SOS string being collected; VTE discards these itself
+    control_string: Option<ControlString>,
+    /// Interpret 8-bit C1 controls (0x80-0x9F)
+    c1_controls: bool,
+    /// Continuation bytes still expected for the current UTF-8 character,
+    /// which are not C1 controls
+    utf8_remaining: u8,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlStringKind {
+    Apc,
+    Pm,
+    Sos,
+}
+
+struct ControlString {
+    kind: ControlStringKind,
+    data: Vec<u8>,
+    /// An ESC was seen; the string ends here, and is only kept if the
+    /// next byte completes the ST
+    esc: bool,
+}
+
+/// Longest APC/PM/SOS payload kept; the rest is dropped
+const MAX_CONTROL_STRING: usize = 1 << 20;
+
+/// DEC private modes understood by DECSET, DECRST and DECRQM, by number
+///
+/// 25 (DECTCEM) is set and reset as `CsiSequence::ShowCursor` and
+/// `HideCursor` rather than as a mode.
+pub const DEC_PRIVATE_MODES: &[(u16, Mode)] = &[
+    (1, Mode::ApplicationCursor),
+    (3, Mode::ColumnMode),
+    (4, Mode::ScrollMode),
+    (5, Mode::ScreenMode),
+    (6, Mode::OriginMode),
+    (7, Mode::AutoWrap),
+    (8, Mode::AutoRepeat),
+    (12, Mode::CursorBlink),
+    (25, Mode::CursorVisible),
+    (47, Mode::AlternateScreen),
+    (69, Mode::LeftRightMargin),
+    (1000, Mode::MouseReporting),
+    (1002, Mode::MouseDrag),
+    (1003, Mode::MouseMotion),
+    (1004, Mode::FocusReporting),
+    (1005, Mode::MouseUtf8),
+    (1006, Mode::MouseSgr),
+    (1015, Mode::MouseUrxvt),
+    (1047, Mode::AlternateScreenClear),
+    (1048, Mode::SaveCursor),
+    (1049, Mode::AlternateScreenSaveCursor),
+    (2004, Mode::BracketedPaste),
+];
+
 impl VteParser {
     pub fn new() -> Self {
         Self {
             parser: Parser::new(),
             performer: TerminalPerformer::new(),
+            after_esc: false,
+            control_string: None,
+            c1_controls: false,
+            utf8_remaining: 0,
         }
     }
     
-    /// Get events that have been accumulated and clear the buffer
-    pub fn take_events(&mut self) -> Vec<ParsedEvent> {
-        std::mem::take(&mut self.performer.events)
+    /// Interpret raw 0x80-0x9F bytes as C1 controls (0x9B as CSI, 0x9D as
+    /// OSC, 0x9C as ST and so on) for legacy 8-bit applications
+    ///
+    /// Off by default, since those bytes are normally UTF-8 continuation
+    /// bytes. Continuation bytes of well-formed UTF-8 characters are still
+    /// read as text when enabled.
+    pub fn set_c1_controls(&mut self, enabled: bool) {
+        self.c1_controls = enabled;
+        self.utf8_remaining = 0;
     }
-}
-
-impl TerminalParser for VteParser {
-    fn parse(&mut self, data: &[u8]) -> Vec<ParsedEvent> {
-        // Clear previous events
-        self.performer.events.clear();
+    
+    /// Return to the ground state, dropping any partly parsed sequence
+    ///
+    /// For callers that stop feeding the parser part way through its input,
+    /// e.g. while output bypasses it.
+    pub fn reset(&mut self) {
+        *self = Self {
+            c1_controls: self.c1_controls,
+            ..Self::new()
+        };
+    }
+    
+    /// Whether 8-bit C1 controls are interpreted
+    pub fn c1_controls(&self) -> bool {
+        self.c1_controls
+    }
+    
+    /// Whether `byte` is a C1 control rather than part of a UTF-8 character
+    fn is_c1_control(&mut self, byte: u8) -> bool {
+        if self.utf8_remaining > 0 && (0x80..=0xbf).contains(&byte) {
+            self.utf8_remaining -= 1;
+            return false;
+        }
+        self.utf8_remaining = match byte {
+            0xc2..=0xdf => 1,
+            0xe0..=0xef => 2,
+            0xf0..=0xf4 => 3,
+            _ => 0,
+        };
+        (0x80..=0x9f).contains(&byte)
+    }
+    
+    /// Feed one byte to VTE and the control string scanner
+    fn advance(&mut self, byte: u8) {
+        self.performer.cancelled = matches!(byte, 0x18 | 0x1a);
+        self.scan_control_string(byte);
+        self.parser.advance(&mut self.performer, byte);
+    }
+    
+    /// Track APC/PM/SOS strings alongside VTE
+    ///
+    /// VTE still sees every byte, so its state stays in sync; this only
+    /// mirrors its transitions into and out of the string state.
+    fn scan_control_string(&mut self, byte: u8) {
+        if let Some(string) = &mut self.control_string {
+            if string.esc {
+                let string = self.control_string.take().unwrap();
+                if byte == b'\\' {
+                    self.emit_control_string(string);
+                    self.after_esc = false;
+                    return;
+                }
+                // Any other ESC sequence aborts the string and starts afresh
+                self.after_esc = true;
+            } else {
+                match byte {
+                    0x1b => string.esc = true,
+                    0x18 | 0x1a => self.control_string = None,
+                    0x9c => {
+                        let string = self.control_string.take().unwrap();
+                        self.emit_control_string(string);
+                    }
+                    _ if string.data.len() < MAX_CONTROL_STRING => string.data.push(byte),
+                    _ => {}
+                }
+                return;
+            }
+        }
         
-        // Process each byte through VTE
-        for &byte in data {
-            self.parser.advance(&mut self.performer, byte);
+        if self.after_esc {
+            let kind = match byte {
+                b'_' => Some(ControlStringKind::Apc),
+                b'^' => Some(ControlStringKind::Pm),
+                b'X' => Some(ControlStringKind::Sos),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                self.control_string = Some(ControlString { kind, data: Vec::new(), esc: false });
+            }
+        }
+        self.after_esc = byte == 0x1b;
+    }
+    
+    fn emit_control_string(&mut self, string: ControlString) {
+        trace!("{:?} string: {} bytes", string.kind, string.data.len());
+        self.performer.flush_text();
+        self.performer.events.push(match string.kind {
+            ControlStringKind::Apc => ParsedEvent::ApcString(string.data),
+            ControlStringKind::Pm => ParsedEvent::PmString(string.data),
+            ControlStringKind::Sos => ParsedEvent::SosString(string.data),
+        });
+    }
+    
+    /// Parse input data, handing each event to `callback` as it is parsed
+    ///
+    /// Text borrows from `data` where possible, and the event buffer is
+    /// reused between calls, so steady-state parsing does not allocate
+    /// for text.
+    pub fn parse_with<'a>(&mut self, data: &'a [u8], mut callback: impl FnMut(ParsedEvent<'a>)) {
+        self.parse_with_ranges(data, |event, _| callback(event));
+    }
+    
+    /// Like [`parse_with`](Self::parse_with), also handing over the input
+    /// range each event was parsed from
+    ///
+    /// The ranges follow one another: each starts where the previous event's
+    /// ended, so bytes that produced no event are counted with the next one,
+    /// and ends just past the byte that completed the event. Bytes of a
+    /// sequence still incomplete at the end of `data` are in no range.
+    pub fn parse_with_ranges<'a>(&mut self, data: &'a [u8], mut callback: impl FnMut(ParsedEvent<'a>, Range<usize>)) {
+        let mut offset = 0;
+        while offset < data.len() {
+            let byte = data[offset];
+            if self.performer.ground && is_printable_ascii(byte) {
+                // Runs of plain text skip VTE, which would print them one
+                // character at a time
+                let len = data[offset..]
+                    .iter()
+                    .position(|&b| !is_printable_ascii(b))
+                    .unwrap_or(data.len() - offset);
+                self.performer.print_ascii(&data[offset..offset + len], offset);
+                // Leave the byte scanners as an ASCII byte would
+                self.after_esc = false;
+                self.utf8_remaining = 0;
+                offset += len;
+                self.performer.end_events(offset);
+                continue;
+            }
+            
+            let was_ground = std::mem::replace(&mut self.performer.ground, false);
+            self.performer.offset = offset;
+            if self.c1_controls && self.is_c1_control(byte) {
+                // Feed the 7-bit equivalent, ESC Fe
+                self.advance(0x1b);
+                self.advance(byte - 0x40);
+            } else {
+                self.advance(byte);
+            }
+            // C0 controls other than ESC are executed without leaving the
+            // ground state, and CAN/SUB return to it from anywhere
+            if matches!(byte, 0x18 | 0x1a) || (was_ground && byte < 0x20 && byte != 0x1b) {
+                self.performer.ground = true;
+            }
+            offset += 1;
+            self.performer.end_events(offset);
         }
         
         // Flush any pending text
         self.performer.flush_text();
         
-        // Take accumulated events
-        self.take_events()
+        let mut slices = self.performer.text_slices.drain(..).peekable();
+        let mut start = 0;
+        let events = self.performer.events.drain(..).zip(self.performer.event_ends.drain(..));
+        for (index, (event, end)) in events.enumerate() {
+            match slices.next_if(|(at, _)| *at == index) {
+                Some((_, range)) => callback(ParsedEvent::Text(String::from_utf8_lossy(&data[range])), start..end),
+                None => callback(event, start..end),
+            }
+            start = end;
+        }
+    }
+}
+
+/// Bytes VTE prints as themselves in the ground state
+fn is_printable_ascii(byte: u8) -> bool {
+    (0x20..=0x7e).contains(&byte)
+}
+
+impl TerminalParser for VteParser {
+    fn parse(&mut self, data: &[u8]) -> Vec<ParsedEvent<'static>> {
+        let mut events = Vec::new();
+        self.parse_with(data, |event| events.push(event.into_owned()));
+        events
     }
 }
 
@@ -52,40 +279,129 @@ impl Default for VteParser {
 
 /// VTE performer that translates VTE callbacks into ParsedEvents
 struct TerminalPerformer {
-    events: Vec<ParsedEvent>,
+    events: Vec<ParsedEvent<'static>>,
     current_text: String,
+    /// Offset of the byte being fed to VTE within the current input
+    offset: usize,
+    /// VTE is known to be in its ground state with no partial UTF-8
+    /// character, so printable ASCII can bypass it
+    ground: bool,
+    /// The byte being fed to VTE is CAN or SUB, which abort an OSC or DCS
+    /// string; VTE still dispatches it
+    cancelled: bool,
+    /// Input range `current_text` was printed from, while it is a verbatim
+    /// slice of the input
+    text_range: Option<Range<usize>>,
+    /// Text events in `events` that stand in for a slice of the input,
+    /// by event index
+    text_slices: Vec<(usize, Range<usize>)>,
+    /// Offset just past the last character added to `current_text`
+    text_end: usize,
+    /// Offset just past the byte that completed each event in `events`
+    event_ends: Vec<usize>,
+    /// Last printed graphic character, replayed by REP
+    last_printed: Option<char>,
+    /// DCS request being received, with its payload so far
+    dcs_request: Option<(DcsRequest, Vec<u8>)>,
 }
 
+/// DCS strings whose payload the parser collects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DcsRequest {
+    /// XTGETTCAP (`DCS + q`)
+    Termcap,
+    /// DECRQSS (`DCS $ q`)
+    Status,
+}
+
+/// Longest DCS request payload kept; the rest is dropped
+const MAX_DCS_REQUEST: usize = 4096;
+
 impl TerminalPerformer {
     fn new() -> Self {
         Self {
             events: Vec::new(),
             current_text: String::new(),
+            offset: 0,
+            ground: true,
+            cancelled: false,
+            text_range: None,
+            text_slices: Vec::new(),
+            text_end: 0,
+            event_ends: Vec::new(),
+            last_printed: None,
+            dcs_request: None,
         }
     }
     
+    /// Append a run of printable ASCII that bypassed VTE, starting at
+    /// `start` in the current input
+    fn print_ascii(&mut self, run: &[u8], start: usize) {
+        let end = start + run.len();
+        self.text_range = match self.text_range.take() {
+            None if self.current_text.is_empty() => Some(start..end),
+            Some(range) if range.end == start => Some(range.start..end),
+            _ => None,
+        };
+        self.current_text.extend(run.iter().map(|&b| char::from(b)));
+        self.text_end = end;
+        self.last_printed = run.last().map(|&b| char::from(b));
+    }
+    
+    /// Record `end` as the end offset of events that don't have one yet
+    fn end_events(&mut self, end: usize) {
+        self.event_ends.resize(self.events.len(), end);
+    }
+    
     /// Flush any accumulated text as a Text event
+    ///
+    /// Text that came verbatim from the input is recorded as a range and
+    /// handed out borrowed by [`VteParser::parse_with`].
     fn flush_text(&mut self) {
-        if !self.current_text.is_empty() {
-            let text = std::mem::take(&mut self.current_text);
-            self.events.push(ParsedEvent::Text(text));
+        if self.current_text.is_empty() {
+            return;
         }
+        match self.text_range.take() {
+            Some(range) => {
+                self.text_slices.push((self.events.len(), range));
+                self.events.push(ParsedEvent::Text(Cow::Borrowed("")));
+                self.current_text.clear();
+            }
+            None => {
+                let text = std::mem::take(&mut self.current_text);
+                self.events.push(ParsedEvent::Text(Cow::Owned(text)));
+            }
+        }
+        self.end_events(self.text_end);
     }
     
     /// Parse SGR (Select Graphic Rendition) parameters
-    fn parse_sgr_params(&self, params: &Params) -> Vec<SgrParameter> {
-        let mut sgr_params = Vec::new();
+    ///
+    /// Parameter groups that aren't understood are added to `unknown`.
+    fn parse_sgr_params(&self, params: &Params, unknown: &mut Vec<Vec<u16>>) -> SgrParameters {
+        let mut sgr_params = SgrParameters::new();
         let mut i = 0;
-        let params_vec: Vec<i64> = params.iter().map(|p| p[0] as i64).collect();
+        // Each group is a parameter followed by its colon-separated subparameters
+        let groups: SmallVec<[&[u16]; 16]> = params.iter().collect();
         
-        while i < params_vec.len() {
-            let param = params_vec[i] as u32;
+        while i < groups.len() {
+            let group = groups[i];
+            let param = group[0] as u32;
             match param {
                 0 => sgr_params.push(SgrParameter::Reset),
                 1 => sgr_params.push(SgrParameter::Bold),
                 2 => sgr_params.push(SgrParameter::Dim),
                 3 => sgr_params.push(SgrParameter::Italic),
-                4 => sgr_params.push(SgrParameter::Underline),
+                4 => match group.get(1) {
+                    // Underline style (4:n)
+                    None | Some(1) => sgr_params.push(SgrParameter::Underline),
+                    Some(0) => sgr_params.push(SgrParameter::NoUnderline),
+                    Some(2) => sgr_params.push(SgrParameter::DoubleUnderline),
+                    Some(3) => sgr_params.push(SgrParameter::CurlyUnderline),
+                    Some(4) => sgr_params.push(SgrParameter::DottedUnderline),
+                    Some(5) => sgr_params.push(SgrParameter::DashedUnderline),
+                    Some(_) => unknown.push(group.to_vec()),
+                },
                 5 => sgr_params.push(SgrParameter::Blink),
                 7 => sgr_params.push(SgrParameter::Reverse),
                 8 => sgr_params.push(SgrParameter::Hidden),
@@ -100,28 +416,17 @@ impl TerminalPerformer {
                 28 => sgr_params.push(SgrParameter::NoHidden),
                 29 => sgr_params.push(SgrParameter::NoStrikethrough),
                 
+                53 => sgr_params.push(SgrParameter::Overline),
+                55 => sgr_params.push(SgrParameter::NoOverline),
+                73 => sgr_params.push(SgrParameter::Superscript),
+                74 => sgr_params.push(SgrParameter::Subscript),
+                75 => sgr_params.push(SgrParameter::NoSuperSubscript),
+                
                 // Foreground colors
                 30..=37 => sgr_params.push(SgrParameter::Foreground(Color::from_ansi((param - 30) as u8))),
                 38 => {
-                    // Extended color
-                    if i + 1 < params_vec.len() {
-                        match params_vec[i + 1] {
-                            5 if i + 2 < params_vec.len() => {
-                                // 256 color
-                                let color = Color::Indexed(params_vec[i + 2] as u8);
-                                sgr_params.push(SgrParameter::Foreground(color));
-                                i += 2;
-                            }
-                            2 if i + 4 < params_vec.len() => {
-                                // RGB color
-                                let r = params_vec[i + 2].clamp(0, 255) as u8;
-                                let g = params_vec[i + 3].clamp(0, 255) as u8;
-                                let b = params_vec[i + 4].clamp(0, 255) as u8;
-                                sgr_params.push(SgrParameter::Foreground(Color::Rgb(r, g, b)));
-                                i += 4;
-                            }
-                            _ => {}
-                        }
+                    if let Some(color) = Self::extended_color(&groups, &mut i) {
+                        sgr_params.push(SgrParameter::Foreground(color));
                     }
                 }
                 39 => sgr_params.push(SgrParameter::DefaultForeground),
@@ -129,36 +434,27 @@ impl TerminalPerformer {
                 // Background colors
                 40..=47 => sgr_params.push(SgrParameter::Background(Color::from_ansi((param - 40) as u8))),
                 48 => {
-                    // Extended background color
-                    if i + 1 < params_vec.len() {
-                        match params_vec[i + 1] {
-                            5 if i + 2 < params_vec.len() => {
-                                // 256 color
-                                let color = Color::Indexed(params_vec[i + 2] as u8);
-                                sgr_params.push(SgrParameter::Background(color));
-                                i += 2;
-                            }
-                            2 if i + 4 < params_vec.len() => {
-                                // RGB color
-                                let r = params_vec[i + 2].clamp(0, 255) as u8;
-                                let g = params_vec[i + 3].clamp(0, 255) as u8;
-                                let b = params_vec[i + 4].clamp(0, 255) as u8;
-                                sgr_params.push(SgrParameter::Background(Color::Rgb(r, g, b)));
-                                i += 4;
-                            }
-                            _ => {}
-                        }
+                    if let Some(color) = Self::extended_color(&groups, &mut i) {
+                        sgr_params.push(SgrParameter::Background(color));
                     }
                 }
                 49 => sgr_params.push(SgrParameter::DefaultBackground),
                 
+                // Underline color
+                58 => {
+                    if let Some(color) = Self::extended_color(&groups, &mut i) {
+                        sgr_params.push(SgrParameter::UnderlineColor(color));
+                    }
+                }
+                59 => sgr_params.push(SgrParameter::DefaultUnderlineColor),
+                
                 // Bright foreground colors
                 90..=97 => sgr_params.push(SgrParameter::Foreground(Color::from_ansi((param - 90 + 8) as u8))),
                 
                 // Bright background colors
                 100..=107 => sgr_params.push(SgrParameter::Background(Color::from_ansi((param - 100 + 8) as u8))),
                 
-                _ => debug!("Unhandled SGR parameter: {}", param),
+                _ => unknown.push(group.to_vec()),
             }
             i += 1;
         }
@@ -166,11 +462,161 @@ impl TerminalPerformer {
         sgr_params
     }
     
+    /// Parse the color of an extended color parameter (38, 48 or 58)
+    ///
+    /// Accepts the colon form (`38:5:n`, `38:2::r:g:b`, `38:2:r:g:b`) within
+    /// one group and the legacy semicolon form (`38;5;n`, `38;2;r;g;b`),
+    /// which consumes the following groups by advancing `i`.
+    fn extended_color(groups: &[&[u16]], i: &mut usize) -> Option<Color> {
+        let rgb = |c: &[u16]| Color::Rgb(c[0].min(255) as u8, c[1].min(255) as u8, c[2].min(255) as u8);
+        let group = groups[*i];
+        
+        if group.len() > 1 {
+            return match group[1] {
+                5 => group.get(2).map(|&n| Color::Indexed(n as u8)),
+                // With a color space id the components start one later
+                2 if group.len() >= 6 => Some(rgb(&group[3..6])),
+                2 if group.len() == 5 => Some(rgb(&group[2..5])),
+                _ => None,
+            };
+        }
+        
+        let base = *i;
+        let next = |k: usize| groups.get(base + k).map(|g| g[0]);
+        match next(1)? {
+            5 => {
+                let n = next(2)?;
+                *i += 2;
+                Some(Color::Indexed(n as u8))
+            }
+            2 => {
+                let components = [next(2)?, next(3)?, next(4)?];
+                *i += 4;
+                Some(rgb(&components))
+            }
+            _ => None,
+        }
+    }
+    
+    /// Map an ANSI mode number (SM/RM) to a mode
+    fn ansi_mode(n: u16) -> Option<Mode> {
+        Some(match n {
+            2 => Mode::KeyboardAction,
+            4 => Mode::Insert,
+            12 => Mode::SendReceive,
+            20 => Mode::LineFeed,
+            _ => return None,
+        })
+    }
+    
+    /// Map a DEC private mode number (DECSET/DECRST) to a mode
+    fn dec_private_mode(n: u16) -> Option<Mode> {
+        DEC_PRIVATE_MODES.iter().find(|(number, _)| *number == n).map(|(_, mode)| *mode)
+    }
+    
+    /// Decode the `;`-separated, hex-encoded names of an XTGETTCAP request
+    fn decode_termcap_names(payload: &[u8]) -> Vec<String> {
+        payload
+            .split(|&b| b == b';')
+            .filter_map(|hex| {
+                let hex = std::str::from_utf8(hex).ok()?;
+                if hex.is_empty() || hex.len() % 2 != 0 {
+                    return None;
+                }
+                let bytes = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                    .collect::<Option<Vec<u8>>>()?;
+                String::from_utf8(bytes).ok()
+            })
+            .collect()
+    }
+    
+    /// Report a sequence the parser does not handle
+    fn push_unknown(
+        &mut self,
+        kind: SequenceKind,
+        params: Vec<Vec<u16>>,
+        intermediates: &[u8],
+        final_byte: u8,
+        data: Vec<u8>,
+    ) {
+        debug!("Unhandled {:?} sequence: {:?} {:?} {:?}", kind, params, intermediates, final_byte as char);
+        self.events.push(ParsedEvent::Unknown {
+            kind,
+            params,
+            intermediates: intermediates.to_vec(),
+            final_byte,
+            data,
+        });
+    }
+    
+    fn unknown_csi(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        let params = params.iter().map(|p| p.to_vec()).collect();
+        self.push_unknown(SequenceKind::Csi, params, intermediates, action as u8, Vec::new());
+    }
+    
+    fn unknown_osc(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let final_byte = if bell_terminated { 0x07 } else { b'\\' };
+        self.push_unknown(SequenceKind::Osc, Vec::new(), &[], final_byte, params.join(&b';'));
+    }
+    
+    /// Handle ESC sequences with an intermediate byte
+    fn esc_dispatch_intermediate(&mut self, intermediate: u8, byte: u8) {
+        if intermediate == b'#' {
+            let rendition = match byte {
+                b'3' => LineRendition::DoubleHeightTop,
+                b'4' => LineRendition::DoubleHeightBottom,
+                b'5' => LineRendition::SingleWidth,
+                b'6' => LineRendition::DoubleWidth,
+                _ => {
+                    self.push_unknown(SequenceKind::Esc, Vec::new(), &[intermediate], byte, Vec::new());
+                    return;
+                }
+            };
+            self.events.push(ParsedEvent::Esc(EscSequence::LineRendition(rendition)));
+            return;
+        }
+        
+        let slot = match intermediate {
+            b'(' => 0,
+            b')' => 1,
+            b'*' => 2,
+            b'+' => 3,
+            _ => {
+                self.push_unknown(SequenceKind::Esc, Vec::new(), &[intermediate], byte, Vec::new());
+                return;
+            }
+        };
+        let charset = match byte {
+            b'B' => Charset::Ascii,
+            b'0' => Charset::DecSpecialGraphics,
+            b'A' => Charset::Uk,
+            _ => {
+                // Unsupported character set
+                self.push_unknown(SequenceKind::Esc, Vec::new(), &[intermediate], byte, Vec::new());
+                return;
+            }
+        };
+        self.events.push(ParsedEvent::Esc(EscSequence::DesignateCharset { slot, charset }));
+    }
+    
+    /// Map an OSC 52 selection parameter to a clipboard
+    ///
+    /// Only the first selection is used; an empty parameter means the clipboard.
+    fn clipboard_type(selection: &[u8]) -> ClipboardType {
+        match selection.first() {
+            Some(b'p') | Some(b's') => ClipboardType::Primary,
+            Some(b'q') => ClipboardType::Secondary,
+            _ => ClipboardType::Clipboard,
+        }
+    }
+    
     /// Get a single numeric parameter with default value
     fn get_param(&self, params: &Params, index: usize, default: u16) -> u16 {
         params.iter()
             .nth(index)
-            .map(|p| p[0] as u16)
+            .map(|p| p[0])
             .filter(|&v| v > 0)
             .unwrap_or(default)
     }
@@ -179,12 +625,25 @@ impl TerminalPerformer {
 impl Perform for TerminalPerformer {
     fn print(&mut self, c: char) {
         trace!("VTE print: {:?}", c);
+        self.ground = true;
+        // A character is printed on its last byte. Replacement characters
+        // and characters begun in an earlier input are not in this input.
+        let end = self.offset + 1;
+        let start = end.checked_sub(c.len_utf8()).filter(|_| c != char::REPLACEMENT_CHARACTER);
+        self.text_range = match (start, self.text_range.take()) {
+            (Some(start), None) if self.current_text.is_empty() => Some(start..end),
+            (Some(start), Some(range)) if range.end == start => Some(range.start..end),
+            _ => None,
+        };
         self.current_text.push(c);
+        self.text_end = end;
+        self.last_printed = Some(c);
     }
     
     fn execute(&mut self, byte: u8) {
         trace!("VTE execute: 0x{:02x}", byte);
         self.flush_text();
+        self.last_printed = None;
         
         match byte {
             0x07 => self.events.push(ParsedEvent::Control(ControlEvent::Bell)),
@@ -194,6 +653,8 @@ impl Perform for TerminalPerformer {
             0x0B => self.events.push(ParsedEvent::Control(ControlEvent::VerticalTab)),
             0x0C => self.events.push(ParsedEvent::Control(ControlEvent::FormFeed)),
             0x0D => self.events.push(ParsedEvent::Control(ControlEvent::CarriageReturn)),
+            0x0E => self.events.push(ParsedEvent::Control(ControlEvent::ShiftOut)),
+            0x0F => self.events.push(ParsedEvent::Control(ControlEvent::ShiftIn)),
             _ => debug!("Unhandled execute byte: 0x{:02x}", byte),
         }
     }
@@ -201,21 +662,54 @@ impl Perform for TerminalPerformer {
     fn hook(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
         trace!("VTE hook: params={:?}, intermediates={:?}, ignore={}, action={}", 
                params.iter().collect::<Vec<_>>(), intermediates, ignore, action);
+        self.flush_text();
+        self.last_printed = None;
+        
+        if ignore {
+            return;
+        }
+        match (intermediates, action) {
+            (b"+", 'q') => self.dcs_request = Some((DcsRequest::Termcap, Vec::new())),
+            (b"$", 'q') => self.dcs_request = Some((DcsRequest::Status, Vec::new())),
+            _ => {
+                let params = params.iter().map(|p| p.to_vec()).collect();
+                self.push_unknown(SequenceKind::Dcs, params, intermediates, action as u8, Vec::new());
+            }
+        }
     }
     
     fn put(&mut self, byte: u8) {
         trace!("VTE put: 0x{:02x}", byte);
+        if let Some((_, payload)) = &mut self.dcs_request {
+            if payload.len() < MAX_DCS_REQUEST {
+                payload.push(byte);
+            }
+        }
     }
     
     fn unhook(&mut self) {
         trace!("VTE unhook");
+        match self.dcs_request.take().filter(|_| !self.cancelled) {
+            Some((DcsRequest::Termcap, payload)) => {
+                let names = Self::decode_termcap_names(&payload);
+                if !names.is_empty() {
+                    self.events.push(ParsedEvent::Dcs(DcsSequence::RequestTermcap(names)));
+                }
+            }
+            Some((DcsRequest::Status, payload)) => {
+                if let Ok(setting) = String::from_utf8(payload) {
+                    self.events.push(ParsedEvent::Dcs(DcsSequence::RequestStatus(setting)));
+                }
+            }
+            None => {}
+        }
     }
     
     fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
         trace!("VTE OSC: params={:?}, bell_terminated={}", params.len(), bell_terminated);
         self.flush_text();
         
-        if params.is_empty() {
+        if params.is_empty() || self.cancelled {
             return;
         }
         
@@ -226,13 +720,112 @@ impl Perform for TerminalPerformer {
             
         match osc_num {
             Some(0) | Some(2) => {
-                // Set window title
+                // Set window title; OSC 0 sets the icon name as well
                 if params.len() > 1 {
                     if let Ok(title) = std::str::from_utf8(params[1]) {
+                        if osc_num == Some(0) {
+                            self.events.push(ParsedEvent::Osc(OscSequence::SetIcon(title.to_string())));
+                        }
                         self.events.push(ParsedEvent::Osc(OscSequence::SetTitle(title.to_string())));
                     }
                 }
             }
+            Some(1) => {
+                // Set icon name
+                if params.len() > 1 {
+                    if let Ok(icon) = std::str::from_utf8(params[1]) {
+                        self.events.push(ParsedEvent::Osc(OscSequence::SetIcon(icon.to_string())));
+                    }
+                }
+            }
+            Some(4) => {
+                // Palette colors, as one or more index;spec pairs
+                for pair in params[1..].chunks_exact(2) {
+                    let index = std::str::from_utf8(pair[0])
+                        .ok()
+                        .and_then(|s| s.parse::<u8>().ok());
+                    let color = std::str::from_utf8(pair[1])
+                        .ok()
+                        .and_then(parse_color_spec);
+                    match (index, color) {
+                        (Some(index), Some(color)) => {
+                            self.events.push(ParsedEvent::Osc(OscSequence::SetColor { index, color }));
+                        }
+                        _ => debug!("Invalid OSC 4 color: {:?}", pair),
+                    }
+                }
+            }
+            Some(number @ 10..=12) => {
+                // Dynamic colors; further specs address the following colors
+                for (offset, spec) in params[1..].iter().enumerate() {
+                    let Some(target) = DynamicColor::from_osc(number + offset as u32) else {
+                        break;
+                    };
+                    if *spec == b"?" {
+                        self.events.push(ParsedEvent::Osc(OscSequence::QueryDynamicColor {
+                            target,
+                            bell_terminated,
+                        }));
+                    } else if let Some(color) = std::str::from_utf8(spec).ok().and_then(parse_color_spec) {
+                        self.events.push(ParsedEvent::Osc(OscSequence::SetDynamicColor { target, color }));
+                    } else {
+                        debug!("Invalid OSC {} color: {:?}", number, spec);
+                    }
+                }
+            }
+            Some(9) => {
+                // iTerm2 notification; OSC 9;4 is ConEmu progress, not a message
+                if params.len() > 1 && params[1] != b"4" {
+                    let body = params[1..]
+                        .iter()
+                        .map(|p| String::from_utf8_lossy(p))
+                        .collect::<Vec<_>>()
+                        .join(";");
+                    self.events.push(ParsedEvent::Osc(OscSequence::Notification { title: None, body }));
+                }
+            }
+            Some(777) => {
+                // urxvt notification: 777;notify;title;body
+                if params.len() > 2 && params[1] == b"notify" {
+                    let title = String::from_utf8_lossy(params[2]).into_owned();
+                    let body = params[3..]
+                        .iter()
+                        .map(|p| String::from_utf8_lossy(p))
+                        .collect::<Vec<_>>()
+                        .join(";");
+                    self.events.push(ParsedEvent::Osc(OscSequence::Notification { title: Some(title), body }));
+                }
+            }
+            Some(133) => {
+                // Shell integration; options after the marker are ignored
+                let mark = match params.get(1).copied() {
+                    Some(b"A") => Some(SemanticMark::PromptStart),
+                    Some(b"B") => Some(SemanticMark::CommandStart),
+                    Some(b"C") => Some(SemanticMark::OutputStart),
+                    Some(b"D") => {
+                        let exit_code = params.get(2)
+                            .and_then(|p| std::str::from_utf8(p).ok())
+                            .and_then(|s| s.parse().ok());
+                        Some(SemanticMark::CommandFinished(exit_code))
+                    }
+                    _ => None,
+                };
+                match mark {
+                    Some(mark) => self.events.push(ParsedEvent::Osc(OscSequence::SemanticPrompt(mark))),
+                    None => self.unknown_osc(params, bell_terminated),
+                }
+            }
+            Some(52) => {
+                // Clipboard; the payload is base64 or `?` for a query
+                if params.len() > 2 {
+                    if let Ok(data) = std::str::from_utf8(params[2]) {
+                        self.events.push(ParsedEvent::Osc(OscSequence::Clipboard {
+                            clipboard: Self::clipboard_type(params[1]),
+                            data: data.to_string(),
+                        }));
+                    }
+                }
+            }
             Some(8) => {
                 // Hyperlink
                 if params.len() > 2 {
@@ -261,7 +854,7 @@ impl Perform for TerminalPerformer {
                     }
                 }
             }
-            _ => debug!("Unhandled OSC sequence: {:?}", osc_num),
+            _ => self.unknown_osc(params, bell_terminated),
         }
     }
     
@@ -275,6 +868,7 @@ impl Perform for TerminalPerformer {
         trace!("VTE CSI: params={:?}, intermediates={:?}, ignore={}, action={}", 
                params.iter().collect::<Vec<_>>(), intermediates, ignore, action);
         self.flush_text();
+        self.ground = true;
         
         if ignore {
             return;
@@ -306,10 +900,16 @@ impl Perform for TerminalPerformer {
                 let n = self.get_param(params, 0, 1);
                 self.events.push(ParsedEvent::Csi(CsiSequence::CursorPreviousLine(n)));
             }
-            'G' => {
+            'G' | '`' => {
+                // CHA and HPA both set the absolute column
                 let col = self.get_param(params, 0, 1);
                 self.events.push(ParsedEvent::Csi(CsiSequence::CursorColumn(col)));
             }
+            'd' => {
+                // VPA - absolute row
+                let row = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::CursorRow(row)));
+            }
             'H' | 'f' => {
                 let row = self.get_param(params, 0, 1);
                 let col = self.get_param(params, 1, 1);
@@ -317,7 +917,7 @@ impl Perform for TerminalPerformer {
             }
             
             // Erase
-            'J' => {
+            'J' if intermediates.is_empty() || intermediates == b"?" => {
                 let mode = match params.iter().next().map(|p| p[0]).unwrap_or(0) {
                     0 => EraseMode::Below,
                     1 => EraseMode::Above,
@@ -325,16 +925,78 @@ impl Perform for TerminalPerformer {
                     3 => EraseMode::Saved,
                     _ => EraseMode::Below,
                 };
-                self.events.push(ParsedEvent::Csi(CsiSequence::EraseDisplay(mode)));
+                let seq = if intermediates.is_empty() {
+                    CsiSequence::EraseDisplay(mode)
+                } else {
+                    CsiSequence::SelectiveEraseDisplay(mode)
+                };
+                self.events.push(ParsedEvent::Csi(seq));
             }
-            'K' => {
+            'K' if intermediates.is_empty() || intermediates == b"?" => {
                 let mode = match params.iter().next().map(|p| p[0]).unwrap_or(0) {
                     0 => EraseMode::Below,
                     1 => EraseMode::Above,
                     2 => EraseMode::All,
                     _ => EraseMode::Below,
                 };
-                self.events.push(ParsedEvent::Csi(CsiSequence::EraseLine(mode)));
+                let seq = if intermediates.is_empty() {
+                    CsiSequence::EraseLine(mode)
+                } else {
+                    CsiSequence::SelectiveEraseLine(mode)
+                };
+                self.events.push(ParsedEvent::Csi(seq));
+            }
+            'q' if intermediates == b"\"" => {
+                // DECSCA: 1 protects, 0 and 2 clear protection
+                let protected = params.iter().next().map(|p| p[0]).unwrap_or(0) == 1;
+                self.events.push(ParsedEvent::Csi(CsiSequence::SetCharacterProtection(protected)));
+            }
+            'q' if intermediates == b" " => {
+                // DECSCUSR: 0 is the default, a blinking block
+                let style = match params.iter().next().map(|p| p[0]).unwrap_or(0) {
+                    0 | 1 => CursorStyle::BlinkingBlock,
+                    2 => CursorStyle::Block,
+                    3 => CursorStyle::BlinkingUnderline,
+                    4 => CursorStyle::Underline,
+                    5 => CursorStyle::BlinkingBar,
+                    6 => CursorStyle::Bar,
+                    _ => return self.unknown_csi(params, intermediates, action),
+                };
+                self.events.push(ParsedEvent::Csi(CsiSequence::SetCursorStyle(style)));
+            }
+            
+            // Insert/delete characters
+            '@' if intermediates.is_empty() => {
+                let n = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::InsertCharacters(n)));
+            }
+            'P' => {
+                let n = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::DeleteCharacters(n)));
+            }
+            'X' => {
+                let n = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::EraseCharacters(n)));
+            }
+            
+            // DECIC/DECDC - insert/delete columns
+            '}' if intermediates == b"'" => {
+                let n = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::InsertColumns(n)));
+            }
+            '~' if intermediates == b"'" => {
+                let n = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::DeleteColumns(n)));
+            }
+            
+            // REP - repeat the preceding graphic character
+            'b' => {
+                if let Some(ch) = self.last_printed {
+                    let count = self.get_param(params, 0, 1);
+                    self.events.push(ParsedEvent::Csi(CsiSequence::RepeatCharacter { ch, count }));
+                } else {
+                    debug!("REP without a preceding graphic character");
+                }
             }
             
             // Scrolling
@@ -346,36 +1008,233 @@ impl Perform for TerminalPerformer {
                 let n = self.get_param(params, 0, 1);
                 self.events.push(ParsedEvent::Csi(CsiSequence::ScrollDown(n)));
             }
+            'r' if intermediates.is_empty() => {
+                // DECSTBM - set top and bottom margins
+                let top = self.get_param(params, 0, 1);
+                let bottom = self.get_param(params, 1, 0);
+                self.events.push(ParsedEvent::Csi(CsiSequence::SetScrollRegion { top, bottom }));
+            }
+            
+            // TBC - tab clear
+            'g' if intermediates.is_empty() => {
+                match self.get_param(params, 0, 0) {
+                    0 => self.events.push(ParsedEvent::Csi(CsiSequence::ClearTabStop)),
+                    3 => self.events.push(ParsedEvent::Csi(CsiSequence::ClearAllTabStops)),
+                    _ => self.unknown_csi(params, intermediates, action),
+                }
+            }
+            
+            // XTMODKEYS - only modifyOtherKeys (resource 4) is supported
+            'm' if intermediates == b">" => {
+                match self.get_param(params, 0, 0) {
+                    4 => {
+                        let level = self.get_param(params, 1, 0).min(u8::MAX as u16) as u8;
+                        self.events.push(ParsedEvent::Csi(CsiSequence::SetModifyOtherKeys(level)));
+                    }
+                    _ => self.unknown_csi(params, intermediates, action),
+                }
+            }
+            'n' if intermediates == b">" => {
+                // Disabling modifyOtherKeys is the same as level 0
+                match self.get_param(params, 0, 0) {
+                    4 => self.events.push(ParsedEvent::Csi(CsiSequence::SetModifyOtherKeys(0))),
+                    _ => self.unknown_csi(params, intermediates, action),
+                }
+            }
             
             // SGR - Select Graphic Rendition
             'm' => {
-                let sgr_params = self.parse_sgr_params(params);
-                self.events.push(ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(sgr_params)));
+                let mut unknown = Vec::new();
+                let sgr_params = self.parse_sgr_params(params, &mut unknown);
+                if !sgr_params.is_empty() || unknown.is_empty() {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(sgr_params)));
+                }
+                if !unknown.is_empty() {
+                    self.push_unknown(SequenceKind::Csi, unknown, intermediates, b'm', Vec::new());
+                }
             }
             
             // Cursor visibility
+            'h' | 'l' if intermediates.is_empty() => {
+                let mut unknown = Vec::new();
+                let modes: Vec<Mode> = params
+                    .iter()
+                    .filter_map(|param| {
+                        let mode = Self::ansi_mode(param[0]);
+                        if mode.is_none() {
+                            unknown.push(vec![param[0]]);
+                        }
+                        mode
+                    })
+                    .collect();
+                if !modes.is_empty() {
+                    let seq = if action == 'h' {
+                        CsiSequence::SetMode(modes)
+                    } else {
+                        CsiSequence::ResetMode(modes)
+                    };
+                    self.events.push(ParsedEvent::Csi(seq));
+                }
+                if !unknown.is_empty() {
+                    self.push_unknown(SequenceKind::Csi, unknown, intermediates, action as u8, Vec::new());
+                }
+            }
             'h' if intermediates == b"?" => {
+                let mut modes = Vec::new();
+                let mut unknown = Vec::new();
                 for param in params.iter() {
                     match param[0] {
                         25 => self.events.push(ParsedEvent::Csi(CsiSequence::ShowCursor)),
-                        _ => debug!("Unhandled DECSET mode: {}", param[0]),
+                        n => match Self::dec_private_mode(n) {
+                            Some(mode) => modes.push(mode),
+                            None => unknown.push(vec![n]),
+                        },
                     }
                 }
+                if !modes.is_empty() {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::SetMode(modes)));
+                }
+                if !unknown.is_empty() {
+                    self.push_unknown(SequenceKind::Csi, unknown, intermediates, action as u8, Vec::new());
+                }
             }
             'l' if intermediates == b"?" => {
+                let mut modes = Vec::new();
+                let mut unknown = Vec::new();
                 for param in params.iter() {
                     match param[0] {
                         25 => self.events.push(ParsedEvent::Csi(CsiSequence::HideCursor)),
-                        _ => debug!("Unhandled DECRST mode: {}", param[0]),
+                        n => match Self::dec_private_mode(n) {
+                            Some(mode) => modes.push(mode),
+                            None => unknown.push(vec![n]),
+                        },
                     }
                 }
+                if !modes.is_empty() {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::ResetMode(modes)));
+                }
+                if !unknown.is_empty() {
+                    self.push_unknown(SequenceKind::Csi, unknown, intermediates, action as u8, Vec::new());
+                }
             }
             
-            // Save/Restore cursor
-            's' => self.events.push(ParsedEvent::Csi(CsiSequence::SaveCursor)),
-            'u' => self.events.push(ParsedEvent::Csi(CsiSequence::RestoreCursor)),
+            // DECRQM - request DEC private mode
+            'p' if intermediates == b"?$" => {
+                let number = params.iter().next().map(|p| p[0]).unwrap_or(0);
+                let mode = Self::dec_private_mode(number);
+                self.events.push(ParsedEvent::Csi(CsiSequence::RequestMode { number, mode }));
+            }
             
-            _ => debug!("Unhandled CSI sequence: {}", action),
+            // DECRQM - request ANSI mode
+            'p' if intermediates == b"$" => {
+                let number = params.iter().next().map(|p| p[0]).unwrap_or(0);
+                let mode = Self::ansi_mode(number);
+                self.events.push(ParsedEvent::Csi(CsiSequence::RequestAnsiMode { number, mode }));
+            }
+            
+            // DA1 - primary device attributes
+            'c' if intermediates.is_empty() => {
+                if self.get_param(params, 0, 0) == 0 {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::PrimaryDeviceAttributes));
+                }
+            }
+            
+            // XTVERSION - terminal name and version
+            'q' if intermediates == b">" => {
+                if self.get_param(params, 0, 0) == 0 {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::ReportVersion));
+                }
+            }
+            
+            // MC - media copy
+            'i' if intermediates.is_empty() || intermediates == b"?" => {
+                let private = !intermediates.is_empty();
+                let mc = match (private, params.iter().next().map(|p| p[0]).unwrap_or(0)) {
+                    (false, 0) => Some(MediaCopy::PrintScreen),
+                    (false, 4) => Some(MediaCopy::PrinterController(false)),
+                    (false, 5) => Some(MediaCopy::PrinterController(true)),
+                    (true, 1) => Some(MediaCopy::PrintLine),
+                    (true, 4) => Some(MediaCopy::AutoPrint(false)),
+                    (true, 5) => Some(MediaCopy::AutoPrint(true)),
+                    _ => None,
+                };
+                match mc {
+                    Some(mc) => self.events.push(ParsedEvent::Csi(CsiSequence::MediaCopy(mc))),
+                    None => self.unknown_csi(params, intermediates, action),
+                }
+            }
+            
+            // DSR - device status report
+            'n' if intermediates.is_empty() => {
+                match self.get_param(params, 0, 0) {
+                    5 => self.events.push(ParsedEvent::Csi(CsiSequence::DeviceStatusReport)),
+                    6 => self.events.push(ParsedEvent::Csi(CsiSequence::CursorPositionReport)),
+                    _ => self.unknown_csi(params, intermediates, action),
+                }
+            }
+            
+            // Window manipulation (XTWINOPS)
+            't' if intermediates.is_empty() => {
+                let title_kind = || match self.get_param(params, 1, 0) {
+                    1 => Some(TitleKind::IconName),
+                    2 => Some(TitleKind::WindowTitle),
+                    0 => Some(TitleKind::Both),
+                    _ => None,
+                };
+                let sequence = match self.get_param(params, 0, 0) {
+                    1 => Some(CsiSequence::Deiconify),
+                    2 => Some(CsiSequence::Iconify),
+                    14 => Some(CsiSequence::ReportTextAreaPixels),
+                    16 => Some(CsiSequence::ReportCellPixels),
+                    18 => Some(CsiSequence::ReportTextAreaChars),
+                    22 => title_kind().map(CsiSequence::PushTitle),
+                    23 => title_kind().map(CsiSequence::PopTitle),
+                    _ => None,
+                };
+                match sequence {
+                    Some(sequence) => self.events.push(ParsedEvent::Csi(sequence)),
+                    None => self.unknown_csi(params, intermediates, action),
+                }
+            }
+            
+            // Save/Restore cursor; with parameters this is DECSLRM, which the
+            // processor only honors while DECLRMM is enabled
+            's' if intermediates.is_empty() => {
+                if params.iter().all(|p| p[0] == 0) {
+                    self.events.push(ParsedEvent::Csi(CsiSequence::SaveCursor));
+                } else {
+                    let left = self.get_param(params, 0, 1);
+                    let right = self.get_param(params, 1, 0);
+                    self.events.push(ParsedEvent::Csi(CsiSequence::SetLeftRightMargins { left, right }));
+                }
+            }
+            'u' if intermediates.is_empty() => self.events.push(ParsedEvent::Csi(CsiSequence::RestoreCursor)),
+            
+            // Kitty keyboard protocol
+            'u' if intermediates == b">" => {
+                let flags = self.get_param(params, 0, 0).min(u8::MAX as u16) as u8;
+                self.events.push(ParsedEvent::Csi(CsiSequence::PushKeyboardFlags(flags)));
+            }
+            'u' if intermediates == b"<" => {
+                let count = self.get_param(params, 0, 1);
+                self.events.push(ParsedEvent::Csi(CsiSequence::PopKeyboardFlags(count)));
+            }
+            'u' if intermediates == b"=" => {
+                let flags = self.get_param(params, 0, 0).min(u8::MAX as u16) as u8;
+                let mode = match self.get_param(params, 1, 1) {
+                    1 => KeyboardFlagsMode::Replace,
+                    2 => KeyboardFlagsMode::Set,
+                    3 => KeyboardFlagsMode::Clear,
+                    _ => {
+                        self.unknown_csi(params, intermediates, action);
+                        return;
+                    }
+                };
+                self.events.push(ParsedEvent::Csi(CsiSequence::SetKeyboardFlags { flags, mode }));
+            }
+            'u' if intermediates == b"?" => self.events.push(ParsedEvent::Csi(CsiSequence::QueryKeyboardFlags)),
+            
+            _ => self.unknown_csi(params, intermediates, action),
         }
     }
     
@@ -383,11 +1242,18 @@ impl Perform for TerminalPerformer {
         trace!("VTE ESC: intermediates={:?}, ignore={}, byte=0x{:02x}", 
                intermediates, ignore, byte);
         self.flush_text();
+        self.ground = true;
+        self.last_printed = None;
         
         if ignore {
             return;
         }
         
+        if let Some(&intermediate) = intermediates.first() {
+            self.esc_dispatch_intermediate(intermediate, byte);
+            return;
+        }
+        
         match byte {
             b'D' => self.events.push(ParsedEvent::Esc(EscSequence::Index)),
             b'E' => self.events.push(ParsedEvent::Esc(EscSequence::NextLine)),
@@ -398,7 +1264,9 @@ impl Perform for TerminalPerformer {
             b'8' => self.events.push(ParsedEvent::Esc(EscSequence::RestoreCursor)),
             b'=' => self.events.push(ParsedEvent::Esc(EscSequence::KeypadApplicationMode)),
             b'>' => self.events.push(ParsedEvent::Esc(EscSequence::KeypadNumericMode)),
-            _ => debug!("Unhandled ESC sequence: 0x{:02x}", byte),
+            // ST ending an OSC, DCS or APC/PM/SOS string
+            b'\\' => {}
+            _ => self.push_unknown(SequenceKind::Esc, Vec::new(), &[], byte, Vec::new()),
         }
     }
 }
@@ -445,6 +1313,238 @@ mod tests {
         let events = parser.parse(b"\x1b[10;20H");
         assert_eq!(events.len(), 1);
         assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::CursorPosition { row: 10, col: 20 })));
+        
+        // HPA and VPA
+        let events = parser.parse(b"\x1b[7`\x1b[3d");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::CursorColumn(7))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::CursorRow(3))));
+    }
+    
+    #[test]
+    fn test_insert_delete_erase_characters() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[3@");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::InsertCharacters(3))));
+        
+        // Missing parameter defaults to 1
+        let events = parser.parse(b"\x1b[P");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::DeleteCharacters(1))));
+        
+        let events = parser.parse(b"\x1b[4X");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::EraseCharacters(4))));
+    }
+    
+    #[test]
+    fn test_insert_delete_columns() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[2'}");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::InsertColumns(2))));
+        
+        let events = parser.parse(b"\x1b['~");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::DeleteColumns(1))));
+    }
+    
+    #[test]
+    fn test_repeat_character() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"=\x1b[31m\x1b[4b");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::RepeatCharacter { ch: '=', count: 4 })));
+        
+        // The last character is remembered across parse calls
+        let events = parser.parse(b"\x1b[b");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::RepeatCharacter { ch: '=', count: 1 })));
+        
+        // A control character in between cancels the repeat
+        let events = parser.parse(b"x\r\x1b[3b");
+        assert_eq!(events.len(), 2);
+    }
+    
+    #[test]
+    fn test_scroll_region() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[2;20r");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetScrollRegion { top: 2, bottom: 20 })));
+        
+        // No parameters resets to the full screen
+        let events = parser.parse(b"\x1b[r");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetScrollRegion { top: 1, bottom: 0 })));
+    }
+    
+    #[test]
+    fn test_private_modes() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[?25;69;9999h");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::ShowCursor)));
+        assert!(matches!(&events[1], ParsedEvent::Csi(CsiSequence::SetMode(modes)) if modes == &[Mode::LeftRightMargin]));
+        assert!(matches!(&events[2], ParsedEvent::Unknown { params, .. } if params == &vec![vec![9999]]));
+        
+        let events = parser.parse(b"\x1b[?47;1047;1048;1049l");
+        assert!(matches!(&events[0], ParsedEvent::Csi(CsiSequence::ResetMode(modes)) if modes == &[
+            Mode::AlternateScreen,
+            Mode::AlternateScreenClear,
+            Mode::SaveCursor,
+            Mode::AlternateScreenSaveCursor,
+        ]));
+    }
+    
+    #[test]
+    fn test_save_cursor_and_margins() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[s");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SaveCursor)));
+        
+        let events = parser.parse(b"\x1b[5;40s");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetLeftRightMargins { left: 5, right: 40 })));
+    }
+    
+    #[test]
+    fn test_tab_clear() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[g\x1b[0g\x1b[3g\x1b[2g");
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[3], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::ClearTabStop)));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::ClearTabStop)));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::ClearAllTabStops)));
+    }
+    
+    #[test]
+    fn test_device_status_reports() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[5n\x1b[6n\x1b[?6n");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::DeviceStatusReport)));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::CursorPositionReport)));
+    }
+    
+    #[test]
+    fn test_pixel_size_reports() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[14t\x1b[16t\x1b[8;24;80t");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::ReportTextAreaPixels)));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::ReportCellPixels)));
+    }
+    
+    #[test]
+    fn test_modify_other_keys() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[>4;2m\x1b[>4m\x1b[>4n\x1b[>1;2m");
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[3], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetModifyOtherKeys(2))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::SetModifyOtherKeys(0))));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::SetModifyOtherKeys(0))));
+    }
+    
+    #[test]
+    fn test_kitty_keyboard_negotiation() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[>5u\x1b[<u\x1b[<3u\x1b[=8;2u\x1b[=1u\x1b[?u\x1b[u\x1b[=1;7u");
+        assert_eq!(events.len(), 8);
+        assert!(matches!(events[7], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::PushKeyboardFlags(5))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::PopKeyboardFlags(1))));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::PopKeyboardFlags(3))));
+        assert!(matches!(
+            events[3],
+            ParsedEvent::Csi(CsiSequence::SetKeyboardFlags { flags: 8, mode: KeyboardFlagsMode::Set })
+        ));
+        assert!(matches!(
+            events[4],
+            ParsedEvent::Csi(CsiSequence::SetKeyboardFlags { flags: 1, mode: KeyboardFlagsMode::Replace })
+        ));
+        assert!(matches!(events[5], ParsedEvent::Csi(CsiSequence::QueryKeyboardFlags)));
+        assert!(matches!(events[6], ParsedEvent::Csi(CsiSequence::RestoreCursor)));
+    }
+    
+    #[test]
+    fn test_control_strings() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"a\x1b_Gf=100;AAAA\x1b\\b\x1b^secret\x1b\\\x1bXsos\x1b\\");
+        assert_eq!(events.len(), 5);
+        assert!(matches!(&events[0], ParsedEvent::Text(text) if text == "a"));
+        assert!(matches!(&events[1], ParsedEvent::ApcString(data) if data == b"Gf=100;AAAA"));
+        assert!(matches!(&events[2], ParsedEvent::Text(text) if text == "b"));
+        assert!(matches!(&events[3], ParsedEvent::PmString(data) if data == b"secret"));
+        assert!(matches!(&events[4], ParsedEvent::SosString(data) if data == b"sos"));
+        
+        // Split across reads
+        assert!(parser.parse(b"\x1b_par").is_empty());
+        let events = parser.parse(b"tial\x1b\\");
+        assert!(matches!(&events[..], [ParsedEvent::ApcString(data)] if data == b"partial"));
+        
+        // CAN aborts the string, and text afterwards prints normally
+        let events = parser.parse(b"\x1b_junk\x18ok");
+        assert!(matches!(&events[..], [ParsedEvent::Text(text)] if text == "ok"));
+        
+        // An ESC that is not ST drops the string and starts a new sequence
+        let events = parser.parse(b"\x1b_junk\x1b[1mx");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(_))));
+    }
+    
+    #[test]
+    fn test_unknown_sequences() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[?1;2:3$z\x1b]999;a;b\x07\x1bZ\x1bP1$rx\x1b\\");
+        assert_eq!(events.len(), 4);
+        match &events[0] {
+            ParsedEvent::Unknown { kind: SequenceKind::Csi, params, intermediates, final_byte, .. } => {
+                assert_eq!(params, &[vec![1], vec![2, 3]]);
+                assert_eq!(intermediates, b"?$");
+                assert_eq!(*final_byte, b'z');
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match &events[1] {
+            ParsedEvent::Unknown { kind: SequenceKind::Osc, data, final_byte, .. } => {
+                assert_eq!(data, b"999;a;b");
+                assert_eq!(*final_byte, 0x07);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(events[2], ParsedEvent::Unknown { kind: SequenceKind::Esc, final_byte: b'Z', .. }));
+        assert!(matches!(events[3], ParsedEvent::Unknown { kind: SequenceKind::Dcs, final_byte: b'r', .. }));
+    }
+    
+    #[test]
+    fn test_window_operations() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[2t\x1b[1t\x1b[18t\x1b[22t\x1b[22;2t\x1b[23;1t\x1b[23;9t");
+        assert_eq!(events.len(), 7);
+        assert!(matches!(events[6], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::Iconify)));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::Deiconify)));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::ReportTextAreaChars)));
+        assert!(matches!(events[3], ParsedEvent::Csi(CsiSequence::PushTitle(TitleKind::Both))));
+        assert!(matches!(events[4], ParsedEvent::Csi(CsiSequence::PushTitle(TitleKind::WindowTitle))));
+        assert!(matches!(events[5], ParsedEvent::Csi(CsiSequence::PopTitle(TitleKind::IconName))));
     }
     
     #[test]
@@ -486,12 +1586,121 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_sgr_subparameters() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[38:2::255:0:0;48:5:17;4:3;58:2:1:2:3m");
+        match &events[0] {
+            ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(params)) => {
+                assert_eq!(params.len(), 4);
+                assert!(matches!(params[0], SgrParameter::Foreground(Color::Rgb(255, 0, 0))));
+                assert!(matches!(params[1], SgrParameter::Background(Color::Indexed(17))));
+                assert!(matches!(params[2], SgrParameter::CurlyUnderline));
+                assert!(matches!(params[3], SgrParameter::UnderlineColor(Color::Rgb(1, 2, 3))));
+            }
+            _ => panic!("Expected SGR event"),
+        }
+        
+        // Subparameters don't leak into the following parameters
+        let events = parser.parse(b"\x1b[4:0;1;58;5;9m");
+        match &events[0] {
+            ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(params)) => {
+                assert_eq!(params.len(), 3);
+                assert!(matches!(params[0], SgrParameter::NoUnderline));
+                assert!(matches!(params[1], SgrParameter::Bold));
+                assert!(matches!(params[2], SgrParameter::UnderlineColor(Color::Indexed(9))));
+            }
+            _ => panic!("Expected SGR event"),
+        }
+    }
+    
+    #[test]
+    fn test_unknown_modes_and_sgr() {
+        let mut parser = VteParser::new();
+        
+        // Known parameters still apply; the unknown ones are reported
+        let events = parser.parse(b"\x1b[1;6;4:7m");
+        assert!(matches!(&events[0], ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(params)) if params.len() == 1));
+        match &events[1] {
+            ParsedEvent::Unknown { kind: SequenceKind::Csi, params, final_byte: b'm', .. } => {
+                assert_eq!(params, &vec![vec![6], vec![4, 7]]);
+            }
+            other => panic!("Expected unknown SGR, got {:?}", other),
+        }
+        
+        let events = parser.parse(b"\x1b[6m");
+        assert!(matches!(&events[..], [ParsedEvent::Unknown { kind: SequenceKind::Csi, final_byte: b'm', .. }]));
+        
+        let events = parser.parse(b"\x1b[4;3h\x1b[?2004;9999h\x1b[?9999l");
+        assert!(matches!(&events[0], ParsedEvent::Csi(CsiSequence::SetMode(modes)) if modes == &[Mode::Insert]));
+        assert!(matches!(
+            &events[1],
+            ParsedEvent::Unknown { kind: SequenceKind::Csi, params, intermediates, final_byte: b'h', .. }
+                if params == &vec![vec![3]] && intermediates.is_empty()
+        ));
+        assert!(matches!(&events[2], ParsedEvent::Csi(CsiSequence::SetMode(modes)) if modes == &[Mode::BracketedPaste]));
+        assert!(matches!(
+            &events[3],
+            ParsedEvent::Unknown { kind: SequenceKind::Csi, params, intermediates, final_byte: b'h', .. }
+                if params == &vec![vec![9999]] && intermediates == b"?"
+        ));
+        assert!(matches!(&events[4], ParsedEvent::Unknown { final_byte: b'l', .. }));
+        assert_eq!(events.len(), 5);
+    }
+    
+    #[test]
+    fn test_sgr_overline_and_scripts() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[53;73;74;75;55m");
+        match &events[0] {
+            ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(params)) => {
+                assert!(matches!(
+                    params[..],
+                    [
+                        SgrParameter::Overline,
+                        SgrParameter::Superscript,
+                        SgrParameter::Subscript,
+                        SgrParameter::NoSuperSubscript,
+                        SgrParameter::NoOverline,
+                    ]
+                ));
+            }
+            _ => panic!("Expected SGR event"),
+        }
+    }
+    
+    #[test]
+    fn test_charset_designation() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b(0\x1b)B\x0e\x0f\x1b#8");
+        assert_eq!(events.len(), 5);
+        assert!(matches!(events[4], ParsedEvent::Unknown { kind: SequenceKind::Esc, .. }));
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Esc(EscSequence::DesignateCharset { slot: 0, charset: Charset::DecSpecialGraphics })
+        ));
+        assert!(matches!(
+            events[1],
+            ParsedEvent::Esc(EscSequence::DesignateCharset { slot: 1, charset: Charset::Ascii })
+        ));
+        assert!(matches!(events[2], ParsedEvent::Control(ControlEvent::ShiftOut)));
+        assert!(matches!(events[3], ParsedEvent::Control(ControlEvent::ShiftIn)));
+        
+        // Intermediates no longer leak into plain ESC handling (ESC ( D is not IND)
+        let events = parser.parse(b"\x1b(D");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParsedEvent::Unknown { kind: SequenceKind::Esc, final_byte: b'D', .. }));
+    }
+    
     #[test]
     fn test_osc_sequences() {
         let mut parser = VteParser::new();
         
         // Set title
-        let events = parser.parse(b"\x1b]0;My Terminal\x07");
+        let events = parser.parse(b"\x1b]2;My Terminal\x07");
         assert_eq!(events.len(), 1);
         match &events[0] {
             ParsedEvent::Osc(OscSequence::SetTitle(title)) => {
@@ -500,6 +1709,14 @@ mod tests {
             _ => panic!("Expected OSC SetTitle event"),
         }
         
+        // OSC 0 sets both the icon name and the title
+        let events = parser.parse(b"\x1b]0;My Terminal\x07");
+        assert!(matches!(
+            &events[..],
+            [ParsedEvent::Osc(OscSequence::SetIcon(icon)), ParsedEvent::Osc(OscSequence::SetTitle(title))]
+                if icon == "My Terminal" && title == "My Terminal"
+        ));
+        
         // Hyperlink
         let events = parser.parse(b"\x1b]8;id=test;https://example.com\x07");
         assert_eq!(events.len(), 1);
@@ -511,4 +1728,357 @@ mod tests {
             _ => panic!("Expected OSC SetHyperlink event"),
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_osc_dynamic_colors() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b]11;#102030\x07");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Osc(OscSequence::SetDynamicColor {
+                target: DynamicColor::Background,
+                color: Color::Rgb(0x10, 0x20, 0x30),
+            })
+        ));
+        
+        // Extra parameters move on to the next color
+        let events = parser.parse(b"\x1b]10;?;red\x1b\\");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Osc(OscSequence::QueryDynamicColor {
+                target: DynamicColor::Foreground,
+                bell_terminated: false,
+            })
+        ));
+        assert!(matches!(
+            events[1],
+            ParsedEvent::Osc(OscSequence::SetDynamicColor {
+                target: DynamicColor::Background,
+                color: Color::Rgb(255, 0, 0),
+            })
+        ));
+    }
+    
+    #[test]
+    fn test_osc_clipboard() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ParsedEvent::Osc(OscSequence::Clipboard { clipboard, data }) => {
+                assert_eq!(*clipboard, ClipboardType::Clipboard);
+                assert_eq!(data, "aGVsbG8=");
+            }
+            _ => panic!("Expected OSC Clipboard event"),
+        }
+        
+        let events = parser.parse(b"\x1b]52;p;?\x1b\\");
+        assert!(matches!(
+            &events[0],
+            ParsedEvent::Osc(OscSequence::Clipboard { clipboard: ClipboardType::Primary, data }) if data == "?"
+        ));
+    }
+    
+    #[test]
+    fn test_osc_notifications() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b]9;Build finished; 0 errors\x07");
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ParsedEvent::Osc(OscSequence::Notification { title, body }) => {
+                assert_eq!(*title, None);
+                assert_eq!(body, "Build finished; 0 errors");
+            }
+            _ => panic!("Expected OSC Notification event"),
+        }
+        
+        let events = parser.parse(b"\x1b]777;notify;make;done\x1b\\");
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ParsedEvent::Osc(OscSequence::Notification { title, body }) => {
+                assert_eq!(title.as_deref(), Some("make"));
+                assert_eq!(body, "done");
+            }
+            _ => panic!("Expected OSC Notification event"),
+        }
+        
+        // ConEmu progress reports are not notifications
+        assert!(parser.parse(b"\x1b]9;4;1;50\x07").is_empty());
+    }
+    
+    #[test]
+    fn test_osc_semantic_prompt() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07");
+        assert!(matches!(events[0], ParsedEvent::Osc(OscSequence::SemanticPrompt(SemanticMark::PromptStart))));
+        assert!(matches!(events[2], ParsedEvent::Osc(OscSequence::SemanticPrompt(SemanticMark::CommandStart))));
+        assert!(matches!(
+            events.last(),
+            Some(ParsedEvent::Osc(OscSequence::SemanticPrompt(SemanticMark::OutputStart)))
+        ));
+        
+        let events = parser.parse(b"\x1b]133;D;2\x07\x1b]133;D\x07");
+        assert!(matches!(events[0], ParsedEvent::Osc(OscSequence::SemanticPrompt(SemanticMark::CommandFinished(Some(2))))));
+        assert!(matches!(events[1], ParsedEvent::Osc(OscSequence::SemanticPrompt(SemanticMark::CommandFinished(None)))));
+    }
+    
+    #[test]
+    fn test_osc_palette() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b]4;1;rgb:ff/80/00\x07");
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Osc(OscSequence::SetColor { index: 1, color: Color::Rgb(255, 128, 0) })
+        ));
+        
+        // Several pairs in one sequence; invalid pairs are skipped
+        let events = parser.parse(b"\x1b]4;2;#00ff00;300;red;17;bogus;3;#0000ff\x1b\\");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Osc(OscSequence::SetColor { index: 2, color: Color::Rgb(0, 255, 0) })
+        ));
+        assert!(matches!(
+            events[1],
+            ParsedEvent::Osc(OscSequence::SetColor { index: 3, color: Color::Rgb(0, 0, 255) })
+        ));
+    }
+    
+    #[test]
+    fn test_device_attributes_and_termcap_request() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[c\x1b[0c\x1b[>c");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[2], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::PrimaryDeviceAttributes)));
+        
+        let events = parser.parse(b"\x1b[>q\x1b[>0q\x1b[>1q");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::ReportVersion)));
+        
+        // "TN" and "Co", hex encoded; the odd-length entry is dropped
+        let events = parser.parse(b"\x1bP+q544e;436f;abc\x1b\\");
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ParsedEvent::Dcs(DcsSequence::RequestTermcap(names)) => assert_eq!(names, &["TN", "Co"]),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_parse_with_borrows_text() {
+        let mut parser = VteParser::new();
+        let data = "héllo\x1b[1mworld\r\n".as_bytes();
+        
+        let mut events = Vec::new();
+        parser.parse_with(data, |event| events.push(event));
+        assert_eq!(events.len(), 5);
+        assert!(matches!(&events[0], ParsedEvent::Text(Cow::Borrowed("héllo"))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(_))));
+        assert!(matches!(&events[2], ParsedEvent::Text(Cow::Borrowed("world"))));
+        assert!(matches!(events[3], ParsedEvent::Control(ControlEvent::CarriageReturn)));
+        assert!(matches!(events[4], ParsedEvent::Control(ControlEvent::NewLine)));
+    }
+    
+    #[test]
+    fn test_parse_with_ranges() {
+        let mut parser = VteParser::new();
+        let data = "héllo\x1b[1mwörld\x1b]0;t\x07\r\x1b[".as_bytes();
+        
+        let mut ranges = Vec::new();
+        parser.parse_with_ranges(data, |event, range| ranges.push((event.into_owned(), range)));
+        let ranges: Vec<_> = ranges.into_iter().map(|(_, range)| &data[range]).collect();
+        assert_eq!(ranges, [
+            "héllo".as_bytes(),
+            b"\x1b[1m",
+            "wörld".as_bytes(),
+            b"\x1b]0;t\x07",
+            b"",
+            b"\r",
+        ]);
+    }
+    
+    #[test]
+    fn test_parse_with_owned_fallback() {
+        let mut parser = VteParser::new();
+        
+        // A character split across reads is not part of the second input
+        let mut events = Vec::new();
+        parser.parse_with(b"a\xc3", |event| events.push(event.into_owned()));
+        parser.parse_with(b"\xa9b", |event| events.push(event.into_owned()));
+        assert!(matches!(&events[..], [ParsedEvent::Text(a), ParsedEvent::Text(b)] if a == "a" && b == "éb"));
+        
+        let mut owned = false;
+        parser.parse_with(b"\xc3", |_| {});
+        parser.parse_with(b"\xa9b", |event| owned = matches!(event, ParsedEvent::Text(Cow::Owned(_))));
+        assert!(owned);
+        
+        // Text around invalid UTF-8 is not a contiguous slice of the input
+        let mut owned = false;
+        parser.parse_with(b"x\xffy", |event| owned = matches!(event, ParsedEvent::Text(Cow::Owned(_))));
+        assert!(owned);
+    }
+    
+    #[test]
+    fn test_cancelled_strings_are_dropped() {
+        let mut parser = VteParser::new();
+        
+        // CAN and SUB abort an OSC or DCS instead of terminating it
+        assert!(parser.parse(b"\x1b]0;title\x18").is_empty());
+        assert!(parser.parse(b"\x1b]52;c;aGk=\x1a").is_empty());
+        assert!(parser.parse(b"\x1bP+q544e\x18").is_empty());
+        
+        let events = parser.parse(b"\x1b]2;title\x07");
+        assert!(matches!(&events[..], [ParsedEvent::Osc(OscSequence::SetTitle(title))] if title == "title"));
+    }
+    
+    #[test]
+    fn test_c1_controls() {
+        let mut parser = VteParser::new();
+        assert!(!parser.c1_controls());
+        let events = parser.parse(b"\x9b1mA");
+        assert!(!events.iter().any(|e| matches!(e, ParsedEvent::Csi(_))));
+        
+        parser.set_c1_controls(true);
+        let events = parser.parse(b"\x9b1mA\x9d2;t\xe2\x82\xac\x9cB\x9fapc\x9c\x85");
+        assert_eq!(events.len(), 6);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetGraphicsRendition(_))));
+        assert!(matches!(&events[1], ParsedEvent::Text(text) if text == "A"));
+        assert!(matches!(&events[2], ParsedEvent::Osc(OscSequence::SetTitle(title)) if title == "t€"));
+        assert!(matches!(&events[3], ParsedEvent::Text(text) if text == "B"));
+        assert!(matches!(&events[4], ParsedEvent::ApcString(data) if data == b"apc"));
+        assert!(matches!(events[5], ParsedEvent::Esc(EscSequence::NextLine)));
+        
+        // Continuation bytes in the C1 range are still UTF-8
+        let events = parser.parse("x€\u{2014}".as_bytes());
+        assert!(matches!(&events[..], [ParsedEvent::Text(text)] if text == "x€\u{2014}"));
+    }
+    
+    #[test]
+    fn test_mode_request() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b[?2004$p\x1b[?25$p\x1b[?2026$p");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            ParsedEvent::Csi(CsiSequence::RequestMode { number: 2004, mode: Some(Mode::BracketedPaste) })
+        ));
+        assert!(matches!(
+            events[1],
+            ParsedEvent::Csi(CsiSequence::RequestMode { number: 25, mode: Some(Mode::CursorVisible) })
+        ));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::RequestMode { number: 2026, mode: None })));
+        
+        // Without the `?` the number is an ANSI mode
+        let events = parser.parse(b"\x1b[4$p\x1b[8$p");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::RequestAnsiMode { number: 4, mode: Some(Mode::Insert) })));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::RequestAnsiMode { number: 8, mode: None })));
+    }
+    
+    #[test]
+    fn test_status_string_request() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1bP$qm\x1b\\\x1bP$q q\x1b\\");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], ParsedEvent::Dcs(DcsSequence::RequestStatus(setting)) if setting == "m"));
+        assert!(matches!(&events[1], ParsedEvent::Dcs(DcsSequence::RequestStatus(setting)) if setting == " q"));
+    }
+    
+    #[test]
+    fn test_line_rendition() {
+        let mut parser = VteParser::new();
+        
+        let events = parser.parse(b"\x1b#3\x1b#4\x1b#5\x1b#6");
+        let renditions: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ParsedEvent::Esc(EscSequence::LineRendition(r)) => Some(*r),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            renditions,
+            [
+                LineRendition::DoubleHeightTop,
+                LineRendition::DoubleHeightBottom,
+                LineRendition::SingleWidth,
+                LineRendition::DoubleWidth,
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_selective_erase() {
+        let mut parser = VteParser::new();
+        let events = parser.parse(b"\x1b[1\"q\x1b[2\"q\x1b[?1J\x1b[?K\x1b[>J");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetCharacterProtection(true))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::SetCharacterProtection(false))));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::SelectiveEraseDisplay(EraseMode::Above))));
+        assert!(matches!(events[3], ParsedEvent::Csi(CsiSequence::SelectiveEraseLine(EraseMode::Below))));
+        assert!(matches!(events[4], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+    }
+    
+    #[test]
+    fn test_ansi_modes() {
+        let mut parser = VteParser::new();
+        let events = parser.parse(b"\x1b[4;20h\x1b[20l\x1b[99h");
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], ParsedEvent::Csi(CsiSequence::SetMode(modes)) if modes == &[Mode::Insert, Mode::LineFeed]));
+        assert!(matches!(&events[1], ParsedEvent::Csi(CsiSequence::ResetMode(modes)) if modes == &[Mode::LineFeed]));
+        assert!(matches!(&events[2], ParsedEvent::Unknown { kind: SequenceKind::Csi, final_byte: b'h', .. }));
+    }
+    
+    #[test]
+    fn test_cursor_style() {
+        let mut parser = VteParser::new();
+        let events = parser.parse(b"\x1b[ q\x1b[4 q\x1b[6 q\x1b[7 q\x1b[?12l");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::SetCursorStyle(CursorStyle::BlinkingBlock))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::SetCursorStyle(CursorStyle::Underline))));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::SetCursorStyle(CursorStyle::Bar))));
+        assert!(matches!(events[3], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+        assert!(matches!(&events[4], ParsedEvent::Csi(CsiSequence::ResetMode(modes)) if modes == &[Mode::CursorBlink]));
+    }
+    
+    #[test]
+    fn test_media_copy() {
+        let mut parser = VteParser::new();
+        let events = parser.parse(b"\x1b[i\x1b[5i\x1b[4i\x1b[?1i\x1b[?5i\x1b[?4i\x1b[7i");
+        assert!(matches!(events[0], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::PrintScreen))));
+        assert!(matches!(events[1], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::PrinterController(true)))));
+        assert!(matches!(events[2], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::PrinterController(false)))));
+        assert!(matches!(events[3], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::PrintLine))));
+        assert!(matches!(events[4], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::AutoPrint(true)))));
+        assert!(matches!(events[5], ParsedEvent::Csi(CsiSequence::MediaCopy(MediaCopy::AutoPrint(false)))));
+        assert!(matches!(events[6], ParsedEvent::Unknown { kind: SequenceKind::Csi, .. }));
+    }
+    
+    #[test]
+    fn test_ascii_runs() {
+        let mut parser = VteParser::new();
+        let mut events = Vec::new();
+        let data = b"plain \x1b[1mbold\x1b[m text\x1b[2b";
+        parser.parse_with(data, |event| events.push(event));
+        assert!(matches!(&events[0], ParsedEvent::Text(Cow::Borrowed("plain "))));
+        assert!(matches!(&events[2], ParsedEvent::Text(Cow::Borrowed("bold"))));
+        assert!(matches!(&events[4], ParsedEvent::Text(Cow::Borrowed(" text"))));
+        assert!(matches!(events[5], ParsedEvent::Csi(CsiSequence::RepeatCharacter { ch: 't', count: 2 })));
+        
+        // An ESC ending a broken UTF-8 character is swallowed by VTE and
+        // must not start a string
+        let events = parser.parse(b"\xe2\x1bXab\x1b\\");
+        assert!(matches!(&events[0], ParsedEvent::Text(text) if text == "\u{fffd}Xab"));
+        assert!(!events.iter().any(|event| matches!(event, ParsedEvent::SosString(_))));
+    }
+}
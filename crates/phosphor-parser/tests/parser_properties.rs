@@ -0,0 +1,145 @@
+use phosphor_common::traits::{ParsedEvent, TerminalParser};
+use phosphor_parser::VteParser;
+use proptest::prelude::*;
+
+/// Parse `data` in reads of the given sizes
+fn parse_chunked(data: &[u8], sizes: &[usize]) -> Vec<ParsedEvent<'static>> {
+    let mut parser = VteParser::new();
+    let mut events = Vec::new();
+    let mut rest = data;
+    for &size in sizes.iter().cycle() {
+        if rest.is_empty() {
+            break;
+        }
+        let (chunk, tail) = rest.split_at(size.clamp(1, rest.len()));
+        events.extend(parser.parse(chunk));
+        rest = tail;
+    }
+    events
+}
+
+/// Describe events with adjacent text runs joined, since where a run is
+/// split depends on how the input was read
+fn normalize(events: &[ParsedEvent<'_>]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut text = String::new();
+    for event in events {
+        match event {
+            ParsedEvent::Text(run) => text.push_str(run),
+            other => {
+                if !text.is_empty() {
+                    out.push(format!("Text({:?})", std::mem::take(&mut text)));
+                }
+                out.push(format!("{:?}", other));
+            }
+        }
+    }
+    if !text.is_empty() {
+        out.push(format!("Text({:?})", text));
+    }
+    out
+}
+
+/// Bytes weighted towards escape sequence syntax
+fn escape_heavy_bytes() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop_oneof![
+        4 => any::<u8>(),
+        2 => Just(0x1b),
+        1 => prop::sample::select(b"[]P_^X\\;:?>=<!$+q0123456789m\x07\x18\x1a".to_vec()),
+        1 => prop::sample::select(vec![0xc3, 0xa9, 0xe2, 0x82, 0xac, 0xf0, 0x9f, 0x98, 0x80, 0x9b, 0x9c, 0x9d]),
+    ];
+    prop::collection::vec(byte, 0..512)
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics(data in prop::collection::vec(any::<u8>(), 0..1024)) {
+        VteParser::new().parse(&data);
+    }
+    
+    #[test]
+    fn parse_escape_heavy_input_never_panics(data in escape_heavy_bytes()) {
+        VteParser::new().parse(&data);
+    }
+    
+    #[test]
+    fn c1_controls_never_panic(data in escape_heavy_bytes()) {
+        let mut parser = VteParser::new();
+        parser.set_c1_controls(true);
+        parser.parse(&data);
+    }
+    
+    #[test]
+    fn chunking_does_not_change_events(
+        data in escape_heavy_bytes(),
+        sizes in prop::collection::vec(1usize..16, 1..8),
+    ) {
+        let whole = VteParser::new().parse(&data);
+        let chunked = parse_chunked(&data, &sizes);
+        prop_assert_eq!(normalize(&whole), normalize(&chunked));
+    }
+    
+    #[test]
+    fn parse_with_matches_parse(data in escape_heavy_bytes()) {
+        let mut borrowed = Vec::new();
+        VteParser::new().parse_with(&data, |event| borrowed.push(format!("{:?}", event.into_owned())));
+        let owned: Vec<String> = VteParser::new().parse(&data).iter().map(|event| format!("{:?}", event)).collect();
+        prop_assert_eq!(borrowed, owned);
+    }
+    
+    #[test]
+    fn event_ranges_follow_one_another(data in escape_heavy_bytes()) {
+        let mut ranges = Vec::new();
+        VteParser::new().parse_with_ranges(&data, |_, range| ranges.push(range));
+        let mut start = 0;
+        for range in ranges {
+            prop_assert_eq!(range.start, start);
+            prop_assert!(range.start <= range.end && range.end <= data.len(), "range {:?}", range);
+            start = range.end;
+        }
+    }
+    
+    #[test]
+    fn parser_recovers_after_garbage(data in escape_heavy_bytes()) {
+        let mut parser = VteParser::new();
+        parser.parse(&data);
+        // CAN aborts any sequence in progress; a truncated character is
+        // printed as a replacement character
+        let events = parser.parse(b"\x18");
+        prop_assert!(
+            events.iter().all(|event| matches!(event, ParsedEvent::Text(text) if text == "\u{fffd}")),
+            "CAN produced events: {:?}",
+            events
+        );
+        let events = parser.parse(b"ok");
+        prop_assert!(matches!(&events[..], [ParsedEvent::Text(text)] if text == "ok"), "recovered: {:?}", events);
+    }
+}
+
+#[test]
+fn pathological_nesting_terminates() {
+    let inputs: Vec<Vec<u8>> = vec![
+        b"\x1b[".repeat(100_000),
+        b"\x1bP".repeat(100_000),
+        b"\x1b]".repeat(100_000),
+        b"\x1b_".repeat(100_000),
+        [b"\x1b[".as_slice(), &b"1;".repeat(100_000), b"m"].concat(),
+        [b"\x1b[".as_slice(), &b":".repeat(100_000), b"m"].concat(),
+        [b"\x1b]8;;".as_slice(), &b"x".repeat(1 << 21), b"\x07"].concat(),
+        [b"\x1b_".as_slice(), &b"x".repeat(1 << 21), b"\x1b\\"].concat(),
+        [b"\x1bP+q".as_slice(), &b"41".repeat(1 << 20), b"\x1b\\"].concat(),
+        [b"\x1b[".as_slice(), &b"9".repeat(100_000), b"b"].concat(),
+        b"\xf0\x9f\x98".repeat(100_000),
+        b"\xe2\x1b[m".repeat(100_000),
+    ];
+    for input in inputs {
+        let mut parser = VteParser::new();
+        parser.parse(&input);
+        let events = parser.parse(b"\x18ok");
+        assert!(
+            matches!(events.last(), Some(ParsedEvent::Text(text)) if text.ends_with("ok")),
+            "parser did not recover: {:?}",
+            events
+        );
+    }
+}
@@ -76,7 +76,7 @@ async fn test_terminal_resize() {
     // Send resize command
     let new_size = Size::new(100, 30);
     cmd_sender
-        .send(Command::Resize(new_size))
+        .send(Command::Resize { size: new_size, pixels: None })
         .await
         .expect("Failed to send resize");
     